@@ -108,6 +108,11 @@ impl Attrs {
         }
     }
 
+    /// Sets the foreground color.
+    pub fn set_fg(&mut self, fg: Color) {
+        self.fg = Some(fg);
+    }
+
     /// Combines two [`Attrs`] instances into one, choosing options from `self`
     /// as long as they are [`Some`], otherwise choosing them from `new`.
     pub fn apply_to(&mut self, new: &Self) {
@@ -126,6 +126,18 @@ impl Bg {
         })
     }
 
+    /// Sets the color of the background. This has no effect on
+    /// [`Bg::None`].
+    pub fn set_color(&mut self, new: Color) {
+        match self {
+            Self::None => {}
+            Self::Bubble { color, .. }
+            | Self::BubbleLeft { color, .. }
+            | Self::BubbleRight { color, .. }
+            | Self::BubbleProp { color, .. } => *color = new,
+        }
+    }
+
     pub(crate) fn draw(
         &self,
         cr: &cairo::Context,
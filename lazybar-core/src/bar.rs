@@ -3,6 +3,7 @@ use std::{
     pin::Pin,
     rc::Rc,
     sync::{Arc, LazyLock, Mutex},
+    time::Duration,
 };
 
 use anyhow::{Result, anyhow};
@@ -14,20 +15,23 @@ use tokio::{
     net::UnixStream,
     sync::{OnceCell, mpsc::UnboundedSender},
     task::JoinSet,
+    time::{Instant, MissedTickBehavior, interval_at},
 };
-use tokio_stream::{Stream, StreamMap};
+use tokio_stream::{Stream, StreamMap, wrappers::IntervalStream};
 use x11rb::{
     connection::Connection,
     protocol::{
         self,
+        randr::MonitorInfo,
         xproto::{ConnectionExt, Visualtype, Window},
     },
     xcb_ffi::XCBConnection,
 };
 
 use crate::{
-    Alignment, IpcStream, Margins, PanelDrawFn, PanelHideFn, PanelShowFn,
-    PanelShutdownFn, PanelStream, Position, create_surface, create_window,
+    Alignment, IpcStream, Margins, PanelAnimateFn, PanelDrawFn, PanelHideFn,
+    PanelShowFn, PanelShutdownFn, PanelStream, Position, create_surface,
+    create_window,
     ipc::{self, ChannelEndpoint},
     set_wm_properties,
 };
@@ -55,6 +59,8 @@ pub struct BarInfo {
     pub transparent: bool,
     /// The background color of the bar
     pub bg: Color,
+    /// The monitor that the bar is on
+    pub monitor: MonitorInfo,
     /// The X11 cursor names associated with the bar
     #[cfg(feature = "cursor")]
     pub cursors: Cursors,
@@ -196,6 +202,10 @@ pub struct PanelDrawInfo {
     /// Information about how to draw the cursor over this panel.
     #[cfg(feature = "cursor")]
     pub cursor_info: CursorInfo,
+    /// How often the panel should be redrawn with the same content, and the
+    /// function to run before each of those redraws.
+    #[dbg(formatter = "fmt_option")]
+    pub animation: Option<(Duration, PanelAnimateFn)>,
     /// Information to be shown when `lazybar-msg` sends a "dump" message.
     pub dump: String,
 }
@@ -231,9 +241,22 @@ impl PanelDrawInfo {
             shutdown,
             #[cfg(feature = "cursor")]
             cursor_info,
+            animation: None,
             dump,
         }
     }
+
+    /// Redraws the panel every `interval` while the pointer isn't over it,
+    /// calling `step` before each redraw.
+    #[must_use]
+    pub fn with_animation(
+        mut self,
+        interval: Duration,
+        step: PanelAnimateFn,
+    ) -> Self {
+        self.animation = Some((interval, step));
+        self
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -275,6 +298,7 @@ impl From<&Panel> for PanelStatus {
 ///
 /// Note: scrolling direction may be incorrect depending on your configuration
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default)]
+#[non_exhaustive]
 pub enum MouseButton {
     /// The left mouse button
     #[default]
@@ -287,6 +311,10 @@ pub enum MouseButton {
     ScrollUp,
     /// Scrolling down
     ScrollDown,
+    /// Dragging with the left mouse button. This is sent when the button is
+    /// released somewhere other than where it was pressed, within the same
+    /// panel, and the coordinates are those of the release.
+    Drag,
 }
 
 impl MouseButton {
@@ -393,9 +421,13 @@ pub struct Bar {
     pub(crate) right_panels: Vec<Panel>,
     #[dbg(placeholder = "..")]
     pub(crate) streams: StreamMap<Alignment, StreamMap<usize, PanelStream>>,
+    #[dbg(placeholder = "..")]
+    pub(crate) animations: StreamMap<(Alignment, usize), IntervalStream>,
     pub(crate) ipc: bool,
     mapped: bool,
     center_state: CenterState,
+    press_x: Option<i16>,
+    pointer_x: Option<i16>,
 }
 
 impl Bar {
@@ -424,6 +456,7 @@ impl Bar {
                 height,
                 transparent,
                 bg: bg.clone(),
+                monitor: mon.clone(),
                 #[cfg(feature = "cursor")]
                 cursors,
             })
@@ -486,9 +519,12 @@ impl Bar {
                 center_panels: Vec::new(),
                 right_panels: Vec::new(),
                 streams: StreamMap::new(),
+                animations: StreamMap::new(),
                 ipc,
                 mapped: true,
                 center_state: CenterState::Center,
+                press_x: None,
+                pointer_x: None,
             },
             ipc_stream,
         ))
@@ -557,6 +593,38 @@ impl Bar {
             });
     }
 
+    fn panel_at(&self, x: i16) -> Option<&Panel> {
+        self.left_panels
+            .iter()
+            .chain(self.center_panels.iter())
+            .chain(self.right_panels.iter())
+            .filter(|p| p.draw_info.is_some())
+            .find(|p| {
+                p.x <= x as f64
+                    && p.x + p.draw_info.as_ref().unwrap().width as f64
+                        >= x as f64
+            })
+    }
+
+    fn send_mouse_event(
+        &self,
+        x: i16,
+        y: i16,
+        button: MouseButton,
+    ) -> Result<()> {
+        if let Some(p) = self.panel_at(x)
+            && let Some(e) = &p.endpoint
+        {
+            let e = e.lock().unwrap();
+            e.send.send(Event::Mouse(MouseEvent {
+                button,
+                x: x - p.x as i16,
+                y,
+            }))?;
+        }
+        Ok(())
+    }
+
     /// Handle an event from the X server.
     pub fn process_event(&mut self, event: &protocol::Event) -> Result<()> {
         match event {
@@ -573,90 +641,93 @@ impl Bar {
                     } else {
                         (event.root_x, event.root_y)
                     };
-
-                    let panel = self
-                        .left_panels
-                        .iter()
-                        .chain(self.center_panels.iter())
-                        .chain(self.right_panels.iter())
-                        .filter(|p| p.draw_info.is_some())
-                        .find(|p| {
-                            p.x <= x as f64
-                                && p.x
-                                    + p.draw_info.as_ref().unwrap().width as f64
-                                    >= x as f64
-                        });
-
-                    if let Some(p) = panel {
-                        if let Some(e) = &p.endpoint {
-                            let e = e.lock().unwrap();
-                            e.send.send(Event::Mouse(MouseEvent {
-                                button: MouseButton::try_parse(
-                                    button,
-                                    self.reverse_scroll,
-                                )
-                                // this can never fail due to match arm
-                                .unwrap(),
-                                x: x - p.x as i16,
-                                y,
-                            }))?;
-                        }
+                    if button == 1 {
+                        self.press_x = Some(x);
                     }
-                    Ok(())
+
+                    self.send_mouse_event(
+                        x,
+                        y,
+                        MouseButton::try_parse(button, self.reverse_scroll)
+                            // this can never fail due to match arm
+                            .unwrap(),
+                    )
                 }
                 _ => Ok(()),
             },
-            #[cfg(feature = "cursor")]
-            protocol::Event::MotionNotify(event) => {
+            protocol::Event::ButtonRelease(event) if event.detail == 1 => {
                 let (x, y) = if event.same_screen {
                     (event.event_x, event.event_y)
                 } else {
                     (event.root_x, event.root_y)
                 };
 
-                let panel = self
-                    .left_panels
-                    .iter()
-                    .chain(self.center_panels.iter())
-                    .chain(self.right_panels.iter())
-                    .filter(|p| p.draw_info.is_some())
-                    .find(|p| {
-                        p.x <= x as f64
-                            && p.x + p.draw_info.as_ref().unwrap().width as f64
-                                >= x as f64
-                    });
+                match self.press_x.take() {
+                    Some(press_x)
+                        if press_x != x
+                            && self.panel_at(press_x).map(|p| p.x)
+                                == self.panel_at(x).map(|p| p.x) =>
+                    {
+                        self.send_mouse_event(x, y, MouseButton::Drag)
+                    }
+                    _ => Ok(()),
+                }
+            }
+            protocol::Event::MotionNotify(event) => {
+                let x = if event.same_screen {
+                    event.event_x
+                } else {
+                    event.root_x
+                };
+                self.pointer_x = Some(x);
 
-                if let Some(panel) = panel {
-                    if let Some(ref draw_info) = panel.draw_info {
-                        if let Ok(cursor) =
-                            draw_info.cursor_info.get(MouseEvent {
-                                button: MouseButton::Left,
-                                x: x - panel.x as i16,
-                                y,
-                            })
-                        {
-                            set_cursor(
-                                self.conn.as_ref(),
-                                self.screen,
-                                cursor,
-                                self.window,
-                            )?;
-                        } else {
-                            set_cursor(
-                                self.conn.as_ref(),
-                                self.screen,
-                                Cursor::Default,
-                                self.window,
-                            )?;
-                        }
+                #[cfg(feature = "cursor")]
+                self.update_cursor(
+                    x,
+                    if event.same_screen {
+                        event.event_y
                     } else {
-                        set_cursor(
-                            self.conn.as_ref(),
-                            self.screen,
-                            Cursor::Default,
-                            self.window,
-                        )?;
-                    }
+                        event.root_y
+                    },
+                )?;
+
+                Ok(())
+            }
+            protocol::Event::LeaveNotify(_) => {
+                self.pointer_x = None;
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(feature = "cursor")]
+    fn update_cursor(&self, x: i16, y: i16) -> Result<()> {
+        let panel = self
+            .left_panels
+            .iter()
+            .chain(self.center_panels.iter())
+            .chain(self.right_panels.iter())
+            .filter(|p| p.draw_info.is_some())
+            .find(|p| {
+                p.x <= x as f64
+                    && p.x + p.draw_info.as_ref().unwrap().width as f64
+                        >= x as f64
+            });
+
+        if let Some(panel) = panel {
+            if let Some(ref draw_info) = panel.draw_info {
+                if let Ok(cursor) = draw_info.cursor_info.get(MouseEvent {
+                    button: MouseButton::Left,
+                    x: x - panel.x as i16,
+                    y,
+                }) {
+                    set_cursor(
+                        self.conn.as_ref(),
+                        self.screen,
+                        cursor,
+                        self.window,
+                    )?;
                 } else {
                     set_cursor(
                         self.conn.as_ref(),
@@ -665,11 +736,24 @@ impl Bar {
                         self.window,
                     )?;
                 }
-
-                Ok(())
+            } else {
+                set_cursor(
+                    self.conn.as_ref(),
+                    self.screen,
+                    Cursor::Default,
+                    self.window,
+                )?;
             }
-            _ => Ok(()),
+        } else {
+            set_cursor(
+                self.conn.as_ref(),
+                self.screen,
+                Cursor::Default,
+                self.window,
+            )?;
         }
+
+        Ok(())
     }
 
     fn handle_ipc_event(&mut self, message: &str) -> Result<bool> {
@@ -779,19 +863,22 @@ impl Bar {
                 .filter(|p| p.name == panel);
 
             let target = panels.next();
-            let (endpoint, message) = match if target.is_none() {
-                Err(anyhow!("No panel with name {panel} was found"))
-            } else if panels.next().is_some() {
-                Err(anyhow!(
-                    "This panel has multiple instances and cannot be messaged"
-                ))
-            } else if let Some(ref endpoint) = target.unwrap().endpoint {
-                Ok((endpoint.clone(), message.to_string()))
+            let (endpoint, message) = match if let Some(target) = target {
+                if panels.next().is_some() {
+                    Err(anyhow!(
+                        "This panel has multiple instances and cannot be \
+                         messaged"
+                    ))
+                } else if let Some(ref endpoint) = target.endpoint {
+                    Ok((endpoint.clone(), message.to_string()))
+                } else {
+                    Err(anyhow!(
+                        "The target panel has no associated sender and cannot \
+                         be messaged"
+                    ))
+                }
             } else {
-                Err(anyhow!(
-                    "The target panel has no associated sender and cannot be \
-                     messaged"
-                ))
+                Err(anyhow!("No panel with name {panel} was found"))
             } {
                 Ok(r) => r,
                 Err(e) => {
@@ -892,6 +979,8 @@ impl Bar {
         idx: usize,
         draw_info: PanelDrawInfo,
     ) -> Result<()> {
+        self.update_animation(alignment, idx, &draw_info);
+
         let new_width = f64::from(draw_info.width);
         match alignment {
             Alignment::Left => {
@@ -994,6 +1083,66 @@ impl Bar {
         }
     }
 
+    fn panels(&self, alignment: Alignment) -> &[Panel] {
+        match alignment {
+            Alignment::Left => &self.left_panels,
+            Alignment::Center => &self.center_panels,
+            Alignment::Right => &self.right_panels,
+        }
+    }
+
+    fn update_animation(
+        &mut self,
+        alignment: Alignment,
+        idx: usize,
+        draw_info: &PanelDrawInfo,
+    ) {
+        let old = self
+            .panels(alignment)
+            .get(idx)
+            .and_then(|p| p.draw_info.as_ref())
+            .and_then(|d| d.animation.as_ref())
+            .map(|(interval, _)| *interval);
+        let new = draw_info.animation.as_ref().map(|(interval, _)| *interval);
+        if old == new {
+            return;
+        }
+
+        if let Some(period) = new {
+            let mut interval = interval_at(Instant::now() + period, period);
+            interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+            self.animations
+                .insert((alignment, idx), IntervalStream::new(interval));
+        } else {
+            self.animations.remove(&(alignment, idx));
+        }
+    }
+
+    /// Advance the animation of a panel and redraw it, unless the pointer is
+    /// over it.
+    pub fn animate(&self, alignment: Alignment, idx: usize) -> Result<()> {
+        let Some(panel) = self.panels(alignment).get(idx) else {
+            return Ok(());
+        };
+        let Some(draw_info) = &panel.draw_info else {
+            return Ok(());
+        };
+        let Some((_, step)) = &draw_info.animation else {
+            return Ok(());
+        };
+        if !panel.visible
+            || self.pointer_x.is_some_and(|x| {
+                panel.x <= f64::from(x)
+                    && f64::from(x) <= panel.x + f64::from(draw_info.width)
+            })
+        {
+            return Ok(());
+        }
+
+        step()?;
+        self.redraw_one(alignment, idx)
+    }
+
     fn redraw_one(&self, alignment: Alignment, idx: usize) -> Result<()> {
         match alignment {
             Alignment::Left => {
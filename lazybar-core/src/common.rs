@@ -10,16 +10,20 @@ use config::Value;
 use derive_builder::Builder;
 use derive_debug::Dbg;
 use futures::task::AtomicWaker;
+use pango::Layout;
 use pangocairo::functions::show_layout;
 
 #[cfg(feature = "cursor")]
 use crate::bar::CursorInfo;
 use crate::{
-    Highlight, PanelHideFn, PanelShowFn, Ramp,
+    ColorRamps, Highlight, History, Overflow, PanelHideFn, PanelShowFn, Ramp,
+    Template, Thresholds,
     actions::Actions,
     attrs::Attrs,
     bar::{Dependence, PanelDrawInfo},
     image::Image,
+    inline::PlacedObject,
+    overflow::Scroll,
     remove_array_from_config, remove_bool_from_config,
     remove_string_from_config,
 };
@@ -55,6 +59,55 @@ pub struct PanelCommon {
     pub images: Vec<Image>,
     /// Whether the panel should be visible on startup
     pub visible: bool,
+    /// How the panel should change when its value crosses a threshold. Only
+    /// used by panels that display a single primary value.
+    pub thresholds: Thresholds,
+    /// Ramps that change the panel's colors to follow its value. Only used by
+    /// panels that support ramps.
+    pub color_ramps: ColorRamps,
+    /// The panel's recent values, shown as `%graph%`. Only used by panels
+    /// that support graphs.
+    pub history: History,
+    /// How to handle text that's wider than the panel's maximum width. Used
+    /// by panels that draw with [`PanelCommon::draw`], and by the media
+    /// panels for `%main%`.
+    pub overflow: Overflow,
+}
+
+/// A panel's text as it was last drawn by [`PanelCommon::draw`].
+#[cfg(feature = "pulseaudio")]
+#[derive(Debug)]
+pub(crate) struct HitLayout {
+    layout: Layout,
+    scroll: Option<Scroll>,
+    x: f64,
+    y: f64,
+}
+
+#[cfg(feature = "pulseaudio")]
+impl HitLayout {
+    /// Finds the byte index of the character at a position relative to the
+    /// panel, or [`None`] if there's no text there.
+    pub fn index_at(&self, x: f64, y: f64) -> Option<usize> {
+        let x = x - self.x;
+        let x = match self.scroll {
+            Some(ref scroll) if (0.0..f64::from(scroll.width)).contains(&x) => {
+                scroll.locate(x)?
+            }
+            Some(_) => return None,
+            None => x,
+        };
+        let (width, height) = self.layout.pixel_size();
+        if !(0.0..f64::from(width)).contains(&x) {
+            return None;
+        }
+        let y = (y - self.y).clamp(0.0, f64::from(height - 1));
+        let (_, index, _) = self.layout.xy_to_index(
+            (x * f64::from(pango::SCALE)) as i32,
+            (y * f64::from(pango::SCALE)) as i32,
+        );
+        usize::try_from(index).ok()
+    }
 }
 
 impl PanelCommon {
@@ -67,6 +120,9 @@ impl PanelCommon {
     /// The text will be interpreted as markup. If this is not your intended
     /// behavior, use [`markup_escape_text`][crate::markup_escape_text] to
     /// display what you want or implement this functionality manually.
+    ///
+    /// Text wider than the panel's maximum width is truncated or scrolled
+    /// according to [`PanelCommon::overflow`].
     pub fn draw(
         &self,
         cr: &Rc<cairo::Context>,
@@ -79,10 +135,11 @@ impl PanelCommon {
         show_hide: ShowHide,
         dump: String,
     ) -> Result<PanelDrawInfo> {
-        let layout = pangocairo::functions::create_layout(cr);
-        layout.set_markup(text);
-        attrs.apply_font(&layout);
-        let dims = layout.pixel_size();
+        let (layout, objects, scroll) = self.layout(cr, text, attrs);
+        let text_dims = layout.pixel_size();
+        let dims = scroll
+            .as_ref()
+            .map_or(text_dims, |scroll| (scroll.width, text_dims.1));
 
         let attrs = attrs.clone();
         let bg = attrs.bg.clone().unwrap_or_default();
@@ -107,7 +164,9 @@ impl PanelCommon {
                 ShowHide::None => (None, None),
             };
 
-        Ok(PanelDrawInfo::new(
+        let animation = scroll.as_ref().map(Scroll::animation);
+
+        let draw_info = PanelDrawInfo::new(
             bg.adjust_dims(dims, height),
             dependence,
             Box::new(move |cr, _| {
@@ -125,10 +184,24 @@ impl PanelCommon {
                     highlight.draw(cr, height as f64, dims.0 as f64)?;
                 }
 
+                if scroll.is_some() {
+                    cr.rectangle(0.0, 0.0, dims.0 as f64, height as f64);
+                    cr.clip();
+                }
                 cr.translate(0.0, (height - dims.1) as f64 / 2.0);
 
                 attrs.apply_fg(cr);
-                show_layout(cr, &layout);
+                let show = || {
+                    show_layout(cr, &layout);
+                    for object in &objects {
+                        object.draw(cr, &layout)?;
+                    }
+                    Ok(())
+                };
+                match scroll {
+                    Some(ref scroll) => scroll.draw(cr, show)?,
+                    None => show()?,
+                }
                 cr.restore()?;
                 Ok(())
             }),
@@ -138,7 +211,51 @@ impl PanelCommon {
             #[cfg(feature = "cursor")]
             CursorInfo::Static(self.actions.get_cursor()),
             dump,
-        ))
+        );
+
+        Ok(match animation {
+            Some((interval, step)) => draw_info.with_animation(interval, step),
+            None => draw_info,
+        })
+    }
+
+    /// Lays out `text` the way [`PanelCommon::draw`] does, applying the
+    /// panel's [`Overflow`].
+    fn layout(
+        &self,
+        cr: &cairo::Context,
+        text: &str,
+        attrs: &Attrs,
+    ) -> (Layout, Vec<PlacedObject>, Option<Scroll>) {
+        let layout = pangocairo::functions::create_layout(cr);
+        let (text, objects) = PlacedObject::extract(text);
+        layout.set_markup(text.as_str());
+        attrs.apply_font(&layout);
+        let objects = PlacedObject::attach(&layout, objects);
+        let scroll = self.overflow.fit(cr, &layout, text.as_str(), attrs);
+        (layout, objects, scroll)
+    }
+
+    /// Lays out `text` exactly as [`PanelCommon::draw`] will show it, so that
+    /// mouse events can be mapped back to the text under the pointer.
+    #[cfg(feature = "pulseaudio")]
+    pub(crate) fn hit_layout(
+        &self,
+        cr: &cairo::Context,
+        text: &str,
+        attrs: &Attrs,
+        height: i32,
+    ) -> HitLayout {
+        let (layout, _, scroll) = self.layout(cr, text, attrs);
+        let text_height = f64::from(layout.pixel_size().1);
+        HitLayout {
+            x: attrs.bg.as_ref().map_or(0.0, |bg| {
+                bg.get_offset(text_height, f64::from(height))
+            }),
+            y: (f64::from(height) - text_height) / 2.0,
+            layout,
+            scroll,
+        }
     }
 
     /// Parses a single format from a subset of the global config.
@@ -176,6 +293,44 @@ impl PanelCommon {
         formats
     }
 
+    /// Parses a single format from a subset of the global config and compiles
+    /// it into a [`Template`].
+    pub fn parse_template<S: BuildHasher>(
+        table: &mut HashMap<String, Value, S>,
+        suffix: &'static str,
+        default: &'static str,
+    ) -> Result<Template> {
+        Template::parse(Self::parse_format(table, suffix, default))
+    }
+
+    /// Parses a fixed-size group of formats from a subset of the global config
+    /// and compiles them into [`Template`]s.
+    ///
+    /// If `format` (with no suffix) is present, it replaces the default for
+    /// every suffix, so that a single format with conditionals can be used in
+    /// place of several. Formats with a suffix still take precedence.
+    pub fn parse_templates<S: BuildHasher, const N: usize>(
+        table: &mut HashMap<String, Value, S>,
+        suffixes: &[&'static str; N],
+        defaults: &[&'static str; N],
+    ) -> Result<[Template; N]> {
+        let fallback = remove_string_from_config("format", table);
+        let mut templates = [const { Template::empty() }; N];
+        let mut config = suffixes.iter().zip(defaults);
+        for template in &mut templates {
+            let (suffix, default) = config.next().unwrap();
+            let format = remove_string_from_config(
+                format!("format{suffix}").as_str(),
+                table,
+            )
+            .or_else(|| fallback.clone())
+            .unwrap_or_else(|| (*default).to_string());
+            log::debug!("got format: {:?}", format);
+            *template = Template::parse(format)?;
+        }
+        Ok(templates)
+    }
+
     /// Parses a variable-size group of formats from a subset of the global
     /// config.
     ///
@@ -311,12 +466,16 @@ impl PanelCommon {
     /// the global [`Config`][config::Config].
     ///
     /// Format strings should be specified as `format{suffix} = "value"`. Where
-    /// not noted, panels accept one format string with no suffix.
+    /// not noted, panels accept one format string with no suffix. Most panels
+    /// compile their format strings into [`Template`]s, which support padding,
+    /// filters, and conditionals in addition to `%name%` substitution.
     ///
     /// Dependence should be specified as `dependence = "value"`, where value is
     /// a valid variant of [`Dependence`].
     ///
-    /// See [`Actions::parse`] and [`Image::parse`] for more parsing details.
+    /// See [`Actions::parse`], [`Image::parse`], [`Thresholds::parse`],
+    /// [`ColorRamps::parse`], [`History::parse`], and [`Overflow::parse`] for
+    /// more parsing details.
     pub fn parse_common<S: BuildHasher>(
         table: &mut HashMap<String, Value, S>,
     ) -> Result<Self> {
@@ -362,6 +521,14 @@ impl PanelCommon {
         builder
             .visible(remove_bool_from_config("visible", table).unwrap_or(true));
 
+        builder.thresholds(Thresholds::parse(table)?);
+
+        builder.color_ramps(ColorRamps::parse(table));
+
+        builder.history(History::parse(table)?);
+
+        builder.overflow(Overflow::parse(table));
+
         Ok(builder.build()?)
     }
 }
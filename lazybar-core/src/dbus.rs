@@ -0,0 +1,1094 @@
+//! A minimal D-Bus client, supporting just enough of the wire protocol to
+//! call methods and receive signals on the session bus.
+
+use std::{
+    collections::VecDeque,
+    env, fs,
+    io::{Read, Write},
+    os::{
+        linux::net::SocketAddrExt,
+        unix::{
+            fs::MetadataExt,
+            net::{SocketAddr, UnixStream},
+        },
+    },
+    time::Duration,
+};
+
+use anyhow::{Context, Result, anyhow};
+
+const METHOD_CALL: u8 = 1;
+const METHOD_RETURN: u8 = 2;
+const ERROR: u8 = 3;
+/// The message type of a signal.
+pub const SIGNAL: u8 = 4;
+
+const FIELD_PATH: u8 = 1;
+const FIELD_INTERFACE: u8 = 2;
+const FIELD_MEMBER: u8 = 3;
+const FIELD_ERROR_NAME: u8 = 4;
+const FIELD_REPLY_SERIAL: u8 = 5;
+const FIELD_DESTINATION: u8 = 6;
+const FIELD_SENDER: u8 = 7;
+const FIELD_SIGNATURE: u8 = 8;
+
+/// The name, path, and interface of the bus itself.
+pub const BUS: (&str, &str, &str) = (
+    "org.freedesktop.DBus",
+    "/org/freedesktop/DBus",
+    "org.freedesktop.DBus",
+);
+/// The standard interface for reading and writing properties.
+pub const PROPERTIES: &str = "org.freedesktop.DBus.Properties";
+
+/// A value in the D-Bus type system.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    /// `y`
+    Byte(u8),
+    /// `b`
+    Bool(bool),
+    /// `n`
+    Int16(i16),
+    /// `q`
+    Uint16(u16),
+    /// `i`
+    Int32(i32),
+    /// `u`
+    Uint32(u32),
+    /// `x`
+    Int64(i64),
+    /// `t`
+    Uint64(u64),
+    /// `d`
+    Double(f64),
+    /// `s`
+    Str(String),
+    /// `o`
+    ObjectPath(String),
+    /// `g`
+    Signature(String),
+    /// `h`, an index into the message's file descriptors, which are never
+    /// received here
+    UnixFd(u32),
+    /// `a`, other than dictionaries
+    Array(Vec<Value>),
+    /// `a{..}`
+    Dict(Vec<(Value, Value)>),
+    /// `(..)`
+    Struct(Vec<Value>),
+    /// `v`
+    Variant(Box<Value>),
+}
+
+impl Value {
+    /// Returns the contents of strings, object paths, and signatures.
+    #[must_use]
+    pub fn as_str(&self) -> Option<&str> {
+        match self.unwrap_variant() {
+            Self::Str(s) | Self::ObjectPath(s) | Self::Signature(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns any integer as an `i64`.
+    #[must_use]
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self.unwrap_variant() {
+            Self::Byte(v) => Some(v.into()),
+            Self::Int16(v) => Some(v.into()),
+            Self::Uint16(v) => Some(v.into()),
+            Self::Int32(v) => Some(v.into()),
+            Self::Uint32(v) => Some(v.into()),
+            Self::Int64(v) => Some(v),
+            Self::Uint64(v) => i64::try_from(v).ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of a boolean.
+    #[must_use]
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self.unwrap_variant() {
+            Self::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements of an array or the fields of a struct.
+    #[must_use]
+    pub fn as_slice(&self) -> Option<&[Self]> {
+        match self.unwrap_variant() {
+            Self::Array(v) | Self::Struct(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Looks up a string key in a dictionary, such as the `a{sv}` used for
+    /// properties.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&Self> {
+        match self.unwrap_variant() {
+            Self::Dict(entries) => entries
+                .iter()
+                .find(|(k, _)| k.as_str() == Some(key))
+                .map(|(_, v)| v.unwrap_variant()),
+            _ => None,
+        }
+    }
+
+    /// Returns the contents of a variant, or the value itself otherwise.
+    #[must_use]
+    pub fn unwrap_variant(&self) -> &Self {
+        match self {
+            Self::Variant(v) => v.unwrap_variant(),
+            v => v,
+        }
+    }
+
+    /// The signature of a value that can be sent. The type of an array is
+    /// taken from its elements, so empty arrays can't be sent.
+    fn signature(&self) -> Result<String> {
+        Ok(match self {
+            Self::Byte(_) => "y".into(),
+            Self::Bool(_) => "b".into(),
+            Self::Int16(_) => "n".into(),
+            Self::Uint16(_) => "q".into(),
+            Self::Int32(_) => "i".into(),
+            Self::Uint32(_) => "u".into(),
+            Self::Int64(_) => "x".into(),
+            Self::Uint64(_) => "t".into(),
+            Self::Double(_) => "d".into(),
+            Self::Str(_) => "s".into(),
+            Self::ObjectPath(_) => "o".into(),
+            Self::Signature(_) => "g".into(),
+            Self::Variant(_) => "v".into(),
+            Self::Array(values) => {
+                format!("a{}", Self::element_signature(values.iter())?)
+            }
+            Self::Dict(entries) => format!(
+                "a{{{}{}}}",
+                Self::element_signature(entries.iter().map(|(k, _)| k))?,
+                Self::element_signature(entries.iter().map(|(_, v)| v))?
+            ),
+            Self::Struct(fields) => format!(
+                "({})",
+                fields
+                    .iter()
+                    .map(Self::signature)
+                    .collect::<Result<String>>()?
+            ),
+            Self::UnixFd(_) => return Err(anyhow!("Can't send {self:?}")),
+        })
+    }
+
+    /// The signature shared by all of the elements of an array.
+    fn element_signature<'a>(
+        mut values: impl Iterator<Item = &'a Self>,
+    ) -> Result<String> {
+        let signature = values
+            .next()
+            .ok_or_else(|| anyhow!("Can't send an empty array"))?
+            .signature()?;
+        for value in values {
+            if value.signature()? != signature {
+                return Err(anyhow!("Mixed types in array: {value:?}"));
+            }
+        }
+        Ok(signature)
+    }
+}
+
+/// A message received from the bus.
+#[derive(Debug, Clone, Default)]
+pub struct Message {
+    /// One of [`SIGNAL`] or the other message types.
+    pub kind: u8,
+    /// The serial assigned by the sender.
+    // only needed to answer method calls, which the mock bus in tests does
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub serial: u32,
+    /// The serial of the call that this message replies to.
+    pub reply_serial: Option<u32>,
+    /// The unique name of the sender.
+    pub sender: Option<String>,
+    /// The name that a method call was sent to.
+    #[cfg_attr(not(test), allow(dead_code))]
+    pub destination: Option<String>,
+    /// The object that sent a signal.
+    pub path: Option<String>,
+    /// The interface of a signal.
+    pub interface: Option<String>,
+    /// The name of a signal.
+    pub member: Option<String>,
+    /// The name of an error.
+    pub error_name: Option<String>,
+    /// The arguments.
+    pub body: Vec<Value>,
+}
+
+impl Message {
+    /// Whether this is the given signal.
+    #[must_use]
+    pub fn is_signal(&self, interface: &str, member: &str) -> bool {
+        self.kind == SIGNAL
+            && self.interface.as_deref() == Some(interface)
+            && self.member.as_deref() == Some(member)
+    }
+}
+
+/// A connection to a message bus.
+#[derive(Debug)]
+pub struct Connection {
+    stream: UnixStream,
+    serial: u32,
+    signals: VecDeque<Message>,
+    /// Data read from the stream that doesn't yet make up a whole message
+    buf: Vec<u8>,
+}
+
+impl Connection {
+    /// Connects to the session bus given by `DBUS_SESSION_BUS_ADDRESS`,
+    /// falling back to the usual socket in `XDG_RUNTIME_DIR`.
+    pub fn session() -> Result<Self> {
+        let address = env::var("DBUS_SESSION_BUS_ADDRESS").or_else(|_| {
+            env::var("XDG_RUNTIME_DIR")
+                .map(|dir| format!("unix:path={dir}/bus"))
+        })?;
+        let stream = address
+            .split(';')
+            .find_map(|address| connect(address).ok())
+            .ok_or_else(|| {
+                anyhow!("Failed to connect to the session bus at {address}")
+            })?;
+        Self::open(stream)
+    }
+
+    /// Authenticates with the bus on the other end of `stream`.
+    fn open(stream: UnixStream) -> Result<Self> {
+        let mut conn = Self {
+            stream,
+            serial: 0,
+            signals: VecDeque::new(),
+            buf: Vec::new(),
+        };
+        conn.authenticate()?;
+        conn.call(BUS.0, BUS.1, BUS.2, "Hello", &[])?;
+        Ok(conn)
+    }
+
+    fn authenticate(&mut self) -> Result<()> {
+        let uid = fs::metadata("/proc/self")?.uid().to_string();
+        let hex = uid.bytes().map(|b| format!("{b:02x}")).collect::<String>();
+        self.stream.write_all(b"\0")?;
+        self.stream
+            .write_all(format!("AUTH EXTERNAL {hex}\r\n").as_bytes())?;
+
+        let mut line = Vec::new();
+        let mut byte = [0];
+        while !line.ends_with(b"\r\n") {
+            self.stream.read_exact(&mut byte)?;
+            line.push(byte[0]);
+        }
+        if !line.starts_with(b"OK") {
+            return Err(anyhow!(
+                "D-Bus authentication failed: {}",
+                String::from_utf8_lossy(&line).trim()
+            ));
+        }
+
+        Ok(self.stream.write_all(b"BEGIN\r\n")?)
+    }
+
+    /// Limits how long [`Connection::call`] and [`Connection::next_signal`]
+    /// wait for a message. A call that times out returns an error, and its
+    /// reply is discarded if it arrives later. [`None`] waits forever, which
+    /// is the default.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<()> {
+        Ok(self.stream.set_read_timeout(timeout)?)
+    }
+
+    /// Calls a method and waits for its reply. Signals that arrive in the
+    /// meantime are kept for [`Connection::next_signal`].
+    pub fn call(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        member: &str,
+        args: &[Value],
+    ) -> Result<Vec<Value>> {
+        self.serial += 1;
+        let serial = self.serial;
+        self.stream.write_all(&encode(
+            METHOD_CALL,
+            serial,
+            &[
+                (FIELD_PATH, Value::ObjectPath(path.into())),
+                (FIELD_INTERFACE, Value::Str(interface.into())),
+                (FIELD_MEMBER, Value::Str(member.into())),
+                (FIELD_DESTINATION, Value::Str(destination.into())),
+            ],
+            args,
+        )?)?;
+
+        loop {
+            let message = self.read()?;
+            match message.kind {
+                METHOD_RETURN if message.reply_serial == Some(serial) => {
+                    return Ok(message.body);
+                }
+                ERROR if message.reply_serial == Some(serial) => {
+                    return Err(anyhow!(
+                        "{member} failed: {} {}",
+                        message.error_name.unwrap_or_default(),
+                        message
+                            .body
+                            .first()
+                            .and_then(Value::as_str)
+                            .unwrap_or_default()
+                    ));
+                }
+                SIGNAL => self.signals.push_back(message),
+                _ => {}
+            }
+        }
+    }
+
+    /// Reads a property of an object.
+    pub fn get_property(
+        &mut self,
+        destination: &str,
+        path: &str,
+        interface: &str,
+        name: &str,
+    ) -> Result<Value> {
+        self.call(
+            destination,
+            path,
+            PROPERTIES,
+            "Get",
+            &[Value::Str(interface.into()), Value::Str(name.into())],
+        )?
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("Empty reply reading {name}"))
+    }
+
+    /// Asks the bus to send signals matching `rule`.
+    pub fn add_match(&mut self, rule: &str) -> Result<()> {
+        self.call(BUS.0, BUS.1, BUS.2, "AddMatch", &[Value::Str(rule.into())])
+            .map(|_| ())
+    }
+
+    /// Blocks until a signal arrives.
+    pub fn next_signal(&mut self) -> Result<Message> {
+        if let Some(signal) = self.signals.pop_front() {
+            return Ok(signal);
+        }
+        loop {
+            let message = self.read()?;
+            if message.kind == SIGNAL {
+                return Ok(message);
+            }
+        }
+    }
+
+    /// Reads the next message. Partial messages are kept across calls, so
+    /// that a read that times out doesn't lose track of where the next message
+    /// starts.
+    fn read(&mut self) -> Result<Message> {
+        loop {
+            if self.buf.len() >= 16 {
+                let mut reader = Reader {
+                    data: &self.buf[..16],
+                    pos: 4,
+                    big: big_endian(self.buf[0])?,
+                };
+                let body_len = reader.u32()? as usize;
+                reader.pos = 12;
+                let fields_len = reader.u32()? as usize;
+                let len = (16 + fields_len).next_multiple_of(8) + body_len;
+                if self.buf.len() >= len {
+                    let message = decode(&self.buf[..len]);
+                    self.buf.drain(..len);
+                    return message;
+                }
+            }
+
+            let mut chunk = [0; 4096];
+            let len = self.stream.read(&mut chunk)?;
+            if len == 0 {
+                return Err(anyhow!("The bus closed the connection"));
+            }
+            self.buf.extend_from_slice(&chunk[..len]);
+        }
+    }
+}
+
+/// Whether a message is big-endian, given its first byte.
+fn big_endian(flag: u8) -> Result<bool> {
+    match flag {
+        b'l' => Ok(false),
+        b'B' => Ok(true),
+        other => Err(anyhow!("Invalid endianness {other}")),
+    }
+}
+
+/// Decodes a complete message.
+fn decode(data: &[u8]) -> Result<Message> {
+    let big = big_endian(*data.first().context("Empty D-Bus message")?)?;
+    let mut reader = Reader { data, pos: 8, big };
+    let mut message = Message {
+        kind: data.get(1).copied().unwrap_or_default(),
+        serial: reader.u32()?,
+        ..Message::default()
+    };
+    let mut signature = String::new();
+    if let Value::Array(fields) = reader.read_one("a(yv)")? {
+        for field in fields {
+            let Value::Struct(field) = field else {
+                continue;
+            };
+            let [Value::Byte(code), value] = field.as_slice() else {
+                continue;
+            };
+            let value = value.unwrap_variant();
+            let text = value.as_str().map(ToString::to_string);
+            match *code {
+                FIELD_PATH => message.path = text,
+                FIELD_INTERFACE => message.interface = text,
+                FIELD_MEMBER => message.member = text,
+                FIELD_ERROR_NAME => message.error_name = text,
+                FIELD_REPLY_SERIAL => {
+                    message.reply_serial =
+                        value.as_i64().and_then(|v| u32::try_from(v).ok());
+                }
+                FIELD_DESTINATION => message.destination = text,
+                FIELD_SENDER => message.sender = text,
+                FIELD_SIGNATURE => signature = text.unwrap_or_default(),
+                _ => {}
+            }
+        }
+    }
+
+    let mut reader = Reader {
+        data: data
+            .get(reader.pos.next_multiple_of(8)..)
+            .context("Truncated D-Bus message")?,
+        pos: 0,
+        big,
+    };
+    let signature = signature.as_bytes();
+    let mut i = 0;
+    while i < signature.len() {
+        message.body.push(reader.read(signature, &mut i)?);
+    }
+
+    Ok(message)
+}
+
+/// Connects to a single address, e.g. `unix:path=/run/user/1000/bus`.
+fn connect(address: &str) -> Result<UnixStream> {
+    let params = address
+        .strip_prefix("unix:")
+        .ok_or_else(|| anyhow!("Unsupported address {address}"))?;
+    for param in params.split(',') {
+        let Some((key, value)) = param.split_once('=') else {
+            continue;
+        };
+        let value = unescape(value);
+        match key {
+            "path" => return Ok(UnixStream::connect(value)?),
+            "abstract" => {
+                let addr = SocketAddr::from_abstract_name(value.as_bytes())?;
+                return Ok(UnixStream::connect_addr(&addr)?);
+            }
+            _ => {}
+        }
+    }
+    Err(anyhow!("Unsupported address {address}"))
+}
+
+/// Decodes the `%xx` escapes allowed in addresses.
+fn unescape(value: &str) -> String {
+    let mut out = Vec::new();
+    let mut bytes = value.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'%' {
+            let hex =
+                [bytes.next().unwrap_or(b'0'), bytes.next().unwrap_or(b'0')];
+            out.push(
+                std::str::from_utf8(&hex)
+                    .ok()
+                    .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                    .unwrap_or_default(),
+            );
+        } else {
+            out.push(b);
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The alignment of a type, given the first character of its signature.
+const fn alignment(code: u8) -> usize {
+    match code {
+        b'n' | b'q' => 2,
+        b'b' | b'i' | b'u' | b's' | b'o' | b'a' | b'h' => 4,
+        b'x' | b't' | b'd' | b'(' | b'{' => 8,
+        _ => 1,
+    }
+}
+
+/// Finds the end of the single complete type starting at `i`.
+fn type_end(sig: &[u8], i: usize) -> Result<usize> {
+    match sig.get(i) {
+        Some(b'a') => type_end(sig, i + 1),
+        Some(open @ (b'(' | b'{')) => {
+            let close = if *open == b'(' { b')' } else { b'}' };
+            let mut j = i + 1;
+            while sig.get(j) != Some(&close) {
+                if j >= sig.len() {
+                    return Err(anyhow!("Unterminated signature"));
+                }
+                j = type_end(sig, j)?;
+            }
+            Ok(j + 1)
+        }
+        Some(_) => Ok(i + 1),
+        None => Err(anyhow!("Truncated signature")),
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    big: bool,
+}
+
+impl Reader<'_> {
+    fn align(&mut self, n: usize) {
+        self.pos = self.pos.next_multiple_of(n);
+    }
+
+    fn take<const N: usize>(&mut self) -> Result<[u8; N]> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + N)
+            .ok_or_else(|| anyhow!("Truncated D-Bus message"))?;
+        self.pos += N;
+        Ok(bytes.try_into().unwrap())
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        self.align(4);
+        let bytes = self.take()?;
+        Ok(if self.big {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+
+    fn string(&mut self, len: usize) -> Result<String> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or_else(|| anyhow!("Truncated D-Bus message"))?;
+        // skip the trailing nul
+        self.pos += len + 1;
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    }
+
+    fn read_one(&mut self, sig: &str) -> Result<Value> {
+        self.read(sig.as_bytes(), &mut 0)
+    }
+
+    /// Reads the single complete type starting at `sig[*i]`, advancing `i`
+    /// past it.
+    fn read(&mut self, sig: &[u8], i: &mut usize) -> Result<Value> {
+        let code =
+            *sig.get(*i).ok_or_else(|| anyhow!("Truncated signature"))?;
+        self.align(alignment(code));
+        macro_rules! number {
+            ($t:ty, $variant:ident) => {{
+                let bytes = self.take()?;
+                Value::$variant(if self.big {
+                    <$t>::from_be_bytes(bytes)
+                } else {
+                    <$t>::from_le_bytes(bytes)
+                })
+            }};
+        }
+        let value = match code {
+            b'y' => Value::Byte(self.take::<1>()?[0]),
+            b'b' => Value::Bool(self.u32()? != 0),
+            b'n' => number!(i16, Int16),
+            b'q' => number!(u16, Uint16),
+            b'i' => number!(i32, Int32),
+            b'u' => number!(u32, Uint32),
+            b'x' => number!(i64, Int64),
+            b't' => number!(u64, Uint64),
+            b'd' => number!(f64, Double),
+            b'h' => Value::UnixFd(self.u32()?),
+            b's' => {
+                let len = self.u32()? as usize;
+                Value::Str(self.string(len)?)
+            }
+            b'o' => {
+                let len = self.u32()? as usize;
+                Value::ObjectPath(self.string(len)?)
+            }
+            b'g' => {
+                let len = self.take::<1>()?[0] as usize;
+                Value::Signature(self.string(len)?)
+            }
+            b'v' => {
+                let len = self.take::<1>()?[0] as usize;
+                let inner = self.string(len)?;
+                Value::Variant(Box::new(self.read_one(inner.as_str())?))
+            }
+            b'a' => {
+                let len = self.u32()? as usize;
+                let start = *i + 1;
+                let element = sig
+                    .get(start)
+                    .ok_or_else(|| anyhow!("Truncated signature"))?;
+                // the padding before the first element isn't counted
+                self.align(alignment(*element));
+                let end = self.pos + len;
+                let mut values = Vec::new();
+                let mut entries = Vec::new();
+                while self.pos < end {
+                    let mut j = start;
+                    if *element == b'{' {
+                        self.align(8);
+                        j += 1;
+                        let key = self.read(sig, &mut j)?;
+                        let value = self.read(sig, &mut j)?;
+                        entries.push((key, value));
+                    } else {
+                        values.push(self.read(sig, &mut j)?);
+                    }
+                }
+                *i = type_end(sig, start)?;
+                return Ok(if *element == b'{' {
+                    Value::Dict(entries)
+                } else {
+                    Value::Array(values)
+                });
+            }
+            b'(' => {
+                *i += 1;
+                let mut fields = Vec::new();
+                while sig.get(*i) != Some(&b')') {
+                    fields.push(self.read(sig, i)?);
+                }
+                *i += 1;
+                return Ok(Value::Struct(fields));
+            }
+            other => {
+                return Err(anyhow!("Unsupported type {}", other as char));
+            }
+        };
+        *i += 1;
+        Ok(value)
+    }
+}
+
+#[derive(Default)]
+struct Writer {
+    buf: Vec<u8>,
+}
+
+impl Writer {
+    fn align(&mut self, n: usize) {
+        self.buf.resize(self.buf.len().next_multiple_of(n), 0);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.align(4);
+        self.buf.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn string(&mut self, value: &str) {
+        self.u32(value.len() as u32);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn signature(&mut self, value: &str) {
+        self.buf.push(value.len() as u8);
+        self.buf.extend_from_slice(value.as_bytes());
+        self.buf.push(0);
+    }
+
+    fn write(&mut self, value: &Value) -> Result<()> {
+        let signature = value.signature()?;
+        self.align(alignment(signature.as_bytes()[0]));
+        match value {
+            Value::Byte(v) => self.buf.push(*v),
+            Value::Bool(v) => self.u32(u32::from(*v)),
+            Value::Int16(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            Value::Uint16(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            Value::Int32(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            Value::Uint32(v) | Value::UnixFd(v) => self.u32(*v),
+            Value::Int64(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            Value::Uint64(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            Value::Double(v) => self.buf.extend_from_slice(&v.to_le_bytes()),
+            Value::Str(v) | Value::ObjectPath(v) => self.string(v),
+            Value::Signature(v) => self.signature(v),
+            Value::Variant(v) => {
+                self.signature(v.signature()?.as_str());
+                self.write(v)?;
+            }
+            Value::Array(_) | Value::Dict(_) => {
+                // the length, filled in below
+                self.u32(0);
+                let len_pos = self.buf.len() - 4;
+                // the padding before the first element isn't counted
+                self.align(alignment(signature.as_bytes()[1]));
+                let start = self.buf.len();
+                if let Value::Array(values) = value {
+                    for value in values {
+                        self.write(value)?;
+                    }
+                } else if let Value::Dict(entries) = value {
+                    for (key, value) in entries {
+                        self.align(8);
+                        self.write(key)?;
+                        self.write(value)?;
+                    }
+                }
+                let len = (self.buf.len() - start) as u32;
+                self.buf[len_pos..len_pos + 4]
+                    .copy_from_slice(&len.to_le_bytes());
+            }
+            Value::Struct(fields) => {
+                for field in fields {
+                    self.write(field)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a header field, which is a `(yv)` struct.
+    fn field(&mut self, code: u8, value: &Value) -> Result<()> {
+        self.align(8);
+        self.buf.push(code);
+        self.write(&Value::Variant(Box::new(value.clone())))
+    }
+}
+
+/// Encodes a message of the given type with the given header fields. The
+/// signature field is added automatically.
+fn encode(
+    kind: u8,
+    serial: u32,
+    fields: &[(u8, Value)],
+    args: &[Value],
+) -> Result<Vec<u8>> {
+    let mut body = Writer::default();
+    let mut signature = String::new();
+    for arg in args {
+        signature.push_str(arg.signature()?.as_str());
+        body.write(arg)?;
+    }
+
+    let mut header = Writer::default();
+    header.buf.extend_from_slice(&[b'l', kind, 0, 1]);
+    header.u32(body.buf.len() as u32);
+    header.u32(serial);
+    // the length of the fields array, filled in below
+    header.u32(0);
+    for (code, value) in fields {
+        header.field(*code, value)?;
+    }
+    if !signature.is_empty() {
+        header.field(FIELD_SIGNATURE, &Value::Signature(signature))?;
+    }
+    let fields_len = (header.buf.len() - 16) as u32;
+    header.buf[12..16].copy_from_slice(&fields_len.to_le_bytes());
+    header.align(8);
+
+    header.buf.extend_from_slice(&body.buf);
+    Ok(header.buf)
+}
+
+/// Calls `GetNameOwner`, returning the unique name that owns `name`.
+pub fn name_owner(conn: &mut Connection, name: &str) -> Result<String> {
+    conn.call(
+        BUS.0,
+        BUS.1,
+        BUS.2,
+        "GetNameOwner",
+        &[Value::Str(name.into())],
+    )?
+    .first()
+    .and_then(Value::as_str)
+    .map(ToString::to_string)
+    .context("Invalid reply to GetNameOwner")
+}
+
+/// A fake bus for testing code that talks to other clients.
+#[cfg(test)]
+pub(crate) mod mock {
+    use std::{
+        collections::VecDeque,
+        io::{Read, Write},
+        os::unix::net::UnixStream,
+        thread,
+    };
+
+    use anyhow::Result;
+
+    use super::{
+        Connection, ERROR, FIELD_ERROR_NAME, FIELD_REPLY_SERIAL, METHOD_RETURN,
+        Message, Value, encode,
+    };
+
+    fn read_until(stream: &mut UnixStream, end: &[u8]) -> Result<()> {
+        let mut line = Vec::new();
+        let mut byte = [0];
+        while !line.ends_with(end) {
+            stream.read_exact(&mut byte)?;
+            line.push(byte[0]);
+        }
+        Ok(())
+    }
+
+    /// Connects to a bus that answers each method call (other than `Hello`)
+    /// with the result of `handler`.
+    pub fn connect(
+        mut handler: impl FnMut(&Message) -> Result<Vec<Value>> + Send + 'static,
+    ) -> Result<Connection> {
+        let (client, mut server) = UnixStream::pair()?;
+        thread::spawn(move || -> Result<()> {
+            read_until(&mut server, b"\r\n")?;
+            server.write_all(b"OK 0123456789abcdef0123456789abcdef\r\n")?;
+            read_until(&mut server, b"BEGIN\r\n")?;
+
+            let mut bus = Connection {
+                stream: server,
+                serial: 0,
+                signals: VecDeque::new(),
+                buf: Vec::new(),
+            };
+            // ends when the client hangs up
+            loop {
+                let call = bus.read()?;
+                let reply = if call.member.as_deref() == Some("Hello") {
+                    Ok(vec![Value::Str(String::from(":1.0"))])
+                } else {
+                    handler(&call)
+                };
+                bus.serial += 1;
+                let reply_serial =
+                    (FIELD_REPLY_SERIAL, Value::Uint32(call.serial));
+                let message = match reply {
+                    Ok(body) => encode(
+                        METHOD_RETURN,
+                        bus.serial,
+                        &[reply_serial],
+                        &body,
+                    )?,
+                    Err(e) => encode(
+                        ERROR,
+                        bus.serial,
+                        &[
+                            (
+                                FIELD_ERROR_NAME,
+                                Value::Str(String::from(
+                                    "org.freedesktop.DBus.Error.Failed",
+                                )),
+                            ),
+                            reply_serial,
+                        ],
+                        &[Value::Str(e.to_string())],
+                    )?,
+                };
+                bus.stream.write_all(&message)?;
+            }
+        });
+        Connection::open(client)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+
+    fn round_trip(args: &[Value]) -> Message {
+        let data = encode(
+            SIGNAL,
+            7,
+            &[
+                (FIELD_PATH, Value::ObjectPath(String::from("/a/b"))),
+                (FIELD_INTERFACE, Value::Str(String::from("a.b.C"))),
+                (FIELD_MEMBER, Value::Str(String::from("D"))),
+            ],
+            args,
+        )
+        .unwrap();
+        let message = decode(&data).unwrap();
+        assert_eq!(message.body, args);
+        message
+    }
+
+    #[test]
+    fn header() {
+        let message = round_trip(&[]);
+        assert!(message.is_signal("a.b.C", "D"));
+        assert_eq!(message.serial, 7);
+        assert_eq!(message.path.as_deref(), Some("/a/b"));
+    }
+
+    #[test]
+    fn basic_types() {
+        round_trip(&[
+            Value::Byte(1),
+            Value::Bool(true),
+            Value::Int16(-2),
+            Value::Uint16(3),
+            Value::Int32(-4),
+            Value::Uint32(5),
+            Value::Int64(-6),
+            Value::Uint64(7),
+            Value::Double(8.5),
+            Value::Str(String::from("nine")),
+            Value::ObjectPath(String::from("/ten")),
+            Value::Signature(String::from("a{sv}")),
+            Value::Variant(Box::new(Value::Int64(11))),
+        ]);
+    }
+
+    #[test]
+    fn containers() {
+        let metadata = Value::Dict(vec![
+            (
+                Value::Str(String::from("xesam:title")),
+                Value::Variant(Box::new(Value::Str(String::from("Title")))),
+            ),
+            (
+                Value::Str(String::from("xesam:artist")),
+                Value::Variant(Box::new(Value::Array(vec![
+                    Value::Str(String::from("A")),
+                    Value::Str(String::from("B")),
+                ]))),
+            ),
+        ]);
+        let message = round_trip(&[
+            // forces padding before the 8-byte aligned elements
+            Value::Byte(0),
+            Value::Array(vec![Value::Uint64(1), Value::Uint64(2)]),
+            Value::Byte(0),
+            metadata.clone(),
+            Value::Array(vec![Value::Struct(vec![
+                Value::Byte(1),
+                Value::Variant(Box::new(Value::Bool(false))),
+            ])]),
+        ]);
+        assert_eq!(
+            message.body[3].get("xesam:title").and_then(Value::as_str),
+            Some("Title")
+        );
+        assert_eq!(
+            message.body[3]
+                .get("xesam:artist")
+                .and_then(Value::as_slice)
+                .map(<[Value]>::len),
+            Some(2)
+        );
+    }
+
+    #[test]
+    fn unsendable() {
+        assert!(Value::Array(Vec::new()).signature().is_err());
+        assert!(
+            Value::Array(vec![Value::Byte(0), Value::Bool(false)])
+                .signature()
+                .is_err()
+        );
+        assert!(Value::UnixFd(0).signature().is_err());
+    }
+
+    #[test]
+    fn big_endian_message() {
+        let data = [
+            b'B',
+            METHOD_RETURN,
+            0,
+            1,
+            0,
+            0,
+            0,
+            4,
+            0,
+            0,
+            0,
+            1,
+            0,
+            0,
+            0,
+            7,
+            // the signature field
+            FIELD_SIGNATURE,
+            1,
+            b'g',
+            0,
+            1,
+            b'u',
+            0,
+            0,
+            // the body
+            0,
+            0,
+            1,
+            0,
+        ];
+        assert_eq!(decode(&data).unwrap().body, [Value::Uint32(256)]);
+    }
+
+    #[test]
+    fn truncated() {
+        let data =
+            encode(SIGNAL, 1, &[], &[Value::Str(String::from("truncated"))])
+                .unwrap();
+        assert!(decode(&data[..data.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn calls() {
+        let mut conn = mock::connect(|call| match call.member.as_deref() {
+            Some("Echo") => Ok(call.body.clone()),
+            _ => Err(anyhow!("no such method")),
+        })
+        .unwrap();
+        let args = [Value::Str(String::from("hi")), Value::Int32(1)];
+        assert_eq!(conn.call("a.b", "/", "a.b", "Echo", &args).unwrap(), args);
+        let e = conn.call("a.b", "/", "a.b", "Missing", &[]).unwrap_err();
+        assert!(e.to_string().contains("no such method"));
+    }
+
+    #[test]
+    fn timeout() {
+        let mut conn = mock::connect(|call| match call.member.as_deref() {
+            Some("Slow") => {
+                std::thread::sleep(Duration::from_millis(200));
+                Ok(Vec::new())
+            }
+            _ => Ok(call.body.clone()),
+        })
+        .unwrap();
+        conn.set_timeout(Some(Duration::from_millis(50))).unwrap();
+        assert!(conn.call("a.b", "/", "a.b", "Slow", &[]).is_err());
+        // the late reply to `Slow` is skipped
+        conn.set_timeout(None).unwrap();
+        let args = [Value::Str(String::from("hi"))];
+        assert_eq!(conn.call("a.b", "/", "a.b", "Echo", &args).unwrap(), args);
+    }
+}
@@ -0,0 +1,276 @@
+use std::f64::consts::PI;
+
+use anyhow::Result;
+use csscolorparser::Color;
+
+use crate::{
+    parser, remove_color_from_config, remove_float_from_config,
+    remove_string_from_config,
+};
+
+/// The shape of a [`Gauge`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum GaugeKind {
+    /// A bar that fills from left to right.
+    #[default]
+    Horizontal,
+    /// A level meter that fills from bottom to top.
+    Vertical,
+    /// A ring that fills clockwise from the top.
+    Circular,
+}
+
+/// A small meter that is drawn inline with a panel's text.
+///
+/// Gauges are inserted into format strings with the `gauge` filter (see
+/// [`Template`][crate::Template]), e.g. `{percentage|gauge(battery)}`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gauge {
+    kind: GaugeKind,
+    width: f64,
+    height: f64,
+    min: f64,
+    max: f64,
+    fill: Color,
+    empty: Color,
+    radius: f64,
+    thickness: f64,
+}
+
+impl Default for Gauge {
+    fn default() -> Self {
+        Self::new(GaugeKind::default())
+    }
+}
+
+impl Gauge {
+    /// Creates a gauge of the given kind with the default size and colors.
+    #[must_use]
+    pub fn new(kind: GaugeKind) -> Self {
+        let (width, height) = match kind {
+            GaugeKind::Horizontal => (40.0, 8.0),
+            GaugeKind::Vertical => (8.0, 16.0),
+            GaugeKind::Circular => (14.0, 14.0),
+        };
+        Self {
+            kind,
+            width,
+            height,
+            min: 0.0,
+            max: 100.0,
+            fill: Color::new(1.0, 1.0, 1.0, 1.0),
+            empty: Color::new(1.0, 1.0, 1.0, 0.25),
+            radius: 0.0,
+            thickness: 3.0,
+        }
+    }
+
+    /// Parses a new instance with a given name from the global
+    /// [`Config`][config::Config].
+    ///
+    /// Gauges should be defined in a table called `[gauges]`. If there is no
+    /// gauge called `default`, the name `default` refers to a horizontal bar
+    /// with all other options set to their defaults.
+    ///
+    /// Configuration options:
+    /// - `type`: `horizontal`, `vertical`, or `circular`
+    ///   - type: String
+    ///   - default: `horizontal`
+    /// - `width`: the width of the gauge in pixels
+    ///   - type: f64
+    ///   - default: 40 for horizontal, 8 for vertical, 14 for circular
+    /// - `height`: the height of the gauge in pixels
+    ///   - type: f64
+    ///   - default: 8 for horizontal, 16 for vertical, 14 for circular
+    /// - `min`: the value at which the gauge is empty
+    ///   - type: f64
+    ///   - default: 0
+    /// - `max`: the value at which the gauge is full
+    ///   - type: f64
+    ///   - default: 100
+    /// - `fill`: the color of the filled part of the gauge. See
+    ///   [csscolorparser] for parsing options.
+    ///   - type: String
+    ///   - default: `#fff`
+    /// - `empty`: the color of the empty part of the gauge.
+    ///   - type: String
+    ///   - default: `#ffffff40`
+    /// - `radius`: the corner radius of bars and meters. Circular gauges use
+    ///   round ends if this is nonzero.
+    ///   - type: f64
+    ///   - default: 0
+    /// - `thickness`: the width of the ring of a circular gauge
+    ///   - type: f64
+    ///   - default: 3
+    #[must_use]
+    pub fn parse(name: impl AsRef<str>) -> Option<Self> {
+        let name = name.as_ref();
+        let gauges_table = parser::GAUGES.get().unwrap();
+        let Some(gauge) = gauges_table.get(name) else {
+            return (name == "default").then(Self::default);
+        };
+        let mut gauge_table = gauge.clone().into_table().ok()?;
+
+        let kind = match remove_string_from_config("type", &mut gauge_table)
+            .as_deref()
+        {
+            None | Some("horizontal") => GaugeKind::Horizontal,
+            Some("vertical") => GaugeKind::Vertical,
+            Some("circular") => GaugeKind::Circular,
+            Some(other) => {
+                log::warn!("Unknown gauge type {other}, using horizontal");
+                GaugeKind::Horizontal
+            }
+        };
+        let mut gauge = Self::new(kind);
+
+        if let Some(width) = remove_float_from_config("width", &mut gauge_table)
+        {
+            gauge.width = width.max(0.0);
+        }
+        if let Some(height) =
+            remove_float_from_config("height", &mut gauge_table)
+        {
+            gauge.height = height.max(0.0);
+        }
+        if let Some(min) = remove_float_from_config("min", &mut gauge_table) {
+            gauge.min = min;
+        }
+        if let Some(max) = remove_float_from_config("max", &mut gauge_table) {
+            gauge.max = max;
+        }
+        if let Some(fill) = remove_color_from_config("fill", &mut gauge_table) {
+            gauge.fill = fill;
+        }
+        if let Some(empty) = remove_color_from_config("empty", &mut gauge_table)
+        {
+            gauge.empty = empty;
+        }
+        if let Some(radius) =
+            remove_float_from_config("radius", &mut gauge_table)
+        {
+            gauge.radius = radius.max(0.0);
+        }
+        if let Some(thickness) =
+            remove_float_from_config("thickness", &mut gauge_table)
+        {
+            gauge.thickness = thickness.max(0.0);
+        }
+
+        Some(gauge)
+    }
+
+    pub(crate) const fn size(&self) -> (f64, f64) {
+        (self.width, self.height)
+    }
+
+    fn proportion(&self, value: f64) -> f64 {
+        if self.max <= self.min || value.is_nan() {
+            return 0.0;
+        }
+        ((value - self.min) / (self.max - self.min)).clamp(0.0, 1.0)
+    }
+
+    /// Draws the gauge with its top left corner at (`x`, `y`).
+    pub fn draw(
+        &self,
+        cr: &cairo::Context,
+        x: f64,
+        y: f64,
+        value: f64,
+    ) -> Result<()> {
+        let prop = self.proportion(value);
+        cr.save()?;
+
+        match self.kind {
+            GaugeKind::Horizontal | GaugeKind::Vertical => {
+                let radius = self.radius.min(self.width.min(self.height) / 2.0);
+                rounded_rect(cr, x, y, self.width, self.height, radius);
+                cr.clip();
+
+                set_source(cr, &self.empty);
+                cr.paint()?;
+
+                if self.kind == GaugeKind::Horizontal {
+                    cr.rectangle(x, y, self.width * prop, self.height);
+                } else {
+                    let filled = self.height * prop;
+                    cr.rectangle(
+                        x,
+                        y + self.height - filled,
+                        self.width,
+                        filled,
+                    );
+                }
+                set_source(cr, &self.fill);
+                cr.fill()?;
+            }
+            GaugeKind::Circular => {
+                let (cx, cy) = (x + self.width / 2.0, y + self.height / 2.0);
+                let radius = ((self.width.min(self.height) - self.thickness)
+                    / 2.0)
+                    .max(0.0);
+                cr.set_line_width(self.thickness);
+                if self.radius > 0.0 {
+                    cr.set_line_cap(cairo::LineCap::Round);
+                }
+
+                cr.new_path();
+                cr.arc(cx, cy, radius, 0.0, 2.0 * PI);
+                set_source(cr, &self.empty);
+                cr.stroke()?;
+
+                if prop > 0.0 {
+                    cr.new_path();
+                    cr.arc(
+                        cx,
+                        cy,
+                        radius,
+                        -PI / 2.0,
+                        2.0 * PI * prop - PI / 2.0,
+                    );
+                    set_source(cr, &self.fill);
+                    cr.stroke()?;
+                }
+            }
+        }
+
+        cr.restore()?;
+        Ok(())
+    }
+}
+
+pub(crate) fn set_source(cr: &cairo::Context, color: &Color) {
+    cr.set_source_rgba(
+        color.r.into(),
+        color.g.into(),
+        color.b.into(),
+        color.a.into(),
+    );
+}
+
+fn rounded_rect(
+    cr: &cairo::Context,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    radius: f64,
+) {
+    cr.new_path();
+    if radius <= 0.0 {
+        cr.rectangle(x, y, width, height);
+        return;
+    }
+    cr.arc(x + width - radius, y + radius, radius, -PI / 2.0, 0.0);
+    cr.arc(
+        x + width - radius,
+        y + height - radius,
+        radius,
+        0.0,
+        PI / 2.0,
+    );
+    cr.arc(x + radius, y + height - radius, radius, PI / 2.0, PI);
+    cr.arc(x + radius, y + radius, radius, PI, 3.0 * PI / 2.0);
+    cr.close_path();
+}
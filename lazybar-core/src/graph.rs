@@ -0,0 +1,324 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::BuildHasher,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use cairo::LinearGradient;
+use config::Value;
+use csscolorparser::Color;
+
+use crate::{
+    Ramp, gauge::set_source, inline::Object, parser, remove_color_from_config,
+    remove_float_from_config, remove_string_from_config,
+    remove_uint_from_config, template::Var,
+};
+
+/// A sparkline or area graph of a panel's recent values, drawn inline with
+/// its text.
+///
+/// Panels that keep a [`History`] provide it to their format strings as
+/// `%graph%`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Graph {
+    width: f64,
+    height: f64,
+    min: f64,
+    max: Option<f64>,
+    color: Color,
+    line_width: f64,
+    fill: Option<Color>,
+    ramp: Option<Ramp>,
+}
+
+impl Default for Graph {
+    fn default() -> Self {
+        Self {
+            width: 60.0,
+            height: 14.0,
+            min: 0.0,
+            max: None,
+            color: Color::new(1.0, 1.0, 1.0, 1.0),
+            line_width: 1.0,
+            fill: None,
+            ramp: None,
+        }
+    }
+}
+
+impl Graph {
+    /// Parses a new instance with a given name from the global
+    /// [`Config`][config::Config].
+    ///
+    /// Graphs should be defined in a table called `[graphs]`. If there is no
+    /// graph called `default`, the name `default` refers to a graph with all
+    /// options set to their defaults.
+    ///
+    /// Configuration options:
+    /// - `width`: the width of the graph in pixels
+    ///   - type: f64
+    ///   - default: 60
+    /// - `height`: the height of the graph in pixels
+    ///   - type: f64
+    ///   - default: 14
+    /// - `min`: the value at the bottom of the graph
+    ///   - type: f64
+    ///   - default: 0
+    /// - `max`: the value at the top of the graph. If unset, the graph scales
+    ///   to fit the largest value in the history.
+    ///   - type: f64
+    ///   - default: none
+    /// - `color`: the color of the line. See [csscolorparser] for parsing
+    ///   options.
+    ///   - type: String
+    ///   - default: `#fff`
+    /// - `line_width`: the width of the line in pixels. Set this to 0 to draw
+    ///   only the area under the line.
+    ///   - type: f64
+    ///   - default: 1
+    /// - `fill`: the color of the area under the line.
+    ///   - type: String
+    ///   - default: none
+    /// - `ramp`: A string specifying a ramp whose colors fill the area under
+    ///   the line, following each value. This takes precedence over `fill`. See
+    ///   [`Ramp::parse`] for details.
+    ///   - type: String
+    ///   - default: none
+    #[must_use]
+    pub fn parse(name: impl AsRef<str>) -> Option<Self> {
+        let name = name.as_ref();
+        let graphs_table = parser::GRAPHS.get().unwrap();
+        let Some(graph) = graphs_table.get(name) else {
+            return (name == "default").then(Self::default);
+        };
+        let mut graph_table = graph.clone().into_table().ok()?;
+        let mut graph = Self::default();
+
+        if let Some(width) = remove_float_from_config("width", &mut graph_table)
+        {
+            graph.width = width.max(0.0);
+        }
+        if let Some(height) =
+            remove_float_from_config("height", &mut graph_table)
+        {
+            graph.height = height.max(0.0);
+        }
+        if let Some(min) = remove_float_from_config("min", &mut graph_table) {
+            graph.min = min;
+        }
+        graph.max = remove_float_from_config("max", &mut graph_table);
+        if let Some(color) = remove_color_from_config("color", &mut graph_table)
+        {
+            graph.color = color;
+        }
+        if let Some(line_width) =
+            remove_float_from_config("line_width", &mut graph_table)
+        {
+            graph.line_width = line_width.max(0.0);
+        }
+        graph.fill = remove_color_from_config("fill", &mut graph_table);
+        graph.ramp = remove_string_from_config("ramp", &mut graph_table)
+            .and_then(Ramp::parse);
+
+        Some(graph)
+    }
+
+    pub(crate) const fn size(&self) -> (f64, f64) {
+        (self.width, self.height)
+    }
+
+    /// Draws the graph with its top left corner at (`x`, `y`).
+    ///
+    /// The newest sample is drawn at the right edge, and `length` samples
+    /// span the full width. NaN samples are treated as missing.
+    pub fn draw(
+        &self,
+        cr: &cairo::Context,
+        x: f64,
+        y: f64,
+        length: usize,
+        samples: &[f64],
+    ) -> Result<()> {
+        if samples.is_empty() {
+            return Ok(());
+        }
+
+        let min = self.min;
+        let max = self.max.unwrap_or_else(|| {
+            samples
+                .iter()
+                .copied()
+                .filter(|s| !s.is_nan())
+                .fold(min, f64::max)
+        });
+        let range = if max > min { max - min } else { 1.0 };
+        let step = self.width / (length.max(samples.len()).max(2) - 1) as f64;
+        let points = samples
+            .iter()
+            .rev()
+            .enumerate()
+            .map(|(i, sample)| {
+                let prop = if sample.is_nan() {
+                    0.0
+                } else {
+                    ((sample - min) / range).clamp(0.0, 1.0)
+                };
+                (
+                    (i as f64).mul_add(-step, x + self.width),
+                    (1.0 - prop).mul_add(self.height, y),
+                )
+            })
+            .collect::<Vec<_>>();
+
+        cr.save()?;
+        cr.rectangle(x, y, self.width, self.height);
+        cr.clip();
+
+        if self.ramp.is_some() || self.fill.is_some() {
+            cr.new_path();
+            cr.move_to(points[0].0, y + self.height);
+            for (px, py) in &points {
+                cr.line_to(*px, *py);
+            }
+            cr.line_to(points[points.len() - 1].0, y + self.height);
+            cr.close_path();
+
+            if let Some(ramp) = &self.ramp {
+                let gradient = LinearGradient::new(x, 0.0, x + self.width, 0.0);
+                for ((px, _), sample) in points.iter().zip(samples.iter().rev())
+                {
+                    if let Some(color) = ramp.color(*sample, min, min + range) {
+                        gradient.add_color_stop_rgba(
+                            (px - x) / self.width,
+                            color.r.into(),
+                            color.g.into(),
+                            color.b.into(),
+                            color.a.into(),
+                        );
+                    }
+                }
+                cr.set_source(&gradient)?;
+            } else if let Some(fill) = &self.fill {
+                set_source(cr, fill);
+            }
+            cr.fill()?;
+        }
+
+        if self.line_width > 0.0 {
+            cr.new_path();
+            // missing samples leave a gap in the line
+            for ((px, py), sample) in points.iter().zip(samples.iter().rev()) {
+                if sample.is_nan() {
+                    cr.new_sub_path();
+                } else {
+                    cr.line_to(*px, *py);
+                }
+            }
+            cr.set_line_width(self.line_width);
+            cr.set_line_join(cairo::LineJoin::Round);
+            set_source(cr, &self.color);
+            cr.stroke()?;
+        }
+
+        cr.restore()?;
+        Ok(())
+    }
+}
+
+/// A fixed-length buffer of a panel's recent values, which can be drawn as a
+/// [`Graph`].
+#[derive(Clone, Debug)]
+pub struct History {
+    samples: VecDeque<f64>,
+    length: usize,
+    interval: Duration,
+    last: Option<Instant>,
+    graph: String,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self {
+            samples: VecDeque::new(),
+            length: 30,
+            interval: Duration::ZERO,
+            last: None,
+            graph: String::from("default"),
+        }
+    }
+}
+
+impl History {
+    /// Parses an instance of this type from a subset of the global
+    /// [`Config`][config::Config].
+    ///
+    /// Configuration options:
+    /// - `graph`: A string specifying the graph to use for `%graph%`. See
+    ///   [`Graph::parse`] for details.
+    ///   - type: String
+    ///   - default: `default`
+    /// - `history_length`: The number of samples to keep.
+    ///   - type: u64
+    ///   - default: 30
+    /// - `history_interval`: The minimum amount of time in seconds between
+    ///   samples. Updates that arrive sooner are not recorded. By default,
+    ///   every update is recorded.
+    ///   - type: u64
+    ///   - default: 0
+    pub fn parse<S: BuildHasher>(
+        table: &mut HashMap<String, Value, S>,
+    ) -> Result<Self> {
+        let mut history = Self::default();
+        if let Some(graph) = remove_string_from_config("graph", table) {
+            if Object::graph(graph.as_str()).is_none() {
+                return Err(anyhow!("No graph found with name {graph}"));
+            }
+            history.graph = graph;
+        }
+        if let Some(length) = remove_uint_from_config("history_length", table) {
+            history.length = (length as usize).max(1);
+        }
+        if let Some(interval) =
+            remove_uint_from_config("history_interval", table)
+        {
+            history.interval = Duration::from_secs(interval);
+        }
+        log::debug!("got history: {history:?}");
+        Ok(history)
+    }
+
+    /// Records a new value, discarding the oldest value if the buffer is full.
+    ///
+    /// The value is ignored if it arrives less than `history_interval` after
+    /// the last recorded value.
+    pub fn push(&mut self, value: f64) {
+        let now = Instant::now();
+        if self
+            .last
+            .is_some_and(|last| now.duration_since(last) < self.interval)
+        {
+            return;
+        }
+        self.last = Some(now);
+        if self.samples.len() == self.length {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(value);
+    }
+
+    /// Returns the recorded values, oldest first.
+    pub fn samples(&self) -> impl Iterator<Item = f64> + '_ {
+        self.samples.iter().copied()
+    }
+
+    /// Returns the value to substitute for `%graph%`.
+    #[must_use]
+    pub fn var(&self) -> Var {
+        Var::Object(Object::graph_marker(
+            self.graph.as_str(),
+            self.length,
+            self.samples(),
+        ))
+    }
+}
@@ -1,7 +1,7 @@
-use std::{fs::File, path::PathBuf};
+use std::{fs::File, io::Cursor, path::PathBuf};
 
 use anyhow::{Context, Result};
-use cairo::ImageSurface;
+use cairo::{Format, ImageSurface};
 use derive_builder::Builder;
 
 use crate::{
@@ -19,6 +19,8 @@ pub struct Image {
     x: f64,
     #[builder(default)]
     y: f64,
+    #[builder(default = "1.0")]
+    scale: f64,
 }
 
 impl Image {
@@ -29,9 +31,81 @@ impl Image {
             surface: ImageSurface::create_from_png(&mut file)?,
             x,
             y,
+            scale: 1.0,
         })
     }
 
+    /// Creates a new instance from PNG data in memory, scaled to `height`
+    /// pixels tall.
+    pub fn from_png_data(
+        data: &[u8],
+        x: f64,
+        y: f64,
+        height: f64,
+    ) -> Result<Self> {
+        let surface = ImageSurface::create_from_png(&mut Cursor::new(data))?;
+        let scale = height / f64::from(surface.height().max(1));
+        Ok(Self {
+            surface,
+            x,
+            y,
+            scale,
+        })
+    }
+
+    /// Creates a new instance from `width` by `height` ARGB pixels without
+    /// premultiplied alpha (the format of `_NET_WM_ICON`), scaled to
+    /// `scaled_height` pixels tall.
+    pub fn from_argb_data(
+        pixels: &[u32],
+        width: i32,
+        height: i32,
+        x: f64,
+        y: f64,
+        scaled_height: f64,
+    ) -> Result<Self> {
+        let mut surface = ImageSurface::create(Format::ARgb32, width, height)?;
+        let stride = surface.stride() as usize;
+        {
+            let mut data = surface.data()?;
+            for (i, &pixel) in pixels
+                .iter()
+                .take(width as usize * height as usize)
+                .enumerate()
+            {
+                let alpha = pixel >> 24;
+                let channel = |shift: u32| {
+                    ((pixel >> shift & 0xff) * alpha / 255) << shift
+                };
+                let value =
+                    (alpha << 24) | channel(16) | channel(8) | channel(0);
+                let offset =
+                    (i / width as usize) * stride + (i % width as usize) * 4;
+                data[offset..offset + 4].copy_from_slice(&value.to_ne_bytes());
+            }
+        }
+        surface.mark_dirty();
+
+        Ok(Self {
+            surface,
+            x,
+            y,
+            scale: scaled_height / f64::from(height.max(1)),
+        })
+    }
+
+    /// The width of the image on the bar, in pixels.
+    #[must_use]
+    pub fn width(&self) -> f64 {
+        f64::from(self.surface.width()) * self.scale
+    }
+
+    /// The height of the image on the bar, in pixels.
+    #[must_use]
+    pub fn height(&self) -> f64 {
+        f64::from(self.surface.height()) * self.scale
+    }
+
     /// Attempts to parse a new instance from the global config
     ///
     /// Configuration options:
@@ -68,10 +142,12 @@ impl Image {
     pub fn draw(&self, cr: &cairo::Context) -> Result<()> {
         cr.save()?;
 
+        cr.translate(self.x, self.y);
+        cr.scale(self.scale, self.scale);
         cr.set_source_surface(self.surface.as_ref(), 0.0, 0.0)?;
         cr.rectangle(
-            self.x,
-            self.y,
+            0.0,
+            0.0,
             self.surface.width() as f64,
             self.surface.height() as f64,
         );
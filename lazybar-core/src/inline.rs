@@ -0,0 +1,251 @@
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::{LazyLock, Mutex},
+};
+
+use anyhow::{Context, Result, anyhow};
+use pango::{AttrShape, Attribute, Layout, Rectangle, SCALE};
+
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+use crate::image::Image;
+use crate::{Gauge, Graph};
+
+/// The character that stands in for an object in a panel's text.
+pub(crate) const OBJECT: char = '\u{fffc}';
+/// Marks the start of an encoded object in rendered template output.
+const MARKER_START: char = '\u{f8f0}';
+/// Marks the end of an encoded object in rendered template output.
+const MARKER_END: char = '\u{f8f1}';
+/// Separates the fields of an encoded object.
+const SEPARATOR: char = '\u{1f}';
+
+/// Gauges referenced by panels, parsed once when the panels are parsed.
+static GAUGES: LazyLock<Mutex<HashMap<String, Gauge>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+/// Graphs referenced by panels, parsed once when the panels are parsed.
+static GRAPHS: LazyLock<Mutex<HashMap<String, Graph>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Removes the characters used to encode objects from `text`, so that text
+/// from outside the bar (e.g. a window title) can't be mistaken for an
+/// object.
+pub(crate) fn sanitize(text: &str) -> Cow<'_, str> {
+    let reserved = |c| matches!(c, OBJECT | MARKER_START | MARKER_END);
+    if text.contains(reserved) {
+        Cow::Owned(text.replace(reserved, ""))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Something other than text that can be drawn inline with a panel's text.
+#[derive(Clone, Debug)]
+pub(crate) enum Object {
+    Gauge(Gauge, f64),
+    Graph(Graph, usize, Vec<f64>),
+    /// Only created directly by panels, since it can't be encoded.
+    #[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+    Image(Image),
+}
+
+impl Object {
+    /// Parses the gauge called `name` the first time it's referenced, and
+    /// returns the cached copy afterwards.
+    pub(crate) fn gauge(name: &str) -> Option<Gauge> {
+        let mut gauges = GAUGES.lock().unwrap();
+        if let Some(gauge) = gauges.get(name) {
+            return Some(gauge.clone());
+        }
+        let gauge = Gauge::parse(name)?;
+        gauges.insert(name.to_string(), gauge.clone());
+        Some(gauge)
+    }
+
+    /// Parses the graph called `name` the first time it's referenced, and
+    /// returns the cached copy afterwards.
+    pub(crate) fn graph(name: &str) -> Option<Graph> {
+        let mut graphs = GRAPHS.lock().unwrap();
+        if let Some(graph) = graphs.get(name) {
+            return Some(graph.clone());
+        }
+        let graph = Graph::parse(name)?;
+        graphs.insert(name.to_string(), graph.clone());
+        Some(graph)
+    }
+
+    /// Encodes a gauge and its value so that it can be passed through a
+    /// panel's text and drawn by
+    /// [`PanelCommon::draw`][crate::common::PanelCommon::draw].
+    pub(crate) fn gauge_marker(name: &str, value: f64) -> String {
+        format!("{MARKER_START}gauge{SEPARATOR}{name}{SEPARATOR}{value}{MARKER_END}")
+    }
+
+    /// Encodes a graph and its samples so that it can be passed through a
+    /// panel's text and drawn by
+    /// [`PanelCommon::draw`][crate::common::PanelCommon::draw].
+    pub(crate) fn graph_marker(
+        name: &str,
+        length: usize,
+        samples: impl IntoIterator<Item = f64>,
+    ) -> String {
+        let samples = samples
+            .into_iter()
+            .map(|sample| sample.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            "{MARKER_START}graph{SEPARATOR}{name}{SEPARATOR}{length};\
+             {samples}{MARKER_END}"
+        )
+    }
+
+    fn decode(marker: &str) -> Result<Self> {
+        let mut fields = marker.splitn(3, SEPARATOR);
+        let (Some(kind), Some(name), Some(payload)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(anyhow!("Malformed marker"));
+        };
+        match kind {
+            "gauge" => Ok(Self::Gauge(
+                Self::gauge(name).with_context(|| {
+                    format!("No gauge found with name {name}")
+                })?,
+                payload.parse()?,
+            )),
+            "graph" => {
+                let (length, samples) = payload
+                    .split_once(';')
+                    .context("Malformed graph samples")?;
+                Ok(Self::Graph(
+                    Self::graph(name).with_context(|| {
+                        format!("No graph found with name {name}")
+                    })?,
+                    length.parse()?,
+                    samples
+                        .split(',')
+                        .filter(|sample| !sample.is_empty())
+                        .map(str::parse)
+                        .collect::<Result<_, _>>()?,
+                ))
+            }
+            kind => Err(anyhow!("Unknown inline object {kind}")),
+        }
+    }
+
+    fn size(&self) -> (f64, f64) {
+        match self {
+            Self::Gauge(gauge, _) => gauge.size(),
+            Self::Graph(graph, _, _) => graph.size(),
+            #[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+            Self::Image(image) => (image.width(), image.height()),
+        }
+    }
+}
+
+/// An inline object whose position in a [`Layout`] is known.
+#[derive(Clone, Debug)]
+pub(crate) struct PlacedObject {
+    index: i32,
+    y: i32,
+    object: Object,
+}
+
+impl PlacedObject {
+    /// Removes all object markers from `text`, leaving a placeholder
+    /// character in their place. Stray marker and placeholder characters are
+    /// dropped.
+    pub(crate) fn extract(text: &str) -> (String, Vec<Object>) {
+        if !text.contains(MARKER_START) {
+            return (sanitize(text).into_owned(), Vec::new());
+        }
+
+        let mut out = String::with_capacity(text.len());
+        let mut objects = Vec::new();
+        let mut rest = text;
+        while let Some(start) = rest.find(MARKER_START) {
+            out.push_str(&sanitize(&rest[..start]));
+            rest = &rest[start + MARKER_START.len_utf8()..];
+            let Some(end) = rest.find(MARKER_END) else {
+                break;
+            };
+            match Object::decode(&rest[..end]) {
+                Ok(object) => {
+                    out.push(OBJECT);
+                    objects.push(object);
+                }
+                Err(e) => log::warn!("Failed to draw inline object: {e}"),
+            }
+            rest = &rest[end + MARKER_END.len_utf8()..];
+        }
+        out.push_str(&sanitize(rest));
+
+        (out, objects)
+    }
+
+    /// Reserves space in `layout` for each object.
+    ///
+    /// `layout` should already contain the text returned by
+    /// [`PlacedObject::extract`]. Objects are centered vertically on the first
+    /// line of text.
+    pub(crate) fn attach(layout: &Layout, objects: Vec<Object>) -> Vec<Self> {
+        if objects.is_empty() {
+            return Vec::new();
+        }
+
+        let (_, logical) = layout.extents();
+        let center = logical.height() / 2 - layout.baseline();
+        let list = layout.attributes().unwrap_or_default();
+        let placed = layout
+            .text()
+            .match_indices(OBJECT)
+            .zip(objects)
+            .map(|((index, _), object)| {
+                let (width, height) = object.size();
+                let width = (width * f64::from(SCALE)) as i32;
+                let height = (height * f64::from(SCALE)) as i32;
+                let rect =
+                    Rectangle::new(0, center - height / 2, width, height);
+                let mut attr = Attribute::from(AttrShape::new(&rect, &rect));
+                attr.set_start_index(index as u32);
+                attr.set_end_index((index + OBJECT.len_utf8()) as u32);
+                list.insert(attr);
+                Self {
+                    index: index as i32,
+                    y: rect.y(),
+                    object,
+                }
+            })
+            .collect();
+        layout.set_attributes(Some(&list));
+
+        placed
+    }
+
+    /// Draws the object. `cr` should be positioned where `layout` was drawn.
+    pub(crate) fn draw(
+        &self,
+        cr: &cairo::Context,
+        layout: &Layout,
+    ) -> Result<()> {
+        let pos = layout.index_to_pos(self.index);
+        let x = f64::from(pos.x()) / f64::from(SCALE);
+        let y =
+            f64::from(pos.y() + layout.baseline() + self.y) / f64::from(SCALE);
+        match &self.object {
+            Object::Gauge(gauge, value) => gauge.draw(cr, x, y, *value),
+            Object::Graph(graph, length, samples) => {
+                graph.draw(cr, x, y, *length, samples)
+            }
+            #[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+            Object::Image(image) => {
+                cr.save()?;
+                cr.translate(x, y);
+                image.draw(cr)?;
+                cr.restore()?;
+                Ok(())
+            }
+        }
+    }
+}
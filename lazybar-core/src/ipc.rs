@@ -1,9 +1,18 @@
-use std::{fs::DirBuilder, path::PathBuf};
+use std::{
+    fs::{DirBuilder, read_dir},
+    path::PathBuf,
+    sync::LazyLock,
+};
 
 use anyhow::Result;
+use lazybar_types::PanelEvent;
 use tokio::{
-    net::UnixListener,
-    sync::mpsc::{UnboundedReceiver, UnboundedSender},
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{UnixListener, UnixStream},
+    sync::{
+        broadcast,
+        mpsc::{UnboundedReceiver, UnboundedSender},
+    },
 };
 use tokio_stream::wrappers::UnixListenerStream;
 
@@ -12,6 +21,52 @@ use crate::IpcStream;
 /// The directory in which IPC sockets are created
 pub const IPC_DIR: &str = "/tmp/lazybar-ipc/";
 
+static EVENTS: LazyLock<broadcast::Sender<PanelEvent>> =
+    LazyLock::new(|| broadcast::channel(64).0);
+
+/// Publishes an event to every client that has subscribed to this bar (e.g.
+/// with `lazybar-msg subscribe`).
+pub fn publish(panel: &str, event: &str) {
+    // this only fails if there are no subscribers
+    let _ = EVENTS.send(PanelEvent {
+        panel: panel.to_string(),
+        event: event.to_string(),
+    });
+}
+
+/// Returns a receiver for the events passed to [`publish`].
+#[must_use]
+pub fn subscribe() -> broadcast::Receiver<PanelEvent> {
+    EVENTS.subscribe()
+}
+
+/// Sends a message to every running bar, like `lazybar-msg all`. Responses
+/// are logged but otherwise ignored.
+pub async fn send_all(message: &str) {
+    let Ok(dir) = read_dir(IPC_DIR) else {
+        return;
+    };
+    for path in dir.filter_map(|entry| entry.ok().map(|entry| entry.path())) {
+        let res = async {
+            let mut stream = UnixStream::connect(path.as_path()).await?;
+            stream.write_all(message.as_bytes()).await?;
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await?;
+            anyhow::Ok(response)
+        }
+        .await;
+        match res {
+            Ok(response) => {
+                log::debug!("{}: {response}", path.display());
+            }
+            Err(e) => log::warn!(
+                "Failed to send `{message}` to {}: {e}",
+                path.display()
+            ),
+        }
+    }
+}
+
 /// Initialize IPC for a given bar
 pub fn init(enabled: bool, bar_name: &str) -> (Result<IpcStream>, String) {
     let mut final_name = bar_name.to_string();
@@ -21,6 +21,10 @@
 //!   line argument to run that bar.
 //! - `ramps`: each subtable defines a ramp with the same name, and those names
 //!   are referenced by panel tables (see below).
+//! - `gauges`: each subtable defines a gauge (an inline bar, level meter, or
+//!   ring) that can be drawn in a format string. See [`Gauge::parse`].
+//! - `graphs`: each subtable defines a graph of a panel's recent values that
+//!   can be referenced by panels. See [`Graph::parse`].
 //! - `panels`: each subtable defines a panel with the same name, and those
 //!   names are referenced by bar tables.
 //! - `attrs`: each subtable defines a set of attributes that can be referenced
@@ -33,7 +37,11 @@
 //!   by referencing its key.
 //! - `consts`: each value is a string that can be substituted into any other
 //!   string by using `%{key}`. This format can also be used to reference
-//!   environment variables using `%{env:KEY}`.
+//!   environment variables using `%{env:KEY}`, X resources using
+//!   `%{xrdb:name}`, the contents of a file using `%{file:/path}`, and the
+//!   output of a shell command using `%{cmd:command}`. `env`, `xrdb`, and
+//!   `file` references can provide a fallback value, e.g.
+//!   `%{env:KEY:-default}`. See [`replace_consts`] for details.
 //!
 //! Other than `images` and `consts`, none of these tables need to be declared
 //! explicitly, as they hold no values of their own. `[bars.example]` is
@@ -45,6 +53,10 @@
 //! figure out what you mean, but if you have issues, make sure that your types
 //! are correct.
 //!
+//! Most panels compile their format strings into a [`Template`], which adds
+//! padding, filters, and conditionals to the usual `%name%` substitution. See
+//! its documentation for the syntax.
+//!
 //! # Example Config
 //! ```toml
 #![doc = include_str!("../examples/config.toml")]
@@ -76,20 +88,31 @@ pub mod bar;
 pub mod cleanup;
 /// Common configuration for panels.
 pub mod common;
+#[cfg(feature = "mpris")]
+mod dbus;
+mod gauge;
+mod graph;
 mod highlight;
 /// Support for embedding images onto the bar
 pub mod image;
+mod inline;
 /// Support for inter-process communication, like that provided by the
 /// `lazybar-msg` crate.
 pub mod ipc;
 /// Macros used internally which may be of use to other developers.
 pub mod macros;
+#[cfg(any(feature = "battery", feature = "network", feature = "vpn"))]
+mod netlink;
+mod overflow;
 /// Panels that can be added to the bar. A new panel must implement
 /// [`PanelConfig`].
 pub mod panels;
 /// The parser for the `config.toml` file.
 pub mod parser;
 mod ramp;
+/// A small template language for panel format strings.
+pub mod template;
+mod thresholds;
 mod utils;
 mod x;
 
@@ -111,11 +134,16 @@ pub use builders::BarConfig;
 use config::{Config, Value};
 pub use csscolorparser::Color;
 use directories::ProjectDirs;
+pub use gauge::{Gauge, GaugeKind};
 pub use glib::markup_escape_text;
+pub use graph::{Graph, History};
 pub use highlight::Highlight;
 use ipc::ChannelEndpoint;
 use lazybar_types::EventResponse;
-pub use ramp::Ramp;
+pub use overflow::{Ellipsis, Overflow, Strategy, Width};
+pub use ramp::{ColorRamps, Ramp, Scale};
+pub use template::Template;
+pub use thresholds::{Severity, Threshold, Thresholds};
 use tokio_stream::Stream;
 pub use utils::*;
 use x::{create_surface, create_window, set_wm_properties};
@@ -141,6 +169,11 @@ pub type PanelShowFn = Box<dyn Fn() -> Result<()>>;
 /// Use this to pause polling, unmap a child window, or make any other state
 /// changes that can be cheaply reversed.
 pub type PanelHideFn = Box<dyn Fn() -> Result<()>>;
+/// A function that will be called before each redraw of an animated panel.
+///
+/// Use this to advance the animation. See
+/// [`PanelDrawInfo::with_animation`][bar::PanelDrawInfo::with_animation].
+pub type PanelAnimateFn = Box<dyn Fn() -> Result<()>>;
 /// A function that is called for each panel before the bar shuts down.
 pub type PanelShutdownFn = Box<dyn FnOnce()>;
 /// This function receives a [`MouseEvent`] and determines what the cursor name
@@ -606,6 +639,12 @@ pub mod builders {
                             }
                         }
                     },
+                    Some(((alignment, idx), _)) = bar.animations.next() => {
+                        if let Err(e) = bar.animate(alignment, idx) {
+                            log::warn!("Error animating {alignment} panel at index {idx}");
+                            handle_error(e, &bar, self.ipc).await;
+                        }
+                    },
                     Some(Ok(stream)) = ipc_stream.next(), if bar.ipc => {
                         log::debug!("Received new ipc connection");
 
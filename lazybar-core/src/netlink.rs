@@ -0,0 +1,387 @@
+//! A minimal netlink client, supporting just enough of rtnetlink, generic
+//! netlink, and kernel uevents for the panels that need them.
+
+//!
+//! Each part is only compiled for the features whose panels use it: uevents
+//! for `battery`, rtnetlink and generic netlink for `network` and `vpn`.
+
+use std::{
+    os::fd::{AsFd, BorrowedFd, OwnedFd},
+    pin::Pin,
+    task::{Context as TaskContext, Poll},
+};
+
+use anyhow::Result;
+#[cfg(any(feature = "network", feature = "vpn"))]
+use anyhow::{Context, anyhow};
+#[cfg(any(feature = "network", feature = "vpn"))]
+use rustix::net::{SendFlags, send};
+use rustix::{
+    io::Errno,
+    net::{
+        AddressFamily, RecvFlags, SocketType, bind,
+        netlink::{self, SocketAddrNetlink},
+        recv, socket,
+    },
+};
+use tokio_stream::Stream;
+
+use crate::BlockingStream;
+
+#[cfg(any(feature = "network", feature = "vpn"))]
+const HEADER_LEN: usize = 16;
+#[cfg(any(feature = "network", feature = "vpn"))]
+const GENL_HEADER_LEN: usize = 4;
+
+#[cfg(any(feature = "network", feature = "vpn"))]
+const NLMSG_ERROR: u16 = 2;
+#[cfg(any(feature = "network", feature = "vpn"))]
+const NLMSG_DONE: u16 = 3;
+
+#[cfg(any(feature = "network", feature = "vpn"))]
+const NLM_F_REQUEST: u16 = 0x1;
+#[cfg(any(feature = "network", feature = "vpn"))]
+const NLM_F_ACK: u16 = 0x4;
+/// Requests every matching object rather than a single one.
+#[cfg(any(feature = "network", feature = "vpn"))]
+pub const NLM_F_DUMP: u16 = 0x300;
+
+#[cfg(any(feature = "network", feature = "vpn"))]
+const NLA_TYPE_MASK: u16 = 0x3fff;
+
+#[cfg(any(feature = "network", feature = "vpn"))]
+const GENL_ID_CTRL: u16 = 0x10;
+#[cfg(any(feature = "network", feature = "vpn"))]
+const CTRL_CMD_GETFAMILY: u8 = 3;
+#[cfg(any(feature = "network", feature = "vpn"))]
+const CTRL_ATTR_FAMILY_ID: u16 = 1;
+#[cfg(any(feature = "network", feature = "vpn"))]
+const CTRL_ATTR_FAMILY_NAME: u16 = 2;
+
+/// rtnetlink multicast group for link changes.
+#[cfg(any(feature = "network", feature = "vpn"))]
+pub const RTMGRP_LINK: u32 = 0x1;
+/// rtnetlink multicast group for IPv4 address changes.
+#[cfg(any(feature = "network", feature = "vpn"))]
+pub const RTMGRP_IPV4_IFADDR: u32 = 0x10;
+/// rtnetlink multicast group for IPv4 route changes.
+#[cfg(feature = "network")]
+pub const RTMGRP_IPV4_ROUTE: u32 = 0x40;
+/// rtnetlink multicast group for IPv6 address changes.
+#[cfg(any(feature = "network", feature = "vpn"))]
+pub const RTMGRP_IPV6_IFADDR: u32 = 0x100;
+/// rtnetlink multicast group for IPv6 route changes.
+#[cfg(feature = "network")]
+pub const RTMGRP_IPV6_ROUTE: u32 = 0x400;
+
+#[cfg(any(feature = "network", feature = "vpn"))]
+const fn align(len: usize) -> usize {
+    (len + 3) & !3
+}
+
+/// A netlink socket.
+#[derive(Debug)]
+pub struct Socket {
+    fd: OwnedFd,
+    /// The sequence number of the last request
+    #[cfg(any(feature = "network", feature = "vpn"))]
+    seq: u32,
+}
+
+impl Socket {
+    const fn new(fd: OwnedFd) -> Self {
+        Self {
+            fd,
+            #[cfg(any(feature = "network", feature = "vpn"))]
+            seq: 0,
+        }
+    }
+
+    /// Opens an rtnetlink socket that receives notifications from the given
+    /// multicast groups.
+    #[cfg(any(feature = "network", feature = "vpn"))]
+    pub fn route(groups: u32) -> Result<Self> {
+        let fd = socket(AddressFamily::NETLINK, SocketType::RAW, None)?;
+        bind(&fd, &SocketAddrNetlink::new(0, groups))?;
+        Ok(Self::new(fd))
+    }
+
+    /// Opens a socket that receives the kernel's uevents, the same ones that
+    /// udev acts on.
+    #[cfg(feature = "battery")]
+    pub fn uevent() -> Result<Self> {
+        let fd = socket(
+            AddressFamily::NETLINK,
+            SocketType::RAW,
+            Some(netlink::KOBJECT_UEVENT),
+        )?;
+        bind(&fd, &SocketAddrNetlink::new(0, 1))?;
+        Ok(Self::new(fd))
+    }
+
+    /// Opens a generic netlink socket.
+    #[cfg(any(feature = "network", feature = "vpn"))]
+    pub fn generic() -> Result<Self> {
+        let fd = socket(
+            AddressFamily::NETLINK,
+            SocketType::RAW,
+            Some(netlink::GENERIC),
+        )?;
+        bind(&fd, &SocketAddrNetlink::new(0, 0))?;
+        Ok(Self::new(fd))
+    }
+
+    /// Blocks until a message arrives that satisfies `filter`, if present.
+    pub fn wait(&self, filter: Option<&Filter>) -> Result<()> {
+        let mut buf = [0; 8192];
+        loop {
+            let (len, _) = recv(&self.fd, &mut buf, RecvFlags::empty())?;
+            if filter.is_none_or(|filter| filter(&buf[..len.min(buf.len())])) {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Looks up the id of a generic netlink family by name.
+    #[cfg(any(feature = "network", feature = "vpn"))]
+    pub fn family(&mut self, name: &str) -> Result<u16> {
+        let mut name = name.as_bytes().to_vec();
+        name.push(0);
+        let replies = self.request(
+            GENL_ID_CTRL,
+            0,
+            CTRL_CMD_GETFAMILY,
+            1,
+            &[(CTRL_ATTR_FAMILY_NAME, name.as_slice())],
+        )?;
+        replies
+            .iter()
+            .flat_map(|reply| attrs(reply))
+            .find(|(ty, _)| *ty == CTRL_ATTR_FAMILY_ID)
+            .and_then(|(_, payload)| u16_attr(payload))
+            .context("Generic netlink family not found")
+    }
+
+    /// Sends a generic netlink request and returns the attributes of each
+    /// reply.
+    #[cfg(any(feature = "network", feature = "vpn"))]
+    pub fn request(
+        &mut self,
+        family: u16,
+        flags: u16,
+        cmd: u8,
+        version: u8,
+        request_attrs: &[(u16, &[u8])],
+    ) -> Result<Vec<Vec<u8>>> {
+        let mut body = vec![cmd, version, 0, 0];
+        for (ty, payload) in request_attrs {
+            push_attr(&mut body, *ty, payload);
+        }
+        Ok(self
+            .transact(family, flags, &body)?
+            .into_iter()
+            .map(|reply| {
+                reply.get(GENL_HEADER_LEN..).unwrap_or_default().to_vec()
+            })
+            .collect())
+    }
+
+    /// Sends an rtnetlink request of type `ty` whose body is `header` and
+    /// returns the body of each reply, including its family-specific header.
+    ///
+    /// This should be called on a socket opened with [`Socket::route`] with no
+    /// multicast groups, since notifications would be mixed with the replies.
+    #[cfg(any(feature = "network", feature = "vpn"))]
+    pub fn route_request(
+        &mut self,
+        ty: u16,
+        flags: u16,
+        header: &[u8],
+    ) -> Result<Vec<Vec<u8>>> {
+        self.transact(ty, flags, header)
+    }
+
+    #[cfg(any(feature = "network", feature = "vpn"))]
+    fn transact(
+        &mut self,
+        ty: u16,
+        flags: u16,
+        body: &[u8],
+    ) -> Result<Vec<Vec<u8>>> {
+        self.seq = self.seq.wrapping_add(1);
+
+        let mut msg = vec![0; HEADER_LEN];
+        msg.extend_from_slice(body);
+        let len = msg.len() as u32;
+        msg[0..4].copy_from_slice(&len.to_ne_bytes());
+        msg[4..6].copy_from_slice(&ty.to_ne_bytes());
+        msg[6..8].copy_from_slice(
+            &(flags | NLM_F_REQUEST | NLM_F_ACK).to_ne_bytes(),
+        );
+        msg[8..12].copy_from_slice(&self.seq.to_ne_bytes());
+        send(&self.fd, &msg, SendFlags::empty())?;
+
+        let mut replies = Vec::new();
+        let mut buf = vec![0; 65536];
+        loop {
+            let (len, _) = recv(&self.fd, &mut buf[..], RecvFlags::empty())?;
+            let mut data = &buf[..len];
+            while data.len() >= HEADER_LEN {
+                let len = u32::from_ne_bytes(data[0..4].try_into()?) as usize;
+                let ty = u16::from_ne_bytes(data[4..6].try_into()?);
+                let seq = u32::from_ne_bytes(data[8..12].try_into()?);
+                if len < HEADER_LEN || len > data.len() {
+                    return Err(anyhow!("Truncated netlink message"));
+                }
+                let payload = &data[HEADER_LEN..len];
+                data = &data[align(len).min(data.len())..];
+
+                if seq != self.seq {
+                    continue;
+                }
+                match ty {
+                    NLMSG_DONE => return Ok(replies),
+                    NLMSG_ERROR => {
+                        let code = i32::from_ne_bytes(
+                            payload
+                                .get(0..4)
+                                .context("Bad error")?
+                                .try_into()?,
+                        );
+                        if code == 0 {
+                            // the ack comes after all replies to a request
+                            // that isn't a dump
+                            return Ok(replies);
+                        }
+                        return Err(Errno::from_raw_os_error(-code).into());
+                    }
+                    _ => replies.push(payload.to_vec()),
+                }
+            }
+        }
+    }
+}
+
+impl AsFd for Socket {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+/// Decides whether a raw message read from a socket is worth waking up for.
+pub type Filter = dyn Fn(&[u8]) -> bool + Send + Sync;
+
+/// A stream that yields each time a notification arrives on an rtnetlink or
+/// uevent socket.
+#[derive(Debug)]
+pub struct Notifications(BlockingStream);
+
+impl Notifications {
+    /// Creates a new stream from a socket opened with `Socket::route` or
+    /// `Socket::uevent`.
+    #[cfg(any(feature = "network", feature = "vpn"))]
+    pub fn new(socket: Socket) -> Self {
+        Self::with_filter(socket, None)
+    }
+
+    /// Creates a new stream that only yields for messages that satisfy
+    /// `filter`, which is passed everything read from the socket at once.
+    /// This may hold several rtnetlink messages (see `messages`) or a
+    /// single uevent.
+    #[cfg(any(feature = "battery", feature = "network"))]
+    pub fn filtered(
+        socket: Socket,
+        filter: impl Fn(&[u8]) -> bool + Send + Sync + 'static,
+    ) -> Self {
+        Self::with_filter(socket, Some(Box::new(filter)))
+    }
+
+    fn with_filter(socket: Socket, filter: Option<Box<Filter>>) -> Self {
+        Self(BlockingStream::new("netlink events", move || {
+            match socket.wait(filter.as_deref()) {
+                // the kernel dropped some notifications, but we only care
+                // that something changed
+                Err(e) if e.downcast_ref() == Some(&Errno::NOBUFS) => Ok(()),
+                res => res,
+            }
+        }))
+    }
+}
+
+impl Stream for Notifications {
+    type Item = ();
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut TaskContext<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        Pin::new(&mut self.0).poll_next(cx)
+    }
+}
+
+#[cfg(any(feature = "network", feature = "vpn"))]
+fn push_attr(buf: &mut Vec<u8>, ty: u16, payload: &[u8]) {
+    let len = 4 + payload.len();
+    buf.extend_from_slice(&(len as u16).to_ne_bytes());
+    buf.extend_from_slice(&ty.to_ne_bytes());
+    buf.extend_from_slice(payload);
+    buf.resize(buf.len() + align(len) - len, 0);
+}
+
+/// Iterates over a list of netlink attributes, yielding the type and payload
+/// of each.
+#[cfg(any(feature = "network", feature = "vpn"))]
+pub fn attrs(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if buf.len() < 4 {
+            return None;
+        }
+        let len = u16::from_ne_bytes([buf[0], buf[1]]) as usize;
+        let ty = u16::from_ne_bytes([buf[2], buf[3]]) & NLA_TYPE_MASK;
+        if len < 4 || len > buf.len() {
+            return None;
+        }
+        let payload = &buf[4..len];
+        buf = &buf[align(len).min(buf.len())..];
+        Some((ty, payload))
+    })
+}
+
+/// Finds the attribute with a given type in a list of attributes.
+#[cfg(any(feature = "network", feature = "vpn"))]
+pub fn find_attr(buf: &[u8], ty: u16) -> Option<&[u8]> {
+    attrs(buf)
+        .find(|(t, _)| *t == ty)
+        .map(|(_, payload)| payload)
+}
+
+/// Parses an attribute payload as a native-endian u16.
+#[cfg(any(feature = "network", feature = "vpn"))]
+pub fn u16_attr(payload: &[u8]) -> Option<u16> {
+    Some(u16::from_ne_bytes(payload.get(0..2)?.try_into().ok()?))
+}
+
+/// Iterates over the netlink messages in a buffer read from an rtnetlink
+/// socket, yielding the type and body of each.
+#[cfg(feature = "network")]
+pub fn messages(mut buf: &[u8]) -> impl Iterator<Item = (u16, &[u8])> {
+    std::iter::from_fn(move || {
+        if buf.len() < HEADER_LEN {
+            return None;
+        }
+        let len = u32::from_ne_bytes(buf[0..4].try_into().ok()?) as usize;
+        let ty = u16::from_ne_bytes([buf[4], buf[5]]);
+        if len < HEADER_LEN || len > buf.len() {
+            return None;
+        }
+        let body = &buf[HEADER_LEN..len];
+        buf = &buf[align(len).min(buf.len())..];
+        Some((ty, body))
+    })
+}
+
+/// Parses an attribute payload as a native-endian u32.
+#[cfg(any(feature = "network", feature = "vpn"))]
+pub fn u32_attr(payload: &[u8]) -> Option<u32> {
+    Some(u32::from_ne_bytes(payload.get(0..4)?.try_into().ok()?))
+}
@@ -0,0 +1,406 @@
+use std::{
+    collections::HashMap,
+    hash::BuildHasher,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use config::Value;
+use pango::{EllipsizeMode, Layout};
+use pangocairo::functions::{create_layout, show_layout};
+
+use crate::{
+    Attrs, PanelAnimateFn, remove_string_from_config, remove_uint_from_config,
+};
+
+/// A width in pixels or in characters of the panel's font.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Width {
+    /// A number of pixels
+    Pixels(u32),
+    /// A number of characters, using the average character width of the font
+    Chars(u32),
+}
+
+impl Default for Width {
+    fn default() -> Self {
+        Self::Pixels(0)
+    }
+}
+
+impl Width {
+    /// Parses a number followed by `px` or `ch`, like `300px` or `40ch`.
+    #[must_use]
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if let Some(px) = s.strip_suffix("px") {
+            px.trim().parse().ok().map(Self::Pixels)
+        } else if let Some(chars) = s.strip_suffix("ch") {
+            chars.trim().parse().ok().map(Self::Chars)
+        } else {
+            None
+        }
+    }
+
+    /// Removes `id` from a subset of the global [`Config`][config::Config].
+    /// Bare numbers are read as pixels, or as characters with a deprecation
+    /// warning if `legacy` is set.
+    fn remove<S: BuildHasher>(
+        id: &str,
+        table: &mut HashMap<String, Value, S>,
+        legacy: bool,
+    ) -> Option<Self> {
+        let val = table.remove(id)?;
+        if let Ok(n) = val.clone().into_uint() {
+            let n = n.try_into().unwrap_or(u32::MAX);
+            return Some(if legacy {
+                log::warn!(
+                    "Bare numbers for {id} are deprecated for this panel; \
+                     reading {n} as \"{n}ch\""
+                );
+                Self::Chars(n)
+            } else {
+                Self::Pixels(n)
+            });
+        }
+        let width =
+            val.clone().into_string().ok().and_then(|s| Self::parse(&s));
+        if width.is_none() {
+            log::warn!("Ignoring invalid width {val:?}");
+        }
+        width
+    }
+
+    /// Converts the width to pixels using the font of `layout`.
+    fn pixels(self, layout: &Layout) -> f64 {
+        match self {
+            Self::Pixels(px) => f64::from(px),
+            Self::Chars(chars) => {
+                let metrics = layout
+                    .context()
+                    .metrics(layout.font_description().as_ref(), None);
+                f64::from(chars) * f64::from(metrics.approximate_char_width())
+                    / f64::from(pango::SCALE)
+            }
+        }
+    }
+}
+
+/// Where to cut text that has been truncated.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Ellipsis {
+    /// Keep the end of the text
+    Start,
+    /// Keep the start and end of the text
+    Middle,
+    /// Keep the start of the text
+    #[default]
+    End,
+}
+
+impl Ellipsis {
+    /// Parses `start`, `middle`, or `end`, ignoring case.
+    #[must_use]
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "start" => Some(Self::Start),
+            "middle" => Some(Self::Middle),
+            "end" => Some(Self::End),
+            _ => None,
+        }
+    }
+
+    const fn mode(self) -> EllipsizeMode {
+        match self {
+            Self::Start => EllipsizeMode::Start,
+            Self::Middle => EllipsizeMode::Middle,
+            Self::End => EllipsizeMode::End,
+        }
+    }
+}
+
+/// How to handle text that's wider than the maximum width.
+#[derive(Clone, Debug)]
+pub enum Strategy {
+    /// Cut the text and insert an ellipsis
+    Truncate(Ellipsis),
+    /// Move the text across the available space, wrapping around at the end
+    Scroll {
+        /// How often to move the text
+        interval: Duration,
+    },
+}
+
+impl Default for Strategy {
+    fn default() -> Self {
+        Self::Truncate(Ellipsis::default())
+    }
+}
+
+impl Strategy {
+    /// Parses `strategy`, `ellipsis`, and `scroll_interval` from a subset of
+    /// the global [`Config`][config::Config]. `default_interval` is in
+    /// milliseconds.
+    pub fn parse<S: BuildHasher>(
+        table: &mut HashMap<String, Value, S>,
+        default_interval: u64,
+    ) -> Self {
+        let ellipsis = remove_string_from_config("ellipsis", table)
+            .map(|ellipsis| {
+                Ellipsis::parse(ellipsis.as_str()).unwrap_or_else(|| {
+                    log::warn!("Invalid ellipsis {ellipsis}; using `end`");
+                    Ellipsis::End
+                })
+            })
+            .unwrap_or_default();
+        let interval = Duration::from_millis(
+            remove_uint_from_config("scroll_interval", table)
+                .unwrap_or(default_interval),
+        );
+        match remove_string_from_config("strategy", table).as_deref() {
+            Some("scroll") => Self::Scroll { interval },
+            _ => Self::Truncate(ellipsis),
+        }
+    }
+}
+
+const SCROLL_INTERVAL: u64 = 50;
+const SCROLL_STEP: Width = Width::Pixels(2);
+
+#[derive(Debug, Default)]
+struct ScrollState {
+    text: String,
+    offset: f64,
+}
+
+/// Limits the width of a panel, truncating or scrolling any text that
+/// doesn't fit.
+///
+/// Scrolling panels are redrawn by the bar every `scroll_interval`, except
+/// while the pointer is over them.
+///
+/// The `mpd` and `mpris` panels only apply this to `%main%`. The panels that
+/// document otherwise ignore it, and a warning is logged if `max_width` is set
+/// on them.
+#[derive(Clone, Debug, Default)]
+pub struct Overflow {
+    max_width: Width,
+    strategy: Strategy,
+    separator: String,
+    step: Width,
+    state: Arc<Mutex<ScrollState>>,
+}
+
+impl Overflow {
+    /// Parses an instance of this type from a subset of the global
+    /// [`Config`][config::Config].
+    ///
+    /// Configuration options:
+    /// - `max_width`: The maximum width of the panel. 0 means no maximum.
+    ///   - type: u64 (pixels) or String - a number followed by `px` (pixels) or
+    ///     `ch` (average character widths of the panel's font), like `"40ch"`
+    ///   - default: 0
+    /// - `strategy`: How to handle text wider than `max_width`.
+    ///   - type: String - `truncate` or `scroll`
+    ///   - default: `truncate`
+    /// - `ellipsis`: Where to cut truncated text.
+    ///   - type: String - `start`, `middle`, or `end`
+    ///   - default: `end`
+    /// - `scroll_interval`: How often in milliseconds to move scrolling text.
+    ///   - type: u64
+    ///   - default: 50
+    /// - `scroll_step`: How far to move scrolling text each time.
+    ///   - type: u64 (pixels) or String, like `max_width`
+    ///   - default: 2
+    /// - `scroll_separator`: What to put between the end of the text and the
+    ///   beginning when it scrolls.
+    ///   - type: String
+    ///   - default: `  ` (two spaces)
+    pub fn parse<S: BuildHasher>(
+        table: &mut HashMap<String, Value, S>,
+    ) -> Self {
+        Self::parse_with(table, false, SCROLL_INTERVAL, SCROLL_STEP)
+    }
+
+    /// Like [`Overflow::parse`], for panels whose `max_width` used to be
+    /// measured in characters. A bare number for `max_width` is still read as
+    /// characters, with a deprecation warning.
+    #[cfg(feature = "xwindow")]
+    pub(crate) fn parse_legacy<S: BuildHasher>(
+        table: &mut HashMap<String, Value, S>,
+    ) -> Self {
+        Self::parse_with(table, true, SCROLL_INTERVAL, SCROLL_STEP)
+    }
+
+    /// Like [`Overflow::parse_legacy`], for the media panels, which used to
+    /// scroll `%main%` by one character every second. Those are still the
+    /// defaults for `scroll_step` and `scroll_interval`.
+    #[cfg(any(feature = "mpd", feature = "mpris"))]
+    pub(crate) fn parse_media<S: BuildHasher>(
+        table: &mut HashMap<String, Value, S>,
+    ) -> Self {
+        Self::parse_with(table, true, 1000, Width::Chars(1))
+    }
+
+    fn parse_with<S: BuildHasher>(
+        table: &mut HashMap<String, Value, S>,
+        legacy: bool,
+        default_interval: u64,
+        default_step: Width,
+    ) -> Self {
+        let overflow = Self {
+            max_width: Width::remove("max_width", table, legacy)
+                .unwrap_or_default(),
+            strategy: Strategy::parse(table, default_interval),
+            separator: remove_string_from_config("scroll_separator", table)
+                .unwrap_or_else(|| String::from("  ")),
+            step: Width::remove("scroll_step", table, false)
+                .unwrap_or(default_step),
+            state: Arc::new(Mutex::new(ScrollState::default())),
+        };
+        log::debug!("got overflow: {overflow:?}");
+        overflow
+    }
+
+    /// Makes `layout` fit within `max_width`. `text` identifies the content
+    /// of the layout so that scrolling restarts when it changes.
+    ///
+    /// Returns [`None`] if the layout fits (possibly after truncating it).
+    /// Otherwise, the returned [`Scroll`] should be used to draw it.
+    pub(crate) fn fit(
+        &self,
+        cr: &cairo::Context,
+        layout: &Layout,
+        text: &str,
+        attrs: &Attrs,
+    ) -> Option<Scroll> {
+        let max_width = self.max_width.pixels(layout).round() as i32;
+        if max_width <= 0 || layout.pixel_size().0 <= max_width {
+            return None;
+        }
+
+        match self.strategy {
+            Strategy::Truncate(ellipsis) => {
+                layout.set_width(max_width * pango::SCALE);
+                layout.set_ellipsize(ellipsis.mode());
+                None
+            }
+            Strategy::Scroll { interval } => {
+                let separator = create_layout(cr);
+                separator.set_markup(self.separator.as_str());
+                attrs.apply_font(&separator);
+
+                let mut state = self.state.lock().unwrap();
+                if state.text != text {
+                    state.text = text.to_owned();
+                    state.offset = 0.0;
+                }
+
+                Some(Scroll {
+                    width: max_width,
+                    period: f64::from(
+                        layout.pixel_size().0 + separator.pixel_size().0,
+                    ),
+                    separator,
+                    interval,
+                    step: self.step.pixels(layout),
+                    state: self.state.clone(),
+                })
+            }
+        }
+    }
+}
+
+/// A layout that's too wide for its panel and moves across it.
+#[derive(Debug)]
+pub(crate) struct Scroll {
+    /// The width of the visible area
+    pub width: i32,
+    period: f64,
+    separator: Layout,
+    interval: Duration,
+    step: f64,
+    state: Arc<Mutex<ScrollState>>,
+}
+
+impl Scroll {
+    /// How far the text has moved to the left.
+    pub fn offset(&self) -> f64 {
+        self.state.lock().unwrap().offset
+    }
+
+    /// Draws the text at its current offset, followed by the separator and
+    /// the start of the text again. `cr` should be positioned where the text
+    /// would be drawn if it fit, and clipped to the visible area. `show`
+    /// draws the text at the current point.
+    pub fn draw(
+        &self,
+        cr: &cairo::Context,
+        show: impl Fn() -> anyhow::Result<()>,
+    ) -> anyhow::Result<()> {
+        let separator_width = f64::from(self.separator.pixel_size().0);
+        cr.save()?;
+        cr.translate(-self.offset(), 0.0);
+        show()?;
+        cr.translate(self.period - separator_width, 0.0);
+        show_layout(cr, &self.separator);
+        cr.translate(separator_width, 0.0);
+        show()?;
+        cr.restore()?;
+        Ok(())
+    }
+
+    /// Maps a position in the visible area to one in the scrolling text, or
+    /// [`None`] if it's over the separator.
+    #[cfg(feature = "pulseaudio")]
+    pub fn locate(&self, x: f64) -> Option<f64> {
+        let x = (x + self.offset()) % self.period;
+        (x < self.period - f64::from(self.separator.pixel_size().0))
+            .then_some(x)
+    }
+
+    /// The interval and function to pass to
+    /// [`PanelDrawInfo::with_animation`][crate::bar::PanelDrawInfo::with_animation].
+    pub fn animation(&self) -> (Duration, PanelAnimateFn) {
+        let state = self.state.clone();
+        let (step, period) = (self.step, self.period);
+        (
+            self.interval,
+            Box::new(move || {
+                let mut state = state.lock().unwrap();
+                state.offset = (state.offset + step) % period;
+                Ok(())
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width() {
+        assert_eq!(Width::parse("300px"), Some(Width::Pixels(300)));
+        assert_eq!(Width::parse(" 40 ch "), Some(Width::Chars(40)));
+        assert_eq!(Width::parse("40"), None);
+        assert_eq!(Width::parse("-1px"), None);
+    }
+
+    #[test]
+    fn legacy_width() {
+        let mut table = HashMap::from([
+            (String::from("max_width"), Value::from(40)),
+            (String::from("scroll_step"), Value::from("1ch")),
+        ]);
+        let overflow = Overflow::parse_with(&mut table, true, 50, SCROLL_STEP);
+        assert_eq!(overflow.max_width, Width::Chars(40));
+        assert_eq!(overflow.step, Width::Chars(1));
+
+        let mut table =
+            HashMap::from([(String::from("max_width"), Value::from(40))]);
+        let overflow = Overflow::parse(&mut table);
+        assert_eq!(overflow.max_width, Width::Pixels(40));
+        assert_eq!(overflow.step, SCROLL_STEP);
+    }
+}
@@ -1,14 +1,15 @@
 use std::{
+    cell::Cell,
     collections::HashMap,
-    fs::File,
-    io::Read,
+    fs,
     pin::Pin,
+    process::Command,
     rc::Rc,
     sync::{Arc, Mutex},
     time::Duration,
 };
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use async_trait::async_trait;
 use config::Config;
 use derive_builder::Builder;
@@ -18,30 +19,35 @@ use tokio_stream::{Stream, StreamExt, StreamMap};
 
 use crate::{
     Attrs, Highlight, ManagedIntervalStream, PanelConfig, PanelRunResult, Ramp,
-    array_to_struct,
+    Severity, Template, Thresholds, array_to_struct,
     bar::PanelDrawInfo,
     common::{PanelCommon, ShowHide},
+    glob_match, ipc, netlink, remove_float_from_config,
     remove_string_from_config, remove_uint_from_config,
+    template::Var,
 };
 
+const POWER_SUPPLY: &str = "/sys/class/power_supply";
+
 /// Shows the current battery level.
 #[derive(Builder, Debug, Clone)]
 #[builder_struct_attr(allow(missing_docs))]
 #[builder_impl_attr(allow(missing_docs))]
-#[allow(dead_code)]
 pub struct Battery {
     name: &'static str,
-    #[builder(default = r#"String::from("BAT0")"#)]
+    #[builder(default = r#"String::from("BAT*")"#)]
     battery: String,
     #[builder(default = r#"String::from("AC")"#)]
     adapter: String,
     #[builder(default, setter(strip_option))]
     full_at: Option<u8>,
-    #[builder(default = "Duration::from_secs(10)")]
+    #[builder(default = "Duration::from_secs(30)")]
     duration: Duration,
     #[builder(default)]
     waker: Arc<AtomicWaker>,
-    formats: BatteryFormats<String>,
+    #[builder(default)]
+    alerts: Alerts,
+    formats: BatteryFormats<Template>,
     attrs: Attrs,
     #[builder(default, setter(strip_option))]
     highlight: Option<Highlight>,
@@ -56,61 +62,96 @@ impl Battery {
         height: i32,
         paused: Arc<Mutex<bool>>,
     ) -> Result<PanelDrawInfo> {
-        let mut capacity_f = File::open(format!(
-            "/sys/class/power_supply/{}/capacity",
-            self.battery
-        ))?;
-        let mut capacity = String::new();
-        capacity_f.read_to_string(&mut capacity)?;
-        let capacity_val = capacity.trim().parse::<u8>()?;
-        let text =
-            if self.full_at.is_some_and(|full_at| capacity_val > full_at) {
-                self.formats.full.replace("%percentage%", capacity.trim())
-            } else {
-                let mut status_f = File::open(format!(
-                    "/sys/class/power_supply/{}/status",
-                    self.battery
-                ))?;
-                let mut status = String::new();
-                status_f.read_to_string(&mut status)?;
-
-                match status.trim() {
-                    "Charging" => self
-                        .formats
-                        .charging
-                        .replace("%percentage%", capacity.trim()),
-                    "Discharging" => self
-                        .formats
-                        .discharging
-                        .replace("%percentage%", capacity.trim()),
-                    "Not charging" => self
-                        .formats
-                        .not_charging
-                        .replace("%percentage%", capacity.trim()),
-                    "Full" => self
-                        .formats
-                        .full
-                        .replace("%percentage%", capacity.trim()),
-                    "Unknown" => self
-                        .formats
-                        .unknown
-                        .replace("%percentage%", capacity.trim()),
-                    _ => String::from("Unknown battery state"),
-                }
+        let batteries = Supply::query_all(self.battery.as_str());
+        if batteries.is_empty() {
+            return Err(anyhow!(
+                "No batteries found matching {}",
+                self.battery
+            ));
+        }
+        let total = Supply::aggregate(&batteries);
+        let capacity = total.capacity.round().clamp(0.0, 100.0) as u8;
+        let status = total.status.as_str();
+        let ac = query_ac(self.adapter.as_str());
+        self.alerts.update(
+            self.name,
+            &self.common.thresholds,
+            total.capacity,
+            status == "Charging" || ac == Some(true),
+        );
+
+        let format = if self.full_at.is_some_and(|full_at| capacity > full_at) {
+            Some(&self.formats.full)
+        } else {
+            match status {
+                "Charging" => Some(&self.formats.charging),
+                "Discharging" => Some(&self.formats.discharging),
+                "Not charging" => Some(&self.formats.not_charging),
+                "Full" => Some(&self.formats.full),
+                "Unknown" => Some(&self.formats.unknown),
+                _ => None,
+            }
+        };
+
+        let (format, attrs, highlight) = match format {
+            Some(format) => {
+                let (attrs, highlight) = self.common.color_ramps.apply(
+                    capacity,
+                    0,
+                    100,
+                    &self.attrs,
+                    self.highlight.as_ref(),
+                );
+                let (format, attrs, highlight) = self.common.thresholds.style(
+                    capacity as f64,
+                    format,
+                    &attrs,
+                    highlight.as_ref(),
+                );
+                (Some(format), attrs, highlight)
             }
-            .replace(
-                "%ramp%",
-                self.ramp
-                    .choose(capacity.trim().parse::<u32>()?, 0, 100)
-                    .as_str(),
-            );
+            None => (None, self.attrs.clone(), self.highlight.clone()),
+        };
+
+        let text = format.map_or_else(
+            || String::from("Unknown battery state"),
+            |format| {
+                format.render(|name| match name {
+                    "percentage" => Some(capacity.into()),
+                    "status" => Some(status.into()),
+                    "charging" => Some((status == "Charging").into()),
+                    "ramp" => Some(self.ramp.choose(capacity, 0, 100).into()),
+                    "ac" => Some(ac?.into()),
+                    "batteries" => Some(batteries.len().into()),
+                    "watts" => Some(Var::float(total.power? / 1e6, 1)),
+                    "health" => Some(Var::float(
+                        total.energy_full? / total.energy_full_design? * 100.0,
+                        0,
+                    )),
+                    "time_remaining" => (status == "Discharging")
+                        .then(|| format_hours(total.energy_now? / total.power?))
+                        .flatten()
+                        .map(Var::from),
+                    "time_to_full" => (status == "Charging")
+                        .then(|| {
+                            format_hours(
+                                (total.energy_full? - total.energy_now?)
+                                    / total.power?,
+                            )
+                        })
+                        .flatten()
+                        .map(Var::from),
+                    _ => None,
+                })
+            },
+        );
 
         self.common.draw(
             cr,
             text.as_str(),
-            &self.attrs,
+            &attrs,
             self.common.dependence,
-            self.highlight.clone(),
+            highlight,
             self.common.images.clone(),
             height,
             ShowHide::Default(paused, self.waker.clone()),
@@ -124,38 +165,56 @@ impl PanelConfig for Battery {
     /// Parses an instance of the panel from the global [`Config`]
     ///
     /// Configuration options:
-    /// - `battery`: specify which battery to monitor
+    /// - `battery`: specify which battery to monitor. This may be a glob, in
+    ///   which case every matching battery is combined, weighted by energy.
+    ///   - type: String
+    ///   - default: "BAT*"
+    /// - `adapter`: specify which adapter to monitor for `%ac%`. If there is no
+    ///   such adapter, any mains power supply is used instead.
     ///   - type: String
-    ///   - default: "BAT0"
-    /// - `adapter`: specify which adapter to monitor
     ///   - default: "AC"
-    ///   - currently unused
     /// - `full_at`: specify the minimum percentage to use `format_full`. If
     ///   set, ignores the `status` file when the battery percentage is above
     ///   the provided value.
     ///   - type: u64
-    /// - `interval`: how often (in seconds) to poll for new values
+    /// - `interval`: how often (in seconds) to poll for new values. Changes
+    ///   reported by acpid or the kernel (e.g. plugging in the adapter) are
+    ///   shown immediately regardless, so this mostly affects `%watts%` and the
+    ///   time estimates.
     ///   - type: u64
-    ///   - default: 10
+    ///   - default: 30
+    /// - `format`: a format string to use for every state that doesn't have its
+    ///   own format string. See [`PanelCommon::parse_templates`].
+    ///   - type: String
+    ///   - formatting options:
+    ///     - `%percentage%`, `%status%`, `%charging%`, `%ramp%`
+    ///     - `%ac%`: whether the adapter is online
+    ///     - `%batteries%`: the number of batteries found
+    ///     - `%watts%`: the current power draw (or charge rate) in watts
+    ///     - `%health%`: the full capacity as a percentage of the design
+    ///       capacity
+    ///     - `%time_remaining%`: the estimated time until empty, e.g. `2:05`,
+    ///       while discharging
+    ///     - `%time_to_full%`: the estimated time until full while charging
     /// - `format_charging`: format string when the battery is charging
     ///   - type: String
-    ///   - formatting options: `%percentage%`
+    ///   - formatting options: same as `format`
     ///   - default: "CHG: %percentage%%"
     /// - `format_discharging`: format string when the battery is discharging
     ///   - type: String
-    ///   - formatting options: `%percentage%`
+    ///   - formatting options: same as `format`
     ///   - default: "DSCHG: %percentage%%"
     /// - `format_not_charging`: format string when the battery is not charging
     ///   - type: String
-    ///   - formatting options: `%percentage%`
+    ///   - formatting options: same as `format`
     ///   - default: "NCHG: %percentage%%"
     /// - `format_full`: format string when the battery is full
     ///   - type: String
-    ///   - formatting options: `%percentage%`
+    ///   - formatting options: same as `format`
     ///   - default: "FULL: %percentage%%"
     /// - `format_unknown`: format string when the battery is unknown
     ///   - type: String
-    ///   - formatting options: `%percentage%`
+    ///   - formatting options: same as `format`
     ///   - default: "%percentage%%"
     /// - `attrs`: A string specifying the attrs for the panel. See
     ///   [`Attrs::parse`] for details.
@@ -163,7 +222,28 @@ impl PanelConfig for Battery {
     ///   [`Highlight::parse`] for details.
     /// - `ramp`: A string specifying the ramp to show battery level. See
     ///   [`Ramp::parse`] for details.
-    /// - See [`PanelCommon::parse_common`].
+    /// - `ramp_fg`, `ramp_bg`, `ramp_highlight`: Ramps whose colors follow
+    ///   battery level. See [`ColorRamps::parse`][crate::ColorRamps::parse] for
+    ///   details.
+    /// - `command_warn`, `command_crit`: shell commands to run when the battery
+    ///   drops past the warning or critical threshold while discharging, e.g.
+    ///   `notify-send "Battery low"` and `systemctl suspend`. Each command runs
+    ///   once per crossing. Starting the bar while the battery is already past
+    ///   a threshold doesn't count as a crossing.
+    ///   - type: String
+    /// - `message_warn`, `message_crit`: IPC messages to send to every running
+    ///   bar at the same times, in the same format as `lazybar-msg all`.
+    ///   - type: String
+    /// - `hysteresis`: how many percentage points the battery must recover past
+    ///   a threshold before it can be crossed again
+    ///   - type: f64
+    ///   - default: 2
+    /// - See [`PanelCommon::parse_common`]. Thresholds apply to the battery
+    ///   percentage, so lower values are worse unless `below = false` is set.
+    ///   Use `attrs_crit` and `blink_crit` to make a critical battery stand
+    ///   out. Each time the battery's state changes, the panel publishes
+    ///   `normal`, `warn`, or `crit` to IPC subscribers (see `lazybar-msg
+    ///   subscribe`).
     fn parse(
         name: &'static str,
         table: &mut HashMap<String, config::Value>,
@@ -184,7 +264,17 @@ impl PanelConfig for Battery {
         if let Some(duration) = remove_uint_from_config("interval", table) {
             builder.duration(Duration::from_secs(duration));
         }
-        let formats = PanelCommon::parse_formats(
+        builder.alerts(Alerts {
+            command_warn: remove_string_from_config("command_warn", table),
+            command_crit: remove_string_from_config("command_crit", table),
+            message_warn: remove_string_from_config("message_warn", table),
+            message_crit: remove_string_from_config("message_crit", table),
+            hysteresis: remove_float_from_config("hysteresis", table)
+                .unwrap_or(2.0)
+                .max(0.0),
+            severity: Cell::default(),
+        });
+        let formats = PanelCommon::parse_templates(
             table,
             &[
                 "_charging",
@@ -200,8 +290,9 @@ impl PanelConfig for Battery {
                 "FULL: %percentage%%",
                 "%percentage%%",
             ],
-        );
-        let common = PanelCommon::parse_common(table)?;
+        )?;
+        let mut common = PanelCommon::parse_common(table)?;
+        common.thresholds.default_below(true);
 
         builder.formats(BatteryFormats::new(formats));
         builder.attrs(PanelCommon::parse_attr(table, ""));
@@ -226,26 +317,46 @@ impl PanelConfig for Battery {
         self.attrs.apply_to(&global_attrs);
 
         let mut map =
-            StreamMap::<_, Pin<Box<dyn Stream<Item = ()>>>>::with_capacity(2);
+            StreamMap::<_, Pin<Box<dyn Stream<Item = ()>>>>::with_capacity(3);
 
         let interval = Arc::new(Mutex::new(interval(self.duration)));
         let paused = Arc::new(Mutex::new(false));
-        let waker = Arc::new(AtomicWaker::new());
 
         map.insert(
             0,
             Box::pin(
-                ManagedIntervalStream::new(interval, paused.clone(), waker)
-                    .map(|_| ()),
+                ManagedIntervalStream::new(
+                    interval,
+                    paused.clone(),
+                    self.waker.clone(),
+                )
+                .map(|_| ()),
             ),
         );
         let stream = acpid_plug::connect().await;
         if let Ok(stream) = stream {
             map.insert(1, Box::pin(stream.map(|_| ())));
         }
+        match netlink::Socket::uevent() {
+            Ok(socket) => {
+                map.insert(
+                    2,
+                    Box::pin(netlink::Notifications::filtered(
+                        socket,
+                        is_power_supply_event,
+                    )),
+                );
+            }
+            Err(e) => log::warn!("Failed to listen for uevents: {e}"),
+        }
 
         Ok((
-            Box::pin(map.map(move |_| self.draw(&cr, height, paused.clone()))),
+            Box::pin(
+                self.common
+                    .thresholds
+                    .blink(map, paused.clone(), self.waker.clone())
+                    .map(move |_| self.draw(&cr, height, paused.clone())),
+            ),
             None,
         ))
     }
@@ -259,3 +370,211 @@ array_to_struct!(
     full,
     unknown
 );
+
+/// What to do when the battery crosses one of the panel's thresholds.
+#[derive(Debug, Clone, Default)]
+struct Alerts {
+    command_warn: Option<String>,
+    command_crit: Option<String>,
+    message_warn: Option<String>,
+    message_crit: Option<String>,
+    hysteresis: f64,
+    /// [`None`] until the first reading, so that starting the bar below a
+    /// threshold doesn't count as crossing it
+    severity: Cell<Option<Severity>>,
+}
+
+impl Alerts {
+    fn update(
+        &self,
+        panel: &str,
+        thresholds: &Thresholds,
+        value: f64,
+        charging: bool,
+    ) {
+        let current = self.severity.get();
+        let severity = if charging {
+            Severity::Normal
+        } else {
+            thresholds.severity_from(
+                current.unwrap_or_default(),
+                value,
+                self.hysteresis,
+            )
+        };
+        self.severity.set(Some(severity));
+        let Some(current) = current else {
+            return;
+        };
+        if severity == current {
+            return;
+        }
+        let worse = severity > current;
+
+        ipc::publish(panel, severity.as_str());
+        if !worse {
+            return;
+        }
+
+        let (command, message) = match severity {
+            Severity::Normal => return,
+            Severity::Warn => (&self.command_warn, &self.message_warn),
+            Severity::Crit => (&self.command_crit, &self.message_crit),
+        };
+        if let Some(command) = command.clone() {
+            tokio::task::spawn_blocking(move || {
+                match Command::new("sh").arg("-c").arg(&command).status() {
+                    Ok(status) if !status.success() => {
+                        log::warn!("`{command}` exited with {status}");
+                    }
+                    Ok(_) => {}
+                    Err(e) => log::warn!("Failed to run `{command}`: {e}"),
+                }
+            });
+        }
+        if let Some(message) = message.clone() {
+            tokio::task::spawn(async move {
+                ipc::send_all(message.as_str()).await;
+            });
+        }
+    }
+}
+
+/// The state of a power supply, or several power supplies combined. Energies
+/// are in µWh and power is in µW.
+#[derive(Debug, Clone, Default)]
+struct Supply {
+    capacity: f64,
+    status: String,
+    energy_now: Option<f64>,
+    energy_full: Option<f64>,
+    energy_full_design: Option<f64>,
+    power: Option<f64>,
+}
+
+impl Supply {
+    /// Reads every battery whose name matches `glob`.
+    fn query_all(glob: &str) -> Vec<Self> {
+        let Ok(dir) = fs::read_dir(POWER_SUPPLY) else {
+            return Vec::new();
+        };
+        let mut names = dir
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| glob_match(glob, name))
+            .filter(|name| {
+                read_sysfs(name, "type").is_some_and(|ty| ty == "Battery")
+            })
+            .collect::<Vec<_>>();
+        names.sort();
+        names.iter().filter_map(|name| Self::query(name)).collect()
+    }
+
+    fn query(name: &str) -> Option<Self> {
+        let number = |file: &str| read_sysfs(name, file)?.parse::<f64>().ok();
+        // batteries that report charge (µAh) and current (µA) instead of
+        // energy and power are converted using their voltage
+        let volts = number("voltage_min_design")
+            .or_else(|| number("voltage_now"))
+            .map(|uv| uv / 1e6);
+        let energy = |kind: &str| {
+            number(format!("energy_{kind}").as_str()).or_else(|| {
+                Some(number(format!("charge_{kind}").as_str())? * volts?)
+            })
+        };
+
+        let energy_now = energy("now");
+        let energy_full = energy("full");
+        let capacity = number("capacity").or_else(|| {
+            Some(energy_now? / energy_full?.max(f64::EPSILON) * 100.0)
+        })?;
+        Some(Self {
+            capacity,
+            status: read_sysfs(name, "status")
+                .unwrap_or_else(|| String::from("Unknown")),
+            energy_now,
+            energy_full,
+            energy_full_design: energy("full_design"),
+            power: number("power_now")
+                .or_else(|| Some(number("current_now")? * volts?))
+                .map(f64::abs),
+        })
+    }
+
+    /// Combines several batteries into one, weighting their capacities by
+    /// energy when it is known.
+    fn aggregate(batteries: &[Self]) -> Self {
+        let sum = |field: fn(&Self) -> Option<f64>| {
+            batteries.iter().map(field).sum::<Option<f64>>()
+        };
+        let energy_now = sum(|b| b.energy_now);
+        let energy_full = sum(|b| b.energy_full);
+        let capacity = match (energy_now, energy_full) {
+            (Some(now), Some(full)) if full > 0.0 => now / full * 100.0,
+            _ => {
+                batteries.iter().map(|b| b.capacity).sum::<f64>()
+                    / batteries.len().max(1) as f64
+            }
+        };
+
+        let any = |status: &str| batteries.iter().any(|b| b.status == status);
+        let status = if any("Charging") {
+            "Charging"
+        } else if any("Discharging") {
+            "Discharging"
+        } else if batteries.iter().all(|b| b.status == "Full") {
+            "Full"
+        } else if any("Not charging") {
+            "Not charging"
+        } else {
+            "Unknown"
+        };
+
+        Self {
+            capacity,
+            status: String::from(status),
+            energy_now,
+            energy_full,
+            energy_full_design: sum(|b| b.energy_full_design),
+            power: batteries
+                .iter()
+                .filter_map(|b| b.power)
+                .reduce(|a, b| a + b)
+                .filter(|power| *power > 0.0),
+        }
+    }
+}
+
+fn read_sysfs(supply: &str, file: &str) -> Option<String> {
+    fs::read_to_string(format!("{POWER_SUPPLY}/{supply}/{file}"))
+        .ok()
+        .map(|s| s.trim().to_string())
+}
+
+/// Returns whether `adapter` is online, or whether any mains power supply is
+/// online if `adapter` doesn't exist.
+fn query_ac(adapter: &str) -> Option<bool> {
+    if let Some(online) = read_sysfs(adapter, "online") {
+        return Some(online == "1");
+    }
+    let mains = fs::read_dir(POWER_SUPPLY)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter(|name| read_sysfs(name, "type").is_some_and(|ty| ty == "Mains"))
+        .filter_map(|name| read_sysfs(name.as_str(), "online"))
+        .collect::<Vec<_>>();
+    (!mains.is_empty()).then(|| mains.iter().any(|online| online == "1"))
+}
+
+/// Formats a number of hours as `H:MM`.
+fn format_hours(hours: f64) -> Option<String> {
+    if !hours.is_finite() || hours < 0.0 {
+        return None;
+    }
+    let minutes = (hours * 60.0).round() as u64;
+    Some(format!("{}:{:02}", minutes / 60, minutes % 60))
+}
+
+fn is_power_supply_event(msg: &[u8]) -> bool {
+    msg.split(|b| *b == 0)
+        .any(|field| field == b"SUBSYSTEM=power_supply")
+}
@@ -174,6 +174,7 @@ impl Clock {
                     MouseButton::Middle => actions.middle.clone(),
                     MouseButton::ScrollUp => actions.up.clone(),
                     MouseButton::ScrollDown => actions.down.clone(),
+                    MouseButton::Drag => None,
                 };
                 Self::process_event(
                     Event::Action(action),
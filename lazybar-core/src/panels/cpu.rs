@@ -1,31 +1,32 @@
 use std::{
     collections::HashMap,
-    fs::File,
+    fs::{self, File},
     io::Read,
+    path::PathBuf,
     rc::Rc,
-    sync::{Arc, LazyLock, Mutex},
+    sync::{Arc, Mutex},
     time::Duration,
 };
 
 use anyhow::{Result, anyhow};
 use async_trait::async_trait;
 use derive_builder::Builder;
+use derive_debug::Dbg;
 use futures::task::AtomicWaker;
-use regex::Regex;
 use tokio_stream::StreamExt;
 
 use crate::{
     Attrs, Highlight, ManagedIntervalStream, PanelConfig, PanelRunResult, Ramp,
+    Template,
     bar::PanelDrawInfo,
     common::{PanelCommon, ShowHide},
     remove_string_from_config, remove_uint_from_config,
+    template::Var,
 };
 
-static REGEX: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r"cpu\s*(?<user>\d+) (?<nice>\d+) (?<system>\d+) (?<idle>\d+) \d+ \d+ \d+ (?<steal>\d+)").unwrap()
-});
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
 
-#[derive(Debug, Clone, Builder)]
+#[derive(Dbg, Clone, Builder)]
 #[builder_struct_attr(allow(missing_docs))]
 #[builder_impl_attr(allow(missing_docs))]
 /// Display information about CPU usage based on `/proc/stat`
@@ -37,8 +38,23 @@ pub struct Cpu {
     waker: Arc<AtomicWaker>,
     #[builder(default = r#"String::from("/proc/stat")"#)]
     path: String,
-    last_load: Load,
-    format: &'static str,
+    last_loads: Vec<Load>,
+    #[builder(default)]
+    usage: Usage,
+    #[builder(default)]
+    cores: Vec<f64>,
+    #[builder(default)]
+    freqs: Vec<u64>,
+    #[builder(default)]
+    max_freq: Option<u64>,
+    #[builder(default)]
+    loadavg: [f64; 3],
+    #[builder(default)]
+    top: Option<(String, f64)>,
+    #[builder(default)]
+    #[dbg(skip)]
+    proc_times: HashMap<i32, (String, u64)>,
+    format: Template,
     attrs: Attrs,
     #[builder(default, setter(strip_option))]
     highlight: Option<Highlight>,
@@ -47,36 +63,130 @@ pub struct Cpu {
 }
 
 impl Cpu {
+    fn update(&mut self) -> Result<()> {
+        let loads = read_loads(self.path.as_str())?;
+        let total_diff =
+            loads[0].total().saturating_sub(self.last_loads[0].total());
+
+        self.usage = Usage::between(&self.last_loads[0], &loads[0]);
+        self.cores = loads
+            .iter()
+            .skip(1)
+            .zip(self.last_loads.iter().skip(1))
+            .map(|(new, old)| Usage::between(old, new).total)
+            .collect();
+        self.last_loads = loads;
+
+        (self.freqs, self.max_freq) = read_freqs();
+        self.loadavg = read_loadavg().unwrap_or_default();
+
+        if ["top", "top_percentage"].into_iter().any(|name| {
+            self.format.uses(name) || self.common.thresholds.uses(name)
+        }) {
+            let times = read_proc_times();
+            self.top = times
+                .iter()
+                .filter_map(|(pid, (comm, time))| {
+                    let (_, last) = self.proc_times.get(pid)?;
+                    Some((comm, time.saturating_sub(*last)))
+                })
+                .max_by_key(|(_, diff)| *diff)
+                .map(|(comm, diff)| {
+                    (
+                        comm.clone(),
+                        if total_diff == 0 {
+                            0.0
+                        } else {
+                            diff as f64 / total_diff as f64 * 100.0
+                        },
+                    )
+                });
+            self.proc_times = times;
+        }
+
+        self.common.history.push(self.usage.total);
+        Ok(())
+    }
+
     fn draw(
         &mut self,
         cr: &Rc<cairo::Context>,
         height: i32,
         paused: Arc<Mutex<bool>>,
+        update: bool,
     ) -> Result<PanelDrawInfo> {
-        let load = read_current_load(self.path.as_str())?;
-
-        let diff = load.total - self.last_load.total;
-
-        let percentage = (diff - (load.idle - self.last_load.idle)) as f64
-            / diff as f64
-            * 100.0;
+        if update {
+            self.update()?;
+        }
 
-        let text = self
-            .format
-            .replace("%percentage%", format!("{percentage:.0}").as_str())
-            .replace(
-                "%ramp%",
-                self.ramp.choose(percentage, 0.0, 100.0).as_str(),
-            );
+        let percentage = self.usage.total;
+        let (attrs, highlight) = self.common.color_ramps.apply(
+            percentage,
+            0.0,
+            100.0,
+            &self.attrs,
+            self.highlight.as_ref(),
+        );
+        let (format, attrs, highlight) = self.common.thresholds.style(
+            percentage,
+            &self.format,
+            &attrs,
+            highlight.as_ref(),
+        );
 
-        self.last_load = load;
+        let text = format.render(|name| match name {
+            "percentage" => Some(Var::float(percentage, 0)),
+            "busy" => Some(Var::float(self.usage.busy, 0)),
+            "user" => Some(Var::float(self.usage.user, 0)),
+            "system" => Some(Var::float(self.usage.system, 0)),
+            "iowait" => Some(Var::float(self.usage.iowait, 0)),
+            "ramp" => Some(self.ramp.choose(percentage, 0.0, 100.0).into()),
+            "graph" => Some(self.common.history.var()),
+            "cores" => Some(
+                self.cores
+                    .iter()
+                    .map(|core| {
+                        BARS[((core / 100.0 * BARS.len() as f64) as usize)
+                            .min(BARS.len() - 1)]
+                    })
+                    .collect::<String>()
+                    .into(),
+            ),
+            "freq" => Some(
+                (self.freqs.iter().sum::<u64>()
+                    / (self.freqs.len() as u64).max(1))
+                .into(),
+            ),
+            "max_freq" => Some(self.max_freq?.into()),
+            "load1" => Some(Var::float(self.loadavg[0], 2)),
+            "load5" => Some(Var::float(self.loadavg[1], 2)),
+            "load15" => Some(Var::float(self.loadavg[2], 2)),
+            "top" => Some(
+                glib::markup_escape_text(self.top.as_ref()?.0.as_str())
+                    .as_str()
+                    .into(),
+            ),
+            "top_percentage" => Some(Var::float(self.top.as_ref()?.1, 0)),
+            name => {
+                if let Some(core) = name.strip_prefix("core") {
+                    Some(Var::float(
+                        *self.cores.get(core.parse::<usize>().ok()?)?,
+                        0,
+                    ))
+                } else if let Some(core) = name.strip_prefix("freq") {
+                    Some((*self.freqs.get(core.parse::<usize>().ok()?)?).into())
+                } else {
+                    None
+                }
+            }
+        });
 
         self.common.draw(
             cr,
             text.as_str(),
-            &self.attrs,
+            &attrs,
             self.common.dependence,
-            self.highlight.clone(),
+            highlight,
             self.common.images.clone(),
             height,
             ShowHide::Default(paused, self.waker.clone()),
@@ -101,14 +211,41 @@ impl PanelConfig for Cpu {
     /// - `format`: the format string
     ///   - type: String
     ///   - default: `CPU: %percentage%%`
-    ///   - formatting options: `%percentage%`
+    ///   - formatting options:
+    ///     - `%percentage%`: total CPU usage, the time spent running out of the
+    ///       time spent running or idle
+    ///     - `%busy%`: the percentage of all time that the CPU wasn't idle or
+    ///       waiting for I/O, including time spent handling interrupts
+    ///     - `%user%`, `%system%`, `%iowait%`: the percentage of time spent in
+    ///       user mode (including niced processes), kernel mode (including
+    ///       interrupts), and waiting for I/O
+    ///     - `%core0%`, `%core1%`, ...: the usage of each core
+    ///     - `%cores%`: the usage of each core as a compact bar graph
+    ///     - `%freq%`: the average current frequency of all cores in MHz
+    ///     - `%freq0%`, `%freq1%`, ...: the current frequency of each core in
+    ///       MHz
+    ///     - `%max_freq%`: the maximum frequency of the CPU in MHz
+    ///     - `%load1%`, `%load5%`, `%load15%`: the load averages from
+    ///       `/proc/loadavg`
+    ///     - `%top%`, `%top_percentage%`: the name and CPU usage of the process
+    ///       using the most CPU time. These are only computed if they are used.
+    ///     - `%ramp%`, `%graph%`
+    ///
+    ///     See [`Template`] for the full syntax.
     /// - `attrs`: A string specifying the attrs for the panel. See
     ///   [`Attrs::parse`] for details.
     /// - `highlight`: A string specifying the highlight for the panel. See
     ///   [`Highlight::parse`] for details.
     /// - `ramp`: A string specifying the ramp to show CPU usage. See
     ///   [`Ramp::parse`] for details.
-    /// - See [`PanelCommon::parse_common`].
+    /// - `ramp_fg`, `ramp_bg`, `ramp_highlight`: Ramps whose colors follow CPU
+    ///   usage. See [`ColorRamps::parse`][crate::ColorRamps::parse] for
+    ///   details.
+    /// - `graph`, `history_length`, `history_interval`: Control `%graph%`,
+    ///   which shows recent CPU usage. See
+    ///   [`History::parse`][crate::History::parse] for details.
+    /// - See [`PanelCommon::parse_common`]. Thresholds apply to the CPU usage
+    ///   percentage.
     fn parse(
         name: &'static str,
         table: &mut HashMap<String, config::Value>,
@@ -121,17 +258,18 @@ impl PanelConfig for Cpu {
             builder.interval(Duration::from_secs(interval));
         }
         if let Some(path) = remove_string_from_config("path", table) {
-            builder.last_load(read_current_load(path.as_str())?);
+            builder.last_loads(read_loads(path.as_str())?);
             builder.path(path);
         } else {
-            builder.last_load(read_current_load("/proc/stat")?);
+            builder.last_loads(read_loads("/proc/stat")?);
         }
         let common = PanelCommon::parse_common(table)?;
-        let format = PanelCommon::parse_format(table, "", "CPU: %percentage%%");
+        let format =
+            PanelCommon::parse_template(table, "", "CPU: %percentage%%")?;
         let attr = PanelCommon::parse_attr(table, "");
         let ramp = PanelCommon::parse_ramp(table, "");
         builder.common(common);
-        builder.format(format.leak());
+        builder.format(format);
         builder.attrs(attr);
         builder.highlight(PanelCommon::parse_highlight(table, ""));
         builder.ramp(ramp);
@@ -153,40 +291,193 @@ impl PanelConfig for Cpu {
 
         let paused = Arc::new(Mutex::new(false));
 
-        let stream = ManagedIntervalStream::builder()
-            .duration(self.interval)
-            .paused(paused.clone())
-            .build()?
-            .map(move |_| self.draw(&cr, height, paused.clone()));
+        let stream = self
+            .common
+            .thresholds
+            .blink(
+                ManagedIntervalStream::builder()
+                    .duration(self.interval)
+                    .paused(paused.clone())
+                    .build()?,
+                paused.clone(),
+                self.waker.clone(),
+            )
+            .map(move |update| {
+                self.draw(&cr, height, paused.clone(), update.is_some())
+            });
 
         Ok((Box::pin(stream), None))
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 struct Load {
+    user: u64,
+    nice: u64,
+    system: u64,
     idle: u64,
-    total: u64,
+    iowait: u64,
+    irq: u64,
+    softirq: u64,
+    steal: u64,
 }
 
-fn read_current_load(path: &str) -> Result<Load> {
+impl Load {
+    const fn total(&self) -> u64 {
+        self.user
+            + self.nice
+            + self.system
+            + self.idle
+            + self.iowait
+            + self.irq
+            + self.softirq
+            + self.steal
+    }
+}
+
+/// CPU usage over an interval, as percentages of the total time.
+#[derive(Debug, Clone, Copy, Default)]
+struct Usage {
+    /// Time spent running (including steal time) out of the time spent
+    /// running or idle. Time spent waiting for I/O or handling interrupts
+    /// counts toward neither.
+    total: f64,
+    /// Time spent doing anything other than idling or waiting for I/O.
+    busy: f64,
+    user: f64,
+    system: f64,
+    iowait: f64,
+}
+
+impl Usage {
+    fn between(old: &Load, new: &Load) -> Self {
+        let diff = new.total().saturating_sub(old.total());
+        if diff == 0 {
+            return Self::default();
+        }
+        let percent = |new: u64, old: u64| {
+            new.saturating_sub(old) as f64 / diff as f64 * 100.0
+        };
+        let running =
+            |load: &Load| load.user + load.nice + load.system + load.steal;
+        let (running, idle) = (
+            running(new).saturating_sub(running(old)),
+            new.idle.saturating_sub(old.idle),
+        );
+        Self {
+            total: if running + idle == 0 {
+                0.0
+            } else {
+                running as f64 / (running + idle) as f64 * 100.0
+            },
+            busy: 100.0 - percent(new.idle + new.iowait, old.idle + old.iowait),
+            user: percent(new.user + new.nice, old.user + old.nice),
+            system: percent(
+                new.system + new.irq + new.softirq,
+                old.system + old.irq + old.softirq,
+            ),
+            iowait: percent(new.iowait, old.iowait),
+        }
+    }
+}
+
+/// Reads the aggregate load followed by the load of each core.
+fn read_loads(path: &str) -> Result<Vec<Load>> {
     let mut stat = String::new();
     File::open(path)?.read_to_string(&mut stat)?;
 
-    let (_, [user, nice, system, idle, steal]) = REGEX
-        .captures(stat.as_str())
-        .ok_or_else(|| {
-            anyhow!("Failed to read CPU information from {:?}", path)
-        })?
-        .extract();
+    let loads = stat
+        .lines()
+        .filter(|line| line.starts_with("cpu"))
+        .map(|line| {
+            let mut fields = line
+                .split_whitespace()
+                .skip(1)
+                .map(|field| field.parse::<u64>().unwrap_or_default());
+            let mut next = || fields.next().unwrap_or_default();
+            Load {
+                user: next(),
+                nice: next(),
+                system: next(),
+                idle: next(),
+                iowait: next(),
+                irq: next(),
+                softirq: next(),
+                steal: next(),
+            }
+        })
+        .collect::<Vec<_>>();
 
-    let user = user.parse::<u64>()?;
-    let nice = nice.parse::<u64>()?;
-    let system = system.parse::<u64>()?;
-    let idle = idle.parse::<u64>()?;
-    let steal = steal.parse::<u64>()?;
+    if !stat.starts_with("cpu ") || loads.is_empty() {
+        return Err(anyhow!("Failed to read CPU information from {:?}", path));
+    }
+
+    Ok(loads)
+}
+
+/// Reads the current frequency of each core and the highest maximum
+/// frequency, in MHz.
+fn read_freqs() -> (Vec<u64>, Option<u64>) {
+    let read = |path: PathBuf| {
+        fs::read_to_string(path).ok()?.trim().parse::<u64>().ok()
+    };
+
+    let Ok(entries) = fs::read_dir("/sys/devices/system/cpu") else {
+        return (Vec::new(), None);
+    };
+    let mut cores = entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let id = entry
+                .file_name()
+                .to_str()?
+                .strip_prefix("cpu")?
+                .parse::<usize>()
+                .ok()?;
+            Some((id, entry.path().join("cpufreq")))
+        })
+        .collect::<Vec<_>>();
+    cores.sort_unstable_by_key(|(id, _)| *id);
 
-    let total = user + nice + system + idle + steal;
+    let freqs = cores
+        .iter()
+        .filter_map(|(_, path)| read(path.join("scaling_cur_freq")))
+        .map(|khz| khz / 1000)
+        .collect();
+    let max = cores
+        .iter()
+        .filter_map(|(_, path)| read(path.join("cpuinfo_max_freq")))
+        .max()
+        .map(|khz| khz / 1000);
+
+    (freqs, max)
+}
+
+fn read_loadavg() -> Option<[f64; 3]> {
+    let loadavg = fs::read_to_string("/proc/loadavg").ok()?;
+    let mut fields = loadavg
+        .split_whitespace()
+        .map(|field| field.parse::<f64>().ok());
+    Some([fields.next()??, fields.next()??, fields.next()??])
+}
 
-    Ok(Load { idle, total })
+/// Reads the name and total CPU time (user and system) of every process.
+fn read_proc_times() -> HashMap<i32, (String, u64)> {
+    let Ok(entries) = fs::read_dir("/proc") else {
+        return HashMap::new();
+    };
+    entries
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            let pid = entry.file_name().to_str()?.parse::<i32>().ok()?;
+            let stat = fs::read_to_string(entry.path().join("stat")).ok()?;
+            // the command name can contain spaces and parentheses
+            let (start, end) = (stat.find('(')?, stat.rfind(')')?);
+            let comm = stat.get(start + 1..end)?.to_string();
+            let mut fields = stat.get(end + 1..)?.split_whitespace().skip(11);
+            let utime = fields.next()?.parse::<u64>().ok()?;
+            let stime = fields.next()?.parse::<u64>().ok()?;
+            Some((pid, (comm, utime + stime)))
+        })
+        .collect()
 }
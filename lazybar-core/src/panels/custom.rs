@@ -16,7 +16,7 @@ use tokio::time::{Interval, interval};
 use tokio_stream::{Stream, StreamExt};
 
 use crate::{
-    Attrs, Highlight, PanelConfig, PanelRunResult,
+    Attrs, Highlight, PanelConfig, PanelRunResult, Template,
     bar::PanelDrawInfo,
     common::{PanelCommon, ShowHide},
     remove_string_from_config, remove_uint_from_config,
@@ -35,7 +35,7 @@ pub struct Custom {
     interval: Option<Duration>,
     #[builder(default)]
     waker: Arc<AtomicWaker>,
-    format: &'static str,
+    format: Template,
     attrs: Attrs,
     #[builder(default, setter(strip_option))]
     highlight: Option<Highlight>,
@@ -50,16 +50,20 @@ impl Custom {
         paused: Arc<Mutex<bool>>,
     ) -> Result<PanelDrawInfo> {
         let output = self.command.output()?;
-        let text = self
-            .format
-            .replace(
-                "%stdout%",
-                String::from_utf8_lossy(output.stdout.as_slice()).as_ref(),
-            )
-            .replace(
-                "%stderr%",
-                String::from_utf8_lossy(output.stderr.as_slice()).as_ref(),
-            );
+        let text = self.format.render(|name| match name {
+            "stdout" => Some(
+                String::from_utf8_lossy(output.stdout.as_slice())
+                    .into_owned()
+                    .into(),
+            ),
+            "stderr" => Some(
+                String::from_utf8_lossy(output.stderr.as_slice())
+                    .into_owned()
+                    .into(),
+            ),
+            "status" => output.status.code().map(Into::into),
+            _ => None,
+        });
 
         self.common.draw(
             cr,
@@ -90,7 +94,8 @@ impl PanelConfig for Custom {
     /// - `format`: the format string
     ///   - type: String
     ///   - default: `%stdout%`
-    ///   - formatting options: `%stdout%`, `%stderr%`
+    ///   - formatting options: `%stdout%`, `%stderr%`, `%status%` (the exit
+    ///     code). See [`Template`] for the full syntax.
     /// - `attrs`: A string specifying the attrs for the panel. See
     ///   [`Attrs::parse`] for details.
     /// - `highlight`: A string specifying the highlight for the panel. See
@@ -109,7 +114,7 @@ impl PanelConfig for Custom {
             remove_uint_from_config("interval", table).map(Duration::from_secs);
 
         let common = PanelCommon::parse_common(table)?;
-        let format = PanelCommon::parse_format(table, "", "%stdout%");
+        let format = PanelCommon::parse_template(table, "", "%stdout%")?;
         let attrs = PanelCommon::parse_attr(table, "");
         let highlight = PanelCommon::parse_highlight(table, "");
 
@@ -118,7 +123,7 @@ impl PanelConfig for Custom {
             .interval(interval)
             .name(name)
             .common(common)
-            .format(format.leak())
+            .format(format)
             .attrs(attrs)
             .highlight(highlight)
             .build()?)
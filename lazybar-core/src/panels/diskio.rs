@@ -0,0 +1,399 @@
+use std::{
+    collections::HashMap,
+    fs,
+    path::Path,
+    rc::Rc,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use derive_builder::Builder;
+use futures::task::AtomicWaker;
+use tokio_stream::StreamExt;
+
+use crate::{
+    Attrs, Highlight, ManagedIntervalStream, PanelConfig, PanelRunResult, Ramp,
+    Template,
+    bar::PanelDrawInfo,
+    common::{PanelCommon, ShowHide},
+    glob_match, remove_array_from_config, remove_string_from_config,
+    remove_uint_from_config,
+    template::Var,
+};
+
+const DISKSTATS: &str = "/proc/diskstats";
+
+/// The size of a sector as reported by `/proc/diskstats`, regardless of the
+/// device's actual sector size.
+const SECTOR_SIZE: u64 = 512;
+
+/// Virtual devices that are skipped unless `exclude` is set. Device mapper
+/// devices are skipped because their I/O is also counted on the disks
+/// beneath them.
+const DEFAULT_EXCLUDE: [&str; 4] = ["loop*", "ram*", "zram*", "dm-*"];
+
+/// Displays disk throughput, IOPS, and utilization based on
+/// `/proc/diskstats`
+#[derive(Builder, Debug, Clone)]
+#[builder_struct_attr(allow(missing_docs))]
+#[builder_impl_attr(allow(missing_docs))]
+pub struct DiskIo {
+    name: &'static str,
+    #[builder(default = "Duration::from_secs(5)")]
+    interval: Duration,
+    #[builder(default)]
+    waker: Arc<AtomicWaker>,
+    #[builder(default = r#"vec![String::from("*")]"#)]
+    devices: Vec<String>,
+    #[builder(default = r#"DEFAULT_EXCLUDE.map(String::from).to_vec()"#)]
+    exclude: Vec<String>,
+    #[builder(default)]
+    last: Option<(HashMap<String, Counters>, Instant)>,
+    #[builder(default)]
+    rates: Vec<Rates>,
+    #[builder(default = r#"String::from(" ")"#)]
+    separator: String,
+    format: Template,
+    format_device: Template,
+    attrs: Attrs,
+    #[builder(default, setter(strip_option))]
+    highlight: Option<Highlight>,
+    ramp: Ramp,
+    common: PanelCommon,
+}
+
+impl DiskIo {
+    fn update(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let counters = read_diskstats()?
+            .into_iter()
+            .filter(|(name, _)| self.includes(name))
+            .collect::<HashMap<_, _>>();
+
+        if let Some((last, last_time)) = &self.last {
+            let elapsed = now.duration_since(*last_time).as_secs_f64();
+            if elapsed > 0.0 {
+                let mut rates = counters
+                    .iter()
+                    .filter_map(|(name, new)| {
+                        Some(Rates::between(
+                            name.clone(),
+                            last.get(name)?,
+                            new,
+                            elapsed,
+                        ))
+                    })
+                    .collect::<Vec<_>>();
+                rates.sort_unstable_by(|a, b| a.name.cmp(&b.name));
+                self.rates = rates;
+                self.common.history.push(self.total().util);
+            }
+        }
+        self.last = Some((counters, now));
+
+        Ok(())
+    }
+
+    fn includes(&self, name: &str) -> bool {
+        // partitions are counted as part of their disk
+        Path::new("/sys/block").join(name).exists()
+            && self.devices.iter().any(|glob| glob_match(glob, name))
+            && !self.exclude.iter().any(|glob| glob_match(glob, name))
+    }
+
+    /// Sums the rates of every device. Utilization is that of the busiest
+    /// device, since the devices work in parallel.
+    fn total(&self) -> Rates {
+        self.rates
+            .iter()
+            .fold(Rates::default(), |total, rates| Rates {
+                name: total.name,
+                read: total.read + rates.read,
+                write: total.write + rates.write,
+                read_iops: total.read_iops + rates.read_iops,
+                write_iops: total.write_iops + rates.write_iops,
+                util: total.util.max(rates.util),
+            })
+    }
+
+    fn draw(
+        &mut self,
+        cr: &Rc<cairo::Context>,
+        height: i32,
+        paused: Arc<Mutex<bool>>,
+        update: bool,
+    ) -> Result<PanelDrawInfo> {
+        if update {
+            self.update()?;
+        }
+
+        let total = self.total();
+        let (attrs, highlight) = self.common.color_ramps.apply(
+            total.util,
+            0.0,
+            100.0,
+            &self.attrs,
+            self.highlight.as_ref(),
+        );
+        let (format, attrs, highlight) = self.common.thresholds.style(
+            total.util,
+            &self.format,
+            &attrs,
+            highlight.as_ref(),
+        );
+
+        let text = format.render(|name| match name {
+            "devices" => Some(
+                self.rates
+                    .iter()
+                    .map(|rates| {
+                        self.format_device
+                            .render(|name| rates.var(name, &self.ramp))
+                    })
+                    .filter(|segment| !segment.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(self.separator.as_str())
+                    .into(),
+            ),
+            "count" => Some(self.rates.len().into()),
+            "graph" => Some(self.common.history.var()),
+            name => total.var(name, &self.ramp),
+        });
+
+        self.common.draw(
+            cr,
+            text.as_str(),
+            &attrs,
+            self.common.dependence,
+            highlight,
+            self.common.images.clone(),
+            height,
+            ShowHide::Default(paused, self.waker.clone()),
+            format!("{self:?}"),
+        )
+    }
+}
+
+#[async_trait(?Send)]
+impl PanelConfig for DiskIo {
+    /// Parses an instance of the panel from the global [`Config`]
+    ///
+    /// Configuration options:
+    /// - `interval`: how long to wait in seconds between each check
+    ///   - type: u64
+    ///   - default: 5
+    /// - `devices`: globs matching the disks to include, e.g. `["nvme*"]`.
+    ///   Partitions are always counted as part of their disk.
+    ///   - type: Vec<String>
+    ///   - default: `["*"]`
+    /// - `exclude`: globs matching disks to skip
+    ///   - type: Vec<String>
+    ///   - default: `["loop*", "ram*", "zram*", "dm-*"]`
+    /// - `separator`: the text between devices in `%devices%`
+    ///   - type: String
+    ///   - default: " "
+    /// - `format`: the format string
+    ///   - type: String
+    ///   - default: `IO: {read_rate|human}/s {write_rate|human}/s`
+    ///   - formatting options: See [`Template`] for the full syntax.
+    ///     - `%read_rate%`, `%write_rate%`: the number of bytes read and
+    ///       written per second across all devices
+    ///     - `%rate%`: the sum of the two
+    ///     - `%read_iops%`, `%write_iops%`, `%iops%`: the number of completed
+    ///       read and write operations per second
+    ///     - `%util%`: the percentage of time the busiest device spent doing
+    ///       I/O
+    ///     - `%count%`: the number of devices
+    ///     - `%devices%`: each device formatted with `format_device`
+    ///     - `%ramp%`: `ramp` chosen by `%util%`
+    ///     - `%graph%`: recent values of `%util%`
+    /// - `format_device`: the format string for each device in `%devices%`
+    ///   - type: String
+    ///   - default: `%device%: %util%%`
+    ///   - formatting options: the same as `format` for a single device, plus
+    ///     `%device%`, the name of the device. `%count%`, `%devices%`, and
+    ///     `%graph%` are unavailable.
+    /// - `attrs`: A string specifying the attrs for the panel. See
+    ///   [`Attrs::parse`] for details.
+    /// - `highlight`: A string specifying the highlight for the panel. See
+    ///   [`Highlight::parse`] for details.
+    /// - `ramp`: A string specifying the ramp to show utilization. See
+    ///   [`Ramp::parse`] for details.
+    /// - `ramp_fg`, `ramp_bg`, `ramp_highlight`: Ramps whose colors follow
+    ///   utilization. See [`ColorRamps::parse`][crate::ColorRamps::parse] for
+    ///   details.
+    /// - `graph`, `history_length`, `history_interval`: Control `%graph%`. See
+    ///   [`History::parse`][crate::History::parse] for details.
+    /// - See [`PanelCommon::parse_common`]. Thresholds apply to `%util%`.
+    fn parse(
+        name: &'static str,
+        table: &mut HashMap<String, config::Value>,
+        _global: &config::Config,
+    ) -> Result<Self> {
+        let mut builder = DiskIoBuilder::default();
+
+        builder.name(name);
+        if let Some(interval) = remove_uint_from_config("interval", table) {
+            builder.interval(Duration::from_secs(interval));
+        }
+        let globs = |key: &str, table: &mut HashMap<String, config::Value>| {
+            remove_array_from_config(key, table).map(|v| {
+                v.into_iter()
+                    .filter_map(|v| v.into_string().ok())
+                    .collect::<Vec<_>>()
+            })
+        };
+        if let Some(devices) = globs("devices", table) {
+            builder.devices(devices);
+        }
+        if let Some(exclude) = globs("exclude", table) {
+            builder.exclude(exclude);
+        }
+        if let Some(separator) = remove_string_from_config("separator", table) {
+            builder.separator(separator);
+        }
+
+        let common = PanelCommon::parse_common(table)?;
+        let format = PanelCommon::parse_template(
+            table,
+            "",
+            "IO: {read_rate|human}/s {write_rate|human}/s",
+        )?;
+        let format_device =
+            PanelCommon::parse_template(table, "_device", "%device%: %util%%")?;
+        let attrs = PanelCommon::parse_attr(table, "");
+        let ramp = PanelCommon::parse_ramp(table, "");
+        builder.common(common);
+        builder.format(format);
+        builder.format_device(format_device);
+        builder.attrs(attrs);
+        builder.highlight(PanelCommon::parse_highlight(table, ""));
+        builder.ramp(ramp);
+
+        Ok(builder.build()?)
+    }
+
+    fn props(&self) -> (&'static str, bool) {
+        (self.name, self.common.visible)
+    }
+
+    async fn run(
+        mut self: Box<Self>,
+        cr: Rc<cairo::Context>,
+        global_attrs: Attrs,
+        height: i32,
+    ) -> PanelRunResult {
+        self.attrs.apply_to(&global_attrs);
+        self.update()?;
+
+        let paused = Arc::new(Mutex::new(false));
+
+        let stream = self
+            .common
+            .thresholds
+            .blink(
+                ManagedIntervalStream::builder()
+                    .duration(self.interval)
+                    .paused(paused.clone())
+                    .waker(self.waker.clone())
+                    .build()?,
+                paused.clone(),
+                self.waker.clone(),
+            )
+            .map(move |update| {
+                self.draw(&cr, height, paused.clone(), update.is_some())
+            });
+
+        Ok((Box::pin(stream), None))
+    }
+}
+
+/// The cumulative counters of a device from `/proc/diskstats`.
+#[derive(Debug, Clone, Copy, Default)]
+struct Counters {
+    reads: u64,
+    read_sectors: u64,
+    writes: u64,
+    write_sectors: u64,
+    io_ticks: u64,
+}
+
+/// The activity of a device (or several) over an interval.
+#[derive(Debug, Clone, Default)]
+struct Rates {
+    name: String,
+    read: f64,
+    write: f64,
+    read_iops: f64,
+    write_iops: f64,
+    util: f64,
+}
+
+impl Rates {
+    fn between(
+        name: String,
+        old: &Counters,
+        new: &Counters,
+        elapsed: f64,
+    ) -> Self {
+        let rate =
+            |new: u64, old: u64| new.saturating_sub(old) as f64 / elapsed;
+        Self {
+            name,
+            read: rate(new.read_sectors, old.read_sectors) * SECTOR_SIZE as f64,
+            write: rate(new.write_sectors, old.write_sectors)
+                * SECTOR_SIZE as f64,
+            read_iops: rate(new.reads, old.reads),
+            write_iops: rate(new.writes, old.writes),
+            // io_ticks is in milliseconds
+            util: (rate(new.io_ticks, old.io_ticks) / 10.0).min(100.0),
+        }
+    }
+
+    fn var(&self, name: &str, ramp: &Ramp) -> Option<Var> {
+        match name {
+            "device" if !self.name.is_empty() => {
+                Some(self.name.as_str().into())
+            }
+            "read_rate" => Some(Var::float(self.read, 0)),
+            "write_rate" => Some(Var::float(self.write, 0)),
+            "rate" => Some(Var::float(self.read + self.write, 0)),
+            "read_iops" => Some(Var::float(self.read_iops, 0)),
+            "write_iops" => Some(Var::float(self.write_iops, 0)),
+            "iops" => Some(Var::float(self.read_iops + self.write_iops, 0)),
+            "util" => Some(Var::float(self.util, 0)),
+            "ramp" => Some(ramp.choose(self.util, 0.0, 100.0).into()),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the counters of every device in `/proc/diskstats`, keyed by name.
+fn read_diskstats() -> Result<HashMap<String, Counters>> {
+    let stats = fs::read_to_string(DISKSTATS)
+        .map_err(|e| anyhow!("Failed to read {DISKSTATS}: {e}"))?;
+
+    Ok(stats
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace().skip(2);
+            // block device names in sysfs use `!` in place of `/`
+            let name = fields.next()?.replace('/', "!");
+            let fields = fields
+                .map(|field| field.parse::<u64>().ok())
+                .collect::<Option<Vec<_>>>()?;
+            Some((
+                name,
+                Counters {
+                    reads: *fields.first()?,
+                    read_sectors: *fields.get(2)?,
+                    writes: *fields.get(4)?,
+                    write_sectors: *fields.get(6)?,
+                    io_ticks: *fields.get(9)?,
+                },
+            ))
+        })
+        .collect())
+}
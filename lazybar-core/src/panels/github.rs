@@ -25,7 +25,7 @@ use tokio::{
 use tokio_stream::Stream;
 
 use crate::{
-    Highlight, PanelConfig, PanelStream,
+    Highlight, PanelConfig, PanelStream, Template,
     attrs::Attrs,
     bar::{Event, PanelDrawInfo},
     common::{PanelCommon, ShowHide},
@@ -51,7 +51,7 @@ pub struct Github {
     include: bool,
     #[builder(default = "true")]
     show_zero: bool,
-    format: &'static str,
+    format: Template,
     attrs: Attrs,
     #[builder(default, setter(strip_option))]
     highlight: Option<Highlight>,
@@ -69,7 +69,10 @@ impl Github {
         let mut text = if !self.show_zero && count == 0 {
             String::new()
         } else {
-            self.format.replace("%count%", count.to_string().as_str())
+            self.format.render(|name| match name {
+                "count" => Some(count.into()),
+                _ => None,
+            })
         };
 
         if count == 50 {
@@ -112,7 +115,7 @@ impl PanelConfig for Github {
     /// - `show_zero`: Whether or not the panel is shown when you have zero
     ///   notifications.
     /// - `format`: The formatting option. The only formatting option is
-    ///   `%count%`.
+    ///   `%count%`. See [`Template`] for the full syntax.
     /// - `attrs`: A string specifying the attrs for the panel. See
     ///   [`Attrs::parse`] for details.
     /// - `highlight`: A string specifying the highlight for the panel. See
@@ -156,12 +159,12 @@ impl PanelConfig for Github {
         }
 
         let common = PanelCommon::parse_common(table)?;
-        let format = PanelCommon::parse_format(table, "", "%count%");
+        let format = PanelCommon::parse_template(table, "", "%count%")?;
         let attrs = PanelCommon::parse_attr(table, "");
         let highlight = PanelCommon::parse_highlight(table, "");
 
         builder.common(common);
-        builder.format(format.leak());
+        builder.format(format);
         builder.attrs(attrs);
         builder.highlight(highlight);
 
@@ -13,7 +13,7 @@ use tokio::task::{self, JoinHandle};
 use tokio_stream::StreamExt;
 
 use crate::{
-    Highlight, PanelConfig, PanelRunResult,
+    Highlight, PanelConfig, PanelRunResult, Template,
     attrs::Attrs,
     bar::PanelDrawInfo,
     common::{PanelCommon, ShowHide},
@@ -27,7 +27,7 @@ use crate::{
 pub struct I3Mode {
     name: &'static str,
     show_default: bool,
-    format: &'static str,
+    format: Template,
     attrs: Attrs,
     #[builder(default, setter(strip_option))]
     highlight: Option<Highlight>,
@@ -49,7 +49,12 @@ impl I3Mode {
 
         self.common.draw(
             cr,
-            self.format.replace("%mode%", mode).as_str(),
+            self.format
+                .render(|name| match name {
+                    "mode" => Some(mode.into()),
+                    _ => None,
+                })
+                .as_str(),
             &self.attrs,
             self.common.dependence,
             self.highlight.clone(),
@@ -69,7 +74,7 @@ impl PanelConfig for I3Mode {
     /// - `show_default`: Whether to show the panel when the mode is `default`.
     ///   - default: `false`
     /// - `format`: The formatting option. The only formatting option is
-    ///   `%mode%`.
+    ///   `%mode%`. See [`Template`] for the full syntax.
     /// - `attrs`: A string specifying the attrs for the panel. See
     ///   [`Attrs::parse`] for details.
     /// - `highlight`: A string specifying the highlight for the panel. See
@@ -92,12 +97,12 @@ impl PanelConfig for I3Mode {
         );
 
         let common = PanelCommon::parse_common(table)?;
-        let format = PanelCommon::parse_format(table, "", "%mode%");
+        let format = PanelCommon::parse_template(table, "", "%mode%")?;
         let attrs = PanelCommon::parse_attr(table, "");
         let highlight = PanelCommon::parse_highlight(table, "");
 
         builder.common(common);
-        builder.format(format.leak());
+        builder.format(format);
         builder.attrs(attrs);
         builder.highlight(highlight);
 
@@ -23,7 +23,7 @@ use tokio::task::{self, JoinHandle};
 use tokio_stream::{Stream, StreamExt};
 
 use crate::{
-    Attrs, Highlight, PanelConfig, PanelRunResult,
+    Attrs, Highlight, PanelConfig, PanelRunResult, Template,
     bar::PanelDrawInfo,
     common::{PanelCommon, ShowHide},
     remove_string_from_config,
@@ -39,7 +39,7 @@ pub struct Inotify {
     path: String,
     #[builder(default)]
     waker: Arc<AtomicWaker>,
-    format: &'static str,
+    format: Template,
     attrs: Attrs,
     #[builder(default, setter(strip_option))]
     highlight: Option<Highlight>,
@@ -57,9 +57,10 @@ impl Inotify {
         let mut buf = String::new();
         file.lock().unwrap().read_to_string(&mut buf)?;
         file.lock().unwrap().rewind()?;
-        let text = self
-            .format
-            .replace("%file%", buf.lines().next().unwrap_or(""));
+        let text = self.format.render(|name| match name {
+            "file" => Some(buf.lines().next().unwrap_or("").into()),
+            _ => None,
+        });
 
         self.common.draw(
             cr,
@@ -86,7 +87,7 @@ impl PanelConfig for Inotify {
     /// - `format`: the format string
     ///   - type: String
     ///   - default: `%file%`
-    ///   - formatting options: `%file%`
+    ///   - formatting options: `%file%`. See [`Template`] for the full syntax.
     /// - `attrs`: A string specifying the attrs for the panel. See
     ///   [`Attrs::parse`] for details.
     /// - `highlight`: A string specifying the highlight for the panel. See
@@ -106,12 +107,12 @@ impl PanelConfig for Inotify {
         }
 
         let common = PanelCommon::parse_common(table)?;
-        let format = PanelCommon::parse_format(table, "", "%file%");
+        let format = PanelCommon::parse_template(table, "", "%file%")?;
         let attrs = PanelCommon::parse_attr(table, "");
         let highlight = PanelCommon::parse_highlight(table, "");
 
         builder.common(common);
-        builder.format(format.leak());
+        builder.format(format);
         builder.attrs(attrs);
         builder.highlight(highlight);
 
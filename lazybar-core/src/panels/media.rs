@@ -0,0 +1,366 @@
+//! Formatting and drawing shared by the media player panels.
+
+use std::{
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use aho_corasick::AhoCorasick;
+use csscolorparser::Color;
+use futures::task::AtomicWaker;
+use pango::{AttrShape, Attribute, Layout, Rectangle};
+use pangocairo::functions::{create_layout, show_layout};
+
+#[cfg(feature = "cursor")]
+use crate::bar::{Cursor, CursorInfo};
+use crate::{
+    Attrs, ButtonIndex, Highlight, IndexCache, bar::PanelDrawInfo,
+    common::PanelCommon, image::Image, inline::OBJECT, overflow::Scroll,
+};
+
+/// Replaces every match of `formatter` in `format` with the result of
+/// `lookup`. Matches for which `lookup` returns `None` are removed.
+pub(crate) fn expand(
+    formatter: &AhoCorasick,
+    format: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> String {
+    let mut text = String::new();
+    formatter.replace_all_with(format, &mut text, |_, content, dst| {
+        if let Some(value) = lookup(content) {
+            dst.push_str(value.as_str());
+        }
+        true
+    });
+    text
+}
+
+/// Finds the byte ranges of each button in the rendered text of `format`.
+/// `%main%` is replaced by a placeholder when it's laid out, so its range is
+/// the placeholder's.
+pub(crate) fn build_index_cache(
+    formatter: &AhoCorasick,
+    format: &str,
+    lookup: impl Fn(&str) -> Option<String>,
+) -> IndexCache {
+    let mut index_cache = Vec::new();
+    if let Ok((_, haystack, _)) = pango::parse_markup(format, '\0') {
+        let mut offset = 0;
+        for mat in formatter.find_iter(haystack.as_str()) {
+            let content = &haystack[mat.range()];
+            let Some(value) = lookup(content) else {
+                offset -= content.len() as isize;
+                continue;
+            };
+            // main is special
+            let length = if content == "%main%" {
+                OBJECT.len_utf8()
+            } else {
+                pango::parse_markup(value.as_str(), '\0')
+                    .map_or_else(|_| value.len(), |l| l.1.len())
+            };
+            index_cache.push(ButtonIndex {
+                name: content.replace('%', ""),
+                start: (mat.start() as isize + offset) as usize,
+                length,
+            });
+            offset += length as isize - content.len() as isize;
+        }
+    }
+    index_cache
+}
+
+/// Finds the name of the button under the point `(x, y)`. `layout` is the
+/// last layout drawn and its x coordinate within the panel.
+pub(crate) fn button_at(
+    layout: &(Layout, f64),
+    index_cache: &IndexCache,
+    x: i16,
+    y: i16,
+) -> Option<String> {
+    let idx = layout
+        .0
+        .xy_to_index(
+            ((f64::from(x) - layout.1) * pango::SCALE as f64) as i32,
+            i32::from(y) * pango::SCALE,
+        )
+        .1 as usize;
+    index_cache
+        .iter()
+        .find(|index| index.start <= idx && idx <= index.start + index.length)
+        .map(|index| index.name.clone())
+}
+
+/// Returns the x coordinate and width in pixels of the button called
+/// `name`.
+pub(crate) fn region_extent(
+    layout: &Layout,
+    index_cache: &IndexCache,
+    name: &str,
+) -> Option<(f64, f64)> {
+    let index = index_cache.iter().find(|index| index.name == name)?;
+    let start = layout.index_to_pos(index.start as i32).x() as f64
+        / pango::SCALE as f64;
+    let end = layout.index_to_pos((index.start + index.length) as i32).x()
+        as f64
+        / pango::SCALE as f64;
+    Some((start, end - start))
+}
+
+/// Returns how far along the button called `name` the x coordinate `x` is,
+/// from 0 to 1.
+#[cfg(feature = "mpd")]
+pub(crate) fn fraction_at(
+    layout: &(Layout, f64),
+    index_cache: &IndexCache,
+    name: &str,
+    x: i16,
+) -> Option<f64> {
+    let (start, width) = region_extent(&layout.0, index_cache, name)?;
+    (width > 0.0)
+        .then(|| ((f64::from(x) - layout.1 - start) / width).clamp(0.0, 1.0))
+}
+
+/// The state needed to draw a media panel once its text is known.
+pub(crate) struct MediaDraw<'a> {
+    pub text: &'a str,
+    pub main: &'a str,
+    pub index_cache: IndexCache,
+    pub last_layout: Rc<Mutex<Option<(Layout, f64)>>>,
+    pub shared_cache: Arc<Mutex<Option<IndexCache>>>,
+    pub attrs: &'a Attrs,
+    pub common: &'a PanelCommon,
+    /// Drawn to the left of the text
+    pub art: Option<Image>,
+    pub progress_bg: &'a Color,
+    /// Whether `%main%` should scroll if it's too wide
+    pub playing: bool,
+    pub paused: Arc<Mutex<bool>>,
+    pub wakers: Vec<Arc<AtomicWaker>>,
+    pub dump: String,
+}
+
+/// The text of a media panel, with `%main%` laid out separately so that it
+/// can be fitted to the panel's maximum width.
+pub(crate) struct MediaLayout {
+    layout: Layout,
+    main: Layout,
+    scroll: Option<Scroll>,
+    /// The x coordinate of `%main%`
+    pub start: f64,
+    /// The visible width of `%main%`
+    pub width: f64,
+}
+
+impl MediaDraw<'_> {
+    /// Lays out the text, fitting `main` to the panel's
+    /// [`Overflow`][crate::Overflow] and reserving space for it in place of
+    /// `%main%`.
+    pub fn layout(&self, cr: &Rc<cairo::Context>) -> MediaLayout {
+        let main = create_layout(cr);
+        main.set_markup(glib::markup_escape_text(self.main).as_str());
+        self.attrs.apply_font(&main);
+        let scroll = self.common.overflow.fit(cr, &main, self.main, self.attrs);
+        let main_width = scroll
+            .as_ref()
+            .map_or(main.pixel_size().0, |scroll| scroll.width);
+
+        let layout = create_layout(cr);
+        layout.set_markup(
+            self.text
+                .replace("%main%", OBJECT.encode_utf8(&mut [0; 4]))
+                .as_str(),
+        );
+        self.attrs.apply_font(&layout);
+        if let Some(index) =
+            self.index_cache.iter().find(|index| index.name == "main")
+        {
+            let (_, logical) = main.extents();
+            let rect = Rectangle::new(
+                0,
+                -main.baseline(),
+                main_width * pango::SCALE,
+                logical.height(),
+            );
+            let list = layout.attributes().unwrap_or_default();
+            let mut attr = Attribute::from(AttrShape::new(&rect, &rect));
+            attr.set_start_index(index.start as u32);
+            attr.set_end_index((index.start + index.length) as u32);
+            list.insert(attr);
+            layout.set_attributes(Some(&list));
+        }
+
+        let (start, width) = region_extent(&layout, &self.index_cache, "main")
+            .unwrap_or_else(|| (0.0, f64::from(layout.pixel_size().0)));
+        MediaLayout {
+            layout,
+            main,
+            scroll,
+            start,
+            width,
+        }
+    }
+
+    /// Builds the draw info. `bar` is the x coordinate and width of the
+    /// progress bar, if it should be shown.
+    pub fn finish(
+        self,
+        layout: MediaLayout,
+        height: i32,
+        bar: Option<(f64, f64)>,
+    ) -> PanelDrawInfo {
+        let MediaLayout {
+            layout,
+            main,
+            scroll,
+            start: main_x,
+            width: main_width,
+        } = layout;
+        let has_main =
+            self.index_cache.iter().any(|index| index.name == "main");
+        let main_y = f64::from(layout.baseline() - main.baseline())
+            / f64::from(pango::SCALE);
+        let animation = scroll
+            .as_ref()
+            .filter(|_| self.playing)
+            .map(Scroll::animation);
+
+        let art = self.art;
+        let art_width = art.as_ref().map_or(0.0, Image::width);
+        let size = layout.pixel_size();
+        let width = size.0 + art_width.ceil() as i32;
+        *self.shared_cache.lock().unwrap() = Some(self.index_cache);
+        *self.last_layout.lock().unwrap() = Some((
+            layout.clone(),
+            self.attrs.bg.as_ref().map_or(0.0, |bg| {
+                bg.get_offset(f64::from(size.1), f64::from(height))
+            }) + art_width,
+        ));
+
+        let attrs = self.attrs.clone();
+        let highlight = bar.filter(|(_, width)| *width > 0.0).map(|bar| {
+            (
+                bar,
+                Highlight::new(
+                    height as f64,
+                    self.progress_bg.clone(),
+                    0.0,
+                    Color {
+                        r: 0.0,
+                        g: 0.0,
+                        b: 0.0,
+                        a: 0.0,
+                    },
+                ),
+            )
+        });
+        let images = self.common.images.clone();
+        let paused = self.paused;
+        let paused_ = paused.clone();
+        let wakers = self.wakers;
+
+        #[cfg(feature = "cursor")]
+        let last_layout = self.last_layout.clone();
+        #[cfg(feature = "cursor")]
+        let index_cache = self.shared_cache.clone();
+
+        let draw_info = PanelDrawInfo::new(
+            (width, height),
+            self.common.dependence,
+            Box::new(move |cr, _| {
+                cr.save()?;
+
+                let offset = if let Some(bg) = &attrs.bg {
+                    bg.draw(cr, width as f64, size.1 as f64, height as f64)?
+                } else {
+                    0.0
+                };
+
+                if let Some(ref art) = art {
+                    cr.save()?;
+                    cr.translate(offset, 0.0);
+                    art.draw(cr)?;
+                    cr.restore()?;
+                }
+                let offset = offset + art_width;
+
+                if let Some(((start, width), ref highlight)) = highlight {
+                    cr.save()?;
+
+                    cr.translate(offset + start, 0.0);
+                    highlight.draw(cr, height as f64, width)?;
+
+                    cr.restore()?;
+                }
+
+                for image in &images {
+                    image.draw(cr)?;
+                }
+
+                cr.translate(offset, f64::from(height - size.1) / 2.0);
+
+                attrs.apply_fg(cr);
+                show_layout(cr, &layout);
+
+                if has_main {
+                    cr.translate(main_x, main_y);
+                    let show = || {
+                        show_layout(cr, &main);
+                        Ok(())
+                    };
+                    match scroll {
+                        Some(ref scroll) => {
+                            cr.rectangle(
+                                0.0,
+                                0.0,
+                                main_width,
+                                f64::from(main.pixel_size().1),
+                            );
+                            cr.clip();
+                            scroll.draw(cr, show)?;
+                        }
+                        None => show()?,
+                    }
+                }
+
+                cr.restore()?;
+                Ok(())
+            }),
+            Some(Box::new(move || {
+                *paused.lock().unwrap() = false;
+                for waker in &wakers {
+                    waker.wake();
+                }
+                Ok(())
+            })),
+            Some(Box::new(move || {
+                *paused_.lock().unwrap() = true;
+                Ok(())
+            })),
+            None,
+            #[cfg(feature = "cursor")]
+            CursorInfo::Dynamic(Box::new(move |event| {
+                Ok(
+                    match (
+                        &*last_layout.lock().unwrap(),
+                        &*index_cache.lock().unwrap(),
+                    ) {
+                        (Some(layout), Some(cache)) => {
+                            match button_at(layout, cache, event.x, event.y) {
+                                Some(name) if name != "main" => Cursor::Click,
+                                _ => Cursor::Default,
+                            }
+                        }
+                        _ => Cursor::Default,
+                    },
+                )
+            })),
+            self.dump,
+        );
+
+        match animation {
+            Some((interval, step)) => draw_info.with_animation(interval, step),
+            None => draw_info,
+        }
+    }
+}
@@ -1,13 +1,12 @@
 use std::{
     collections::HashMap,
-    fs::File,
+    fs::{self, File},
     io::Read,
     rc::Rc,
     sync::{Arc, LazyLock, Mutex},
     time::Duration,
 };
 
-use aho_corasick::AhoCorasick;
 use anyhow::{Result, anyhow};
 use async_trait::async_trait;
 use config::Config;
@@ -18,9 +17,11 @@ use tokio_stream::StreamExt;
 
 use crate::{
     Attrs, Highlight, ManagedIntervalStream, PanelConfig, PanelRunResult,
+    Template,
     bar::PanelDrawInfo,
     common::{PanelCommon, ShowHide},
     remove_string_from_config, remove_uint_from_config,
+    template::Var,
 };
 
 static REGEX: LazyLock<Regex> = LazyLock::new(|| {
@@ -28,7 +29,7 @@ static REGEX: LazyLock<Regex> = LazyLock::new(|| {
 });
 
 /// Displays memory/swap usage based on information from (by default)
-/// `/proc/meminfo`
+/// `/proc/meminfo`, along with memory pressure and compressed memory
 #[derive(Builder, Debug, Clone)]
 #[builder_struct_attr(allow(missing_docs))]
 #[builder_impl_attr(allow(missing_docs))]
@@ -40,8 +41,10 @@ pub struct Memory {
     waker: Arc<AtomicWaker>,
     #[builder(default = r#"String::from("/proc/meminfo")"#)]
     path: String,
-    formatter: AhoCorasick,
-    format: &'static str,
+    #[builder(default = "10")]
+    psi_window: u64,
+    format: Template,
+    format_noswap: Template,
     attrs: Attrs,
     #[builder(default, setter(strip_option))]
     highlight: Option<Highlight>,
@@ -50,10 +53,11 @@ pub struct Memory {
 
 impl Memory {
     fn draw(
-        &self,
+        &mut self,
         cr: &Rc<cairo::Context>,
         height: i32,
         paused: Arc<Mutex<bool>>,
+        update: bool,
     ) -> Result<PanelDrawInfo> {
         let mut meminfo = String::new();
         File::open(self.path.as_str())?.read_to_string(&mut meminfo)?;
@@ -83,148 +87,122 @@ impl Memory {
             .ok_or_else(|| {
                 anyhow!("couldn't find or approximate MemAvailable")
             })?;
-        let mem_used = mem_total - mem_free;
-
-        let percentage_used =
-            (mem_used as f64 / mem_total as f64 * 100.0) as u64;
-
-        let swap_total = *map
-            .get("SwapTotal")
-            .ok_or_else(|| anyhow!("couldn't find SwapTotal"))?;
-        let swap_free = *map
-            .get("SwapFree")
-            .ok_or_else(|| anyhow!("couldn't find SwapFree"))?;
-        let swap_used = swap_total - swap_free;
-
-        let percentage_swap_used =
-            (swap_used as f64 / swap_total as f64 * 100.0) as u64;
-
-        let mut text = String::new();
-        self.formatter.replace_all_with(
-            self.format,
-            &mut text,
-            |_, content, dst| match content {
-                "%gb_used%" => {
-                    dst.push_str(
-                        format!("{:.2}", (mem_used as f64 / 1024.0 / 1024.0))
-                            .as_str(),
-                    );
-                    true
-                }
-                "%gb_free%" => {
-                    dst.push_str(
-                        format!("{:.2}", (mem_free as f64 / 1024.0 / 1024.0))
-                            .as_str(),
-                    );
-                    true
-                }
-                "%gb_total%" => {
-                    dst.push_str(
-                        format!("{:.2}", (mem_total as f64 / 1024.0 / 1024.0))
-                            .as_str(),
-                    );
-                    true
-                }
-                "%mb_used%" => {
-                    dst.push_str(
-                        ((mem_used as f64 / 1024.0) as u64)
-                            .to_string()
-                            .as_str(),
-                    );
-                    true
-                }
-                "%mb_free%" => {
-                    dst.push_str(
-                        ((mem_free as f64 / 1024.0) as u64)
-                            .to_string()
-                            .as_str(),
-                    );
-                    true
-                }
-                "%mb_total%" => {
-                    dst.push_str(
-                        ((mem_total as f64 / 1024.0) as u64)
-                            .to_string()
-                            .as_str(),
-                    );
-                    true
-                }
-                "%gb_swap_used%" => {
-                    dst.push_str(
-                        format!("{:.2}", (swap_used as f64 / 1024.0 / 1024.0))
-                            .as_str(),
-                    );
-                    true
-                }
-                "%gb_swap_free%" => {
-                    dst.push_str(
-                        format!("{:.2}", (swap_free as f64 / 1024.0 / 1024.0))
-                            .as_str(),
-                    );
-                    true
-                }
-                "%gb_swap_total%" => {
-                    dst.push_str(
-                        format!("{:.2}", (swap_total as f64 / 1024.0 / 1024.0))
-                            .as_str(),
-                    );
-                    true
-                }
-                "%mb_swap_used%" => {
-                    dst.push_str(
-                        ((swap_used as f64 / 1024.0) as u64)
-                            .to_string()
-                            .as_str(),
-                    );
-                    true
-                }
-                "%mb_swap_free%" => {
-                    dst.push_str(
-                        ((swap_free as f64 / 1024.0) as u64)
-                            .to_string()
-                            .as_str(),
-                    );
-                    true
-                }
-                "%mb_swap_total%" => {
-                    dst.push_str(
-                        ((swap_total as f64 / 1024.0) as u64)
-                            .to_string()
-                            .as_str(),
-                    );
-                    true
-                }
-                "%percentage_used%" => {
-                    dst.push_str(percentage_used.to_string().as_str());
-                    true
-                }
-                "%percentage_free%" => {
-                    dst.push_str((100 - percentage_used).to_string().as_str());
-                    true
-                }
-                "%percentage_swap_used%" => {
-                    dst.push_str(percentage_swap_used.to_string().as_str());
-                    true
-                }
-                "%percentage_swap_free%" => {
-                    dst.push_str(
-                        (100 - percentage_swap_used).to_string().as_str(),
-                    );
-                    true
-                }
-                other => {
-                    dst.push_str(other);
-                    true
-                }
-            },
+        let mem_used = mem_total.saturating_sub(mem_free);
+
+        let percentage_used = percentage(mem_used, mem_total);
+
+        // swapless systems may omit these entirely
+        let swap_total = map.get("SwapTotal").copied().unwrap_or_default();
+        let swap_free = map.get("SwapFree").copied().unwrap_or_default();
+        let swap_used = swap_total.saturating_sub(swap_free);
+        let percentage_swap_used = percentage(swap_used, swap_total);
+
+        if update {
+            self.common.history.push(percentage_used as f64);
+        }
+
+        let template = if swap_total == 0 {
+            &self.format_noswap
+        } else {
+            &self.format
+        };
+        let uses = |names: &[&str]| {
+            names.iter().any(|name| {
+                template.uses(name) || self.common.thresholds.uses(name)
+            })
+        };
+        let psi = if uses(&PSI_VARS) {
+            ["memory", "cpu", "io"]
+                .into_iter()
+                .filter_map(|resource| {
+                    Some((resource, read_pressure(resource, self.psi_window)?))
+                })
+                .collect::<HashMap<_, _>>()
+        } else {
+            HashMap::new()
+        };
+        let zram = if uses(&ZRAM_VARS) { read_zram() } else { None };
+        let top = if uses(&["top", "top_rss"]) {
+            read_top_rss()
+        } else {
+            None
+        };
+
+        let gb = |kb: u64| Var::float(kb as f64 / 1024.0 / 1024.0, 2);
+        let mb = |kb: u64| Var::from((kb as f64 / 1024.0) as u64);
+        let human = |kb: u64| Var::human((kb * 1024) as f64);
+        let ratio = |original: u64, compressed: u64| {
+            (compressed > 0)
+                .then(|| Var::float(original as f64 / compressed as f64, 2))
+        };
+        let (format, attrs, highlight) = self.common.thresholds.style(
+            percentage_used as f64,
+            template,
+            &self.attrs,
+            self.highlight.as_ref(),
         );
+        let text = format.render(|name| match name {
+            "gb_used" => Some(gb(mem_used)),
+            "gb_free" => Some(gb(mem_free)),
+            "gb_total" => Some(gb(mem_total)),
+            "mb_used" => Some(mb(mem_used)),
+            "mb_free" => Some(mb(mem_free)),
+            "mb_total" => Some(mb(mem_total)),
+            "gb_swap_used" => Some(gb(swap_used)),
+            "gb_swap_free" => Some(gb(swap_free)),
+            "gb_swap_total" => Some(gb(swap_total)),
+            "mb_swap_used" => Some(mb(swap_used)),
+            "mb_swap_free" => Some(mb(swap_free)),
+            "mb_swap_total" => Some(mb(swap_total)),
+            "used" => Some((mem_used * 1024).into()),
+            "free" => Some((mem_free * 1024).into()),
+            "total" => Some((mem_total * 1024).into()),
+            "swap_used" => Some((swap_used * 1024).into()),
+            "swap_free" => Some((swap_free * 1024).into()),
+            "swap_total" => Some((swap_total * 1024).into()),
+            "percentage_used" => Some(percentage_used.into()),
+            "percentage_free" => Some((100 - percentage_used).into()),
+            "percentage_swap_used" => Some(percentage_swap_used.into()),
+            "percentage_swap_free" => Some((100 - percentage_swap_used).into()),
+            "human_used" => Some(human(mem_used)),
+            "human_free" => Some(human(mem_free)),
+            "human_total" => Some(human(mem_total)),
+            "human_swap_used" => Some(human(swap_used)),
+            "human_swap_free" => Some(human(swap_free)),
+            "human_swap_total" => Some(human(swap_total)),
+            "zram_original" => Some(zram?.original.into()),
+            "zram_compressed" => Some(zram?.compressed.into()),
+            "zram_used" => Some(zram?.used.into()),
+            "zram_ratio" => ratio(zram?.original, zram?.compressed),
+            "zswap" => Some((map.get("Zswap")? * 1024).into()),
+            "zswapped" => Some((map.get("Zswapped")? * 1024).into()),
+            "zswap_ratio" => ratio(*map.get("Zswapped")?, *map.get("Zswap")?),
+            "top" => Some(
+                glib::markup_escape_text(top.as_ref()?.0.as_str())
+                    .as_str()
+                    .into(),
+            ),
+            "top_rss" => Some((top.as_ref()?.1 * 1024).into()),
+            "graph" => Some(self.common.history.var()),
+            name => {
+                let name = name.strip_prefix("psi_")?;
+                let (resource, kind) =
+                    name.rsplit_once('_').unwrap_or(("memory", name));
+                let pressure = psi.get(resource)?;
+                match kind {
+                    "some" => Some(Var::float(pressure.some, 2)),
+                    "full" => Some(Var::float(pressure.full?, 2)),
+                    _ => None,
+                }
+            }
+        });
 
         self.common.draw(
             cr,
             text.as_str(),
-            &self.attrs,
+            &attrs,
             self.common.dependence,
-            self.highlight.clone(),
+            highlight,
             self.common.images.clone(),
             height,
             ShowHide::Default(paused, self.waker.clone()),
@@ -245,16 +223,48 @@ impl PanelConfig for Memory {
     ///   - default: `/proc/meminfo` - If you're considering changing this, you
     ///     might want to use a different panel like
     ///     [`Inotify`][crate::panels::Inotify]
+    /// - `psi_window`: the window in seconds over which pressure stall
+    ///   percentages are averaged. One of 10, 60, or 300.
+    ///   - type: u64
+    ///   - default: 10
     /// - `format`: the format string
     ///   - type: String
     ///   - default: `RAM: %percentage_used%`
-    ///   - formatting options: `%{gb,mb}_[swap_]{total,used,free}%,
-    ///     %percentage_[swap_]{used,free}%`
+    ///   - formatting options: See [`Template`] for the full syntax.
+    ///     - `%{gb,mb}_[swap_]{total,used,free}%`,
+    ///       `%percentage_[swap_]{used,free}%`
+    ///     - `%[swap_]{total,used,free}%`: sizes in bytes (e.g. `{used|human}`)
+    ///     - `%human_[swap_]{total,used,free}%`: sizes with automatically
+    ///       scaled units, e.g. `1.5 GiB`
+    ///     - `%psi_some%`, `%psi_full%`: the percentage of time in which some
+    ///       or all tasks were stalled waiting for memory, from
+    ///       `/proc/pressure/memory`
+    ///     - `%psi_{cpu,io}_{some,full}%`: the same for CPU and I/O
+    ///     - `%zram_{original,compressed,used}%`: the size of the data stored
+    ///       in all zram devices before and after compression, and the memory
+    ///       used to store it, in bytes
+    ///     - `%zram_ratio%`: the compression ratio of zram
+    ///     - `%zswap%`, `%zswapped%`, `%zswap_ratio%`: the size of the zswap
+    ///       pool and of the data stored in it, in bytes, and their ratio
+    ///     - `%top%`, `%top_rss%`: the name and resident memory in bytes of the
+    ///       process using the most memory
+    ///     - `%graph%`
+    ///
+    ///     Pressure, zram, and process information is only read if it is
+    ///     used.
+    /// - `format_noswap`: the format string used instead of `format` on systems
+    ///   without swap. Swap percentages are 0 on such systems.
+    ///   - type: String
+    ///   - default: the value of `format`
     /// - `attrs`: A string specifying the attrs for the panel. See
     ///   [`Attrs::parse`] for details.
     /// - `highlight`: A string specifying the highlight for the panel. See
     ///   [`Highlight::parse`] for details.
-    /// - See [`PanelCommon::parse_common`].
+    /// - `graph`, `history_length`, `history_interval`: Control `%graph%`,
+    ///   which shows recent values of `%percentage_used%`. See
+    ///   [`History::parse`][crate::History::parse] for details.
+    /// - See [`PanelCommon::parse_common`]. Thresholds apply to
+    ///   `%percentage_used%`.
     fn parse(
         name: &'static str,
         table: &mut HashMap<String, config::Value>,
@@ -269,38 +279,29 @@ impl PanelConfig for Memory {
         if let Some(path) = remove_string_from_config("path", table) {
             builder.path(path);
         }
+        if let Some(psi_window) = remove_uint_from_config("psi_window", table) {
+            if ![10, 60, 300].contains(&psi_window) {
+                return Err(anyhow!(
+                    "psi_window must be 10, 60, or 300, not {psi_window}"
+                ));
+            }
+            builder.psi_window(psi_window);
+        }
 
         let common = PanelCommon::parse_common(table)?;
         let format =
             PanelCommon::parse_format(table, "", "RAM: %percentage_used%");
+        let format_noswap = remove_string_from_config("format_noswap", table)
+            .unwrap_or_else(|| format.clone());
         let attrs = PanelCommon::parse_attr(table, "");
         let highlight = PanelCommon::parse_highlight(table, "");
 
         builder.common(common);
-        builder.format(format.leak());
+        builder.format(Template::parse(format)?);
+        builder.format_noswap(Template::parse(format_noswap)?);
         builder.attrs(attrs);
         builder.highlight(highlight);
 
-        builder.formatter(AhoCorasick::new([
-            "%gb_total%",
-            "%gb_used%",
-            "%gb_free%",
-            "%mb_total%",
-            "%mb_used%",
-            "%mb_free%",
-            "%gb_swap_total%",
-            "%gb_swap_used%",
-            "%gb_swap_free%",
-            "%mb_swap_total%",
-            "%mb_swap_used%",
-            "%mb_swap_free%",
-            "%percentage_used%",
-            "%percentage_free%",
-            "%percentage_swap_used%",
-            "%percentage_swap_free%",
-            "%ramp%",
-        ])?);
-
         Ok(builder.build()?)
     }
 
@@ -318,12 +319,142 @@ impl PanelConfig for Memory {
 
         let paused = Arc::new(Mutex::new(false));
 
-        let stream = ManagedIntervalStream::builder()
-            .duration(self.interval)
-            .paused(paused.clone())
-            .build()?
-            .map(move |_| self.draw(&cr, height, paused.clone()));
+        let stream = self
+            .common
+            .thresholds
+            .blink(
+                ManagedIntervalStream::builder()
+                    .duration(self.interval)
+                    .paused(paused.clone())
+                    .build()?,
+                paused.clone(),
+                self.waker.clone(),
+            )
+            .map(move |update| {
+                self.draw(&cr, height, paused.clone(), update.is_some())
+            });
 
         Ok((Box::pin(stream), None))
     }
 }
+
+const PSI_VARS: [&str; 6] = [
+    "psi_some",
+    "psi_full",
+    "psi_cpu_some",
+    "psi_cpu_full",
+    "psi_io_some",
+    "psi_io_full",
+];
+
+const ZRAM_VARS: [&str; 4] = [
+    "zram_original",
+    "zram_compressed",
+    "zram_used",
+    "zram_ratio",
+];
+
+fn percentage(used: u64, total: u64) -> u64 {
+    if total == 0 {
+        0
+    } else {
+        (used as f64 / total as f64 * 100.0) as u64
+    }
+}
+
+/// Pressure stall percentages for a resource, averaged over some window.
+#[derive(Debug, Clone, Copy)]
+struct Pressure {
+    some: f64,
+    /// Absent on kernels which don't report `full` for the CPU.
+    full: Option<f64>,
+}
+
+/// Reads `/proc/pressure/<resource>`.
+fn read_pressure(resource: &str, window: u64) -> Option<Pressure> {
+    let pressure = fs::read_to_string(format!("/proc/pressure/{resource}"))
+        .map_err(|e| log::debug!("Failed to read {resource} pressure: {e}"))
+        .ok()?;
+    let key = format!("avg{window}=");
+    let avg = |kind: &str| {
+        pressure
+            .lines()
+            .find_map(|line| line.strip_prefix(kind))?
+            .split_whitespace()
+            .find_map(|field| field.strip_prefix(key.as_str()))?
+            .parse::<f64>()
+            .ok()
+    };
+    Some(Pressure {
+        some: avg("some ")?,
+        full: avg("full "),
+    })
+}
+
+/// The combined usage of every zram device, in bytes.
+#[derive(Debug, Clone, Copy, Default)]
+struct Zram {
+    original: u64,
+    compressed: u64,
+    used: u64,
+}
+
+/// Sums the first three fields of `mm_stat` across zram devices, or returns
+/// `None` if there are none.
+fn read_zram() -> Option<Zram> {
+    let devices = fs::read_dir("/sys/block")
+        .ok()?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            if !entry.file_name().to_str()?.starts_with("zram") {
+                return None;
+            }
+            let stat = fs::read_to_string(entry.path().join("mm_stat")).ok()?;
+            let mut fields = stat
+                .split_whitespace()
+                .map(|field| field.parse::<u64>().ok());
+            Some(Zram {
+                original: fields.next()??,
+                compressed: fields.next()??,
+                used: fields.next()??,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    (!devices.is_empty()).then(|| {
+        devices
+            .into_iter()
+            .fold(Zram::default(), |total, zram| Zram {
+                original: total.original + zram.original,
+                compressed: total.compressed + zram.compressed,
+                used: total.used + zram.used,
+            })
+    })
+}
+
+/// Finds the name and resident set size in kB of the process with the
+/// largest resident set.
+fn read_top_rss() -> Option<(String, u64)> {
+    fs::read_dir("/proc")
+        .ok()?
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            entry.file_name().to_str()?.parse::<i32>().ok()?;
+            let status =
+                fs::read_to_string(entry.path().join("status")).ok()?;
+            let field = |key: &str| {
+                status.lines().find_map(|line| {
+                    Some(line.strip_prefix(key)?.strip_prefix(':')?.trim())
+                })
+            };
+            let name = field("Name")?.to_string();
+            // kernel threads have no resident set
+            let rss = field("VmRSS")?
+                .trim_end_matches("kB")
+                .trim()
+                .parse::<u64>()
+                .ok()?;
+            Some((name, rss))
+        })
+        .max_by_key(|(_, rss)| *rss)
+}
@@ -6,16 +6,22 @@ mod clock;
 mod cpu;
 #[cfg(feature = "custom")]
 mod custom;
+#[cfg(feature = "diskio")]
+mod diskio;
 #[cfg(feature = "github")]
 mod github;
 #[cfg(feature = "i3")]
 mod i3mode;
 #[cfg(feature = "inotify")]
 mod inotify;
+#[cfg(any(feature = "mpd", feature = "mpris"))]
+mod media;
 #[cfg(feature = "memory")]
 mod memory;
 #[cfg(feature = "mpd")]
 mod mpd;
+#[cfg(feature = "mpris")]
+mod mpris;
 #[cfg(feature = "network")]
 mod network;
 #[cfg(feature = "ping")]
@@ -28,8 +34,12 @@ mod separator;
 mod storage;
 #[cfg(feature = "systray")]
 mod systray;
+#[cfg(feature = "tasklist")]
+mod tasklist;
 #[cfg(feature = "temp")]
 mod temp;
+#[cfg(feature = "vpn")]
+mod vpn;
 #[cfg(feature = "xwindow")]
 mod xwindow;
 #[cfg(feature = "xworkspaces")]
@@ -43,6 +53,8 @@ pub use clock::Clock;
 pub use cpu::Cpu;
 #[cfg(feature = "custom")]
 pub use custom::Custom;
+#[cfg(feature = "diskio")]
+pub use diskio::DiskIo;
 #[cfg(feature = "github")]
 pub use github::Github;
 #[cfg(feature = "i3")]
@@ -53,6 +65,8 @@ pub use inotify::Inotify;
 pub use memory::Memory;
 #[cfg(feature = "mpd")]
 pub use mpd::Mpd;
+#[cfg(feature = "mpris")]
+pub use mpris::Mpris;
 #[cfg(feature = "network")]
 pub use network::Network;
 #[cfg(feature = "ping")]
@@ -65,8 +79,12 @@ pub use separator::Separator;
 pub use storage::Storage;
 #[cfg(feature = "systray")]
 pub use systray::Systray;
+#[cfg(feature = "tasklist")]
+pub use tasklist::Tasklist;
 #[cfg(feature = "temp")]
 pub use temp::Temp;
+#[cfg(feature = "vpn")]
+pub use vpn::Vpn;
 #[cfg(feature = "xwindow")]
 pub use xwindow::XWindow;
 #[cfg(feature = "xworkspaces")]
@@ -82,6 +100,8 @@ pub mod builders {
     pub use super::cpu::{CpuBuilder, CpuBuilderError};
     #[cfg(feature = "custom")]
     pub use super::custom::{CustomBuilder, CustomBuilderError};
+    #[cfg(feature = "diskio")]
+    pub use super::diskio::{DiskIoBuilder, DiskIoBuilderError};
     #[cfg(feature = "github")]
     pub use super::github::{GithubBuilder, GithubBuilderError};
     #[cfg(feature = "i3")]
@@ -92,6 +112,8 @@ pub mod builders {
     pub use super::memory::{MemoryBuilder, MemoryBuilderError};
     #[cfg(feature = "mpd")]
     pub use super::mpd::{MpdBuilder, MpdBuilderError};
+    #[cfg(feature = "mpris")]
+    pub use super::mpris::{MprisBuilder, MprisBuilderError};
     #[cfg(feature = "network")]
     pub use super::network::{NetworkBuilder, NetworkBuilderError};
     #[cfg(feature = "ping")]
@@ -104,8 +126,12 @@ pub mod builders {
     pub use super::storage::{StorageBuilder, StorageBuilderError};
     #[cfg(feature = "systray")]
     pub use super::systray::{SystrayBuilder, SystrayBuilderError};
+    #[cfg(feature = "tasklist")]
+    pub use super::tasklist::{TasklistBuilder, TasklistBuilderError};
     #[cfg(feature = "temp")]
     pub use super::temp::{TempBuilder, TempBuilderError};
+    #[cfg(feature = "vpn")]
+    pub use super::vpn::{VpnBuilder, VpnBuilderError};
     #[cfg(feature = "xwindow")]
     pub use super::xwindow::{XWindowBuilder, XWindowBuilderError};
     #[cfg(feature = "xworkspaces")]
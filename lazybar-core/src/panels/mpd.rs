@@ -3,13 +3,16 @@ use std::{
     pin::Pin,
     rc::Rc,
     str::FromStr,
-    sync::{Arc, Mutex},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
     task::Poll,
     time::Duration,
 };
 
-use aho_corasick::{AhoCorasick, Match};
-use anyhow::Result;
+use aho_corasick::AhoCorasick;
+use anyhow::{Result, anyhow};
 use async_trait::async_trait;
 use config::Config;
 use csscolorparser::Color;
@@ -18,7 +21,6 @@ use futures::task::AtomicWaker;
 use lazybar_types::EventResponse;
 use mpd::{Client, Idle, State, Status, Subsystem};
 use pango::Layout;
-use pangocairo::functions::{create_layout, show_layout};
 use tokio::{
     sync::mpsc::{UnboundedSender, unbounded_channel},
     task::{self, JoinHandle},
@@ -27,31 +29,25 @@ use tokio::{
 use tokio_stream::{
     Stream, StreamExt, StreamMap, wrappers::UnboundedReceiverStream,
 };
-use unicode_segmentation::UnicodeSegmentation;
 
-#[cfg(feature = "cursor")]
-use crate::bar::{Cursor, CursorInfo};
+use super::media::{self, MediaDraw};
 use crate::{
-    Attrs, ButtonIndex, Highlight, IndexCache, ManagedIntervalStream,
-    PanelConfig, PanelRunResult, array_to_struct,
+    Attrs, IndexCache, ManagedIntervalStream, Overflow, PanelConfig,
+    PanelRunResult, array_to_struct,
     bar::{Event, MouseButton, PanelDrawInfo},
     common::PanelCommon,
+    image::Image,
     ipc::ChannelEndpoint,
     remove_bool_from_config, remove_color_from_config,
     remove_string_from_config, remove_uint_from_config,
 };
 
-#[derive(Clone, Debug)]
-enum Strategy {
-    Scroll { interval: Duration },
-    Truncate,
-}
-
 #[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
 enum EventType {
     Player,
-    Scroll,
     Progress,
+    Tick,
+    Art,
     Action,
 }
 
@@ -75,6 +71,9 @@ array_to_struct!(
     consume
 );
 
+/// The URI of a song and the raw data of its album art.
+type FetchedArt = (String, Vec<u8>);
+
 /// Displays information about music currently playing through
 /// [MPD](https://musicpd.org)
 #[derive(Builder, Debug, Clone)]
@@ -89,19 +88,26 @@ pub struct Mpd {
     highlight_conn: Option<Arc<Mutex<Client>>>,
     #[builder(default = "false")]
     progress_bar: bool,
-    #[builder(default = "Strategy::Truncate")]
-    strategy: Strategy,
-    #[builder(default = "0", setter(skip))]
-    scroll_idx: usize,
-    #[builder(default = r#"String::from("  ")"#)]
-    scroll_separator: String,
     #[builder(default = r##"Color::from_str("#f00").unwrap()"##)]
     progress_bg: Color,
-    #[builder(default = "0.0", setter(skip))]
-    last_progress_width: f64,
-    #[builder(default = "0")]
-    max_width: usize,
-    last_layout: Rc<Mutex<Option<(Layout, String)>>>,
+    /// The visible width in pixels of `%main%` when it was last drawn
+    #[builder(default, setter(skip))]
+    main_width: Arc<AtomicU32>,
+    #[builder(default = "5")]
+    volume_step: i8,
+    /// Used to fetch album art, if it's enabled
+    #[builder(default, setter(strip_option))]
+    art_conn: Option<Arc<Mutex<Client>>>,
+    /// The URI of the current song and its art, once it's been fetched
+    #[builder(default, setter(skip))]
+    art: Option<(String, Option<Image>)>,
+    /// Art that has been fetched in the background but not yet decoded,
+    /// keyed by song URI
+    #[builder(default, setter(skip))]
+    fetched_art: Arc<Mutex<Option<FetchedArt>>>,
+    #[builder(default, setter(skip))]
+    art_send: Option<UnboundedSender<()>>,
+    last_layout: Rc<Mutex<Option<(Layout, f64)>>>,
     index_cache: Arc<Mutex<Option<IndexCache>>>,
     formatter: AhoCorasick,
     formats: MpdFormats<String>,
@@ -114,282 +120,109 @@ impl Mpd {
         &mut self,
         cr: &Rc<cairo::Context>,
         height: i32,
-        event: EventType,
         paused: Arc<Mutex<bool>>,
         wakers: [Arc<AtomicWaker>; 3],
     ) -> Result<PanelDrawInfo> {
         let conn = self.noidle_conn.clone();
         let status = conn.lock().unwrap().status()?;
+        if self.art_conn.is_some() {
+            self.update_art(height)?;
+        }
+
         let format = match status.state {
             State::Play => self.formats.playing.clone(),
             State::Pause => self.formats.paused.clone(),
             State::Stop => self.formats.stopped.clone(),
         };
 
-        let mut main = String::new();
-        self.formatter.replace_all_with(
-            self.formats.main.as_str(),
-            &mut main,
-            |_, content, dst| self.replace(content, dst, &status),
-        );
-        let mut text = String::new();
-        self.formatter.replace_all_with(
-            format.as_str(),
-            &mut text,
-            |_, content, dst| self.replace(content, dst, &status),
-        );
-
-        let mut index_cache = Vec::new();
-        if let Ok(haystack) = pango::parse_markup(format.as_str(), '\0') {
-            let haystack = haystack.1.as_str();
-            let mut ignored = String::new();
-            let mut offset = 0;
-            self.formatter.replace_all_with(
-                haystack,
-                &mut ignored,
-                |mat, content, dst| {
-                    self.build_cache(
-                        mat,
-                        content,
-                        dst,
-                        main.as_str(),
-                        &status,
-                        &mut index_cache,
-                        &mut offset,
-                    )
-                },
-            );
-        }
-
-        let layout = create_layout(cr);
-
-        match self.strategy {
-            Strategy::Scroll { interval: _ } => {
-                match event {
-                    EventType::Scroll => {
-                        if status.state == State::Play {
-                            self.scroll_idx = (self.scroll_idx + 1)
-                                % (main.graphemes(true).count()
-                                    + self.scroll_separator.len());
-                        }
-                    }
-                    EventType::Player => self.scroll_idx = 0,
-                    _ => {}
-                }
-                let scrolling = self.max_width > 0
-                    && main.graphemes(true).count() > self.max_width;
-                if scrolling {
-                    main.push_str(self.scroll_separator.as_str());
-                }
-                let at_end = self.scroll_idx
-                    > main.graphemes(true).count() - self.max_width;
-                let replacement_text = if scrolling {
-                    if at_end {
-                        format!(
-                            "{}{}",
-                            glib::markup_escape_text(
-                                main.as_str()
-                                    .graphemes(true)
-                                    .skip(self.scroll_idx)
-                                    .collect::<String>()
-                                    .as_str()
-                            )
-                            .as_str(),
-                            glib::markup_escape_text(
-                                main.as_str()
-                                    .graphemes(true)
-                                    .take(
-                                        self.max_width
-                                            - (main.graphemes(true).count()
-                                                - self.scroll_idx)
-                                    )
-                                    .collect::<String>()
-                                    .as_str()
-                            )
-                            .as_str(),
-                        )
-                    } else {
-                        glib::markup_escape_text(
-                            main.as_str()
-                                .graphemes(true)
-                                .skip(self.scroll_idx)
-                                .take(self.max_width)
-                                .collect::<String>()
-                                .as_str(),
-                        )
-                        .to_string()
-                    }
-                } else {
-                    glib::markup_escape_text(main.as_str()).to_string()
-                };
-                layout.set_markup(
-                    text.replace("%main%", replacement_text.as_str()).as_str(),
-                );
-            }
-            Strategy::Truncate => {
-                let replacement_text = if self.max_width > 0 {
-                    glib::markup_escape_text(
-                        main.graphemes(true)
-                            .take(self.max_width)
-                            .collect::<String>()
-                            .as_str(),
-                    )
-                    .to_string()
-                } else {
-                    main.clone()
-                };
-                let layout_text =
-                    text.replace("%main%", replacement_text.as_str());
-                layout.set_markup(layout_text.as_str());
-            }
-        }
-
-        self.attrs.apply_font(&layout);
-
-        let size = layout.pixel_size();
-        let (bar_start_idx, bar_max_width_idx) = index_cache
-            .iter()
-            .find(|index| index.name == "main")
-            .map_or_else(
-                || (0, text.len() as i32),
-                |index| (index.start as i32, index.length as i32),
-            );
-        let bar_start =
-            layout.index_to_pos(bar_start_idx).x() as f64 / pango::SCALE as f64;
-        let bar_max_width =
-            layout.index_to_pos(bar_start_idx + bar_max_width_idx).x() as f64
-                / pango::SCALE as f64
-                - bar_start;
-        *self.index_cache.lock().unwrap() = Some(index_cache);
-        *self.last_layout.lock().unwrap() =
-            Some((layout.clone(), layout.text().to_string()));
-
-        if event == EventType::Progress {
-            self.last_progress_width = status.elapsed.unwrap().as_secs_f64()
-                / status.duration.unwrap().as_secs_f64()
-                * bar_max_width;
-            let char_width = bar_max_width
-                / main.graphemes(true).count().min(self.max_width) as f64;
-            self.last_progress_width =
-                (self.last_progress_width / char_width).round() * char_width;
-        }
+        let lookup = |content: &str| self.format_from_content(content, &status);
+        let main =
+            media::expand(&self.formatter, self.formats.main.as_str(), lookup);
+        let text = media::expand(&self.formatter, format.as_str(), lookup);
+
+        let lookup = |content: &str| self.format_from_content(content, &status);
+        let index_cache =
+            media::build_index_cache(&self.formatter, format.as_str(), lookup);
+
+        let draw = MediaDraw {
+            text: text.as_str(),
+            main: main.as_str(),
+            index_cache,
+            last_layout: self.last_layout.clone(),
+            shared_cache: self.index_cache.clone(),
+            attrs: &self.attrs,
+            common: &self.common,
+            art: self.art.as_ref().and_then(|(_, image)| image.clone()),
+            progress_bg: &self.progress_bg,
+            playing: status.state == State::Play,
+            paused,
+            wakers: wakers.to_vec(),
+            dump: format!("{self:?}"),
+        };
+        let layout = draw.layout(cr);
+        self.main_width
+            .store(layout.width.round() as u32, Ordering::Relaxed);
 
-        let bar_width = self.last_progress_width;
-        let attrs = self.attrs.clone();
-        let progress_bg = self.progress_bg.clone();
-        let highlight = if bar_width > 0.0 {
-            Some(Highlight::new(
-                height as f64,
-                progress_bg,
-                0.0,
-                Color {
-                    r: 0.0,
-                    g: 0.0,
-                    b: 0.0,
-                    a: 0.0,
-                },
+        let bar = if self.progress_bar
+            && let (Some(elapsed), Some(duration)) =
+                (status.elapsed, status.duration)
+        {
+            Some((
+                layout.start,
+                (elapsed.as_secs_f64() / duration.as_secs_f64())
+                    .clamp(0.0, 1.0)
+                    * layout.width,
             ))
         } else {
             None
         };
-        let images = self.common.images.clone();
-        let paused_ = paused.clone();
-
-        #[cfg(feature = "cursor")]
-        let last_layout = self.last_layout.clone();
-        #[cfg(feature = "cursor")]
-        let index_cache = self.index_cache.clone();
-
-        Ok(PanelDrawInfo::new(
-            (size.0, height),
-            self.common.dependence,
-            Box::new(move |cr, _| {
-                cr.save()?;
-
-                let offset = if let Some(bg) = &attrs.bg {
-                    bg.draw(cr, size.0 as f64, size.1 as f64, height as f64)?
-                } else {
-                    0.0
-                };
 
-                if let Some(ref highlight) = highlight {
-                    cr.save()?;
-
-                    cr.translate(bar_start, 0.0);
-                    highlight.draw(
-                        cr,
-                        height as f64,
-                        bar_width.min(bar_max_width),
-                    )?;
-
-                    cr.restore()?;
-                }
-
-                for image in &images {
-                    image.draw(cr)?;
-                }
-
-                cr.translate(offset, f64::from(height - size.1) / 2.0);
-
-                attrs.apply_fg(cr);
-                show_layout(cr, &layout);
+        Ok(draw.finish(layout, height, bar))
+    }
 
-                cr.restore()?;
-                Ok(())
-            }),
-            Some(Box::new(move || {
-                *paused.lock().unwrap() = false;
-                for waker in &wakers {
-                    waker.wake();
-                }
-                Ok(())
-            })),
-            Some(Box::new(move || {
-                *paused_.lock().unwrap() = true;
-                Ok(())
-            })),
-            None,
-            #[cfg(feature = "cursor")]
-            CursorInfo::Dynamic(Box::new(move |event| {
-                Ok(
-                    if let Some(ref layout) =
-                        *last_layout.clone().lock().unwrap()
-                    {
-                        if let Some(ref cache) =
-                            *index_cache.clone().lock().unwrap()
-                        {
-                            let idx = layout
-                                .0
-                                .xy_to_index(
-                                    event.x as i32 * pango::SCALE,
-                                    event.y as i32 * pango::SCALE,
-                                )
-                                .1
-                                as usize;
-                            cache
-                                .iter()
-                                .find(|index| {
-                                    index.start <= idx
-                                        && idx <= index.start + index.length
-                                })
-                                .map(|index| {
-                                    if index.name == "main" {
-                                        Cursor::Default
-                                    } else {
-                                        Cursor::Click
-                                    }
-                                })
-                                .unwrap_or_default()
-                        } else {
-                            Cursor::Default
+    /// Starts fetching the art for the current song if it changed, and
+    /// decodes the art once it arrives. MPD can take a while to read the art,
+    /// so it's fetched on another connection off the draw path.
+    fn update_art(&mut self, height: i32) -> Result<()> {
+        let song = self.noidle_conn.lock().unwrap().currentsong()?;
+        if song.as_ref().map(|s| &s.file) != self.art.as_ref().map(|a| &a.0) {
+            self.art = song.as_ref().map(|song| (song.file.clone(), None));
+            if let (Some(song), Some(conn), Some(send)) =
+                (song, self.art_conn.clone(), self.art_send.clone())
+            {
+                let fetched = self.fetched_art.clone();
+                task::spawn_blocking(move || {
+                    match conn.lock().unwrap().albumart(&song) {
+                        Ok(data) => {
+                            *fetched.lock().unwrap() = Some((song.file, data));
+                            let _ = send.send(());
                         }
-                    } else {
-                        Cursor::Default
-                    },
+                        Err(e) => {
+                            log::debug!("No album art for {}: {e}", song.file);
+                        }
+                    }
+                });
+            }
+        }
+
+        if let Some((file, image @ None)) = &mut self.art {
+            let mut fetched = self.fetched_art.lock().unwrap();
+            if fetched.as_ref().is_some_and(|(f, _)| f == file)
+                && let Some((_, data)) = fetched.take()
+            {
+                *image = Image::from_png_data(
+                    data.as_slice(),
+                    0.0,
+                    0.0,
+                    f64::from(height),
                 )
-            })),
-            format!("{self:?}"),
-        ))
+                .map_err(|e| {
+                    log::debug!("Failed to decode art for {file}: {e}")
+                })
+                .ok();
+            }
+        }
+        Ok(())
     }
 
     fn format_from_content(
@@ -441,136 +274,124 @@ impl Mpd {
             "%random%" => Some(self.formats.random.clone()),
             "%single%" => Some(self.formats.single.clone()),
             "%consume%" => Some(self.formats.consume.clone()),
+            "%elapsed%" => Some(format_duration(status.elapsed)),
+            "%duration%" => Some(format_duration(status.duration)),
+            "%queue_pos%" => Some(
+                status
+                    .song
+                    .map_or_else(String::new, |s| (s.pos + 1).to_string()),
+            ),
+            "%queue_len%" => Some(status.queue_len.to_string()),
+            "%volume%" => Some(if status.volume < 0 {
+                String::new()
+            } else {
+                status.volume.to_string()
+            }),
+            "%crossfade%" => {
+                Some(status.crossfade.unwrap_or_default().as_secs().to_string())
+            }
             _ => None,
         }
     }
 
-    fn replace(
-        &self,
-        content: &str,
-        dst: &mut String,
-        status: &Status,
-    ) -> bool {
-        if let Some(str_to_push) = self.format_from_content(content, status) {
-            dst.push_str(str_to_push.as_str());
+    fn action(
+        value: &str,
+        conn: &Arc<Mutex<Client>>,
+        volume_step: i8,
+    ) -> Result<()> {
+        let mut conn = conn.lock().unwrap();
+        match value {
+            "next" => conn.next()?,
+            "prev" => conn.prev()?,
+            "play" => conn.play()?,
+            "pause" => conn.pause(true)?,
+            "toggle" => conn.toggle_pause()?,
+            "shuffle" => conn.shuffle(..)?,
+            "repeat" => {
+                let repeat = conn.status()?.repeat;
+                conn.repeat(!repeat)?;
+            }
+            "random" => {
+                let random = conn.status()?.random;
+                conn.random(!random)?;
+            }
+            "single" => {
+                let single = conn.status()?.single;
+                conn.single(!single)?;
+            }
+            "consume" => {
+                let consume = conn.status()?.consume;
+                conn.consume(!consume)?;
+            }
+            "volume_up" | "volume_down" => {
+                let volume = conn.status()?.volume;
+                if volume < 0 {
+                    return Err(anyhow!("MPD has no mixer"));
+                }
+                let step = if value == "volume_up" {
+                    volume_step
+                } else {
+                    -volume_step
+                };
+                conn.volume(volume.saturating_add(step).clamp(0, 100))?;
+            }
+            "main" => {}
+            _ => log::warn!("Unknown action event '{value}'"),
         }
-        true
+        Ok(())
     }
 
-    fn build_cache(
-        &self,
-        mat: &Match,
-        content: &str,
-        dst: &mut String,
-        main: &str,
-        status: &Status,
-        index_cache: &mut IndexCache,
-        offset: &mut isize,
-    ) -> bool {
-        if let Some(str_to_push) = self.format_from_content(content, status) {
-            let name = content.replace('%', "");
-            dst.push_str(str_to_push.as_str());
-            // main is special
-            let length = if content == "%main%" {
-                main.len().min(self.max_width)
-            } else {
-                pango::parse_markup(str_to_push.as_str(), '\0')
-                    .map_or_else(|_| str_to_push.len(), |l| l.1.len())
-            };
-            index_cache.push(ButtonIndex {
-                name,
-                start: (mat.start() as isize + *offset) as usize,
-                length,
-            });
-            *offset += (length - content.len()) as isize;
+    /// Seeks to `fraction` of the way through the current song.
+    fn seek(conn: &Arc<Mutex<Client>>, fraction: f64) -> Result<()> {
+        let mut conn = conn.lock().unwrap();
+        if let Some(duration) = conn.status()?.duration {
+            conn.rewind(duration.mul_f64(fraction))?;
         }
-        true
+        Ok(())
     }
 
     fn process_event(
         event: &Event,
-        conn: Arc<Mutex<Client>>,
-        last_layout: Rc<Mutex<Option<(Layout, String)>>>,
-        index_cache: Arc<Mutex<Option<IndexCache>>>,
+        conn: &Arc<Mutex<Client>>,
+        last_layout: &Rc<Mutex<Option<(Layout, f64)>>>,
+        index_cache: &Arc<Mutex<Option<IndexCache>>>,
+        volume_step: i8,
         send: &UnboundedSender<EventResponse>,
     ) -> Result<()> {
         let result = match event {
-            Event::Action(Some(value)) => match value.as_str() {
-                "next" => conn.lock().unwrap().next(),
-                "prev" => conn.lock().unwrap().prev(),
-                "play" => conn.lock().unwrap().play(),
-                "pause" => conn.lock().unwrap().pause(true),
-                "toggle" => conn.lock().unwrap().toggle_pause(),
-                "shuffle" => conn.lock().unwrap().shuffle(..),
-                "repeat" => {
-                    let mut conn = conn.lock().unwrap();
-                    let repeat = conn.status()?.repeat;
-                    conn.repeat(!repeat)
-                }
-                "random" => {
-                    let mut conn = conn.lock().unwrap();
-                    let random = conn.status()?.random;
-                    conn.random(!random)
-                }
-                "single" => {
-                    let mut conn = conn.lock().unwrap();
-                    let single = conn.status()?.single;
-                    conn.single(!single)
-                }
-                "consume" => {
-                    let mut conn = conn.lock().unwrap();
-                    let consume = conn.status()?.consume;
-                    conn.consume(!consume)
-                }
-                "main" => Ok(()),
-                _ => {
-                    log::warn!("Unknown action event '{value}'");
-                    Ok(())
-                }
-            },
+            Event::Action(Some(value)) => {
+                Self::action(value.as_str(), conn, volume_step)
+            }
             Event::Action(None) => Ok(()),
             Event::Mouse(event) => {
-                match event.button {
-                    MouseButton::Left
-                    | MouseButton::Right
-                    | MouseButton::Middle => {
-                        if let Some(ref layout) =
-                            *last_layout.clone().lock().unwrap()
-                        {
-                            if let Some(ref cache) =
-                                *index_cache.clone().lock().unwrap()
-                            {
-                                let idx = layout
-                                    .0
-                                    .xy_to_index(
-                                        event.x as i32 * pango::SCALE,
-                                        event.y as i32 * pango::SCALE,
-                                    )
-                                    .1
-                                    as usize;
-                                cache
-                                    .iter()
-                                    .find(|index| {
-                                        index.start <= idx
-                                            && idx <= index.start + index.length
-                                    })
-                                    .map(|index| {
-                                        Self::process_event(
-                                            &Event::Action(Some(
-                                                index.name.clone(),
-                                            )),
-                                            conn,
-                                            last_layout,
-                                            index_cache,
-                                            send,
-                                        )
-                                    });
-                            }
-                        }
+                let (name, fraction) = match (
+                    &*last_layout.lock().unwrap(),
+                    &*index_cache.lock().unwrap(),
+                ) {
+                    (Some(layout), Some(cache)) => (
+                        media::button_at(layout, cache, event.x, event.y),
+                        media::fraction_at(layout, cache, "main", event.x),
+                    ),
+                    _ => (None, None),
+                };
+                match (event.button, name.as_deref()) {
+                    (MouseButton::Left | MouseButton::Drag, Some("main")) => {
+                        fraction.map_or(Ok(()), |f| Self::seek(conn, f))
+                    }
+                    (
+                        MouseButton::Left
+                        | MouseButton::Right
+                        | MouseButton::Middle,
+                        Some(name),
+                    ) => Self::action(name, conn, volume_step),
+                    (MouseButton::ScrollUp, _) => {
+                        Self::action("volume_up", conn, volume_step)
                     }
-                    _ => {}
+                    (MouseButton::ScrollDown, _) => {
+                        Self::action("volume_down", conn, volume_step)
+                    }
+                    _ => Ok(()),
                 }
-                Ok(())
             }
         }
         .map_or_else(
@@ -590,6 +411,9 @@ impl Mpd {
         if let Some(ref highlight_conn) = self.highlight_conn {
             *highlight_conn.lock().unwrap() = Client::connect(addr)?;
         }
+        if let Some(ref art_conn) = self.art_conn {
+            *art_conn.lock().unwrap() = Client::connect(addr)?;
+        }
         Ok(())
     }
 }
@@ -608,27 +432,33 @@ impl PanelConfig for Mpd {
     ///   - type: String
     ///   - formatting options: `%title%`, `%artist%`, `%next%`, `%prev%`,
     ///     `%play%`, `%pause%`, `%toggle%`, `%main%`, `%shuffle%`, `%repeat%`,
-    ///     `%random%`, `%single%`, `%consume%`
+    ///     `%random%`, `%single%`, `%consume%`, `%elapsed%`, `%duration%`,
+    ///     `%queue_pos%`, `%queue_len%`, `%volume%`, `%crossfade%`
     ///   - default: `%main%`
     /// - `format_paused`: the format string to display on the panel when music
     ///   is paused
     ///   - type: String
     ///   - formatting options: `%title%`, `%artist%`, `%next%`, `%prev%`,
     ///     `%play%`, `%pause%`, `%toggle%`, `%main%`, `%shuffle%`, `%repeat%`,
-    ///     `%random%`, `%single%`, `%consume%`
+    ///     `%random%`, `%single%`, `%consume%`, `%elapsed%`, `%duration%`,
+    ///     `%queue_pos%`, `%queue_len%`, `%volume%`, `%crossfade%`
     ///   - default: `%main%`
     /// - `format_stopped`: the format string to display on the panel when no
     ///   music is playing
     ///   - type: String
     ///   - formatting options: `%title%`, `%artist%`, `%next%`, `%prev%`,
     ///     `%play%`, `%pause%`, `%toggle%`, `%main%`, `%shuffle%`, `%repeat%`,
-    ///     `%random%`, `%single%`, `%consume%`
+    ///     `%random%`, `%single%`, `%consume%`, `%elapsed%`, `%duration%`,
+    ///     `%queue_pos%`, `%queue_len%`, `%volume%`, `%crossfade%`
     ///   - default: `not playing`
     /// - `format_main`: the format of the main panel region. Only this section
     ///   will scroll, and the progress bar will be limited to this region.
     ///   - type: String
-    ///   - formatting options: `%title%`, `%artist%` (the others from above
-    ///     will work, but they won't function as buttons)
+    ///   - formatting options: `%title%`, `%artist%`, `%elapsed%`,
+    ///     `%duration%`, `%queue_pos%`, `%queue_len%`, `%volume%`,
+    ///     `%crossfade%` (the buttons from above will work, but they won't
+    ///     function as buttons). Clicking this region seeks to that point in
+    ///     the song, as does dragging along it.
     ///   - default: `%title% - %artist%`
     /// - `format_next`: the format of the button to skip forward one song
     ///   - type: String
@@ -669,29 +499,37 @@ impl PanelConfig for Mpd {
     /// - `format_consume`: the format of the consume button
     ///   - type: String
     ///   - default: empty
+    /// - `album_art`: whether to show the current song's album art, scaled to
+    ///   the height of the bar, to the left of the text. MPD must be able to
+    ///   find the art (see its `albumart` command), and only PNG images are
+    ///   supported. The art is fetched in the background, so it appears shortly
+    ///   after the song changes.
+    ///   - type: bool
+    ///   - default: `false`
+    /// - `volume_step`: how much to change the volume when scrolling over the
+    ///   panel
+    ///   - type: u64
+    ///   - default: 5
     /// - `progress_bar`: whether to show a progress bar behind the text
     ///   - type: bool
     ///   - default: `false`
     /// - `progress_bg`: the background color of the progress bar (ignored if
     ///   `!progress_bar`)
-    /// - `max_width`: the maximum width in characters of the panel (0 means no
-    ///   maximum)
-    ///   - type: u64
-    ///   - default: 0
-    /// - `strategy`: how to handle overflow of `max_width`
-    ///   - type: String - `scroll` or `truncate`
-    ///   - default: truncate
-    /// - `scroll_interval`: how often in milliseconds to scroll the text
-    ///   - type: u64
-    ///   - default: 1000
-    /// - `scroll_separator`: what to put between the end of the string and the
-    ///   beginning when it scrolls (ignored if `strategy != scroll`)
-    ///   - type: String
-    ///   - default: `  ` (two spaces)
+    /// - `max_width`, `strategy`, `ellipsis`, `scroll_interval`, `scroll_step`,
+    ///   and `scroll_separator`: see [`Overflow::parse`]. These only apply to
+    ///   `%main%`, so the buttons are always visible. For compatibility, a bare
+    ///   number for `max_width` is a number of characters (this is deprecated;
+    ///   use e.g. `"40ch"` or `"300px"`), and scrolling text moves one
+    ///   character (`"1ch"`) every 1000ms by default. Text only scrolls while
+    ///   music is playing.
     /// - `attrs`: A string specifying the attrs for the panel. See
     ///   [`Attrs::parse`] for details.
     /// - See [`PanelCommon::parse_common`]. `click_*` and `scroll_*` are
-    ///   currently ignored.
+    ///   currently ignored. Scrolling changes the volume.
+    ///
+    /// The panel also responds to the actions `next`, `prev`, `play`,
+    /// `pause`, `toggle`, `shuffle`, `repeat`, `random`, `single`, `consume`,
+    /// `volume_up`, and `volume_down` sent over IPC.
     fn parse(
         name: &'static str,
         table: &mut HashMap<String, config::Value>,
@@ -720,29 +558,19 @@ impl PanelConfig for Mpd {
             )?)));
         }
 
-        if let Some(strategy) = remove_string_from_config("strategy", table) {
-            builder.strategy(match strategy.as_str() {
-                "scroll" => Strategy::Scroll {
-                    interval: Duration::from_millis(
-                        remove_uint_from_config("scroll_interval", table)
-                            .unwrap_or(1000),
-                    ),
-                },
-                _ => Strategy::Truncate,
-            });
-        }
-        if let Some(separator) =
-            remove_string_from_config("scroll_separator", table)
-        {
-            builder.scroll_separator(separator);
-        }
         if let Some(progress_bg) =
             remove_color_from_config("progress_bg", table)
         {
             builder.progress_bg(progress_bg);
         }
-        if let Some(max_width) = remove_uint_from_config("max_width", table) {
-            builder.max_width(max_width as usize);
+        if remove_bool_from_config("album_art", table).unwrap_or_default() {
+            builder.art_conn(Arc::new(Mutex::new(Client::connect(
+                &*final_address,
+            )?)));
+        }
+        if let Some(volume_step) = remove_uint_from_config("volume_step", table)
+        {
+            builder.volume_step(volume_step.min(100) as i8);
         }
         builder.last_layout(Rc::new(Mutex::new(None)));
         builder.index_cache(Arc::new(Mutex::new(None)));
@@ -760,9 +588,17 @@ impl PanelConfig for Mpd {
             "%random%",
             "%single%",
             "%consume%",
+            "%elapsed%",
+            "%duration%",
+            "%queue_pos%",
+            "%queue_len%",
+            "%volume%",
+            "%crossfade%",
         ])?);
 
-        let common = PanelCommon::parse_common(table)?;
+        let overflow = Overflow::parse_media(table);
+        let mut common = PanelCommon::parse_common(table)?;
+        common.overflow = overflow;
         let formats = PanelCommon::parse_formats(
             table,
             &[
@@ -850,7 +686,7 @@ impl PanelConfig for Mpd {
                     waker: progress_waker.clone(),
                     song_length: None,
                     song_elapsed: None,
-                    max_width: self.max_width,
+                    main_width: self.main_width.clone(),
                     conn: self.highlight_conn.clone().unwrap(),
                     noidle_conn: self.noidle_conn.clone(),
                     handle: None,
@@ -860,35 +696,54 @@ impl PanelConfig for Mpd {
             );
         }
 
-        let scroll_waker = Arc::new(AtomicWaker::new());
+        let tick_waker = Arc::new(AtomicWaker::new());
 
-        if let Strategy::Scroll { interval: i } = self.strategy {
+        if [
+            &self.formats.playing,
+            &self.formats.paused,
+            &self.formats.stopped,
+            &self.formats.main,
+        ]
+        .iter()
+        .any(|format| format.contains("%elapsed%"))
+        {
             map.insert(
-                EventType::Scroll,
+                EventType::Tick,
                 Box::pin(
                     ManagedIntervalStream::builder()
-                        .duration(i)
+                        .duration(Duration::from_secs(1))
                         .paused(paused.clone())
-                        .waker(scroll_waker.clone())
+                        .waker(tick_waker.clone())
                         .build()?
                         .map(|_| Ok(())),
                 ),
             );
         }
 
+        if self.art_conn.is_some() {
+            let (art_send, art_recv) = unbounded_channel();
+            self.art_send = Some(art_send);
+            map.insert(
+                EventType::Art,
+                Box::pin(UnboundedReceiverStream::new(art_recv).map(Ok)),
+            );
+        }
+
         let (event_send, event_recv) = unbounded_channel();
         let (response_send, response_recv) = unbounded_channel();
         let conn = self.noidle_conn.clone();
         let last_layout = self.last_layout.clone();
         let index_cache = self.index_cache.clone();
+        let volume_step = self.volume_step;
         map.insert(
             EventType::Action,
             Box::pin(UnboundedReceiverStream::new(event_recv).map(move |s| {
                 Self::process_event(
                     &s,
-                    conn.clone(),
-                    last_layout.clone(),
-                    index_cache.clone(),
+                    &conn,
+                    &last_layout,
+                    &index_cache,
+                    volume_step,
                     &response_send,
                 )
             })),
@@ -897,17 +752,16 @@ impl PanelConfig for Mpd {
         self.attrs.apply_to(&global_attrs);
 
         Ok((
-            Box::pin(map.map(move |(t, r)| {
+            Box::pin(map.map(move |(_, r)| {
                 r?;
                 let val = self.draw(
                     &cr,
                     height,
-                    t,
                     paused.clone(),
                     [
                         mpd_waker.clone(),
                         progress_waker.clone(),
-                        scroll_waker.clone(),
+                        tick_waker.clone(),
                     ],
                 );
                 if let Err(ref e) = val {
@@ -926,13 +780,23 @@ impl PanelConfig for Mpd {
     }
 }
 
+/// Formats a duration as `m:ss`, or `h:mm:ss` if it's at least an hour.
+fn format_duration(duration: Option<Duration>) -> String {
+    let secs = duration.unwrap_or_default().as_secs();
+    if secs >= 3600 {
+        format!("{}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+    } else {
+        format!("{}:{:02}", secs / 60, secs % 60)
+    }
+}
+
 struct HighlightStream {
     interval: Interval,
     paused: Arc<Mutex<bool>>,
     waker: Arc<AtomicWaker>,
     song_length: Option<Duration>,
     song_elapsed: Option<Duration>,
-    max_width: usize,
+    main_width: Arc<AtomicU32>,
     conn: Arc<Mutex<Client>>,
     noidle_conn: Arc<Mutex<Client>>,
     handle: Option<JoinHandle<Result<()>>>,
@@ -970,7 +834,13 @@ impl Stream for HighlightStream {
             self.song_elapsed = status.elapsed;
             self.playing = status.state == State::Play;
             if let Some(length) = self.song_length {
-                self.interval = interval(length / self.max_width as u32);
+                // update about once per pixel, or once per second before the
+                // first draw
+                let steps = match self.main_width.load(Ordering::Relaxed) {
+                    0 => length.as_secs() as usize,
+                    width => width as usize,
+                };
+                self.interval = interval(length / steps.max(1) as u32);
             }
         }
         if self.playing {
@@ -1009,7 +879,12 @@ impl Stream for MpdStream {
             }
         } else {
             let conn = self.conn.clone();
-            let subsystems = &[Subsystem::Player];
+            let subsystems = &[
+                Subsystem::Player,
+                Subsystem::Mixer,
+                Subsystem::Options,
+                Subsystem::Queue,
+            ];
             let waker = cx.waker().clone();
             self.handle = Some(task::spawn_blocking(move || {
                 let _ = conn.lock().unwrap().wait(subsystems);
@@ -0,0 +1,1089 @@
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    rc::Rc,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    task::Poll,
+    time::{Duration, Instant},
+};
+
+use aho_corasick::AhoCorasick;
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use config::Config;
+use csscolorparser::Color;
+use derive_builder::Builder;
+use futures::{FutureExt, task::AtomicWaker};
+use lazybar_types::EventResponse;
+use pango::Layout;
+use tokio::{
+    sync::mpsc::{UnboundedSender, unbounded_channel},
+    task::{self, JoinHandle},
+};
+use tokio_stream::{
+    Stream, StreamExt, StreamMap, wrappers::UnboundedReceiverStream,
+};
+
+use super::media::{self, MediaDraw};
+use crate::{
+    Attrs, IndexCache, ManagedIntervalStream, Overflow, PanelConfig,
+    PanelRunResult, array_to_struct,
+    bar::{Event, MouseButton, PanelDrawInfo},
+    common::PanelCommon,
+    dbus::{self, BUS, Connection, Message, PROPERTIES, Value},
+    glob_match,
+    ipc::ChannelEndpoint,
+    remove_bool_from_config, remove_color_from_config,
+    remove_string_from_config,
+};
+
+const PREFIX: &str = "org.mpris.MediaPlayer2.";
+const PATH: &str = "/org/mpris/MediaPlayer2";
+const ROOT_INTERFACE: &str = "org.mpris.MediaPlayer2";
+const PLAYER_INTERFACE: &str = "org.mpris.MediaPlayer2.Player";
+/// How long to wait for a player to answer. Method calls are made while
+/// drawing, so a player that hangs would otherwise freeze the bar.
+const CALL_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+enum EventType {
+    Player,
+    Progress,
+    Action,
+}
+
+array_to_struct!(
+    MprisFormats,
+    playing,
+    paused,
+    stopped,
+    main,
+    next,
+    prev,
+    play,
+    pause,
+    stop,
+    toggle_playing,
+    toggle_paused,
+    toggle_stopped,
+    shuffle,
+    repeat
+);
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum PlaybackStatus {
+    Playing,
+    Paused,
+    #[default]
+    Stopped,
+}
+
+#[derive(Debug, Clone)]
+struct Player {
+    /// The well-known name, e.g. `org.mpris.MediaPlayer2.spotify`
+    name: String,
+    /// The unique name, which is the sender of the player's signals
+    owner: String,
+    identity: String,
+}
+
+impl Player {
+    fn new(conn: &mut Connection, name: String) -> Result<Self> {
+        let owner = dbus::name_owner(conn, name.as_str())?;
+        let identity = conn
+            .get_property(name.as_str(), PATH, ROOT_INTERFACE, "Identity")
+            .ok()
+            .and_then(|v| v.as_str().map(ToString::to_string))
+            .unwrap_or_else(|| name.trim_start_matches(PREFIX).to_owned());
+        Ok(Self {
+            name,
+            owner,
+            identity,
+        })
+    }
+}
+
+/// The players on the bus, and which one is shown.
+#[derive(Debug, Default)]
+struct Players {
+    players: Vec<Player>,
+    current: usize,
+}
+
+impl Players {
+    fn discover(conn: &mut Connection, pattern: &str) -> Result<Self> {
+        let names = conn.call(BUS.0, BUS.1, BUS.2, "ListNames", &[])?;
+        let mut players = Self::default();
+        for name in names
+            .first()
+            .and_then(Value::as_slice)
+            .unwrap_or_default()
+            .iter()
+            .filter_map(Value::as_str)
+            .filter(|name| matches(pattern, name))
+        {
+            match Player::new(conn, name.to_owned()) {
+                Ok(player) => players.players.push(player),
+                Err(e) => log::warn!("Failed to query {name}: {e}"),
+            }
+        }
+
+        // prefer a player that's already playing
+        if let Some(idx) = players.players.iter().position(|player| {
+            Playback::fetch(conn, player.name.as_str())
+                .is_ok_and(|p| p.status == PlaybackStatus::Playing)
+        }) {
+            players.current = idx;
+        }
+
+        Ok(players)
+    }
+
+    fn current(&self) -> Option<&Player> {
+        self.players.get(self.current)
+    }
+
+    fn add(&mut self, player: Player) {
+        self.remove(player.name.as_str());
+        self.players.push(player);
+    }
+
+    fn remove(&mut self, name: &str) {
+        if let Some(idx) = self.players.iter().position(|p| p.name == name) {
+            self.players.remove(idx);
+            if idx < self.current {
+                self.current -= 1;
+            } else if self.current >= self.players.len() {
+                self.current = 0;
+            }
+        }
+    }
+
+    /// Shows the player that sent a signal.
+    fn activate(&mut self, owner: &str) {
+        if let Some(idx) = self.players.iter().position(|p| p.owner == owner) {
+            self.current = idx;
+        }
+    }
+
+    fn cycle(&mut self, forward: bool) {
+        let len = self.players.len().max(1);
+        self.current = if forward {
+            (self.current + 1) % len
+        } else {
+            (self.current + len - 1) % len
+        };
+    }
+}
+
+/// Whether a bus name belongs to a player matching `pattern`.
+fn matches(pattern: &str, name: &str) -> bool {
+    name.strip_prefix(PREFIX)
+        .is_some_and(|suffix| glob_match(pattern, suffix))
+}
+
+#[derive(Debug, Clone)]
+struct Playback {
+    status: PlaybackStatus,
+    title: Option<String>,
+    artists: Vec<String>,
+    album: Option<String>,
+    length: Option<Duration>,
+    position: Duration,
+    fetched: Instant,
+}
+
+impl Playback {
+    fn fetch(conn: &mut Connection, name: &str) -> Result<Self> {
+        let props = conn
+            .call(
+                name,
+                PATH,
+                PROPERTIES,
+                "GetAll",
+                &[Value::Str(PLAYER_INTERFACE.to_owned())],
+            )?
+            .into_iter()
+            .next()
+            .ok_or_else(|| anyhow!("Empty reply from {name}"))?;
+
+        let status = match props.get("PlaybackStatus").and_then(Value::as_str) {
+            Some("Playing") => PlaybackStatus::Playing,
+            Some("Paused") => PlaybackStatus::Paused,
+            _ => PlaybackStatus::Stopped,
+        };
+        let metadata = props.get("Metadata");
+        let string = |key| {
+            metadata
+                .and_then(|m| m.get(key))
+                .and_then(Value::as_str)
+                .filter(|s| !s.is_empty())
+                .map(ToString::to_string)
+        };
+        let micros = |v: Option<&Value>| {
+            v.and_then(Value::as_i64)
+                .and_then(|v| u64::try_from(v).ok())
+                .map(Duration::from_micros)
+        };
+
+        Ok(Self {
+            status,
+            title: string("xesam:title"),
+            artists: metadata
+                .and_then(|m| m.get("xesam:artist"))
+                .and_then(Value::as_slice)
+                .unwrap_or_default()
+                .iter()
+                .filter_map(Value::as_str)
+                .map(ToString::to_string)
+                .collect(),
+            album: string("xesam:album"),
+            length: micros(metadata.and_then(|m| m.get("mpris:length")))
+                .filter(|length| !length.is_zero()),
+            position: micros(props.get("Position")).unwrap_or_default(),
+            fetched: Instant::now(),
+        })
+    }
+
+    /// The position in the track, accounting for the time since the last
+    /// fetch.
+    fn elapsed(&self) -> Duration {
+        let elapsed = if self.status == PlaybackStatus::Playing {
+            self.position + self.fetched.elapsed()
+        } else {
+            self.position
+        };
+        self.length.map_or(elapsed, |length| elapsed.min(length))
+    }
+}
+
+/// Displays information about music playing in any player that implements
+/// [MPRIS](https://specifications.freedesktop.org/mpris-spec/latest/),
+/// such as Spotify, Firefox, or mpv.
+#[derive(Builder, Debug, Clone)]
+#[builder_struct_attr(allow(missing_docs))]
+#[builder_impl_attr(allow(missing_docs))]
+pub struct Mpris {
+    name: &'static str,
+    #[builder(default = r#"String::from("*")"#)]
+    player: String,
+    #[builder(default)]
+    players: Arc<Mutex<Players>>,
+    #[builder(default, setter(skip))]
+    playback: Option<Playback>,
+    #[builder(default = "false")]
+    progress_bar: bool,
+    #[builder(default = r##"Color::from_str("#f00").unwrap()"##)]
+    progress_bg: Color,
+    last_layout: Rc<Mutex<Option<(Layout, f64)>>>,
+    index_cache: Arc<Mutex<Option<IndexCache>>>,
+    formatter: AhoCorasick,
+    formats: MprisFormats<String>,
+    attrs: Attrs,
+    common: PanelCommon,
+}
+
+impl Mpris {
+    fn draw(
+        &mut self,
+        cr: &Rc<cairo::Context>,
+        conn: &Arc<Mutex<Connection>>,
+        height: i32,
+        event: EventType,
+        paused: Arc<Mutex<bool>>,
+        wakers: [Arc<AtomicWaker>; 2],
+    ) -> Result<PanelDrawInfo> {
+        if matches!(event, EventType::Player | EventType::Action)
+            || self.playback.is_none()
+        {
+            let name = self
+                .players
+                .lock()
+                .unwrap()
+                .current()
+                .map(|p| p.name.clone());
+            self.playback = name.and_then(|name| {
+                Playback::fetch(&mut conn.lock().unwrap(), name.as_str())
+                    .map_err(|e| log::debug!("Failed to query {name}: {e}"))
+                    .ok()
+            });
+        }
+
+        let status = self
+            .playback
+            .as_ref()
+            .map_or(PlaybackStatus::Stopped, |p| p.status);
+        let format = match status {
+            PlaybackStatus::Playing => self.formats.playing.clone(),
+            PlaybackStatus::Paused => self.formats.paused.clone(),
+            PlaybackStatus::Stopped => self.formats.stopped.clone(),
+        };
+
+        let lookup = |content: &str| self.format_from_content(content, status);
+        let main =
+            media::expand(&self.formatter, self.formats.main.as_str(), lookup);
+        let text = media::expand(&self.formatter, format.as_str(), lookup);
+
+        let lookup = |content: &str| self.format_from_content(content, status);
+        let index_cache =
+            media::build_index_cache(&self.formatter, format.as_str(), lookup);
+
+        let draw = MediaDraw {
+            text: text.as_str(),
+            main: main.as_str(),
+            index_cache,
+            last_layout: self.last_layout.clone(),
+            shared_cache: self.index_cache.clone(),
+            attrs: &self.attrs,
+            common: &self.common,
+            art: None,
+            progress_bg: &self.progress_bg,
+            playing: status == PlaybackStatus::Playing,
+            paused,
+            wakers: wakers.to_vec(),
+            dump: format!("{self:?}"),
+        };
+        let layout = draw.layout(cr);
+
+        let bar = if self.progress_bar
+            && let Some(playback) = &self.playback
+            && let Some(length) = playback.length
+        {
+            Some((
+                layout.start,
+                (playback.elapsed().as_secs_f64() / length.as_secs_f64())
+                    .clamp(0.0, 1.0)
+                    * layout.width,
+            ))
+        } else {
+            None
+        };
+
+        Ok(draw.finish(layout, height, bar))
+    }
+
+    fn format_from_content(
+        &self,
+        content: &str,
+        status: PlaybackStatus,
+    ) -> Option<String> {
+        let playback = self.playback.as_ref();
+        match content {
+            "%title%" => Some(
+                playback
+                    .and_then(|p| p.title.clone())
+                    .unwrap_or_else(|| String::from("Unknown")),
+            ),
+            "%artist%" => Some(
+                playback
+                    .map(|p| p.artists.join(", "))
+                    .filter(|artists| !artists.is_empty())
+                    .unwrap_or_else(|| String::from("Unknown")),
+            ),
+            "%album%" => Some(
+                playback
+                    .and_then(|p| p.album.clone())
+                    .unwrap_or_else(|| String::from("Unknown")),
+            ),
+            "%player%" => Some(
+                self.players
+                    .lock()
+                    .unwrap()
+                    .current()
+                    .map(|p| p.identity.clone())
+                    .unwrap_or_default(),
+            ),
+            "%next%" => Some(self.formats.next.clone()),
+            "%prev%" => Some(self.formats.prev.clone()),
+            "%play%" => Some(self.formats.play.clone()),
+            "%pause%" => Some(self.formats.pause.clone()),
+            "%stop%" => Some(self.formats.stop.clone()),
+            "%toggle%" => Some(match status {
+                PlaybackStatus::Playing => self.formats.toggle_playing.clone(),
+                PlaybackStatus::Paused => self.formats.toggle_paused.clone(),
+                PlaybackStatus::Stopped => self.formats.toggle_stopped.clone(),
+            }),
+            "%main%" => Some(content.to_owned()),
+            "%shuffle%" => Some(self.formats.shuffle.clone()),
+            "%repeat%" => Some(self.formats.repeat.clone()),
+            _ => None,
+        }
+    }
+
+    /// Updates the list of players in response to a signal.
+    fn handle_signal(
+        &self,
+        conn: &Arc<Mutex<Connection>>,
+        message: &Message,
+    ) -> Result<()> {
+        let mut players = self.players.lock().unwrap();
+        if message.is_signal(BUS.2, "NameOwnerChanged") {
+            let [name, _, new_owner] = message.body.as_slice() else {
+                return Ok(());
+            };
+            let (Some(name), Some(new_owner)) =
+                (name.as_str(), new_owner.as_str())
+            else {
+                return Ok(());
+            };
+            if !matches(self.player.as_str(), name) {
+                return Ok(());
+            }
+            if new_owner.is_empty() {
+                players.remove(name);
+            } else {
+                players.add(Player::new(
+                    &mut conn.lock().unwrap(),
+                    name.to_owned(),
+                )?);
+            }
+        } else if message.is_signal(PROPERTIES, "PropertiesChanged")
+            && let [interface, changed, ..] = message.body.as_slice()
+            && interface.as_str() == Some(PLAYER_INTERFACE)
+            && changed.get("PlaybackStatus").and_then(Value::as_str)
+                == Some("Playing")
+            && let Some(sender) = &message.sender
+        {
+            // follow whichever player started playing most recently
+            players.activate(sender.as_str());
+        }
+        Ok(())
+    }
+
+    fn action(
+        name: &str,
+        conn: &Arc<Mutex<Connection>>,
+        players: &Arc<Mutex<Players>>,
+    ) -> Result<()> {
+        match name {
+            "next_player" => {
+                players.lock().unwrap().cycle(true);
+                return Ok(());
+            }
+            "prev_player" => {
+                players.lock().unwrap().cycle(false);
+                return Ok(());
+            }
+            "main" => return Ok(()),
+            _ => {}
+        }
+
+        let player = players
+            .lock()
+            .unwrap()
+            .current()
+            .map(|p| p.name.clone())
+            .ok_or_else(|| anyhow!("No player found"))?;
+        let mut conn = conn.lock().unwrap();
+        let mut call = |member| {
+            conn.call(player.as_str(), PATH, PLAYER_INTERFACE, member, &[])
+                .map(|_| ())
+        };
+        match name {
+            "next" => call("Next"),
+            "prev" => call("Previous"),
+            "play" => call("Play"),
+            "pause" => call("Pause"),
+            "toggle" => call("PlayPause"),
+            "stop" => call("Stop"),
+            "shuffle" => {
+                let shuffle = conn
+                    .get_property(
+                        player.as_str(),
+                        PATH,
+                        PLAYER_INTERFACE,
+                        "Shuffle",
+                    )?
+                    .as_bool()
+                    .unwrap_or_default();
+                set_property(
+                    &mut conn,
+                    player.as_str(),
+                    "Shuffle",
+                    Value::Bool(!shuffle),
+                )
+            }
+            "repeat" => {
+                let loop_status = conn.get_property(
+                    player.as_str(),
+                    PATH,
+                    PLAYER_INTERFACE,
+                    "LoopStatus",
+                )?;
+                let next = if loop_status.as_str() == Some("None") {
+                    "Playlist"
+                } else {
+                    "None"
+                };
+                set_property(
+                    &mut conn,
+                    player.as_str(),
+                    "LoopStatus",
+                    Value::Str(next.to_owned()),
+                )
+            }
+            _ => {
+                log::warn!("Unknown action event '{name}'");
+                Ok(())
+            }
+        }
+    }
+
+    fn process_event(
+        event: &Event,
+        conn: &Arc<Mutex<Connection>>,
+        players: &Arc<Mutex<Players>>,
+        last_layout: &Rc<Mutex<Option<(Layout, f64)>>>,
+        index_cache: &Arc<Mutex<Option<IndexCache>>>,
+        send: &UnboundedSender<EventResponse>,
+    ) -> Result<()> {
+        let result = match event {
+            Event::Action(Some(value)) => {
+                Self::action(value.as_str(), conn, players)
+            }
+            Event::Action(None) => Ok(()),
+            Event::Mouse(event) => match event.button {
+                MouseButton::Left
+                | MouseButton::Right
+                | MouseButton::Middle => {
+                    let name = match (
+                        &*last_layout.lock().unwrap(),
+                        &*index_cache.lock().unwrap(),
+                    ) {
+                        (Some(layout), Some(cache)) => {
+                            media::button_at(layout, cache, event.x, event.y)
+                        }
+                        _ => None,
+                    };
+                    name.map_or(Ok(()), |name| {
+                        Self::action(name.as_str(), conn, players)
+                    })
+                }
+                MouseButton::ScrollUp => {
+                    Self::action("prev_player", conn, players)
+                }
+                MouseButton::ScrollDown => {
+                    Self::action("next_player", conn, players)
+                }
+                MouseButton::Drag => Ok(()),
+            },
+        }
+        .map_or_else(
+            |e| {
+                EventResponse::Err(format!(
+                    "Event {event:?} produced an error: {e}",
+                ))
+            },
+            |()| EventResponse::Ok(None),
+        );
+        Ok(send.send(result)?)
+    }
+}
+
+fn set_property(
+    conn: &mut Connection,
+    player: &str,
+    property: &str,
+    value: Value,
+) -> Result<()> {
+    conn.call(
+        player,
+        PATH,
+        PROPERTIES,
+        "Set",
+        &[
+            Value::Str(PLAYER_INTERFACE.to_owned()),
+            Value::Str(property.to_owned()),
+            Value::Variant(Box::new(value)),
+        ],
+    )
+    .map(|_| ())
+}
+
+#[async_trait(?Send)]
+impl PanelConfig for Mpris {
+    /// Parses an instance of the panel from the global [`Config`]
+    ///
+    /// Configuration options:
+    /// - `player`: a glob matched against the part of each player's bus name
+    ///   after `org.mpris.MediaPlayer2.`, e.g. `spotify` or `firefox*`. Of the
+    ///   matching players, the one that most recently started playing is shown.
+    ///   - type: String
+    ///   - default: `*`
+    /// - `format_playing`: the format string to display on the panel when music
+    ///   is playing
+    ///   - type: String
+    ///   - formatting options: `%title%`, `%artist%`, `%album%`, `%player%`,
+    ///     `%next%`, `%prev%`, `%play%`, `%pause%`, `%stop%`, `%toggle%`,
+    ///     `%main%`, `%shuffle%`, `%repeat%`
+    ///   - default: `%main%`
+    /// - `format_paused`: the format string to display on the panel when music
+    ///   is paused
+    ///   - type: String
+    ///   - formatting options: see `format_playing`
+    ///   - default: `%main%`
+    /// - `format_stopped`: the format string to display on the panel when no
+    ///   music is playing or no player is running
+    ///   - type: String
+    ///   - formatting options: see `format_playing`
+    ///   - default: `not playing`
+    /// - `format_main`: the format of the main panel region. Only this section
+    ///   will scroll, and the progress bar will be limited to this region.
+    ///   - type: String
+    ///   - formatting options: `%title%`, `%artist%`, `%album%`, `%player%`
+    ///     (the others from above will work, but they won't function as
+    ///     buttons)
+    ///   - default: `%title% - %artist%`
+    /// - `format_next`: the format of the button to skip forward one song
+    ///   - type: String
+    ///   - default: empty
+    /// - `format_prev`: the format of the button to skip back one song
+    ///   - type: String
+    ///   - default: empty
+    /// - `format_play`: the format of the play button
+    ///   - type: String
+    ///   - default: empty
+    /// - `format_pause`: the format of the pause button
+    ///   - type: String
+    ///   - default: empty
+    /// - `format_stop`: the format of the stop button
+    ///   - type: String
+    ///   - default: empty
+    /// - `format_toggle_playing`: the format of the play/pause button when
+    ///   music is playing
+    ///   - type: String
+    ///   - default: empty
+    /// - `format_toggle_paused`: the format of the play/pause button when music
+    ///   is paused
+    ///   - type: String
+    ///   - default: empty
+    /// - `format_toggle_stopped`: the format of the play/pause button when no
+    ///   music is playing
+    ///   - type: String
+    ///   - default: empty
+    /// - `format_shuffle`: the format of the shuffle button
+    ///   - type: String
+    ///   - default: empty
+    /// - `format_repeat`: the format of the repeat button, which toggles
+    ///   between repeating the playlist and not repeating
+    ///   - type: String
+    ///   - default: empty
+    /// - `progress_bar`: whether to show a progress bar behind the text
+    ///   - type: bool
+    ///   - default: `false`
+    /// - `progress_bg`: the background color of the progress bar (ignored if
+    ///   `!progress_bar`)
+    /// - `max_width`, `strategy`, `ellipsis`, `scroll_interval`, `scroll_step`,
+    ///   and `scroll_separator`: see [`Overflow::parse`]. These only apply to
+    ///   `%main%`, so the buttons are always visible. For compatibility, a bare
+    ///   number for `max_width` is a number of characters (this is deprecated;
+    ///   use e.g. `"40ch"` or `"300px"`), and scrolling text moves one
+    ///   character (`"1ch"`) every 1000ms by default. Text only scrolls while
+    ///   music is playing.
+    /// - `attrs`: A string specifying the attrs for the panel. See
+    ///   [`Attrs::parse`] for details.
+    /// - See [`PanelCommon::parse_common`]. `click_*` and `scroll_*` are
+    ///   currently ignored. Clicking a button performs its action, and
+    ///   scrolling switches between players.
+    ///
+    /// The panel also responds to the actions `next`, `prev`, `play`,
+    /// `pause`, `stop`, `toggle`, `shuffle`, `repeat`, `next_player`, and
+    /// `prev_player` sent over IPC.
+    fn parse(
+        name: &'static str,
+        table: &mut HashMap<String, config::Value>,
+        _global: &Config,
+    ) -> Result<Self> {
+        let mut builder = MprisBuilder::default();
+
+        builder.name(name);
+
+        if let Some(player) = remove_string_from_config("player", table) {
+            builder.player(player);
+        }
+
+        if let Some(progress_bar) =
+            remove_bool_from_config("progress_bar", table)
+        {
+            builder.progress_bar(progress_bar);
+        }
+        if let Some(progress_bg) =
+            remove_color_from_config("progress_bg", table)
+        {
+            builder.progress_bg(progress_bg);
+        }
+        builder.last_layout(Rc::new(Mutex::new(None)));
+        builder.index_cache(Arc::new(Mutex::new(None)));
+        builder.formatter(AhoCorasick::new([
+            "%title%",
+            "%artist%",
+            "%album%",
+            "%player%",
+            "%next%",
+            "%prev%",
+            "%play%",
+            "%pause%",
+            "%stop%",
+            "%toggle%",
+            "%main%",
+            "%shuffle%",
+            "%repeat%",
+        ])?);
+
+        let overflow = Overflow::parse_media(table);
+        let mut common = PanelCommon::parse_common(table)?;
+        common.overflow = overflow;
+        let formats = PanelCommon::parse_formats(
+            table,
+            &[
+                "_playing",
+                "_paused",
+                "_stopped",
+                "_main",
+                "_next",
+                "_prev",
+                "_play",
+                "_pause",
+                "_stop",
+                "_toggle_playing",
+                "_toggle_paused",
+                "_toggle_stopped",
+                "_shuffle",
+                "_repeat",
+            ],
+            &[
+                "%main%",
+                "%main%",
+                "not playing",
+                "%title% - %artist%",
+                "",
+                "",
+                "",
+                "",
+                "",
+                "",
+                "",
+                "",
+                "",
+                "",
+            ],
+        );
+        let attrs = PanelCommon::parse_attr(table, "");
+
+        builder.common(common);
+        builder.formats(MprisFormats::new(formats));
+        builder.attrs(attrs);
+
+        Ok(builder.build()?)
+    }
+
+    fn props(&self) -> (&'static str, bool) {
+        (self.name, self.common.visible)
+    }
+
+    async fn run(
+        mut self: Box<Self>,
+        cr: Rc<cairo::Context>,
+        global_attrs: Attrs,
+        height: i32,
+    ) -> PanelRunResult {
+        let mut map = StreamMap::<
+            EventType,
+            Pin<Box<dyn Stream<Item = Result<Option<Message>>>>>,
+        >::new();
+
+        let mut conn = Connection::session()?;
+        conn.set_timeout(Some(CALL_TIMEOUT))?;
+        *self.players.lock().unwrap() =
+            Players::discover(&mut conn, self.player.as_str())?;
+        let conn = Arc::new(Mutex::new(conn));
+
+        let paused = Arc::new(Mutex::new(false));
+        let signal_waker = Arc::new(AtomicWaker::new());
+
+        map.insert(
+            EventType::Player,
+            Box::pin(tokio_stream::once(Ok(None)).chain(SignalStream {
+                conn: Arc::new(Mutex::new(signal_connection()?)),
+                handle: None,
+                done: false,
+                paused: paused.clone(),
+                waker: signal_waker.clone(),
+            })),
+        );
+
+        let progress_waker = Arc::new(AtomicWaker::new());
+
+        if self.progress_bar {
+            map.insert(
+                EventType::Progress,
+                Box::pin(
+                    ManagedIntervalStream::builder()
+                        .duration(Duration::from_secs(1))
+                        .paused(paused.clone())
+                        .waker(progress_waker.clone())
+                        .build()?
+                        .map(|_| Ok(None)),
+                ),
+            );
+        }
+
+        let (event_send, event_recv) = unbounded_channel();
+        let (response_send, response_recv) = unbounded_channel();
+        let event_conn = conn.clone();
+        let players = self.players.clone();
+        let last_layout = self.last_layout.clone();
+        let index_cache = self.index_cache.clone();
+        map.insert(
+            EventType::Action,
+            Box::pin(UnboundedReceiverStream::new(event_recv).map(move |s| {
+                Self::process_event(
+                    &s,
+                    &event_conn,
+                    &players,
+                    &last_layout,
+                    &index_cache,
+                    &response_send,
+                )
+                .map(|()| None)
+            })),
+        );
+
+        self.attrs.apply_to(&global_attrs);
+
+        Ok((
+            Box::pin(map.map(move |(t, r)| {
+                if let Some(message) = r?
+                    && let Err(e) = self.handle_signal(&conn, &message)
+                {
+                    log::warn!("mpris panel failed to handle signal: {e}");
+                }
+                self.draw(
+                    &cr,
+                    &conn,
+                    height,
+                    t,
+                    paused.clone(),
+                    [signal_waker.clone(), progress_waker.clone()],
+                )
+            })),
+            Some(ChannelEndpoint::new(event_send, response_recv)),
+        ))
+    }
+}
+
+/// Opens a second connection to the session bus that receives the signals
+/// the panel follows, so that waiting for them doesn't block method calls.
+fn signal_connection() -> Result<Connection> {
+    let mut conn = Connection::session()?;
+    conn.set_timeout(Some(CALL_TIMEOUT))?;
+    conn.add_match(
+        format!(
+            "type='signal',sender='{}',interface='{}',member='\
+             NameOwnerChanged',arg0namespace='{ROOT_INTERFACE}'",
+            BUS.0, BUS.2
+        )
+        .as_str(),
+    )?;
+    conn.add_match(
+        format!(
+            "type='signal',interface='{PROPERTIES}',member='PropertiesChanged'\
+             ,path='{PATH}'"
+        )
+        .as_str(),
+    )?;
+    conn.add_match(
+        format!(
+            "type='signal',interface='{PLAYER_INTERFACE}',member='Seeked',\
+             path='{PATH}'"
+        )
+        .as_str(),
+    )?;
+    // signals can be arbitrarily far apart
+    conn.set_timeout(None)?;
+    Ok(conn)
+}
+
+struct SignalStream {
+    conn: Arc<Mutex<Connection>>,
+    handle: Option<JoinHandle<Result<Message>>>,
+    /// Set once the connection fails, after which the stream ends rather than
+    /// failing over and over
+    done: bool,
+    paused: Arc<Mutex<bool>>,
+    waker: Arc<AtomicWaker>,
+}
+
+impl Stream for SignalStream {
+    type Item = Result<Option<Message>>;
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        self.waker.register(cx.waker());
+        if self.done {
+            Poll::Ready(None)
+        } else if *self.paused.lock().unwrap() {
+            Poll::Pending
+        } else if let Some(handle) = &mut self.handle {
+            if handle.is_finished() {
+                let value = handle.poll_unpin(cx).map(|r| {
+                    r.map_err(anyhow::Error::from).and_then(|r| r).map(Some)
+                });
+                if let Poll::Ready(value) = &value {
+                    self.handle = None;
+                    if let Err(e) = value {
+                        log::warn!(
+                            "mpris panel stopped listening for signals: {e}"
+                        );
+                        self.done = true;
+                    }
+                }
+                value.map(Some)
+            } else {
+                Poll::Pending
+            }
+        } else {
+            let conn = self.conn.clone();
+            let waker = cx.waker().clone();
+            self.handle = Some(task::spawn_blocking(move || {
+                let message = conn.lock().unwrap().next_signal();
+                waker.wake();
+                message
+            }));
+            Poll::Pending
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+
+    use super::*;
+    use crate::dbus::mock;
+
+    fn string(s: &str) -> Value {
+        Value::Str(s.to_owned())
+    }
+
+    fn variant(value: Value) -> Value {
+        Value::Variant(Box::new(value))
+    }
+
+    /// A bus with two players: `mock`, which is playing, and `other`, which
+    /// is paused. Calls to anything else on the players are logged to
+    /// `calls`.
+    fn bus(calls: Arc<Mutex<Vec<String>>>) -> Connection {
+        mock::connect(move |call| {
+            let player = call
+                .destination
+                .as_deref()
+                .and_then(|name| name.strip_prefix(PREFIX));
+            match (call.member.as_deref().unwrap_or_default(), player) {
+                ("ListNames", None) => Ok(vec![Value::Array(vec![
+                    string(BUS.0),
+                    string("org.mpris.MediaPlayer2.mock"),
+                    string("org.mpris.MediaPlayer2.other"),
+                    string("org.example.NotAPlayer"),
+                ])]),
+                ("GetNameOwner", None) => Ok(vec![string(
+                    if call.body.first().and_then(Value::as_str)
+                        == Some("org.mpris.MediaPlayer2.mock")
+                    {
+                        ":1.1"
+                    } else {
+                        ":1.2"
+                    },
+                )]),
+                ("Get", Some(player))
+                    if call.body.get(1).and_then(Value::as_str)
+                        == Some("Identity") =>
+                {
+                    Ok(vec![variant(string(format!("{player}!").as_str()))])
+                }
+                ("GetAll", Some(player)) => Ok(vec![Value::Dict(vec![
+                    (
+                        string("PlaybackStatus"),
+                        variant(string(if player == "mock" {
+                            "Playing"
+                        } else {
+                            "Paused"
+                        })),
+                    ),
+                    (string("Position"), variant(Value::Int64(1_000_000))),
+                    (
+                        string("Metadata"),
+                        variant(Value::Dict(vec![
+                            (string("xesam:title"), variant(string(player))),
+                            (
+                                string("xesam:artist"),
+                                variant(Value::Array(vec![
+                                    string("A"),
+                                    string("B"),
+                                ])),
+                            ),
+                            (
+                                string("mpris:length"),
+                                variant(Value::Int64(60_000_000)),
+                            ),
+                        ])),
+                    ),
+                ])]),
+                (member, Some(player)) => {
+                    calls.lock().unwrap().push(format!("{player}.{member}"));
+                    Ok(Vec::new())
+                }
+                (member, None) => Err(anyhow!("Unknown method {member}")),
+            }
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn discover() {
+        let mut conn = bus(Arc::default());
+
+        let players = Players::discover(&mut conn, "*").unwrap();
+        assert_eq!(players.players.len(), 2);
+        // the playing player is preferred
+        let current = players.current().unwrap();
+        assert_eq!(current.name, "org.mpris.MediaPlayer2.mock");
+        assert_eq!(current.owner, ":1.1");
+        assert_eq!(current.identity, "mock!");
+
+        let players = Players::discover(&mut conn, "oth*").unwrap();
+        assert_eq!(players.players.len(), 1);
+        assert_eq!(players.current().unwrap().owner, ":1.2");
+    }
+
+    #[test]
+    fn fetch() {
+        let mut conn = bus(Arc::default());
+
+        let playback =
+            Playback::fetch(&mut conn, "org.mpris.MediaPlayer2.other").unwrap();
+        assert_eq!(playback.status, PlaybackStatus::Paused);
+        assert_eq!(playback.title.as_deref(), Some("other"));
+        assert_eq!(playback.artists, ["A", "B"]);
+        assert_eq!(playback.album, None);
+        assert_eq!(playback.length, Some(Duration::from_secs(60)));
+        assert_eq!(playback.elapsed(), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn actions() {
+        let calls = Arc::default();
+        let mut conn = bus(Arc::clone(&calls));
+        let players =
+            Arc::new(Mutex::new(Players::discover(&mut conn, "*").unwrap()));
+        let conn = Arc::new(Mutex::new(conn));
+
+        Mpris::action("toggle", &conn, &players).unwrap();
+        Mpris::action("next_player", &conn, &players).unwrap();
+        Mpris::action("next", &conn, &players).unwrap();
+        assert_eq!(*calls.lock().unwrap(), ["mock.PlayPause", "other.Next"]);
+    }
+}
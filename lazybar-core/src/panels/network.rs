@@ -1,11 +1,16 @@
 use std::{
     collections::HashMap,
     ffi::{CStr, c_char, c_void},
+    fs,
     net::IpAddr,
+    pin::Pin,
     ptr,
     rc::Rc,
-    sync::{Arc, Mutex},
-    time::Duration,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU32, Ordering},
+    },
+    time::{Duration, Instant},
 };
 
 use anyhow::Result;
@@ -13,24 +18,37 @@ use async_trait::async_trait;
 use config::{Config, Value};
 use derive_builder::Builder;
 use futures::task::AtomicWaker;
-use if_addrs::{IfAddr, get_if_addrs};
+use if_addrs::{IfAddr, Interface, get_if_addrs};
 use rustix::{
     io::Errno,
     ioctl::{Ioctl, Opcode, ioctl},
     net::{AddressFamily, SocketType, socket},
 };
-use tokio_stream::StreamExt;
+use tokio_stream::{Stream, StreamExt};
 
 use crate::{
     Attrs, Highlight, ManagedIntervalStream, PanelConfig, PanelRunResult,
-    array_to_struct,
+    Template, array_to_struct,
     bar::PanelDrawInfo,
     common::{PanelCommon, ShowHide},
+    glob_match, netlink, remove_array_from_config, remove_bool_from_config,
     remove_string_from_config, remove_uint_from_config,
+    template::Var,
 };
 
 array_to_struct!(NetworkFormats, connected, disconnected);
 
+/// Why the panel is being redrawn.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Trigger {
+    /// The polling interval elapsed
+    Interval,
+    /// A link or address changed
+    Link,
+    /// A route changed, so the default route may have moved
+    Route,
+}
+
 /// Displays information about the current network connection on a given
 /// interface.
 #[derive(Builder, Debug, Clone)]
@@ -40,11 +58,29 @@ pub struct Network {
     name: &'static str,
     #[builder(default = r#"String::from("wlan0")"#)]
     if_name: String,
+    #[builder(default, setter(strip_option))]
+    auto: Option<AutoSelect>,
+    #[builder(default)]
+    current: Option<String>,
+    #[builder(default, setter(skip))]
+    watching_routes: bool,
+    /// The index of the interface being shown, or 0 if it doesn't exist.
+    /// Link and address notifications for other interfaces are ignored.
+    #[builder(default, setter(skip))]
+    index: Arc<AtomicU32>,
+    #[builder(default = "true")]
+    ipv6_fallback: bool,
     #[builder(default = r#"Duration::from_secs(10)"#)]
     duration: Duration,
     #[builder(default)]
     waker: Arc<AtomicWaker>,
-    formats: NetworkFormats<String>,
+    #[builder(default)]
+    last_stats: Option<(Stats, Instant)>,
+    #[builder(default)]
+    rx_rate: f64,
+    #[builder(default)]
+    tx_rate: f64,
+    formats: NetworkFormats<Template>,
     attrs: Attrs,
     #[builder(default, setter(strip_option))]
     highlight: Option<Highlight>,
@@ -53,33 +89,97 @@ pub struct Network {
 
 impl Network {
     fn draw(
-        &self,
+        &mut self,
         cr: &Rc<cairo::Context>,
         height: i32,
         paused: Arc<Mutex<bool>>,
+        trigger: Trigger,
     ) -> Result<PanelDrawInfo> {
-        let essid = glib::markup_escape_text(
-            query_essid(self.if_name.as_str())
-                .unwrap_or_default()
-                .as_str(),
+        let if_name = match &self.auto {
+            // the default route can only move when a route changes, so the
+            // route table isn't dumped for other events
+            Some(auto)
+                if self.current.is_none()
+                    || trigger == Trigger::Route
+                    || (trigger == Trigger::Interval
+                        && !self.watching_routes) =>
+            {
+                auto.select()
+            }
+            Some(_) => self.current.clone(),
+            None => Some(self.if_name.clone()),
+        };
+        if if_name != self.current {
+            // rates can't be compared across interfaces
+            self.last_stats = None;
+            self.rx_rate = 0.0;
+            self.tx_rate = 0.0;
+            self.current.clone_from(&if_name);
+        }
+        let if_name = if_name.unwrap_or_default();
+        self.index.store(
+            interface_index(if_name.as_str()).unwrap_or_default(),
+            Ordering::Relaxed,
         );
-        let ip = query_ip(self.if_name.as_str());
-
-        let text = ip.map_or_else(
-            || {
-                self.formats
-                    .disconnected
-                    .replace("%ifname%", self.if_name.as_str())
-                    .replace("%essid%", essid.as_str())
-            },
-            |ip| {
-                self.formats
-                    .connected
-                    .replace("%ifname%", self.if_name.as_str())
-                    .replace("%essid%", essid.as_str())
-                    .replace("%local_ip%", ip.to_string().as_str())
-            },
+
+        let now = Instant::now();
+        let stats = Stats::query(if_name.as_str());
+        // netlink events can arrive in bursts, so throughput is only sampled
+        // on the interval to keep the rates and history evenly spaced
+        if trigger == Trigger::Interval {
+            if let (Some(stats), Some((last_stats, last_time))) =
+                (stats, self.last_stats)
+            {
+                let elapsed = now.duration_since(last_time).as_secs_f64();
+                if elapsed > 0.0 {
+                    self.rx_rate =
+                        stats.rx.saturating_sub(last_stats.rx) as f64 / elapsed;
+                    self.tx_rate =
+                        stats.tx.saturating_sub(last_stats.tx) as f64 / elapsed;
+                    self.common.history.push(self.rx_rate + self.tx_rate);
+                }
+            }
+            self.last_stats = stats.map(|stats| (stats, now));
+        }
+
+        let essid = glib::markup_escape_text(
+            query_essid(if_name.as_str()).unwrap_or_default().as_str(),
         );
+        let (ip, ip6) = query_ip(if_name.as_str());
+        let ip = if self.ipv6_fallback { ip.or(ip6) } else { ip };
+
+        let format = if ip.is_some() {
+            &self.formats.connected
+        } else {
+            &self.formats.disconnected
+        };
+        let station = ["signal", "signal_percentage", "bitrate"]
+            .into_iter()
+            .any(|name| format.uses(name))
+            .then(|| query_station(if_name.as_str()))
+            .flatten();
+        let text = format.render(|name| match name {
+            "ifname" => (!if_name.is_empty()).then(|| if_name.as_str().into()),
+            "type" => Some(interface_type(if_name.as_str())?.into()),
+            "essid" => Some(essid.as_str().into()),
+            "local_ip" => Some(ip?.to_string().into()),
+            "local_ip6" => Some(ip6?.to_string().into()),
+            "connected" => Some(ip.is_some().into()),
+            "graph" => Some(self.common.history.var()),
+            "rx_rate" => Some(Var::float(self.rx_rate, 0)),
+            "tx_rate" => Some(Var::float(self.tx_rate, 0)),
+            "rx_total" => Some(stats?.rx.into()),
+            "tx_total" => Some(stats?.tx.into()),
+            "speed" => Some(stats?.speed?.into()),
+            "signal" => Some(station?.signal.into()),
+            "signal_percentage" => Some(
+                ((i32::from(station?.signal) + 90) * 100 / 60)
+                    .clamp(0, 100)
+                    .into(),
+            ),
+            "bitrate" => Some(Var::float(station?.bitrate?, 1)),
+            _ => None,
+        });
 
         self.common.draw(
             cr,
@@ -101,14 +201,59 @@ impl PanelConfig for Network {
     ///
     /// Configuration options:
     /// - `if_name`: the name of the given interface. These can be listed with
-    ///   `ip link`.
+    ///   `ip link`. If this is `auto`, the panel follows the interface that
+    ///   holds the default route, falling back to any interface that matches
+    ///   the options below if there is no default route.
     ///   - type: String
     ///   - default: "wlan0"
-    /// - `interval`: the amount of time in seconds to wait between polls
+    /// - `include`: when `if_name` is `auto`, only interfaces whose names match
+    ///   one of these globs are considered (e.g. `["wl*", "en*"]`). By default,
+    ///   every interface other than `lo` is considered.
+    ///   - type: Vec<String>
+    ///   - default: []
+    /// - `exclude`: when `if_name` is `auto`, interfaces whose names match one
+    ///   of these globs are never chosen (e.g. `["docker*", "veth*"]`).
+    ///   - type: Vec<String>
+    ///   - default: []
+    /// - `priority`: when `if_name` is `auto` and several interfaces have a
+    ///   default route, the first one to match the earliest glob in this list
+    ///   is chosen. Ties are broken by route metric.
+    ///   - type: Vec<String>
+    ///   - default: []
+    /// - `interval`: the amount of time in seconds to wait between polls.
+    ///   Changes to the interface's state and addresses (and the default route,
+    ///   when `if_name` is `auto`) are shown immediately regardless, but
+    ///   throughput is only measured this often.
     ///   - type: u64
     ///   - default: 10
+    /// - `format`: a format string to use for both states, unless overridden
+    ///   below. See [`PanelCommon::parse_templates`].
+    ///   - type: String
+    ///   - formatting options:
+    ///     - `%ifname%`, `%essid%`, `%connected%`
+    ///     - `%type%`: `wired`, `wireless`, or `tun`
+    ///     - `%local_ip%`: the first IPv4 address. If there is no IPv4 address
+    ///       and `ipv6_fallback` is set, this is `%local_ip6%` instead.
+    ///     - `%local_ip6%`: the first global IPv6 address, or link-local
+    ///       address if there is no global address
+    ///     - `%rx_rate%`, `%tx_rate%`: the number of bytes received and
+    ///       transmitted per second, e.g. `{rx_rate|human}/s`
+    ///     - `%rx_total%`, `%tx_total%`: the number of bytes received and
+    ///       transmitted since the interface came up
+    ///     - `%speed%`: the link speed in Mb/s, if the driver reports it (most
+    ///       wireless drivers don't)
+    ///     - `%signal%`, `%signal_percentage%`: the wireless signal strength in
+    ///       dBm and as an approximate percentage
+    ///     - `%bitrate%`: the wireless transmit bitrate in Mb/s
+    ///     - `%graph%`
+    /// - `ipv6_fallback`: whether an interface with only IPv6 addresses counts
+    ///   as connected and shows its IPv6 address as `%local_ip%`. Note that
+    ///   this includes link-local addresses, which most interfaces get as soon
+    ///   as they're up. Disable this to only count IPv4 connections.
+    ///   - type: bool
+    ///   - default: true
     /// - `format_connected`: the format string when there is a connection
-    ///   present on the interface
+    ///   present on the interface (an address as described for `%local_ip%`)
     ///   - type: String
     ///   - default: "%ifname% %essid% %local_ip%"
     /// - `format_disconnected`: the format string when there is no connection
@@ -119,6 +264,9 @@ impl PanelConfig for Network {
     ///   [`Attrs::parse`] for details.
     /// - `highlight`: A string specifying the highlight for the panel. See
     ///   [`Highlight::parse`] for details.
+    /// - `graph`, `history_length`, `history_interval`: Control `%graph%`,
+    ///   which shows recent throughput (bytes received and transmitted per
+    ///   second). See [`History::parse`][crate::History::parse] for details.
     /// - See [`PanelCommon::parse_common`].
     fn parse(
         name: &'static str,
@@ -129,18 +277,26 @@ impl PanelConfig for Network {
 
         builder.name(name);
         if let Some(if_name) = remove_string_from_config("if_name", table) {
+            if if_name == "auto" {
+                builder.auto(AutoSelect::parse(table));
+            }
             builder.if_name(if_name);
         }
         if let Some(duration) = remove_uint_from_config("interval", table) {
             builder.duration(Duration::from_secs(duration));
         }
+        if let Some(ipv6_fallback) =
+            remove_bool_from_config("ipv6_fallback", table)
+        {
+            builder.ipv6_fallback(ipv6_fallback);
+        }
 
         let common = PanelCommon::parse_common(table)?;
-        let formats = PanelCommon::parse_formats(
+        let formats = PanelCommon::parse_templates(
             table,
             &["_connected", "_disconnected"],
             &["%ifname% %essid% %local_ip%", "%ifname% disconnected"],
-        );
+        )?;
         let attrs = PanelCommon::parse_attr(table, "");
         let highlight = PanelCommon::parse_highlight(table, "");
 
@@ -165,17 +321,261 @@ impl PanelConfig for Network {
         self.attrs.apply_to(&global_attrs);
 
         let paused = Arc::new(Mutex::new(false));
-        let stream = ManagedIntervalStream::builder()
+        let interval = ManagedIntervalStream::builder()
             .duration(self.duration)
             .paused(paused.clone())
             .waker(self.waker.clone())
             .build()?
-            .map(move |_| self.draw(&cr, height, paused.clone()));
+            .map(|_| Trigger::Interval);
+        let mut events: Pin<Box<dyn Stream<Item = Trigger>>> =
+            match netlink::Socket::route(
+                netlink::RTMGRP_LINK
+                    | netlink::RTMGRP_IPV4_IFADDR
+                    | netlink::RTMGRP_IPV6_IFADDR,
+            ) {
+                Ok(socket) => {
+                    let index = self.index.clone();
+                    Box::pin(
+                        interval.merge(
+                            netlink::Notifications::filtered(
+                                socket,
+                                move |buf| {
+                                    concerns(buf, index.load(Ordering::Relaxed))
+                                },
+                            )
+                            .map(|()| Trigger::Link),
+                        ),
+                    )
+                }
+                Err(e) => {
+                    log::warn!(
+                        "Failed to listen for network changes, only polling: \
+                         {e}"
+                    );
+                    Box::pin(interval)
+                }
+            };
+        // routes only matter when the interface is chosen automatically
+        if self.auto.is_some() {
+            match netlink::Socket::route(
+                netlink::RTMGRP_IPV4_ROUTE | netlink::RTMGRP_IPV6_ROUTE,
+            ) {
+                Ok(socket) => {
+                    self.watching_routes = true;
+                    events = Box::pin(
+                        events.merge(
+                            netlink::Notifications::new(socket)
+                                .map(|()| Trigger::Route),
+                        ),
+                    );
+                }
+                Err(e) => log::warn!(
+                    "Failed to listen for route changes, only polling: {e}"
+                ),
+            }
+        }
+        let stream = events.map(move |trigger| {
+            self.draw(&cr, height, paused.clone(), trigger)
+        });
 
         Ok((Box::pin(stream), None))
     }
 }
 
+const RTM_NEWLINK: u16 = 16;
+const RTM_DELLINK: u16 = 17;
+const RTM_NEWADDR: u16 = 20;
+const RTM_DELADDR: u16 = 21;
+const RTM_GETROUTE: u16 = 26;
+const RTM_HEADER_LEN: usize = 12;
+const RT_TABLE_MAIN: u8 = 254;
+const RTN_UNICAST: u8 = 1;
+const RTA_OIF: u16 = 4;
+const RTA_PRIORITY: u16 = 6;
+const RTA_TABLE: u16 = 15;
+
+/// Chooses an interface automatically when `if_name` is `auto`.
+#[derive(Debug, Clone, Default)]
+struct AutoSelect {
+    include: Vec<String>,
+    exclude: Vec<String>,
+    priority: Vec<String>,
+    /// Used to dump the route table, opened on first use
+    socket: Arc<Mutex<Option<netlink::Socket>>>,
+}
+
+impl AutoSelect {
+    fn parse(table: &mut HashMap<String, Value>) -> Self {
+        let mut globs = |key: &str| {
+            remove_array_from_config(key, table)
+                .unwrap_or_default()
+                .into_iter()
+                .filter_map(|v| v.into_string().ok())
+                .collect::<Vec<_>>()
+        };
+        Self {
+            include: globs("include"),
+            exclude: globs("exclude"),
+            priority: globs("priority"),
+            socket: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    fn allows(&self, if_name: &str) -> bool {
+        let matches = |globs: &[String]| {
+            globs.iter().any(|glob| glob_match(glob, if_name))
+        };
+        if self.include.is_empty() {
+            if_name != "lo" && !matches(&self.exclude)
+        } else {
+            matches(&self.include) && !matches(&self.exclude)
+        }
+    }
+
+    fn rank(&self, if_name: &str) -> usize {
+        self.priority
+            .iter()
+            .position(|glob| glob_match(glob, if_name))
+            .unwrap_or(self.priority.len())
+    }
+
+    /// Returns the name of the best interface with a default route, or the
+    /// best interface overall if no allowed interface has a default route.
+    fn select(&self) -> Option<String> {
+        let names = interface_names();
+        let mut routed = self
+            .default_routes()
+            .unwrap_or_else(|e| {
+                log::warn!("Failed to query default routes: {e}");
+                Vec::new()
+            })
+            .into_iter()
+            .filter_map(|(index, metric)| {
+                let name = names.get(&index)?;
+                self.allows(name).then_some((name, metric))
+            })
+            .collect::<Vec<_>>();
+        routed.sort_by_key(|(name, metric)| (self.rank(name), *metric));
+        if let Some((name, _)) = routed.first() {
+            return Some((*name).clone());
+        }
+
+        let mut fallback = names
+            .into_values()
+            .filter(|name| self.allows(name))
+            .collect::<Vec<_>>();
+        fallback.sort_by(|a, b| {
+            self.rank(a).cmp(&self.rank(b)).then_with(|| a.cmp(b))
+        });
+        fallback.into_iter().next()
+    }
+
+    /// Queries the default routes, reusing the socket from previous queries.
+    /// The socket is reopened next time if anything goes wrong.
+    fn default_routes(&self) -> Result<Vec<(u32, u32)>> {
+        let mut socket = self.socket.lock().unwrap();
+        let routes = match socket.as_mut() {
+            Some(socket) => query_default_routes(socket),
+            None => {
+                let mut new = netlink::Socket::route(0)?;
+                let routes = query_default_routes(&mut new);
+                *socket = Some(new);
+                routes
+            }
+        };
+        if routes.is_err() {
+            *socket = None;
+        }
+        routes
+    }
+}
+
+/// Returns true if any of the link or address notifications in `buf` are
+/// about the interface with index `index`. Until the interface exists (when
+/// `index` is 0), any notification could be about it.
+fn concerns(buf: &[u8], index: u32) -> bool {
+    index == 0
+        || netlink::messages(buf).any(|(ty, body)| {
+            // both ifinfomsg and ifaddrmsg hold the index at offset 4
+            matches!(ty, RTM_NEWLINK | RTM_DELLINK | RTM_NEWADDR | RTM_DELADDR)
+                && body.get(4..8).and_then(netlink::u32_attr) == Some(index)
+        })
+}
+
+/// Looks up the index of an interface by name.
+fn interface_index(if_name: &str) -> Option<u32> {
+    fs::read_to_string(format!("/sys/class/net/{if_name}/ifindex"))
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Maps interface indices to names.
+fn interface_names() -> HashMap<u32, String> {
+    let Ok(dir) = fs::read_dir("/sys/class/net") else {
+        return HashMap::new();
+    };
+    dir.filter_map(|entry| {
+        let name = entry.ok()?.file_name().into_string().ok()?;
+        Some((interface_index(name.as_str())?, name))
+    })
+    .collect()
+}
+
+/// Returns the interface index and metric of each default route in the main
+/// routing table.
+fn query_default_routes(
+    socket: &mut netlink::Socket,
+) -> Result<Vec<(u32, u32)>> {
+    let replies = socket.route_request(
+        RTM_GETROUTE,
+        netlink::NLM_F_DUMP,
+        &[0; RTM_HEADER_LEN],
+    )?;
+
+    Ok(replies
+        .iter()
+        .filter(|reply| reply.len() >= RTM_HEADER_LEN)
+        .filter_map(|reply| {
+            let (header, attrs) = reply.split_at(RTM_HEADER_LEN);
+            // rtmsg: family, dst_len, src_len, tos, table, protocol, scope,
+            // type, flags
+            let table = netlink::find_attr(attrs, RTA_TABLE)
+                .and_then(netlink::u32_attr)
+                .unwrap_or_else(|| u32::from(header[4]));
+            if header[1] != 0
+                || header[7] != RTN_UNICAST
+                || table != u32::from(RT_TABLE_MAIN)
+            {
+                return None;
+            }
+            let index = netlink::find_attr(attrs, RTA_OIF)
+                .and_then(netlink::u32_attr)?;
+            let metric = netlink::find_attr(attrs, RTA_PRIORITY)
+                .and_then(netlink::u32_attr)
+                .unwrap_or_default();
+            Some((index, metric))
+        })
+        .collect())
+}
+
+/// Classifies an interface as `wired`, `wireless`, or `tun`.
+fn interface_type(if_name: &str) -> Option<&'static str> {
+    let path = format!("/sys/class/net/{if_name}");
+    let ty = fs::read_to_string(format!("{path}/type")).ok()?;
+    // ARPHRD_NONE is used by layer 3 tunnels like wireguard
+    if ty.trim() == "65534" || fs::exists(format!("{path}/tun_flags")).ok()? {
+        Some("tun")
+    } else if fs::exists(format!("{path}/wireless")).ok()?
+        || fs::exists(format!("{path}/phy80211")).ok()?
+    {
+        Some("wireless")
+    } else {
+        Some("wired")
+    }
+}
+
 #[repr(C)]
 struct Essid {
     ptr: *const c_char,
@@ -231,17 +631,108 @@ fn query_essid(if_name: &str) -> Result<String> {
     Ok(unsafe { CStr::from_ptr(res) }.to_str()?.to_owned())
 }
 
-fn query_ip(if_name: &str) -> Option<IpAddr> {
-    let (v4, v6) =
-        get_if_addrs()
-            .ok()?
-            .into_iter()
-            .partition::<Vec<_>, _>(|i| match i.addr {
-                IfAddr::V4(_) => true,
-                IfAddr::V6(_) => false,
-            });
+/// Returns the first IPv4 address and the first IPv6 address of an interface,
+/// preferring global IPv6 addresses to link-local ones.
+fn query_ip(if_name: &str) -> (Option<IpAddr>, Option<IpAddr>) {
+    let Ok(addrs) = get_if_addrs() else {
+        return (None, None);
+    };
+    let addrs = addrs
+        .into_iter()
+        .filter(|i| i.name == if_name)
+        .collect::<Vec<_>>();
+
+    let v4 = addrs
+        .iter()
+        .find(|i| matches!(i.addr, IfAddr::V4(_)))
+        .map(Interface::ip);
+    let mut v6 = addrs
+        .iter()
+        .filter_map(|i| match &i.addr {
+            IfAddr::V6(addr) => Some(addr.ip),
+            IfAddr::V4(_) => None,
+        })
+        .collect::<Vec<_>>();
+    // link-local addresses (fe80::/10) go last
+    v6.sort_by_key(|ip| ip.segments()[0] & 0xffc0 == 0xfe80);
+
+    (v4, v6.first().copied().map(IpAddr::V6))
+}
 
-    Some(v4.into_iter().chain(v6).find(|i| i.name == if_name)?.ip())
+#[derive(Debug, Clone, Copy)]
+struct Stats {
+    rx: u64,
+    tx: u64,
+    speed: Option<u64>,
+}
+
+impl Stats {
+    fn query(if_name: &str) -> Option<Self> {
+        let read = |path: &str| {
+            fs::read_to_string(format!("/sys/class/net/{if_name}/{path}"))
+                .ok()?
+                .trim()
+                .parse::<i64>()
+                .ok()
+        };
+        Some(Self {
+            rx: read("statistics/rx_bytes")?.try_into().ok()?,
+            tx: read("statistics/tx_bytes")?.try_into().ok()?,
+            // this is -1 or unreadable for most wireless interfaces
+            speed: read("speed").and_then(|speed| speed.try_into().ok()),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Station {
+    signal: i8,
+    bitrate: Option<f64>,
+}
+
+const NL80211_CMD_GET_STATION: u8 = 17;
+const NL80211_ATTR_IFINDEX: u16 = 3;
+const NL80211_ATTR_STA_INFO: u16 = 21;
+const NL80211_STA_INFO_SIGNAL: u16 = 7;
+const NL80211_STA_INFO_TX_BITRATE: u16 = 8;
+const NL80211_RATE_INFO_BITRATE: u16 = 1;
+const NL80211_RATE_INFO_BITRATE32: u16 = 5;
+
+/// Queries nl80211 for the signal strength and bitrate of the access point
+/// that a wireless interface is connected to.
+fn query_station(if_name: &str) -> Option<Station> {
+    let index = interface_index(if_name)?;
+
+    let mut socket = netlink::Socket::generic().ok()?;
+    let family = socket.family("nl80211").ok()?;
+    let replies = socket
+        .request(
+            family,
+            netlink::NLM_F_DUMP,
+            NL80211_CMD_GET_STATION,
+            0,
+            &[(NL80211_ATTR_IFINDEX, &index.to_ne_bytes())],
+        )
+        .ok()?;
+
+    replies.iter().find_map(|reply| {
+        let info = netlink::find_attr(reply, NL80211_ATTR_STA_INFO)?;
+        let signal =
+            *netlink::find_attr(info, NL80211_STA_INFO_SIGNAL)?.first()? as i8;
+        let bitrate = netlink::find_attr(info, NL80211_STA_INFO_TX_BITRATE)
+            .and_then(|rate| {
+                netlink::find_attr(rate, NL80211_RATE_INFO_BITRATE32)
+                    .and_then(netlink::u32_attr)
+                    .or_else(|| {
+                        netlink::find_attr(rate, NL80211_RATE_INFO_BITRATE)
+                            .and_then(netlink::u16_attr)
+                            .map(u32::from)
+                    })
+            })
+            // reported in units of 100 kb/s
+            .map(|rate| f64::from(rate) / 10.0);
+        Some(Station { signal, bitrate })
+    })
 }
 
 struct EssidIoctl {
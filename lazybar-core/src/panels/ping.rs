@@ -20,7 +20,8 @@ use tokio::{
 use tokio_stream::{Stream, StreamExt};
 
 use crate::{
-    Attrs, Highlight, PanelConfig, PanelRunResult, Ramp, array_to_struct,
+    Attrs, Highlight, PanelConfig, PanelRunResult, Ramp, Template,
+    array_to_struct,
     bar::PanelDrawInfo,
     common::{PanelCommon, ShowHide},
     remove_string_from_config, remove_uint_from_config,
@@ -47,7 +48,9 @@ pub struct Ping {
     pings: usize,
     #[builder(default, setter(strip_option))]
     max_ping: Option<u32>,
-    formats: PingFormats<String>,
+    #[builder(default)]
+    last_ping: Option<u128>,
+    formats: PingFormats<Template>,
     attrs: Attrs,
     #[builder(default, setter(strip_option))]
     highlight: Option<Highlight>,
@@ -57,37 +60,58 @@ pub struct Ping {
 
 impl Ping {
     fn draw(
-        &self,
+        &mut self,
         cr: &Rc<cairo::Context>,
-        ping: Result<u128>,
+        ping: Option<Result<u128>>,
         height: i32,
         paused: Arc<Mutex<bool>>,
     ) -> Result<PanelDrawInfo> {
-        let text = ping.map_or_else(
-            |_| self.formats.disconnected.to_string(),
-            |ping| {
-                self.formats
-                    .connected
-                    .replace("%ping%", ping.to_string().as_str())
-                    .replace(
-                        "%ramp%",
-                        self.ramp
-                            .choose::<u32>(
-                                ping as u32,
-                                0,
-                                self.max_ping.unwrap_or(2000).clamp(0, 2000),
-                            )
-                            .as_str(),
-                    )
-            },
+        if let Some(ping) = ping {
+            self.last_ping = ping.ok();
+            self.common
+                .history
+                .push(self.last_ping.map_or(f64::NAN, |ping| ping as f64));
+        }
+        let ping = self.last_ping;
+        let format = if ping.is_some() {
+            &self.formats.connected
+        } else {
+            &self.formats.disconnected
+        };
+        let max_ping = self.max_ping.unwrap_or(2000).clamp(0, 2000);
+        let (attrs, highlight) = match ping {
+            Some(ping) => self.common.color_ramps.apply(
+                ping as u32,
+                0,
+                max_ping,
+                &self.attrs,
+                self.highlight.as_ref(),
+            ),
+            None => (self.attrs.clone(), self.highlight.clone()),
+        };
+        // NaN never crosses a threshold
+        let (format, attrs, highlight) = self.common.thresholds.style(
+            ping.map_or(f64::NAN, |ping| ping as f64),
+            format,
+            &attrs,
+            highlight.as_ref(),
         );
+        let text = format.render(|name| match name {
+            "ping" => Some(ping?.into()),
+            "ramp" => {
+                Some(self.ramp.choose::<u32>(ping? as u32, 0, max_ping).into())
+            }
+            "connected" => Some(ping.is_some().into()),
+            "graph" => Some(self.common.history.var()),
+            _ => None,
+        });
 
         self.common.draw(
             cr,
             text.as_str(),
-            &self.attrs,
+            &attrs,
             self.common.dependence,
-            self.highlight.clone(),
+            highlight,
             self.common.images.clone(),
             height,
             ShowHide::Default(paused, self.waker.clone()),
@@ -109,9 +133,13 @@ impl PanelConfig for Ping {
     /// - `pings`: how many times to ping per run (the results will be averaged)
     ///   - type: u64
     ///   - default 5
+    /// - `format`: a format string to use for both states, unless overridden
+    ///   below. See [`PanelCommon::parse_templates`].
+    ///   - type: String
+    ///   - formatting options: `%ping%`, `%ramp%`, `%connected%`, `%graph%`
     /// - `format_connected`: the format string
     ///   - type: String
-    ///   - formatting options: `%ping%`, `%ramp%`
+    ///   - formatting options: same as `format`
     ///   - default: `%ping%ms`
     /// - `format_disconnected`: the format string when all pings fail
     ///   - type: String
@@ -126,9 +154,15 @@ impl PanelConfig for Ping {
     ///   [`Attrs::parse`] for details.
     /// - `highlight`: A string specifying the highlight for the panel. See
     ///   [`Highlight::parse`] for details.
-    /// - `ramp`: A string specifying the ramp to show ping. See [`Ramp::parse`]
-    ///   for details.
-    /// - See [`PanelCommon::parse_common`].
+    /// - `ramp_fg`, `ramp_bg`, `ramp_highlight`: Ramps whose colors follow the
+    ///   ping time, using the same range as `ramp`. Ping times are a good fit
+    ///   for ramps with `scale = "log"`. See
+    ///   [`ColorRamps::parse`][crate::ColorRamps::parse] for details.
+    /// - `graph`, `history_length`, `history_interval`: Control `%graph%`,
+    ///   which shows recent ping times, with gaps where all pings failed. See
+    ///   [`History::parse`][crate::History::parse] for details.
+    /// - See [`PanelCommon::parse_common`]. Thresholds apply to the ping time
+    ///   in milliseconds.
     fn parse(
         name: &'static str,
         table: &mut HashMap<String, config::Value>,
@@ -156,11 +190,11 @@ impl PanelConfig for Ping {
         }
 
         let common = PanelCommon::parse_common(table)?;
-        let formats = PanelCommon::parse_formats(
+        let formats = PanelCommon::parse_templates(
             table,
             &["_connected", "_disconnected"],
             &["%ping%ms", "disconnected"],
-        );
+        )?;
         let attrs = PanelCommon::parse_attr(table, "");
         let highlight = PanelCommon::parse_highlight(table, "");
         let ramp = PanelCommon::parse_ramp(table, "");
@@ -201,8 +235,12 @@ impl PanelConfig for Ping {
             paused: paused.clone(),
             waker: self.waker.clone(),
             handle: None,
-        }
-        .map(move |ping| self.draw(&cr, ping, height, paused.clone()));
+        };
+        let stream = self
+            .common
+            .thresholds
+            .blink(stream, paused.clone(), self.waker.clone())
+            .map(move |ping| self.draw(&cr, ping, height, paused.clone()));
 
         Ok((Box::pin(stream), None))
     }
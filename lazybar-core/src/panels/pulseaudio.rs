@@ -1,11 +1,12 @@
 use std::{
     cell::RefCell,
     collections::HashMap,
+    mem,
     pin::Pin,
     rc::Rc,
     sync::{
         Arc, Mutex,
-        mpsc::{Receiver, channel},
+        mpsc::{Receiver, Sender, channel},
     },
     task::Poll,
 };
@@ -19,12 +20,14 @@ use lazybar_types::EventResponse;
 use libpulse_binding::{
     callbacks::ListResult,
     context::{
-        self, FlagSet, State, introspect::Introspector,
-        subscribe::InterestMaskSet,
+        self, FlagSet, State,
+        introspect::Introspector,
+        subscribe::{Facility, InterestMaskSet},
     },
     mainloop::threaded,
     operation,
-    volume::Volume,
+    proplist::{Proplist, properties},
+    volume::{ChannelVolumes, Volume},
 };
 use tokio::{
     sync::mpsc::{UnboundedSender, unbounded_channel},
@@ -35,36 +38,207 @@ use tokio_stream::{
 };
 
 use crate::{
-    Attrs, Highlight, PanelConfig, PanelRunResult, Ramp,
+    Attrs, ButtonIndex, Highlight, IndexCache, PanelConfig, PanelRunResult,
+    Ramp, Template,
     actions::Actions,
     array_to_struct,
     bar::{Event, MouseButton, PanelDrawInfo},
-    common::{PanelCommon, ShowHide},
+    common::{HitLayout, PanelCommon, ShowHide},
+    get_table_from_config,
+    inline::PlacedObject,
     ipc::ChannelEndpoint,
     remove_string_from_config, remove_uint_from_config,
+    template::Var,
 };
 
 array_to_struct!(PulseaudioFormats, unmuted, muted);
 array_to_struct!(PulseaudioRamps, unmuted, muted);
 
-/// Displays the current volume and mute status of a given sink.
+/// Stands in for `%streams%` while the positions of the streams are found.
+const STREAMS_MARKER: char = '\u{f8f2}';
+
+/// Whether the panel shows an output or an input device.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+enum DeviceType {
+    #[default]
+    Sink,
+    Source,
+}
+
+impl DeviceType {
+    const fn default_name(self) -> &'static str {
+        match self {
+            Self::Sink => "@DEFAULT_SINK@",
+            Self::Source => "@DEFAULT_SOURCE@",
+        }
+    }
+
+    const fn facility(self) -> Facility {
+        match self {
+            Self::Sink => Facility::Sink,
+            Self::Source => Facility::Source,
+        }
+    }
+
+    const fn interest(self) -> InterestMaskSet {
+        match self {
+            Self::Sink => InterestMaskSet::SINK,
+            Self::Source => InterestMaskSet::SOURCE,
+        }
+    }
+}
+
+/// The state of the device, along with the applications that are recording
+/// from any source.
+#[derive(Debug, Clone, Default)]
+struct Status {
+    volume: Volume,
+    mute: bool,
+    device: String,
+    port: String,
+    port_kind: &'static str,
+    recording: Vec<String>,
+    streams: Vec<SinkInput>,
+}
+
+/// An application that is playing audio.
+#[derive(Debug, Clone)]
+struct SinkInput {
+    index: u32,
+    app: String,
+    binary: Option<String>,
+    volume: ChannelVolumes,
+    mute: bool,
+}
+
+impl SinkInput {
+    fn matches(&self, app: &str) -> bool {
+        self.app.eq_ignore_ascii_case(app)
+            || self
+                .binary
+                .as_ref()
+                .is_some_and(|binary| binary.eq_ignore_ascii_case(app))
+    }
+}
+
+/// Raises or lowers each channel by `unit` percentage points, up to 100%.
+fn step(volume: &mut ChannelVolumes, unit: u32, up: bool) {
+    volume.get_mut().iter_mut().for_each(|v| {
+        v.0 = if up {
+            (v.0 + unit * Volume::NORMAL.0 / 100).min(Volume::NORMAL.0)
+        } else {
+            v.0.saturating_sub(unit * Volume::NORMAL.0 / 100)
+        };
+    });
+}
+
+fn percentage(volume: Volume) -> Var {
+    Var::float(
+        (volume.0 as f64 / Volume::NORMAL.0 as f64 * 100.0).round(),
+        0,
+    )
+}
+
+/// Finds the position of each stream in the text shown by the panel.
+///
+/// `text` should contain [`STREAMS_MARKER`] where the streams will be
+/// inserted. Only the first set of streams is clickable.
+fn stream_regions(
+    text: &str,
+    segments: &[(u32, String)],
+    separator: &str,
+) -> IndexCache {
+    let plain_len = |markup: &str| {
+        let (markup, _) = PlacedObject::extract(markup);
+        pango::parse_markup(markup.as_str(), '\0')
+            .map_or_else(|_| markup.len(), |l| l.1.len())
+    };
+    let (text, _) = PlacedObject::extract(text);
+    let Some(mut start) = pango::parse_markup(text.as_str(), '\0')
+        .ok()
+        .and_then(|l| l.1.find(STREAMS_MARKER))
+    else {
+        return Vec::new();
+    };
+    let separator = plain_len(separator);
+
+    segments
+        .iter()
+        .map(|(index, segment)| {
+            let length = plain_len(segment);
+            let button = ButtonIndex {
+                name: index.to_string(),
+                start,
+                length,
+            };
+            start += length + separator;
+            button
+        })
+        .collect()
+}
+
+/// Guesses what kind of device is plugged into a port, for choosing an
+/// icon.
+fn port_kind(port: &str, proplist: &Proplist) -> &'static str {
+    let port = port.to_lowercase();
+    let bus = proplist.get_str(properties::DEVICE_BUS);
+    let form_factor = proplist
+        .get_str(properties::DEVICE_FORM_FACTOR)
+        .unwrap_or_default();
+    if bus.as_deref() == Some("bluetooth") {
+        "bluetooth"
+    } else if port.contains("headset")
+        || ["headset", "hands-free"].contains(&form_factor.as_str())
+    {
+        "headset"
+    } else if port.contains("headphone") || form_factor == "headphone" {
+        "headphones"
+    } else if port.contains("hdmi")
+        || port.contains("displayport")
+        || form_factor == "tv"
+    {
+        "hdmi"
+    } else if bus.as_deref() == Some("usb") {
+        "usb"
+    } else if port.contains("speaker")
+        || ["speaker", "internal"].contains(&form_factor.as_str())
+    {
+        "speaker"
+    } else if port.contains("mic") || form_factor == "microphone" {
+        "mic"
+    } else {
+        "other"
+    }
+}
+
+/// Displays the current volume and mute status of a given sink or source.
 #[derive(Builder, Debug)]
 #[builder_struct_attr(allow(missing_docs))]
 #[builder_impl_attr(allow(missing_docs))]
 pub struct Pulseaudio {
     name: &'static str,
-    #[builder(default = r#"String::from("@DEFAULT_SINK@")"#)]
-    sink: String,
+    #[builder(default)]
+    device_type: DeviceType,
+    device: String,
     #[builder(default, setter(strip_option))]
     server: Option<String>,
     #[builder(default = "10")]
     unit: u32,
-    recv: Arc<Mutex<Receiver<(Volume, bool)>>>,
+    #[builder(default)]
+    port_icons: HashMap<String, String>,
+    #[builder(default)]
+    stream_icons: HashMap<String, String>,
+    #[builder(default = r#"String::from(" ")"#)]
+    stream_separator: String,
+    #[builder(default)]
+    regions: Rc<Mutex<Option<(HitLayout, IndexCache)>>>,
+    recv: Arc<Mutex<Receiver<Status>>>,
     #[builder(default)]
     paused: Arc<Mutex<bool>>,
     #[builder(default)]
     waker: Arc<AtomicWaker>,
-    formats: PulseaudioFormats<String>,
+    formats: PulseaudioFormats<Template>,
+    format_stream: Template,
     attrs: Attrs,
     #[builder(default, setter(strip_option))]
     highlight: Option<Highlight>,
@@ -78,32 +252,101 @@ impl Pulseaudio {
         cr: &Rc<cairo::Context>,
         height: i32,
         paused: Arc<Mutex<bool>>,
-        data: Result<Option<(Volume, bool)>>,
-        last_data: &Arc<Mutex<(Volume, bool)>>,
+        data: Result<Option<Status>>,
+        last_data: &Arc<Mutex<Status>>,
     ) -> Result<PanelDrawInfo> {
-        let (volume, mute) = match data {
+        let status = match data {
             Ok(Some(data)) => data,
-            Ok(None) => *last_data.lock().unwrap(),
+            Ok(None) => last_data.lock().unwrap().clone(),
             Err(e) => return Err(e),
         };
-        *last_data.lock().unwrap() = (volume, mute);
+        last_data.lock().unwrap().clone_from(&status);
+        let Status {
+            volume,
+            mute,
+            device,
+            port,
+            port_kind,
+            recording,
+            streams,
+        } = status;
         let (format, ramp) = if mute {
-            (self.formats.muted.clone(), self.ramps.muted.clone())
+            (&self.formats.muted, &self.ramps.muted)
+        } else {
+            (&self.formats.unmuted, &self.ramps.unmuted)
+        };
+        let (attrs, highlight) = self.common.color_ramps.apply(
+            volume.0,
+            Volume::MUTED.0,
+            Volume::NORMAL.0,
+            &self.attrs,
+            self.highlight.as_ref(),
+        );
+        let text = format.render(|name| match name {
+            "ramp" => Some(
+                ramp.choose(volume.0, Volume::MUTED.0, Volume::NORMAL.0)
+                    .into(),
+            ),
+            "volume" => Some(volume.to_string().into()),
+            "percentage" => Some(percentage(volume)),
+            "muted" => Some(mute.into()),
+            "streams" => Some(STREAMS_MARKER.to_string().into()),
+            "device" => {
+                Some(glib::markup_escape_text(device.as_str()).as_str().into())
+            }
+            "port" => {
+                Some(glib::markup_escape_text(port.as_str()).as_str().into())
+            }
+            "port_icon" => Some(
+                self.port_icons
+                    .get(port_kind)
+                    .or_else(|| self.port_icons.get("default"))?
+                    .as_str()
+                    .into(),
+            ),
+            "recording" => Some(
+                glib::markup_escape_text(recording.join(", ").as_str())
+                    .as_str()
+                    .into(),
+            ),
+            "recording_count" => Some(recording.len().into()),
+            _ => None,
+        });
+
+        let segments = streams
+            .iter()
+            .map(|stream| (stream.index, self.render_stream(stream)))
+            .filter(|(_, segment)| !segment.is_empty())
+            .collect::<Vec<_>>();
+        let regions = stream_regions(
+            text.as_str(),
+            segments.as_slice(),
+            self.stream_separator.as_str(),
+        );
+        let text = text.replace(
+            STREAMS_MARKER,
+            segments
+                .into_iter()
+                .map(|(_, segment)| segment)
+                .collect::<Vec<_>>()
+                .join(self.stream_separator.as_str())
+                .as_str(),
+        );
+        *self.regions.lock().unwrap() = if regions.is_empty() {
+            None
         } else {
-            (self.formats.unmuted.clone(), self.ramps.unmuted.clone())
+            Some((
+                self.common.hit_layout(cr, text.as_str(), &attrs, height),
+                regions,
+            ))
         };
-        let ramp_text =
-            ramp.choose(volume.0, Volume::MUTED.0, Volume::NORMAL.0);
-        let text = format
-            .replace("%ramp%", ramp_text.as_str())
-            .replace("%volume%", volume.to_string().as_str());
 
         self.common.draw(
             cr,
             text.as_str(),
-            &self.attrs,
+            &attrs,
             self.common.dependence,
-            self.highlight.clone(),
+            highlight,
             self.common.images.clone(),
             height,
             ShowHide::Default(paused, self.waker.clone()),
@@ -111,116 +354,106 @@ impl Pulseaudio {
         )
     }
 
+    fn render_stream(&self, stream: &SinkInput) -> String {
+        let volume = stream.volume.get()[0];
+        let ramp = if stream.mute {
+            &self.ramps.muted
+        } else {
+            &self.ramps.unmuted
+        };
+        self.format_stream.render(|name| match name {
+            "app" => Some(
+                glib::markup_escape_text(stream.app.as_str())
+                    .as_str()
+                    .into(),
+            ),
+            "icon" => Some(
+                self.stream_icons
+                    .get(stream.app.to_lowercase().as_str())
+                    .or_else(|| {
+                        self.stream_icons.get(
+                            stream.binary.as_ref()?.to_lowercase().as_str(),
+                        )
+                    })
+                    .or_else(|| self.stream_icons.get("default"))?
+                    .as_str()
+                    .into(),
+            ),
+            "ramp" => Some(
+                ramp.choose(volume.0, Volume::MUTED.0, Volume::NORMAL.0)
+                    .into(),
+            ),
+            "volume" => Some(volume.to_string().into()),
+            "percentage" => Some(percentage(volume)),
+            "muted" => Some(stream.mute.into()),
+            _ => None,
+        })
+    }
+
     fn process_event(
         event: &Event,
         actions: Actions,
-        sink: &str,
+        device: &Device,
         unit: u32,
-        introspector: Rc<RefCell<Introspector>>,
-        mainloop: Rc<RefCell<threaded::Mainloop>>,
+        regions: &Rc<Mutex<Option<(HitLayout, IndexCache)>>>,
         response_send: UnboundedSender<EventResponse>,
     ) -> Result<()> {
         match event {
             Event::Action(Some(value)) if value == "increment" => {
-                let (send, recv) = std::sync::mpsc::channel();
-                mainloop.borrow_mut().lock();
-                introspector.borrow_mut().get_sink_info_by_name(
-                    sink,
-                    move |r| {
-                        if let ListResult::Item(i) = r {
-                            let _ = send.send(i.volume);
-                        }
-                    },
-                );
-                mainloop.borrow_mut().unlock();
-                let volume = recv.recv();
-                if let Ok(mut volume) = volume {
-                    volume.get_mut().iter_mut().for_each(|v| {
-                        v.0 = (v.0 + unit * Volume::NORMAL.0 / 100)
-                            .min(Volume::NORMAL.0);
-                    });
-                    mainloop.borrow_mut().lock();
-                    let o = {
-                        let ml_ref = Rc::clone(&mainloop);
-                        introspector.borrow_mut().set_sink_volume_by_name(
-                            sink,
-                            &volume,
-                            Some(Box::new(move |_success| unsafe {
-                                (*ml_ref.as_ptr()).signal(false);
-                            })),
-                        )
-                    };
-
-                    while o.get_state() != operation::State::Done {
-                        mainloop.borrow_mut().wait();
-                    }
-
-                    mainloop.borrow_mut().unlock();
+                if let Some((mut volume, _)) = device.query() {
+                    step(&mut volume, unit, true);
+                    device.set_volume(&volume);
                 }
 
                 Ok(response_send.send(EventResponse::Ok(None))?)
             }
             Event::Action(Some(value)) if value == "decrement" => {
-                let (send, recv) = std::sync::mpsc::channel();
-                mainloop.borrow_mut().lock();
-                introspector.borrow_mut().get_sink_info_by_name(
-                    sink,
-                    move |r| {
-                        if let ListResult::Item(i) = r {
-                            let _ = send.send(i.volume);
-                        }
-                    },
-                );
-                mainloop.borrow_mut().unlock();
-                let volume = recv.recv();
-                if let Ok(mut volume) = volume {
-                    volume.get_mut().iter_mut().for_each(|v| {
-                        v.0 = v.0.saturating_sub(unit * Volume::NORMAL.0 / 100);
-                    });
-                    mainloop.borrow_mut().lock();
-                    let o = {
-                        let ml_ref = Rc::clone(&mainloop);
-                        introspector.borrow_mut().set_sink_volume_by_name(
-                            sink,
-                            &volume,
-                            Some(Box::new(move |_success| unsafe {
-                                (*ml_ref.as_ptr()).signal(false);
-                            })),
-                        )
-                    };
-
-                    while o.get_state() != operation::State::Done {
-                        mainloop.borrow_mut().wait();
-                    }
-
-                    mainloop.borrow_mut().unlock();
+                if let Some((mut volume, _)) = device.query() {
+                    step(&mut volume, unit, false);
+                    device.set_volume(&volume);
                 }
 
                 Ok(response_send.send(EventResponse::Ok(None))?)
             }
             Event::Action(Some(value)) if value == "toggle" => {
-                let (send, recv) = std::sync::mpsc::channel();
-                mainloop.borrow_mut().lock();
-                introspector.borrow_mut().get_sink_info_by_name(
-                    sink,
-                    move |r| {
-                        if let ListResult::Item(i) = r {
-                            let _ = send.send(i.mute);
-                        }
-                    },
-                );
-                mainloop.borrow_mut().unlock();
-                let mute = recv.recv();
-                if let Ok(mute) = mute {
-                    mainloop.borrow_mut().lock();
-                    introspector
-                        .borrow_mut()
-                        .set_sink_mute_by_name(sink, !mute, None);
-                    mainloop.borrow_mut().unlock();
+                if let Some((_, mute)) = device.query() {
+                    device.set_mute(!mute);
                 }
 
                 Ok(response_send.send(EventResponse::Ok(None))?)
             }
+            Event::Action(Some(value))
+                if matches!(
+                    value.as_str(),
+                    "next_sink" | "prev_sink" | "next_source" | "prev_source"
+                ) =>
+            {
+                let device_type = if value.ends_with("sink") {
+                    DeviceType::Sink
+                } else {
+                    DeviceType::Source
+                };
+                device.cycle(device_type, value.starts_with("next"));
+
+                Ok(response_send.send(EventResponse::Ok(None))?)
+            }
+            Event::Action(Some(value))
+                if let Some((action, app)) = value.split_once(':')
+                    && ["increment", "decrement", "toggle"]
+                        .contains(&action) =>
+            {
+                let count = device.adjust_streams(
+                    |stream| stream.matches(app),
+                    action,
+                    unit,
+                );
+
+                Ok(response_send.send(if count == 0 {
+                    EventResponse::Err(format!("No streams from {app}"))
+                } else {
+                    EventResponse::Ok(None)
+                })?)
+            }
             Event::Action(Some(value)) => {
                 let value = value.to_owned();
                 Ok(response_send.send(EventResponse::Err(format!(
@@ -229,20 +462,52 @@ impl Pulseaudio {
             }
             Event::Action(None) => Ok(()),
             Event::Mouse(event) => {
+                let stream = regions.lock().unwrap().as_ref().and_then(
+                    |(layout, cache)| {
+                        let idx = layout
+                            .index_at(f64::from(event.x), f64::from(event.y))?;
+                        cache
+                            .iter()
+                            .find(|index| {
+                                index.start <= idx
+                                    && idx < index.start + index.length
+                            })?
+                            .name
+                            .parse::<u32>()
+                            .ok()
+                    },
+                );
+                let stream_action = match event.button {
+                    MouseButton::ScrollUp => Some("increment"),
+                    MouseButton::ScrollDown => Some("decrement"),
+                    MouseButton::Middle => Some("toggle"),
+                    MouseButton::Left
+                    | MouseButton::Right
+                    | MouseButton::Drag => None,
+                };
+                if let (Some(index), Some(action)) = (stream, stream_action) {
+                    device.adjust_streams(
+                        |stream| stream.index == index,
+                        action,
+                        unit,
+                    );
+                    return Ok(response_send.send(EventResponse::Ok(None))?);
+                }
+
                 let action = match event.button {
                     MouseButton::Left => actions.left.clone(),
                     MouseButton::Right => actions.right.clone(),
                     MouseButton::Middle => actions.middle.clone(),
                     MouseButton::ScrollUp => actions.up.clone(),
                     MouseButton::ScrollDown => actions.down.clone(),
+                    MouseButton::Drag => None,
                 };
                 Ok(Self::process_event(
                     &Event::Action(action),
                     actions,
-                    sink,
+                    device,
                     unit,
-                    introspector,
-                    mainloop,
+                    regions,
                     response_send,
                 )?)
             }
@@ -250,16 +515,413 @@ impl Pulseaudio {
     }
 }
 
+/// A sink or source, used to carry out events.
+#[derive(Clone)]
+struct Device {
+    device_type: DeviceType,
+    name: String,
+    context: Rc<RefCell<context::Context>>,
+    introspector: Rc<RefCell<Introspector>>,
+    mainloop: Rc<RefCell<threaded::Mainloop>>,
+    status: Arc<Mutex<Status>>,
+}
+
+impl Device {
+    /// Fetches the current volume and mute state of the device.
+    fn query(&self) -> Option<(ChannelVolumes, bool)> {
+        let (send, recv) = channel();
+        self.mainloop.borrow_mut().lock();
+        match self.device_type {
+            DeviceType::Sink => {
+                self.introspector.borrow_mut().get_sink_info_by_name(
+                    self.name.as_str(),
+                    move |r| {
+                        if let ListResult::Item(i) = r {
+                            let _ = send.send((i.volume, i.mute));
+                        }
+                    },
+                );
+            }
+            DeviceType::Source => {
+                self.introspector.borrow_mut().get_source_info_by_name(
+                    self.name.as_str(),
+                    move |r| {
+                        if let ListResult::Item(i) = r {
+                            let _ = send.send((i.volume, i.mute));
+                        }
+                    },
+                );
+            }
+        }
+        self.mainloop.borrow_mut().unlock();
+        recv.recv().ok()
+    }
+
+    fn set_volume(&self, volume: &ChannelVolumes) {
+        self.mainloop.borrow_mut().lock();
+        let ml_ref = Rc::clone(&self.mainloop);
+        let callback = Some(Box::new(move |_success| unsafe {
+            (*ml_ref.as_ptr()).signal(false);
+        }) as Box<dyn FnMut(bool)>);
+        let o = match self.device_type {
+            DeviceType::Sink => self
+                .introspector
+                .borrow_mut()
+                .set_sink_volume_by_name(self.name.as_str(), volume, callback),
+            DeviceType::Source => {
+                self.introspector.borrow_mut().set_source_volume_by_name(
+                    self.name.as_str(),
+                    volume,
+                    callback,
+                )
+            }
+        };
+
+        while o.get_state() != operation::State::Done {
+            self.mainloop.borrow_mut().wait();
+        }
+
+        self.mainloop.borrow_mut().unlock();
+    }
+
+    /// Makes the next or previous sink or source the default, and moves every
+    /// stream to it.
+    fn cycle(&self, device_type: DeviceType, forward: bool) {
+        let (send, recv) = channel();
+        self.mainloop.borrow_mut().lock();
+        {
+            let introspector = self.introspector.borrow();
+            let mut names = Vec::new();
+            match device_type {
+                DeviceType::Sink => {
+                    introspector.get_sink_info_list(move |r| match r {
+                        ListResult::Item(i) => {
+                            names
+                                .extend(i.name.as_ref().map(|n| n.to_string()));
+                        }
+                        ListResult::End => {
+                            let _ = send.send(mem::take(&mut names));
+                        }
+                        ListResult::Error => {}
+                    });
+                }
+                DeviceType::Source => {
+                    introspector.get_source_info_list(move |r| match r {
+                        // monitors aren't inputs that anyone would choose
+                        ListResult::Item(i) if i.monitor_of_sink.is_none() => {
+                            names
+                                .extend(i.name.as_ref().map(|n| n.to_string()));
+                        }
+                        ListResult::Item(_) | ListResult::Error => {}
+                        ListResult::End => {
+                            let _ = send.send(mem::take(&mut names));
+                        }
+                    });
+                }
+            }
+        }
+        let (default_send, default_recv) = channel();
+        self.introspector.borrow().get_server_info(move |info| {
+            let default = match device_type {
+                DeviceType::Sink => info.default_sink_name.as_ref(),
+                DeviceType::Source => info.default_source_name.as_ref(),
+            };
+            let _ = default_send.send(default.map(|n| n.to_string()));
+        });
+        self.mainloop.borrow_mut().unlock();
+
+        let (Ok(names), Ok(default)) = (recv.recv(), default_recv.recv())
+        else {
+            return;
+        };
+        if names.is_empty() {
+            return;
+        }
+        let current = default
+            .and_then(|default| names.iter().position(|n| *n == default));
+        let next = match (current, forward) {
+            (Some(i), true) => (i + 1) % names.len(),
+            (Some(i), false) => (i + names.len() - 1) % names.len(),
+            (None, _) => 0,
+        };
+        let name = names[next].as_str();
+
+        self.mainloop.borrow_mut().lock();
+        let ml_ref = Rc::clone(&self.mainloop);
+        let callback = move |_success| unsafe {
+            (*ml_ref.as_ptr()).signal(false);
+        };
+        let o = match device_type {
+            DeviceType::Sink => {
+                self.context.borrow_mut().set_default_sink(name, callback)
+            }
+            DeviceType::Source => {
+                self.context.borrow_mut().set_default_source(name, callback)
+            }
+        };
+        while o.get_state() != operation::State::Done {
+            self.mainloop.borrow_mut().wait();
+        }
+
+        let (send, recv) = channel();
+        let mut streams = Vec::new();
+        match device_type {
+            DeviceType::Sink => {
+                self.introspector
+                    .borrow()
+                    .get_sink_input_info_list(move |r| match r {
+                        ListResult::Item(i) => streams.push(i.index),
+                        ListResult::End => {
+                            let _ = send.send(mem::take(&mut streams));
+                        }
+                        ListResult::Error => {}
+                    });
+            }
+            DeviceType::Source => {
+                self.introspector.borrow().get_source_output_info_list(
+                    move |r| match r {
+                        ListResult::Item(i) => streams.push(i.index),
+                        ListResult::End => {
+                            let _ = send.send(mem::take(&mut streams));
+                        }
+                        ListResult::Error => {}
+                    },
+                );
+            }
+        }
+        self.mainloop.borrow_mut().unlock();
+
+        let Ok(streams) = recv.recv() else {
+            return;
+        };
+        self.mainloop.borrow_mut().lock();
+        for index in streams {
+            match device_type {
+                DeviceType::Sink => {
+                    self.introspector
+                        .borrow_mut()
+                        .move_sink_input_by_name(index, name, None);
+                }
+                DeviceType::Source => {
+                    self.introspector
+                        .borrow_mut()
+                        .move_source_output_by_name(index, name, None);
+                }
+            }
+        }
+        self.mainloop.borrow_mut().unlock();
+    }
+
+    /// Changes the volume of (`increment` or `decrement`) or mutes or unmutes
+    /// (`toggle`) each stream for which `filter` returns true. Returns the
+    /// number of streams affected.
+    fn adjust_streams(
+        &self,
+        filter: impl Fn(&SinkInput) -> bool,
+        action: &str,
+        unit: u32,
+    ) -> usize {
+        let streams = self
+            .status
+            .lock()
+            .unwrap()
+            .streams
+            .iter()
+            .filter(|stream| filter(stream))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        self.mainloop.borrow_mut().lock();
+        for stream in &streams {
+            let mut introspector = self.introspector.borrow_mut();
+            if action == "toggle" {
+                introspector.set_sink_input_mute(
+                    stream.index,
+                    !stream.mute,
+                    None,
+                );
+            } else {
+                let mut volume = stream.volume;
+                step(&mut volume, unit, action == "increment");
+                introspector.set_sink_input_volume(stream.index, &volume, None);
+            }
+        }
+        self.mainloop.borrow_mut().unlock();
+
+        streams.len()
+    }
+
+    fn set_mute(&self, mute: bool) {
+        self.mainloop.borrow_mut().lock();
+        match self.device_type {
+            DeviceType::Sink => {
+                self.introspector.borrow_mut().set_sink_mute_by_name(
+                    self.name.as_str(),
+                    mute,
+                    None,
+                );
+            }
+            DeviceType::Source => {
+                self.introspector.borrow_mut().set_source_mute_by_name(
+                    self.name.as_str(),
+                    mute,
+                    None,
+                );
+            }
+        }
+        self.mainloop.borrow_mut().unlock();
+    }
+}
+
+/// Requests the volume and mute state of the device, sending the updated
+/// status once it arrives. Must be called with the mainloop locked or from
+/// one of its callbacks.
+fn query_device(
+    introspector: &Introspector,
+    device_type: DeviceType,
+    name: &str,
+    status: Arc<Mutex<Status>>,
+    send: Sender<Status>,
+) {
+    let update = move |volume: &ChannelVolumes,
+                       mute: bool,
+                       description: Option<&str>,
+                       port: Option<(Option<&str>, Option<&str>)>,
+                       proplist: &Proplist| {
+        let (port_name, port_description) = port.unwrap_or_default();
+        let mut status = status.lock().unwrap();
+        status.volume = volume.get()[0];
+        status.mute = mute;
+        status.device = description.unwrap_or_default().to_string();
+        status.port = port_description.unwrap_or_default().to_string();
+        status.port_kind = port_kind(port_name.unwrap_or_default(), proplist);
+        let _ = send.send(status.clone());
+    };
+    match device_type {
+        DeviceType::Sink => {
+            introspector.get_sink_info_by_name(name, move |r| {
+                if let ListResult::Item(i) = r {
+                    update(
+                        &i.volume,
+                        i.mute,
+                        i.description.as_deref(),
+                        i.active_port.as_ref().map(|port| {
+                            (port.name.as_deref(), port.description.as_deref())
+                        }),
+                        &i.proplist,
+                    );
+                }
+            });
+        }
+        DeviceType::Source => {
+            introspector.get_source_info_by_name(name, move |r| {
+                if let ListResult::Item(i) = r {
+                    update(
+                        &i.volume,
+                        i.mute,
+                        i.description.as_deref(),
+                        i.active_port.as_ref().map(|port| {
+                            (port.name.as_deref(), port.description.as_deref())
+                        }),
+                        &i.proplist,
+                    );
+                }
+            });
+        }
+    }
+}
+
+/// Requests the names of the applications that are recording from any
+/// source, sending the updated status once the list is complete. Must be
+/// called with the mainloop locked or from one of its callbacks.
+fn query_recording(
+    introspector: &Introspector,
+    status: Arc<Mutex<Status>>,
+    send: Sender<Status>,
+) {
+    let mut recording = Vec::new();
+    introspector.get_source_output_info_list(move |r| match r {
+        ListResult::Item(output) => {
+            // volume meters like pavucontrol's aren't really recording
+            let is_meter = output
+                .proplist
+                .get_str(properties::APPLICATION_ID)
+                .is_some_and(|id| id == "org.PulseAudio.pavucontrol");
+            if output.corked || is_meter {
+                return;
+            }
+            if let Some(name) = output
+                .proplist
+                .get_str(properties::APPLICATION_NAME)
+                .or_else(|| output.name.as_ref().map(ToString::to_string))
+                && !recording.contains(&name)
+            {
+                recording.push(name);
+            }
+        }
+        ListResult::End => {
+            let mut status = status.lock().unwrap();
+            status.recording = mem::take(&mut recording);
+            let _ = send.send(status.clone());
+        }
+        ListResult::Error => {
+            recording.clear();
+        }
+    });
+}
+
+/// Requests the applications that are playing audio, sending the updated
+/// status once the list is complete. Must be called with the mainloop locked
+/// or from one of its callbacks.
+fn query_streams(
+    introspector: &Introspector,
+    status: Arc<Mutex<Status>>,
+    send: Sender<Status>,
+) {
+    let mut streams = Vec::new();
+    introspector.get_sink_input_info_list(move |r| match r {
+        ListResult::Item(input) => {
+            if input.corked {
+                return;
+            }
+            let Some(app) = input
+                .proplist
+                .get_str(properties::APPLICATION_NAME)
+                .or_else(|| input.name.as_ref().map(ToString::to_string))
+            else {
+                return;
+            };
+            streams.push(SinkInput {
+                index: input.index,
+                app,
+                binary: input
+                    .proplist
+                    .get_str(properties::APPLICATION_PROCESS_BINARY),
+                volume: input.volume,
+                mute: input.mute,
+            });
+        }
+        ListResult::End => {
+            let mut status = status.lock().unwrap();
+            status.streams = mem::take(&mut streams);
+            let _ = send.send(status.clone());
+        }
+        ListResult::Error => {
+            streams.clear();
+        }
+    });
+}
+
 #[derive(Debug)]
 struct PulseaudioStream {
-    recv: Arc<Mutex<Receiver<(Volume, bool)>>>,
+    recv: Arc<Mutex<Receiver<Status>>>,
     paused: Arc<Mutex<bool>>,
     waker: Arc<AtomicWaker>,
-    handle: Option<JoinHandle<Result<(Volume, bool)>>>,
+    handle: Option<JoinHandle<Result<Status>>>,
 }
 
 impl Stream for PulseaudioStream {
-    type Item = (Volume, bool);
+    type Item = Status;
 
     fn poll_next(
         mut self: Pin<&mut Self>,
@@ -295,7 +957,7 @@ impl Stream for PulseaudioStream {
 
 impl PulseaudioStream {
     const fn new(
-        recv: Arc<Mutex<Receiver<(Volume, bool)>>>,
+        recv: Arc<Mutex<Receiver<Status>>>,
         paused: Arc<Mutex<bool>>,
         waker: Arc<AtomicWaker>,
     ) -> Self {
@@ -313,38 +975,87 @@ impl PanelConfig for Pulseaudio {
     /// Parses an instance of the panel from the global [`Config`]
     ///
     /// Configuration options:
-    /// - `sink`: the sink about which to display information
+    /// - `device_type`: whether to show an output (`sink`) or an input
+    ///   (`source`), such as a microphone
+    ///   - type: String
+    ///   - default: `sink`
+    /// - `device`: the sink or source about which to display information.
+    ///   `sink` is accepted as an alias for backwards compatibility. The
+    ///   default follows the default device as it changes.
     ///   - type: String
-    ///   - default: "@DEFAULT_SINK@"
+    ///   - default: "@DEFAULT_SINK@" or "@DEFAULT_SOURCE@"
     /// - `server`: the pulseaudio server to which to connect
     ///   - type: String
     ///   - default: None (This does not mean no default; rather
     ///     [`Option::None`] is passed to the connect function and pulseaudio
     ///     will make its best guess. This is the right option on most systems.)
     /// - `unit`: The number of percentage points by which to adjust the volume
-    ///   of the chosen sink
+    ///   of the chosen device
     ///   - type: u64
     ///   - default: 10
-    /// - `format_unmuted`: the format string when the default sink is unmuted
+    /// - `port_icons`: a table mapping kinds of ports to the text of
+    ///   `%port_icon%`. The kinds are `speaker`, `headphones`, `headset`,
+    ///   `hdmi`, `bluetooth`, `usb`, `mic`, and `other`. The icon for `default`
+    ///   is used for kinds without one.
+    ///   - type: Table
+    ///   - default: {}
+    /// - `format`: a format string to use for both states, unless overridden
+    ///   below. See [`PanelCommon::parse_templates`].
+    ///   - type: String
+    ///   - formatting options:
+    ///     - `%volume%`, `%percentage%`, `%muted%`, `%ramp%`
+    ///     - `%device%`, `%port%`: the descriptions of the device and its
+    ///       active port, e.g. `Built-in Audio` and `Headphones`
+    ///     - `%port_icon%`: the icon from `port_icons` for the active port
+    ///     - `%recording%`: the names of the applications that are recording
+    ///       from any source, separated by commas. This is available regardless
+    ///       of `device_type`, e.g. `{if recording} {recording}{end}` as a
+    ///       privacy indicator.
+    ///     - `%recording_count%`: the number of such applications
+    ///     - `%streams%`: each application that is playing audio, formatted
+    ///       with `format_stream`. Scrolling over an application changes its
+    ///       volume and middle-clicking it mutes or unmutes it.
+    /// - `format_unmuted`: the format string when the device is unmuted
     ///   - type: String
     ///   - default: `%ramp%%volume%%`
-    ///   - formatting options: `%volume%`, `%ramp%`
-    /// - `format_muted`: the format string when the default sink is muted
+    ///   - formatting options: same as `format`
+    /// - `format_muted`: the format string when the device is muted
     ///   - type: String
     ///   - default: `%ramp%%volume%%`
-    ///   - formatting options: `%volume%`, `%ramp%`
+    ///   - formatting options: same as `format`
+    /// - `format_stream`: the format string for each application in `%streams%`
+    ///   - type: String
+    ///   - default: `%app% %percentage%%`
+    ///   - formatting options: `%app%`, `%icon%`, `%volume%`, `%percentage%`,
+    ///     `%muted%`, and `%ramp%`, which uses `ramp_unmuted` or `ramp_muted`
+    /// - `stream_separator`: the text between applications in `%streams%`
+    ///   - type: String
+    ///   - default: " "
+    /// - `stream_icons`: a table mapping application names or executables to
+    ///   the text of `%icon%`, e.g. `{ firefox = "󰈹" }`. The icon for `default`
+    ///   is used for applications without one.
+    ///   - type: Table
+    ///   - default: {}
     /// - `attrs`: A string specifying the attrs for the panel. See
     ///   [`Attrs::parse`] for details.
     /// - `highlight`: A string specifying the highlight for the panel. See
     ///   [`Highlight::parse`] for details.
     /// - `ramp_unmuted`: Shows an icon based on the volume level. See
-    ///   [`Ramp::parse`] for parsing details. This ramp is used when the sink
+    ///   [`Ramp::parse`] for parsing details. This ramp is used when the device
     ///   is unmuted.
     /// - `ramp_muted`: Shows an icon based on the volume level. See
-    ///   [`Ramp::parse`] for parsing details. This ramp is used when the sink
+    ///   [`Ramp::parse`] for parsing details. This ramp is used when the device
     ///   is muted.
+    /// - `ramp_fg`, `ramp_bg`, `ramp_highlight`: Ramps whose colors follow the
+    ///   volume level. See [`ColorRamps::parse`][crate::ColorRamps::parse] for
+    ///   details.
     /// - See [`PanelCommon::parse_common`]. The supported events are
-    ///   `increment`, `decrement`, and `toggle`.
+    ///   `increment`, `decrement`, and `toggle`, which act on the chosen
+    ///   device, and `next_sink`, `prev_sink`, `next_source`, and
+    ///   `prev_source`, which make the next or previous device the default and
+    ///   move all playing or recording streams to it. `increment:<app>`,
+    ///   `decrement:<app>`, and `toggle:<app>` act on every stream from an
+    ///   application, matched by name or executable (e.g. `toggle:firefox`).
     fn parse(
         name: &'static str,
         table: &mut HashMap<String, Value>,
@@ -353,32 +1064,78 @@ impl PanelConfig for Pulseaudio {
         let mut builder = PulseaudioBuilder::default();
 
         builder.name(name);
-        if let Some(sink) = remove_string_from_config("sink", table) {
-            builder.sink(sink);
-        }
+        let device_type =
+            match remove_string_from_config("device_type", table).as_deref() {
+                None | Some("sink") => DeviceType::Sink,
+                Some("source") => DeviceType::Source,
+                Some(other) => {
+                    return Err(anyhow!(
+                        "Invalid device_type {other}: expected sink or source"
+                    ));
+                }
+            };
+        builder.device_type(device_type);
+        builder.device(
+            remove_string_from_config("device", table)
+                .or_else(|| remove_string_from_config("sink", table))
+                .unwrap_or_else(|| device_type.default_name().to_string()),
+        );
         if let Some(server) = remove_string_from_config("server", table) {
             builder.server(server);
         }
         if let Some(unit) = remove_uint_from_config("unit", table) {
             builder.unit(unit as u32);
         }
+        if let Some(stream_icons) = get_table_from_config("stream_icons", table)
+        {
+            table.remove("stream_icons");
+            builder.stream_icons(
+                stream_icons
+                    .into_iter()
+                    .filter_map(|(app, icon)| {
+                        Some((app.to_lowercase(), icon.into_string().ok()?))
+                    })
+                    .collect(),
+            );
+        }
+        if let Some(separator) =
+            remove_string_from_config("stream_separator", table)
+        {
+            builder.stream_separator(separator);
+        }
+        if let Some(port_icons) = get_table_from_config("port_icons", table) {
+            table.remove("port_icons");
+            builder.port_icons(
+                port_icons
+                    .into_iter()
+                    .filter_map(|(kind, icon)| {
+                        Some((kind, icon.into_string().ok()?))
+                    })
+                    .collect(),
+            );
+        }
 
         // FIXME: unused channel
         let (_, recv) = channel();
         builder.recv(Arc::new(Mutex::new(recv)));
 
         let common = PanelCommon::parse_common(table)?;
-        let formats = PanelCommon::parse_formats(
+        let formats = PanelCommon::parse_templates(
             table,
             &["_unmuted", "_muted"],
             &["%ramp%%volume%", "%ramp%%volume%"],
-        );
+        )?;
         let attrs = PanelCommon::parse_attr(table, "");
         let highlight = PanelCommon::parse_highlight(table, "");
         let ramps = PanelCommon::parse_ramps(table, &["_unmuted", "_muted"]);
 
         builder.common(common);
         builder.formats(PulseaudioFormats::new(formats));
+        builder.format_stream(PanelCommon::parse_template(
+            table,
+            "_stream",
+            "%app% %percentage%%",
+        )?);
         builder.attrs(attrs);
         builder.highlight(highlight);
         builder.ramps(PulseaudioRamps::new(ramps));
@@ -454,41 +1211,62 @@ impl PanelConfig for Pulseaudio {
 
         let introspector = context.borrow_mut().introspect();
 
-        let (sink_send, sink_recv) = channel();
-        let sink = self.sink.clone();
-
-        let initial = sink_send.clone();
-        let ml_ref = Rc::clone(&mainloop);
-        let o = introspector.get_sink_info_by_name(sink.as_str(), move |r| {
-            if let ListResult::Item(s) = r {
-                let volume = s.volume.get()[0];
-                let mute = s.mute;
-                initial.send((volume, mute)).unwrap();
-                unsafe {
-                    (*ml_ref.as_ptr()).signal(false);
-                }
-            }
-        });
+        let (status_send, status_recv) = channel();
+        let status = Arc::new(Mutex::new(Status::default()));
+        let device_type = self.device_type;
+        let device = self.device.clone();
 
-        while o.get_state() != operation::State::Done {
-            mainloop.borrow_mut().wait();
+        query_device(
+            &introspector,
+            device_type,
+            device.as_str(),
+            status.clone(),
+            status_send.clone(),
+        );
+        query_recording(&introspector, status.clone(), status_send.clone());
+        let show_streams = self.formats.unmuted.uses("streams")
+            || self.formats.muted.uses("streams");
+        let mut interest = device_type.interest()
+            | InterestMaskSet::SOURCE_OUTPUT
+            // server events include changes to the default sink and source
+            | InterestMaskSet::SERVER;
+        if show_streams {
+            query_streams(&introspector, status.clone(), status_send.clone());
+            interest |= InterestMaskSet::SINK_INPUT;
         }
 
-        context
-            .borrow_mut()
-            .subscribe(InterestMaskSet::SINK, |_| {});
+        context.borrow_mut().subscribe(interest, |_| {});
+        let device_status = status.clone();
 
-        let ss = sink_send;
         let cb: Option<Box<dyn FnMut(_, _, _)>> =
-            Some(Box::new(move |_, _, _| {
-                let send = ss.clone();
-                introspector.get_sink_info_by_name(sink.as_str(), move |r| {
-                    if let ListResult::Item(s) = r {
-                        let volume = s.volume.get()[0];
-                        let mute = s.mute;
-                        let _ = send.send((volume, mute));
-                    }
-                });
+            Some(Box::new(move |facility, _, _| match facility {
+                Some(Facility::SourceOutput) => {
+                    query_recording(
+                        &introspector,
+                        status.clone(),
+                        status_send.clone(),
+                    );
+                }
+                Some(Facility::SinkInput) => {
+                    query_streams(
+                        &introspector,
+                        status.clone(),
+                        status_send.clone(),
+                    );
+                }
+                Some(facility)
+                    if facility == device_type.facility()
+                        || facility == Facility::Server =>
+                {
+                    query_device(
+                        &introspector,
+                        device_type,
+                        device.as_str(),
+                        status.clone(),
+                        status_send.clone(),
+                    );
+                }
+                _ => {}
             }));
 
         context.borrow_mut().set_subscribe_callback(cb);
@@ -499,54 +1277,54 @@ impl PanelConfig for Pulseaudio {
             Rc::new(RefCell::new(context.borrow_mut().introspect()));
 
         // prevent these structures from going out of scope
-        Box::leak(Box::new(context));
+        Box::leak(Box::new(context.clone()));
 
         self.attrs.apply_to(&global_attrs);
 
         let mut map = StreamMap::<
             usize,
-            Pin<Box<dyn Stream<Item = Result<Option<(Volume, bool)>>>>>,
+            Pin<Box<dyn Stream<Item = Result<Option<Status>>>>>,
         >::new();
 
-        let initial = sink_recv.recv()?;
-        self.recv = Arc::new(Mutex::new(sink_recv));
+        self.recv = Arc::new(Mutex::new(status_recv));
 
         let stream = PulseaudioStream::new(
             self.recv.clone(),
             self.paused.clone(),
             self.waker.clone(),
         );
-        map.insert(
-            0,
-            Box::pin(
-                tokio_stream::once(Ok(Some(initial)))
-                    .chain(stream.map(Option::Some).map(Result::Ok)),
-            ),
-        );
+        map.insert(0, Box::pin(stream.map(Option::Some).map(Result::Ok)));
 
         let (event_send, event_recv) = unbounded_channel();
         let (response_send, response_recv) = unbounded_channel();
 
         let actions = self.common.actions.clone();
-        let sink = self.sink.clone();
+        let device = Device {
+            device_type: self.device_type,
+            name: self.device.clone(),
+            context,
+            introspector,
+            mainloop,
+            status: device_status,
+        };
         let unit = self.unit;
+        let regions = self.regions.clone();
         map.insert(
             1,
             Box::pin(UnboundedReceiverStream::new(event_recv).map(move |s| {
                 Self::process_event(
                     &s,
                     actions.clone(),
-                    sink.as_str(),
+                    &device,
                     unit,
-                    introspector.clone(),
-                    mainloop.clone(),
+                    &regions,
                     response_send.clone(),
                 )?;
                 Ok(None)
             })),
         );
 
-        let last_data = Arc::new(Mutex::new(initial));
+        let last_data = Arc::new(Mutex::new(Status::default()));
 
         Ok((
             Box::pin(map.map(move |(_, data)| {
@@ -1,27 +1,44 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    fs::{self, File},
+    os::fd::OwnedFd,
+    pin::Pin,
     rc::Rc,
     sync::{Arc, Mutex},
     time::Duration,
 };
 
-use aho_corasick::AhoCorasick;
 use anyhow::Result;
 use async_trait::async_trait;
 use derive_builder::Builder;
 use futures::task::AtomicWaker;
-use rustix::fs::statvfs;
-use tokio_stream::StreamExt;
+use rustix::{
+    event::{PollFd, PollFlags, poll},
+    fs::{StatVfsMountFlags, statvfs},
+};
+use tokio_stream::{Stream, StreamExt};
 
 use crate::{
-    Highlight, ManagedIntervalStream, PanelConfig, PanelRunResult,
+    BlockingStream, Highlight, ManagedIntervalStream, PanelConfig,
+    PanelRunResult, Template,
     attrs::Attrs,
     bar::PanelDrawInfo,
     common::{PanelCommon, ShowHide},
-    remove_string_from_config, remove_uint_from_config,
+    glob_match, remove_array_from_config, remove_string_from_config,
+    remove_uint_from_config,
+    template::Var,
 };
 
-/// Displays information about storage for a given mountpoint.
+const MOUNTINFO: &str = "/proc/self/mountinfo";
+
+/// Which mountpoints to show.
+#[derive(Debug, Clone)]
+enum Mounts {
+    Paths(Vec<String>),
+    Auto { exclude: Vec<String> },
+}
+
+/// Displays information about storage for one or more mountpoints.
 #[derive(Builder, Debug)]
 #[builder_struct_attr(allow(missing_docs))]
 #[builder_impl_attr(allow(missing_docs))]
@@ -31,9 +48,11 @@ pub struct Storage {
     interval: Duration,
     #[builder(default)]
     waker: Arc<AtomicWaker>,
-    path: String,
-    formatter: AhoCorasick,
-    format: &'static str,
+    #[builder(default = r#"Mounts::Paths(vec![String::from("/")])"#)]
+    mounts: Mounts,
+    #[builder(default = r#"String::from(" ")"#)]
+    separator: String,
+    format: Template,
     attrs: Attrs,
     #[builder(default, setter(strip_option))]
     highlight: Option<Highlight>,
@@ -47,103 +66,75 @@ impl Storage {
         height: i32,
         paused: Arc<Mutex<bool>>,
     ) -> Result<PanelDrawInfo> {
-        let fs_info = statvfs(self.path.as_str())?;
+        let table = MountEntry::read_all();
+        let entries = match &self.mounts {
+            Mounts::Paths(paths) => paths
+                .iter()
+                .map(|path| {
+                    table
+                        .iter()
+                        .rev()
+                        .find(|entry| entry.mount_point == *path)
+                        .cloned()
+                        .unwrap_or_else(|| MountEntry::bare(path.clone()))
+                })
+                .collect::<Vec<_>>(),
+            Mounts::Auto { exclude } => MountEntry::real(&table)
+                .into_iter()
+                .filter(|entry| {
+                    !exclude
+                        .iter()
+                        .any(|glob| glob_match(glob, &entry.mount_point))
+                })
+                .collect(),
+        };
 
-        let used = fs_info.f_blocks - fs_info.f_bfree;
-        let avail = fs_info.f_bavail;
-        let percentage_used =
-            (used as f64 / (used + avail) as f64 * 100.0) as u64;
-        let used_bytes = used * fs_info.f_frsize;
-        let avail_bytes = avail * fs_info.f_frsize;
-
-        let mut text = String::new();
-        self.formatter.replace_all_with(
-            self.format,
-            &mut text,
-            |_, content, dst| match content {
-                "%path%" => {
-                    dst.push_str(self.path.as_str());
-                    true
-                }
-                "%gb_used%" => {
-                    dst.push_str(
-                        format!(
-                            "{:.2}",
-                            (used_bytes as f64 / 1024.0 / 1024.0 / 1024.0)
-                        )
-                        .as_str(),
-                    );
-                    true
-                }
-                "%gb_free%" => {
-                    dst.push_str(
-                        format!(
-                            "{:.2}",
-                            (avail_bytes as f64 / 1024.0 / 1024.0 / 1024.0)
-                        )
-                        .as_str(),
-                    );
-                    true
-                }
-                "%gb_total%" => {
-                    dst.push_str(
-                        format!(
-                            "{:.2}",
-                            ((used_bytes + avail_bytes) as f64
-                                / 1024.0
-                                / 1024.0
-                                / 1024.0)
-                        )
-                        .as_str(),
-                    );
-                    true
-                }
-                "%mb_used%" => {
-                    dst.push_str(
-                        ((used_bytes as f64 / 1024.0 / 1024.0) as u64)
-                            .to_string()
-                            .as_str(),
-                    );
-                    true
-                }
-                "%mb_free%" => {
-                    dst.push_str(
-                        ((avail_bytes as f64 / 1024.0 / 1024.0) as u64)
-                            .to_string()
-                            .as_str(),
-                    );
-                    true
-                }
-                "%mb_total%" => {
-                    dst.push_str(
-                        (((used_bytes + avail_bytes) as f64 / 1024.0 / 1024.0)
-                            as u64)
-                            .to_string()
-                            .as_str(),
-                    );
-                    true
-                }
-                "%percentage_used%" => {
-                    dst.push_str(percentage_used.to_string().as_str());
-                    true
-                }
-                "%percentage_free%" => {
-                    dst.push_str((100 - percentage_used).to_string().as_str());
-                    true
+        let usages = entries
+            .iter()
+            .filter_map(|entry| match Usage::query(entry) {
+                Ok(usage) => Some(usage),
+                Err(e) => {
+                    log::debug!("Failed to stat {}: {e}", entry.mount_point);
+                    None
                 }
-                other => {
-                    dst.push_str(other);
-                    true
-                }
-            },
+            })
+            .collect::<Vec<_>>();
+
+        let segments = usages
+            .iter()
+            .map(|usage| {
+                // only the format is taken from the thresholds here, since
+                // the panel as a whole is styled by its fullest mount below
+                let (format, _, _) = self.common.thresholds.style(
+                    usage.percentage_used as f64,
+                    &self.format,
+                    &self.attrs,
+                    None,
+                );
+                format.render(|name| usage.var(name))
+            })
+            .filter(|segment| !segment.is_empty())
+            .collect::<Vec<_>>();
+        let text = segments.join(self.separator.as_str());
+
+        let fullest = usages
+            .iter()
+            .map(|usage| usage.percentage_used)
+            .max()
+            .unwrap_or_default();
+        let (_, attrs, highlight) = self.common.thresholds.style(
+            fullest as f64,
+            &self.format,
+            &self.attrs,
+            self.highlight.as_ref(),
         );
 
         self.common.draw(
             cr,
             text.as_str(),
-            &self.attrs,
+            &attrs,
             self.common.dependence,
-            self.highlight.clone(),
+            highlight,
             self.common.images.clone(),
             height,
             ShowHide::Default(paused, self.waker.clone()),
@@ -154,24 +145,46 @@ impl Storage {
 
 #[async_trait(?Send)]
 impl PanelConfig for Storage {
-    /// - `interval`: how long to wait in seconds between each check
+    /// Parses an instance of the panel from the global [`Config`]
+    ///
+    /// Configuration options:
+    /// - `interval`: how long to wait in seconds between each check. Mounts and
+    ///   unmounts are shown immediately regardless.
     ///   - type: u64
     ///   - default: 10
-    /// - `path`: the file path to check
+    /// - `path`: the mountpoint to check, or `auto` to check every mounted
+    ///   block device (other than loop devices)
+    ///   - type: String
+    ///   - default: `/`
+    /// - `paths`: several mountpoints to check, each of which is shown with
+    ///   `format`. Takes precedence over `path`.
+    ///   - type: Vec<String>
+    /// - `exclude`: when `path` is `auto`, mountpoints matching any of these
+    ///   globs are skipped (e.g. `["/boot*", "/var/lib/docker*"]`)
+    ///   - type: Vec<String>
+    ///   - default: []
+    /// - `separator`: the text between mountpoints
     ///   - type: String
-    ///   - default: `/proc/meminfo` - If you're considering changing this, you
-    ///     might want to use a different panel like
-    ///     [`Inotify`][crate::panels::Inotify]
-    /// - `format`: the format string
+    ///   - default: " "
+    /// - `format`: the format string for each mountpoint
     ///   - type: String
-    ///   - default: `RAM: %percentage_used%`
-    ///   - formatting options: `%{gb,mb}_{total,used,free}%,
-    ///     %percentage_{used,free}%`
+    ///   - default: `%path%: %percentage_used%%`
+    ///   - formatting options: See [`Template`] for the full syntax.
+    ///     - `%path%`: the mountpoint
+    ///     - `%name%`: the last component of the mountpoint, or `/`
+    ///     - `%device%`, `%fstype%`: the mounted device and its filesystem
+    ///     - `%ro%`: whether the filesystem is mounted read-only
+    ///     - `%{gb,mb}_{total,used,free}%`, `%percentage_{used,free}%`
+    ///     - `%{total,used,free}%`: sizes in bytes (e.g. `{used|human}`)
+    ///     - `%inodes_{total,used,free}%`, `%percentage_inodes_used%`
     /// - `attrs`: A string specifying the attrs for the panel. See
     ///   [`Attrs::parse`] for details.
     /// - `highlight`: A string specifying the highlight for the panel. See
     ///   [`Highlight::parse`] for details.
-    /// - See [`PanelCommon::parse_common`].
+    /// - See [`PanelCommon::parse_common`]. Thresholds apply to
+    ///   `%percentage_used%`. `format_warn` and `format_crit` are chosen for
+    ///   each mountpoint, while the panel's attrs and highlight follow the
+    ///   fullest mountpoint.
     fn parse(
         name: &'static str,
         table: &mut HashMap<String, config::Value>,
@@ -183,34 +196,44 @@ impl PanelConfig for Storage {
         if let Some(interval) = remove_uint_from_config("interval", table) {
             builder.interval(Duration::from_secs(interval));
         }
-        if let Some(path) = remove_string_from_config("path", table) {
-            builder.path(path);
+        let globs = |key: &str, table: &mut HashMap<String, config::Value>| {
+            remove_array_from_config(key, table).map(|v| {
+                v.into_iter()
+                    .filter_map(|v| v.into_string().ok())
+                    .collect::<Vec<_>>()
+            })
+        };
+        let paths = globs("paths", table).or_else(|| {
+            remove_string_from_config("path", table).map(|p| vec![p])
+        });
+        let exclude = globs("exclude", table).unwrap_or_default();
+        match paths {
+            Some(paths) if paths.iter().any(|path| path == "auto") => {
+                builder.mounts(Mounts::Auto { exclude });
+            }
+            Some(paths) => {
+                builder.mounts(Mounts::Paths(paths));
+            }
+            None => {}
+        }
+        if let Some(separator) = remove_string_from_config("separator", table) {
+            builder.separator(separator);
         }
 
         let common = PanelCommon::parse_common(table)?;
-        let format =
-            PanelCommon::parse_format(table, "", "%path%: %percentage_used%%");
+        let format = PanelCommon::parse_template(
+            table,
+            "",
+            "%path%: %percentage_used%%",
+        )?;
         let attrs = PanelCommon::parse_attr(table, "");
         let highlight = PanelCommon::parse_highlight(table, "");
 
         builder.common(common);
-        builder.format(format.leak());
+        builder.format(format);
         builder.attrs(attrs);
         builder.highlight(highlight);
 
-        builder.formatter(AhoCorasick::new([
-            "%path%",
-            "%gb_total%",
-            "%gb_used%",
-            "%gb_free%",
-            "%mb_total%",
-            "%mb_used%",
-            "%mb_free%",
-            "%percentage_used%",
-            "%percentage_free%",
-            "%ramp%",
-        ])?);
-
         Ok(builder.build()?)
     }
 
@@ -228,12 +251,201 @@ impl PanelConfig for Storage {
 
         let paused = Arc::new(Mutex::new(false));
 
-        let stream = ManagedIntervalStream::builder()
+        let interval = ManagedIntervalStream::builder()
             .duration(self.interval)
             .paused(paused.clone())
+            .waker(self.waker.clone())
             .build()?
+            .map(|_| ());
+        let events: Pin<Box<dyn Stream<Item = ()>>> =
+            match File::open(MOUNTINFO) {
+                Ok(file) => {
+                    Box::pin(interval.merge(mount_changes(file.into())))
+                }
+                Err(e) => {
+                    log::warn!("Failed to watch {MOUNTINFO}: {e}");
+                    Box::pin(interval)
+                }
+            };
+
+        let stream = self
+            .common
+            .thresholds
+            .blink(events, paused.clone(), self.waker.clone())
             .map(move |_| self.draw(&cr, height, paused.clone()));
 
         Ok((Box::pin(stream), None))
     }
 }
+
+/// A line of `/proc/self/mountinfo`.
+#[derive(Debug, Clone)]
+struct MountEntry {
+    device_id: String,
+    mount_point: String,
+    fstype: Option<String>,
+    source: Option<String>,
+}
+
+impl MountEntry {
+    const fn bare(mount_point: String) -> Self {
+        Self {
+            device_id: String::new(),
+            mount_point,
+            fstype: None,
+            source: None,
+        }
+    }
+
+    fn read_all() -> Vec<Self> {
+        fs::read_to_string(MOUNTINFO)
+            .map(|info| info.lines().filter_map(Self::parse).collect())
+            .unwrap_or_default()
+    }
+
+    fn parse(line: &str) -> Option<Self> {
+        // id parent major:minor root mount_point options [optional...] -
+        // fstype source super_options
+        let (before, after) = line.split_once(" - ")?;
+        let mut before = before.split(' ');
+        let device_id = before.nth(2)?.to_string();
+        let mount_point = unescape(before.nth(1)?);
+        let mut after = after.split(' ');
+        let fstype = after.next().map(ToString::to_string);
+        let source = after.next().map(unescape);
+        Some(Self {
+            device_id,
+            mount_point,
+            fstype,
+            source,
+        })
+    }
+
+    /// Returns the mounts backed by block devices, skipping loop devices and
+    /// further mounts of a device that is already listed (e.g. btrfs
+    /// subvolumes and bind mounts).
+    fn real(table: &[Self]) -> Vec<Self> {
+        let mut seen = HashSet::new();
+        table
+            .iter()
+            .filter(|entry| {
+                entry.source.as_deref().is_some_and(|source| {
+                    source.starts_with("/dev/")
+                        && !source.starts_with("/dev/loop")
+                })
+            })
+            .filter(|entry| seen.insert(entry.device_id.clone()))
+            .cloned()
+            .collect()
+    }
+}
+
+/// Decodes the octal escapes (e.g. `\040` for a space) used in mountinfo.
+fn unescape(field: &str) -> String {
+    let mut out = Vec::with_capacity(field.len());
+    let mut bytes = field.bytes();
+    while let Some(b) = bytes.next() {
+        if b == b'\\' {
+            let digits = bytes.clone().take(3).collect::<Vec<_>>();
+            if let Some(value) = std::str::from_utf8(&digits)
+                .ok()
+                .filter(|digits| digits.len() == 3)
+                .and_then(|digits| u8::from_str_radix(digits, 8).ok())
+            {
+                out.push(value);
+                bytes.nth(2);
+                continue;
+            }
+        }
+        out.push(b);
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// The usage of a mounted filesystem.
+#[derive(Debug, Clone)]
+struct Usage {
+    entry: MountEntry,
+    used: u64,
+    free: u64,
+    percentage_used: u64,
+    inodes_used: u64,
+    inodes_free: u64,
+    read_only: bool,
+}
+
+impl Usage {
+    fn query(entry: &MountEntry) -> Result<Self> {
+        let fs_info = statvfs(entry.mount_point.as_str())?;
+
+        let used = fs_info.f_blocks - fs_info.f_bfree;
+        let avail = fs_info.f_bavail;
+        let percentage_used = if used + avail == 0 {
+            0
+        } else {
+            (used as f64 / (used + avail) as f64 * 100.0) as u64
+        };
+        let inodes_used = fs_info.f_files.saturating_sub(fs_info.f_ffree);
+
+        Ok(Self {
+            entry: entry.clone(),
+            used: used * fs_info.f_frsize,
+            free: avail * fs_info.f_frsize,
+            percentage_used,
+            inodes_used,
+            inodes_free: fs_info.f_favail,
+            read_only: fs_info.f_flag.contains(StatVfsMountFlags::RDONLY),
+        })
+    }
+
+    fn var(&self, name: &str) -> Option<Var> {
+        let total = self.used + self.free;
+        let inodes_total = self.inodes_used + self.inodes_free;
+        let gb =
+            |bytes: u64| Var::float(bytes as f64 / 1024.0 / 1024.0 / 1024.0, 2);
+        let mb =
+            |bytes: u64| Var::from((bytes as f64 / 1024.0 / 1024.0) as u64);
+        let path = self.entry.mount_point.as_str();
+        match name {
+            "path" => Some(path.into()),
+            "name" => Some(
+                path.rsplit('/')
+                    .find(|s| !s.is_empty())
+                    .unwrap_or("/")
+                    .into(),
+            ),
+            "device" => Some(self.entry.source.as_deref()?.into()),
+            "fstype" => Some(self.entry.fstype.as_deref()?.into()),
+            "ro" => Some(self.read_only.into()),
+            "gb_used" => Some(gb(self.used)),
+            "gb_free" => Some(gb(self.free)),
+            "gb_total" => Some(gb(total)),
+            "mb_used" => Some(mb(self.used)),
+            "mb_free" => Some(mb(self.free)),
+            "mb_total" => Some(mb(total)),
+            "used" => Some(self.used.into()),
+            "free" => Some(self.free.into()),
+            "total" => Some(total.into()),
+            "percentage_used" => Some(self.percentage_used.into()),
+            "percentage_free" => Some((100 - self.percentage_used).into()),
+            "inodes_used" => Some(self.inodes_used.into()),
+            "inodes_free" => Some(self.inodes_free.into()),
+            "inodes_total" => Some(inodes_total.into()),
+            // some filesystems (e.g. btrfs) don't have a fixed number of
+            // inodes
+            "percentage_inodes_used" => (inodes_total > 0)
+                .then(|| (self.inodes_used * 100 / inodes_total).into()),
+            _ => None,
+        }
+    }
+}
+
+/// Returns a stream that yields each time the mount table changes. The kernel
+/// signals this with `POLLPRI` on `/proc/self/mountinfo`.
+fn mount_changes(fd: OwnedFd) -> BlockingStream {
+    BlockingStream::new(MOUNTINFO, move || {
+        let mut fds = [PollFd::new(&fd, PollFlags::PRI)];
+        poll(&mut fds, None)?;
+        Ok(())
+    })
+}
@@ -228,10 +228,8 @@ impl Systray {
     }
 
     fn reposition(&self, icon: Icon, x: i32) -> Result<()> {
-        self.conn.configure_window(
-            icon.window,
-            &ConfigureWindowAux::new().x(x as i32),
-        )?;
+        self.conn
+            .configure_window(icon.window, &ConfigureWindowAux::new().x(x))?;
 
         Ok(())
     }
@@ -258,6 +256,8 @@ impl PanelConfig for Systray {
     /// - `sort_reverse`: If this is true, the sorting method above will be
     ///   reversed.
     /// See [`PanelCommon::parse_common`]. This is used only for dependence.
+    /// In particular, `max_width` isn't supported, since the icons are other
+    /// applications' windows, which can't be cut off or scrolled.
     fn parse(
         name: &'static str,
         table: &mut HashMap<String, Value>,
@@ -0,0 +1,619 @@
+use std::{
+    collections::HashMap,
+    pin::Pin,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Context, Result, anyhow};
+use async_trait::async_trait;
+use config::{Config, Value};
+use derive_builder::Builder;
+use lazybar_types::EventResponse;
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+use tokio_stream::{
+    Stream, StreamExt, StreamMap, wrappers::UnboundedReceiverStream,
+};
+use x11rb::{
+    CURRENT_TIME,
+    connection::Connection,
+    errors::ConnectionError,
+    protocol::{
+        randr::MonitorInfo,
+        xproto::{
+            Atom, AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent,
+            ConnectionExt, EventMask, Window,
+        },
+    },
+    rust_connection::RustConnection,
+};
+
+use crate::{
+    Attrs, Highlight, PanelConfig, PanelRunResult, Template, array_to_struct,
+    bar::{BAR_INFO, Event, MouseButton, PanelDrawInfo},
+    common::PanelCommon,
+    ipc::ChannelEndpoint,
+    remove_bool_from_config, remove_string_from_config,
+    x::{
+        InternedAtoms, PropertyStream, Segment, WindowIcons, cycle_segments,
+        draw_segments, get_class, get_clients, get_window_name, is_urgent,
+        monitor_contains, segment_at, window_centers,
+    },
+};
+
+/// The value of `_NET_WM_DESKTOP` for windows on every desktop.
+const ALL_DESKTOPS: u32 = 0xFFFF_FFFF;
+
+#[derive(PartialEq, Eq, Debug)]
+enum TaskState {
+    Active,
+    Urgent,
+    Inactive,
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Atoms {
+    client: Atom,
+    active: Atom,
+    current: Atom,
+    desktop: Atom,
+    r#type: Atom,
+    normal: Atom,
+    state: Atom,
+    skip: Atom,
+    close: Atom,
+    name: Atom,
+    icon: Atom,
+}
+
+impl Atoms {
+    fn get(conn: &RustConnection) -> Result<Self> {
+        Ok(Self {
+            client: InternedAtoms::get(conn, "_NET_CLIENT_LIST")?,
+            active: InternedAtoms::get(conn, "_NET_ACTIVE_WINDOW")?,
+            current: InternedAtoms::get(conn, "_NET_CURRENT_DESKTOP")?,
+            desktop: InternedAtoms::get(conn, "_NET_WM_DESKTOP")?,
+            r#type: InternedAtoms::get(conn, "_NET_WM_WINDOW_TYPE")?,
+            normal: InternedAtoms::get(conn, "_NET_WM_WINDOW_TYPE_NORMAL")?,
+            state: InternedAtoms::get(conn, "_NET_WM_STATE")?,
+            skip: InternedAtoms::get(conn, "_NET_WM_STATE_SKIP_TASKBAR")?,
+            close: InternedAtoms::get(conn, "_NET_CLOSE_WINDOW")?,
+            name: InternedAtoms::get(conn, "_NET_WM_NAME")?,
+            icon: InternedAtoms::get(conn, "_NET_WM_ICON")?,
+        })
+    }
+}
+
+/// Display a list of windows, like a taskbar
+///
+/// Requires an EWMH-compliant window manager
+#[derive(Clone, Debug, Builder)]
+#[builder_struct_attr(allow(missing_docs))]
+#[builder_impl_attr(allow(missing_docs))]
+pub struct Tasklist {
+    name: &'static str,
+    conn: Arc<RustConnection>,
+    screen: usize,
+    formats: TasklistConfig<Template>,
+    attrs: TasklistConfig<Attrs>,
+    highlights: TasklistConfig<Highlight>,
+    #[builder(default = "true")]
+    workspace_only: bool,
+    #[builder(default)]
+    monitor_only: bool,
+    #[builder(default)]
+    icons: WindowIcons,
+    common: PanelCommon,
+}
+
+impl Tasklist {
+    fn draw(
+        &self,
+        cr: &Rc<cairo::Context>,
+        root: Window,
+        height: i32,
+        width_cache: &Arc<Mutex<Vec<(Window, i32)>>>,
+        atoms: Atoms,
+    ) -> Result<PanelDrawInfo> {
+        let active = get_active(&self.conn, root, atoms.active);
+        let current = get_cardinal(&self.conn, root, atoms.current);
+        let monitor = if self.monitor_only {
+            Some(&BAR_INFO.get().context("Bar not initialized")?.monitor)
+        } else {
+            None
+        };
+
+        let windows = self.shown_windows(root, atoms, current, monitor)?;
+        self.icons.retain(|window| windows.contains(&window));
+
+        let segments = windows
+            .iter()
+            .map(|&window| {
+                let state = if active == Some(window) {
+                    TaskState::Active
+                } else if is_urgent(&*self.conn, window) {
+                    TaskState::Urgent
+                } else {
+                    TaskState::Inactive
+                };
+
+                let format = self.formats.get(&state);
+                let class = if format.uses("class")
+                    || (format.uses("icon") && self.icons.uses_class())
+                {
+                    get_class(&*self.conn, window)
+                } else {
+                    Vec::new()
+                };
+                let title = if format.uses("title") {
+                    get_window_name(&*self.conn, window).unwrap_or_default()
+                } else {
+                    String::new()
+                };
+                let mut objects = Vec::new();
+                let icon = if format.uses("icon") {
+                    self.icons.markup(&*self.conn, window, &class, &mut objects)
+                } else {
+                    String::new()
+                };
+                let text = format.render(|var| match var {
+                    "title" => Some(
+                        glib::markup_escape_text(title.as_str())
+                            .to_string()
+                            .into(),
+                    ),
+                    "class" => class.last().map(|class| {
+                        glib::markup_escape_text(class).to_string().into()
+                    }),
+                    "icon" => Some(icon.as_str().into()),
+                    _ => None,
+                });
+
+                Segment::new(
+                    cr,
+                    window,
+                    text.as_str(),
+                    objects,
+                    self.attrs.get(&state),
+                    self.highlights.get(&state),
+                )
+            })
+            .collect();
+
+        // watch for changes to titles, urgency, icons, and desktops
+        draw_segments(
+            segments,
+            width_cache,
+            &self.conn,
+            root,
+            windows,
+            &self.common,
+            height,
+            format!("{self:?}"),
+        )
+    }
+
+    /// Finds the windows that belong in the list. Only normal windows that
+    /// don't ask to be skipped are shown, and they're further limited by
+    /// `workspace_only` and `monitor_only`. The requests for every window are
+    /// sent before any replies are read.
+    fn shown_windows(
+        &self,
+        root: Window,
+        atoms: Atoms,
+        current: Option<u32>,
+        monitor: Option<&MonitorInfo>,
+    ) -> Result<Vec<Window>> {
+        let conn = &*self.conn;
+        let clients = get_clients(conn, root)?;
+        let current = current.filter(|_| self.workspace_only);
+
+        let cookies = clients
+            .iter()
+            .map(|&window| {
+                Ok((
+                    conn.get_property(
+                        false,
+                        window,
+                        atoms.r#type,
+                        AtomEnum::ATOM,
+                        0,
+                        1,
+                    )?,
+                    conn.get_property(
+                        false,
+                        window,
+                        atoms.state,
+                        AtomEnum::ATOM,
+                        0,
+                        64,
+                    )?,
+                    current
+                        .map(|_| {
+                            conn.get_property(
+                                false,
+                                window,
+                                atoms.desktop,
+                                AtomEnum::CARDINAL,
+                                0,
+                                1,
+                            )
+                        })
+                        .transpose()?,
+                ))
+            })
+            .collect::<Result<Vec<_>, ConnectionError>>()?;
+        let centers = monitor.map(|_| window_centers(conn, &clients, root));
+
+        Ok(clients
+            .into_iter()
+            .zip(cookies)
+            .enumerate()
+            .filter_map(|(i, (window, (r#type, state, desktop)))| {
+                let normal = r#type.reply().ok().is_none_or(|r| {
+                    r.value32().is_none_or(|mut iter| {
+                        iter.next().is_none_or(|v| v == atoms.normal)
+                    })
+                });
+                let skip = state.reply().ok().is_some_and(|r| {
+                    r.value32()
+                        .is_some_and(|mut iter| iter.any(|a| a == atoms.skip))
+                });
+                let elsewhere = current.is_some_and(|current| {
+                    desktop
+                        .and_then(|c| c.reply().ok())
+                        .and_then(|r| r.value32()?.next())
+                        .is_some_and(|desktop| {
+                            desktop != current && desktop != ALL_DESKTOPS
+                        })
+                });
+                let on_monitor = monitor.zip(centers.as_ref()).is_none_or(
+                    |(monitor, centers)| {
+                        centers[i].is_some_and(|center| {
+                            monitor_contains(monitor, center)
+                        })
+                    },
+                );
+                (normal && !skip && !elsewhere && on_monitor).then_some(window)
+            })
+            .collect())
+    }
+
+    fn process_event(
+        event: Event,
+        conn: &RustConnection,
+        root: Window,
+        width_cache: &Arc<Mutex<Vec<(Window, i32)>>>,
+        atoms: Atoms,
+        send: &UnboundedSender<EventResponse>,
+    ) -> Result<()> {
+        match event {
+            Event::Action(Some(event)) => {
+                let cache = width_cache.lock().unwrap();
+                let active = get_active(conn, root, atoms.active);
+                let result = match event.as_str() {
+                    "next" => cycle_segments(&cache, active, true)
+                        .map(|window| activate(conn, root, atoms, window)),
+                    "prev" => cycle_segments(&cache, active, false)
+                        .map(|window| activate(conn, root, atoms, window)),
+                    "close" => {
+                        active.map(|window| close(conn, root, atoms, window))
+                    }
+                    _ => {
+                        drop(cache);
+                        send.send(EventResponse::Err(format!(
+                            "Unknown event {event}"
+                        )))?;
+                        return Ok(());
+                    }
+                };
+                drop(cache);
+                result.transpose()?;
+                send.send(EventResponse::Ok(None))?;
+            }
+
+            Event::Action(None) => {}
+
+            Event::Mouse(event) => {
+                let cache = width_cache.lock().unwrap();
+                let target = match event.button {
+                    MouseButton::Left => {
+                        segment_at(&cache, event.x).map(|window| (window, true))
+                    }
+                    MouseButton::Middle => segment_at(&cache, event.x)
+                        .map(|window| (window, false)),
+                    MouseButton::ScrollUp => cycle_segments(
+                        &cache,
+                        get_active(conn, root, atoms.active),
+                        true,
+                    )
+                    .map(|window| (window, true)),
+                    MouseButton::ScrollDown => cycle_segments(
+                        &cache,
+                        get_active(conn, root, atoms.active),
+                        false,
+                    )
+                    .map(|window| (window, true)),
+                    MouseButton::Right | MouseButton::Drag => None,
+                };
+                drop(cache);
+
+                match target {
+                    Some((window, true)) => {
+                        activate(conn, root, atoms, window)?;
+                    }
+                    Some((window, false)) => close(conn, root, atoms, window)?,
+                    None => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl PanelConfig for Tasklist {
+    /// Parses an instance of the panel from the global [`Config`]
+    ///
+    /// Configuration options:
+    /// - `screen`: the name of the X screen to monitor
+    ///   - type: String
+    ///   - default: None (This will tell X to choose the default screen, which
+    ///     is probably what you want.)
+    /// - `format_active`, `format_urgent`, `format_inactive`: The format string
+    ///   for the windows in each state. The active window is the one with
+    ///   focus, and urgent windows are those that demand attention.
+    ///   - type: String
+    ///   - formatting options: `%title%`, `%class%`, `%icon%`
+    ///   - default: `%icon% %title%`
+    /// - `workspace_only`: Whether to show only the windows on the current
+    ///   workspace (and those on every workspace).
+    ///   - type: bool
+    ///   - default: true
+    /// - `monitor_only`: Whether to show only the windows whose centers are on
+    ///   the bar's monitor.
+    ///   - type: bool
+    ///   - default: false
+    /// - `icons`: A table mapping window classes (or instances) to the markup
+    ///   that represents them in `%icon%`.
+    ///   - type: Table
+    ///   - default: empty
+    /// - `window_icons`: Whether to show the icons that windows provide
+    ///   (`_NET_WM_ICON`) in `%icon%` for windows that aren't in `icons`.
+    ///   - type: bool
+    ///   - default: true
+    /// - `icon_size`: The height in pixels of window icons.
+    ///   - type: f64
+    ///   - default: 16
+    /// - `attrs_active`, `attrs_urgent`, `attrs_inactive`: A string specifying
+    ///   the attrs for the windows in each state. See [`Attrs::parse`] for
+    ///   details.
+    /// - `highlight_active`, `highlight_urgent`, `highlight_inactive`: The
+    ///   highlight to be used for the windows in each state. See
+    ///   [`Highlight::parse`] for more details.
+    /// - See [`PanelCommon::parse_common`]. `click_*` and `scroll_*` are
+    ///   currently ignored. Left clicking a window activates it, middle
+    ///   clicking closes it, and scrolling cycles through the windows.
+    ///   `max_width` isn't supported, since cutting off or scrolling the row of
+    ///   windows would hide some of them; shorten each title with
+    ///   `{title|truncate(30)}` (see [`Template`]) instead.
+    ///
+    /// The panel also responds to the actions `next`, `prev`, and `close`
+    /// (which closes the active window) sent over IPC.
+    fn parse(
+        name: &'static str,
+        table: &mut HashMap<String, Value>,
+        _global: &Config,
+    ) -> Result<Self> {
+        let mut builder = TasklistBuilder::default();
+
+        builder.name(name);
+        let screen = remove_string_from_config("screen", table);
+        if let Ok((conn, screen)) = RustConnection::connect(screen.as_deref()) {
+            builder.conn(Arc::new(conn)).screen(screen);
+        } else {
+            log::error!("Failed to connect to X server");
+        }
+
+        if let Some(workspace_only) =
+            remove_bool_from_config("workspace_only", table)
+        {
+            builder.workspace_only(workspace_only);
+        }
+        if let Some(monitor_only) =
+            remove_bool_from_config("monitor_only", table)
+        {
+            builder.monitor_only(monitor_only);
+        }
+        builder.icons(WindowIcons::parse(table));
+
+        let common = PanelCommon::parse_common(table)?;
+        let formats = PanelCommon::parse_templates(
+            table,
+            &["_active", "_urgent", "_inactive"],
+            &["%icon% %title%"; 3],
+        )?;
+        let attrs = PanelCommon::parse_attrs(
+            table,
+            &["_active", "_urgent", "_inactive"],
+        );
+        let highlights = PanelCommon::parse_highlights(
+            table,
+            &["_active", "_urgent", "_inactive"],
+        );
+
+        builder.common(common);
+        builder.formats(TasklistConfig::new(formats));
+        builder.attrs(TasklistConfig::new(attrs));
+        builder.highlights(TasklistConfig::new(highlights));
+
+        Ok(builder.build()?)
+    }
+
+    fn props(&self) -> (&'static str, bool) {
+        (self.name, self.common.visible)
+    }
+
+    async fn run(
+        mut self: Box<Self>,
+        cr: Rc<cairo::Context>,
+        global_attrs: Attrs,
+        height: i32,
+    ) -> PanelRunResult {
+        let atoms = Atoms::get(&self.conn)?;
+
+        let root = self
+            .conn
+            .setup()
+            .roots
+            .get(self.screen)
+            .ok_or_else(|| anyhow!("Screen not found"))?
+            .root;
+        self.conn.change_window_attributes(
+            root,
+            &ChangeWindowAttributesAux::new()
+                .event_mask(EventMask::PROPERTY_CHANGE),
+        )?;
+
+        self.attrs.active.apply_to(&global_attrs);
+        self.attrs.urgent.apply_to(&global_attrs);
+        self.attrs.inactive.apply_to(&global_attrs);
+
+        let mut map =
+            StreamMap::<usize, Pin<Box<dyn Stream<Item = Result<()>>>>>::new();
+
+        map.insert(
+            0,
+            Box::pin(
+                tokio_stream::once(())
+                    .chain(PropertyStream::new(
+                        self.conn.clone(),
+                        &[
+                            atoms.client,
+                            atoms.active,
+                            atoms.current,
+                            atoms.desktop,
+                            atoms.state,
+                            atoms.name,
+                            atoms.icon,
+                            AtomEnum::WM_NAME.into(),
+                            AtomEnum::WM_HINTS.into(),
+                        ],
+                        self.icons.clone(),
+                    )?)
+                    .map(|()| Ok(())),
+            ),
+        );
+
+        let (event_send, event_recv) = unbounded_channel();
+        let (response_send, response_recv) = unbounded_channel();
+        let conn = self.conn.clone();
+        let width_cache = Arc::new(Mutex::new(Vec::new()));
+        let cache = width_cache.clone();
+
+        map.insert(
+            1,
+            Box::pin(UnboundedReceiverStream::new(event_recv).map(move |s| {
+                Self::process_event(
+                    s,
+                    &conn,
+                    root,
+                    &cache,
+                    atoms,
+                    &response_send,
+                )
+            })),
+        );
+
+        Ok((
+            Box::pin(map.map(move |_| {
+                self.draw(&cr, root, height, &width_cache, atoms)
+            })),
+            Some(ChannelEndpoint::new(event_send, response_recv)),
+        ))
+    }
+}
+
+fn get_cardinal(
+    conn: &RustConnection,
+    window: Window,
+    atom: Atom,
+) -> Option<u32> {
+    conn.get_property(false, window, atom, AtomEnum::CARDINAL, 0, 1)
+        .ok()?
+        .reply()
+        .ok()?
+        .value32()?
+        .next()
+}
+
+fn get_active(
+    conn: &RustConnection,
+    root: Window,
+    active_atom: Atom,
+) -> Option<Window> {
+    conn.get_property(false, root, active_atom, AtomEnum::WINDOW, 0, 1)
+        .ok()?
+        .reply()
+        .ok()?
+        .value32()?
+        .next()
+        .filter(|&window| window != 0)
+}
+
+fn activate(
+    conn: &RustConnection,
+    root: Window,
+    atoms: Atoms,
+    window: Window,
+) -> Result<()> {
+    let active = get_active(conn, root, atoms.active).unwrap_or_default();
+    // a source indication of 2 means the request comes from a pager
+    conn.send_event(
+        false,
+        root,
+        EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT,
+        ClientMessageEvent::new(
+            32,
+            window,
+            atoms.active,
+            [2, CURRENT_TIME, active, 0, 0],
+        ),
+    )?;
+    conn.flush()?;
+    Ok(())
+}
+
+fn close(
+    conn: &RustConnection,
+    root: Window,
+    atoms: Atoms,
+    window: Window,
+) -> Result<()> {
+    conn.send_event(
+        false,
+        root,
+        EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT,
+        ClientMessageEvent::new(
+            32,
+            window,
+            atoms.close,
+            [CURRENT_TIME, 2, 0, 0, 0],
+        ),
+    )?;
+    conn.flush()?;
+    Ok(())
+}
+
+array_to_struct!(TasklistConfig, active, urgent, inactive);
+
+impl<T> TasklistConfig<T> {
+    const fn get(&self, state: &TaskState) -> &T {
+        match state {
+            TaskState::Active => &self.active,
+            TaskState::Urgent => &self.urgent,
+            TaskState::Inactive => &self.inactive,
+        }
+    }
+}
@@ -1,12 +1,12 @@
 use std::{
-    fs::File,
-    io::Read,
+    fs,
+    path::{Path, PathBuf},
     rc::Rc,
     sync::{Arc, Mutex},
     time::Duration,
 };
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use async_trait::async_trait;
 use derive_builder::Builder;
 use futures::task::AtomicWaker;
@@ -14,27 +14,37 @@ use tokio_stream::StreamExt;
 
 use crate::{
     Attrs, Highlight, ManagedIntervalStream, PanelConfig, PanelRunResult, Ramp,
+    Template,
     bar::PanelDrawInfo,
     common::{PanelCommon, ShowHide},
-    remove_uint_from_config,
+    glob_match, remove_string_from_config, remove_uint_from_config,
+    template::Var,
 };
 
-/// Displays the temperature of a provided thermal zone.
+/// Displays the temperature of a thermal zone or hwmon sensor.
 ///
 /// The thermal zone meanings are listed in
-/// `/sys/class/thermal/thermal_zone*/type`.
+/// `/sys/class/thermal/thermal_zone*/type`, and hwmon sensors are listed in
+/// `/sys/class/hwmon/hwmon*/name` (and `temp*_label`). `sensors` from
+/// lm-sensors shows the same names.
 #[derive(Debug, Builder, Clone)]
 #[builder_struct_attr(allow(missing_docs))]
 #[builder_impl_attr(allow(missing_docs))]
 pub struct Temp {
     name: &'static str,
-    #[builder(default = "0")]
-    zone: usize,
+    #[builder(default = "Source::Zone(0)")]
+    source: Source,
+    #[builder(default)]
+    mode: Mode,
+    #[builder(default)]
+    unit: Unit,
+    #[builder(default = r#"String::from("*")"#)]
+    fan_hwmon: String,
     #[builder(default = "Duration::from_secs(10)")]
     interval: Duration,
     #[builder(default)]
     waker: Arc<AtomicWaker>,
-    format: &'static str,
+    format: Template,
     attrs: Attrs,
     #[builder(default, setter(strip_option))]
     highlight: Option<Highlight>,
@@ -44,31 +54,76 @@ pub struct Temp {
 
 impl Temp {
     fn draw(
-        &self,
+        &mut self,
         cr: &Rc<cairo::Context>,
         height: i32,
         paused: Arc<Mutex<bool>>,
+        update: bool,
     ) -> Result<PanelDrawInfo> {
-        let mut temp = String::new();
-        File::open(format!(
-            "/sys/class/thermal/thermal_zone{}/temp",
-            self.zone
-        ))?
-        .read_to_string(&mut temp)?;
-
-        let temp = temp.trim().parse::<u32>()? / 1000;
+        let temps = self
+            .source
+            .sensors()
+            .iter()
+            .filter_map(|path| read_millidegrees(path))
+            .collect::<Vec<_>>();
+        let celsius = self.mode.combine(&temps).ok_or_else(|| {
+            anyhow!("No temperature sensors found for {:?}", self.source)
+        })?;
+        let temp = self.unit.convert(celsius).round();
+        // ramps span 0 to 200 degrees celsius
+        let (min, max) = (
+            self.unit.convert(0.0) as i32,
+            self.unit.convert(200.0) as i32,
+        );
+        if update {
+            self.common.history.push(temp);
+        }
+        // scanning every hwmon device is only worth it if a fan is shown
+        let is_fan = |name: &str| {
+            name.strip_prefix("fan")
+                .is_some_and(|n| n.is_empty() || n.parse::<usize>().is_ok())
+        };
+        let fans = if self.format.uses_matching(is_fan)
+            || self.common.thresholds.uses_matching(is_fan)
+        {
+            query_fans(self.fan_hwmon.as_str())
+        } else {
+            Vec::new()
+        };
 
-        let text = self
-            .format
-            .replace("%temp%", temp.to_string().as_str())
-            .replace("%ramp%", self.ramp.choose(temp, 0, 200).as_str());
+        let (attrs, highlight) = self.common.color_ramps.apply(
+            temp as i32,
+            min,
+            max,
+            &self.attrs,
+            self.highlight.as_ref(),
+        );
+        let (format, attrs, highlight) = self.common.thresholds.style(
+            temp,
+            &self.format,
+            &attrs,
+            highlight.as_ref(),
+        );
+        let text = format.render(|name| match name {
+            "temp" => Some(Var::float(temp, 0)),
+            "unit" => Some(self.unit.symbol().into()),
+            "ramp" => Some(self.ramp.choose(temp as i32, min, max).into()),
+            "graph" => Some(self.common.history.var()),
+            "sensors" => Some(temps.len().into()),
+            "fan" => Some(fans.iter().copied().max()?.into()),
+            name => name
+                .strip_prefix("fan")
+                .and_then(|n| n.parse::<usize>().ok())
+                .and_then(|n| fans.get(n.checked_sub(1)?))
+                .map(|rpm| (*rpm).into()),
+        });
 
         self.common.draw(
             cr,
             text.as_str(),
-            &self.attrs,
+            &attrs,
             self.common.dependence,
-            self.highlight.clone(),
+            highlight,
             self.common.images.clone(),
             height,
             ShowHide::Default(paused, self.waker.clone()),
@@ -85,20 +140,62 @@ impl PanelConfig for Temp {
     /// - `format`: the format string
     ///   - type: String
     ///   - default: `TEMP: %temp%`
-    ///   - formatting options: `%temp%`
+    ///   - formatting options: See [`Template`] for the full syntax.
+    ///     - `%temp%`: the temperature in `unit`
+    ///     - `%unit%`: `°C`, `°F`, or `K`
+    ///     - `%sensors%`: the number of sensors that were read
+    ///     - `%fan%`: the speed of the fastest fan in RPM
+    ///     - `%fan1%`, `%fan2%`, etc.: the speed of each fan in RPM, in the
+    ///       order they are found under the hwmon devices matching `fan_hwmon`
+    ///     - `%ramp%`, `%graph%`
     /// - `interval`: how long to wait in seconds between each check
     ///   - type: u64
     ///   - default: 10
-    /// - `zone`: the thermal zone to check
+    /// - `zone`: the number of the thermal zone to check. Zone numbers can
+    ///   change between boots, so one of the options below is usually a better
+    ///   choice.
     ///   - type: u64
     ///   - default: 0
+    /// - `zone_type`: a glob matching the `type` of the thermal zones to check,
+    ///   e.g. `x86_pkg_temp` or `acpitz`. Takes precedence over `zone`.
+    ///   - type: String
+    /// - `hwmon`: a glob matching the `name` of the hwmon devices to check,
+    ///   e.g. `coretemp`, `k10temp`, `nvme`, or `amdgpu`. Takes precedence over
+    ///   `zone_type`.
+    ///   - type: String
+    /// - `label`: a glob matching the labels of the sensors to check on the
+    ///   `hwmon` devices, e.g. `Package id 0` or `Tctl`. By default, every
+    ///   temperature sensor on the devices is checked.
+    ///   - type: String
+    /// - `path`: a glob matching the files to read, e.g.
+    ///   `/sys/class/hwmon/hwmon*/temp1_input`. Each file should contain a
+    ///   temperature in millidegrees Celsius. Takes precedence over all of the
+    ///   options above.
+    ///   - type: String
+    /// - `mode`: how to combine several sensors: `first`, `max`, or `average`
+    ///   - type: String
+    ///   - default: `first`
+    /// - `unit`: `C`, `F`, or `K`. Thresholds and `%graph%` use this unit too.
+    ///   - type: String
+    ///   - default: `C`
+    /// - `fan_hwmon`: a glob matching the `name` of the hwmon devices whose
+    ///   fans are shown
+    ///   - type: String
+    ///   - default: `*`
     /// - `attrs`: A string specifying the attrs for the panel. See
     ///   [`Attrs::parse`] for details.
     /// - `highlight`: A string specifying the highlight for the panel. See
     ///   [`Highlight::parse`] for details.
     /// - `ramp`: A string specifying the ramp to show internal temperature. See
     ///   [`Ramp::parse`] for details.
-    /// - See [`PanelCommon::parse_common`].
+    /// - `ramp_fg`, `ramp_bg`, `ramp_highlight`: Ramps whose colors follow the
+    ///   temperature, from 0 to 200 degrees Celsius. See
+    ///   [`ColorRamps::parse`][crate::ColorRamps::parse] for details.
+    /// - `graph`, `history_length`, `history_interval`: Control `%graph%`,
+    ///   which shows recent temperatures. See
+    ///   [`History::parse`][crate::History::parse] for details.
+    /// - See [`PanelCommon::parse_common`]. Thresholds apply to the
+    ///   temperature.
     fn parse(
         name: &'static str,
         table: &mut std::collections::HashMap<String, config::Value>,
@@ -110,18 +207,52 @@ impl PanelConfig for Temp {
         if let Some(interval) = remove_uint_from_config("interval", table) {
             builder.interval(Duration::from_secs(interval));
         }
-        if let Some(zone) = remove_uint_from_config("zone", table) {
-            builder.zone(zone as usize);
+        let zone = remove_uint_from_config("zone", table);
+        let zone_type = remove_string_from_config("zone_type", table);
+        let hwmon = remove_string_from_config("hwmon", table);
+        let label = remove_string_from_config("label", table);
+        let path = remove_string_from_config("path", table);
+        if let Some(path) = path {
+            builder.source(Source::Path(path));
+        } else if let Some(name) = hwmon {
+            builder.source(Source::Hwmon { name, label });
+        } else if let Some(zone_type) = zone_type {
+            builder.source(Source::ZoneType(zone_type));
+        } else if let Some(zone) = zone {
+            builder.source(Source::Zone(zone as usize));
+        }
+        if let Some(mode) = remove_string_from_config("mode", table) {
+            builder.mode(match mode.as_str() {
+                "first" => Mode::First,
+                "max" => Mode::Max,
+                "average" => Mode::Average,
+                other => {
+                    return Err(anyhow!("Unknown temperature mode {other}"));
+                }
+            });
+        }
+        if let Some(unit) = remove_string_from_config("unit", table) {
+            builder.unit(match unit.to_ascii_uppercase().as_str() {
+                "C" => Unit::Celsius,
+                "F" => Unit::Fahrenheit,
+                "K" => Unit::Kelvin,
+                other => {
+                    return Err(anyhow!("Unknown temperature unit {other}"));
+                }
+            });
+        }
+        if let Some(fan_hwmon) = remove_string_from_config("fan_hwmon", table) {
+            builder.fan_hwmon(fan_hwmon);
         }
 
         let common = PanelCommon::parse_common(table)?;
-        let format = PanelCommon::parse_format(table, "", "TEMP: %temp%");
+        let format = PanelCommon::parse_template(table, "", "TEMP: %temp%")?;
         let attrs = PanelCommon::parse_attr(table, "");
         let highlight = PanelCommon::parse_highlight(table, "");
         let ramp = PanelCommon::parse_ramp(table, "");
 
         builder.common(common);
-        builder.format(format.leak());
+        builder.format(format);
         builder.attrs(attrs);
         builder.highlight(highlight);
         builder.ramp(ramp);
@@ -143,13 +274,207 @@ impl PanelConfig for Temp {
 
         let paused = Arc::new(Mutex::new(false));
 
-        let stream = ManagedIntervalStream::builder()
-            .duration(self.interval)
-            .paused(paused.clone())
-            .waker(self.waker.clone())
-            .build()?
-            .map(move |_| self.draw(&cr, height, paused.clone()));
+        let stream = self
+            .common
+            .thresholds
+            .blink(
+                ManagedIntervalStream::builder()
+                    .duration(self.interval)
+                    .paused(paused.clone())
+                    .waker(self.waker.clone())
+                    .build()?,
+                paused.clone(),
+                self.waker.clone(),
+            )
+            .map(move |update| {
+                self.draw(&cr, height, paused.clone(), update.is_some())
+            });
 
         Ok((Box::pin(stream), None))
     }
 }
+
+const THERMAL: &str = "/sys/class/thermal";
+const HWMON: &str = "/sys/class/hwmon";
+
+/// Where to find temperature sensors.
+#[derive(Debug, Clone)]
+enum Source {
+    Zone(usize),
+    ZoneType(String),
+    Hwmon { name: String, label: Option<String> },
+    Path(String),
+}
+
+impl Source {
+    /// Returns the files to read, each containing a temperature in
+    /// millidegrees Celsius.
+    fn sensors(&self) -> Vec<PathBuf> {
+        match self {
+            Self::Zone(zone) => {
+                vec![PathBuf::from(format!(
+                    "{THERMAL}/thermal_zone{zone}/temp"
+                ))]
+            }
+            Self::ZoneType(glob) => list_dir(Path::new(THERMAL))
+                .into_iter()
+                .filter(|zone| {
+                    zone.file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| name.starts_with("thermal_zone"))
+                })
+                .filter(|zone| {
+                    read_trimmed(&zone.join("type"))
+                        .is_some_and(|ty| glob_match(glob, ty.as_str()))
+                })
+                .map(|zone| zone.join("temp"))
+                .collect(),
+            Self::Hwmon { name, label } => hwmon_devices(name.as_str())
+                .iter()
+                .flat_map(|device| numbered_inputs(device, "temp"))
+                .filter(|(device, n)| {
+                    label.as_ref().is_none_or(|glob| {
+                        read_trimmed(&device.join(format!("temp{n}_label")))
+                            .is_some_and(|label| {
+                                glob_match(glob, label.as_str())
+                            })
+                    })
+                })
+                .map(|(device, n)| device.join(format!("temp{n}_input")))
+                .collect(),
+            Self::Path(glob) => expand_glob(glob.as_str()),
+        }
+    }
+}
+
+/// How to combine several sensors into one temperature.
+#[derive(Debug, Clone, Copy, Default)]
+enum Mode {
+    #[default]
+    First,
+    Max,
+    Average,
+}
+
+impl Mode {
+    fn combine(self, temps: &[f64]) -> Option<f64> {
+        match self {
+            Self::First => temps.first().copied(),
+            Self::Max => temps.iter().copied().reduce(f64::max),
+            Self::Average => (!temps.is_empty())
+                .then(|| temps.iter().sum::<f64>() / temps.len() as f64),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+enum Unit {
+    #[default]
+    Celsius,
+    Fahrenheit,
+    Kelvin,
+}
+
+impl Unit {
+    fn convert(self, celsius: f64) -> f64 {
+        match self {
+            Self::Celsius => celsius,
+            Self::Fahrenheit => celsius.mul_add(1.8, 32.0),
+            Self::Kelvin => celsius + 273.15,
+        }
+    }
+
+    const fn symbol(self) -> &'static str {
+        match self {
+            Self::Celsius => "°C",
+            Self::Fahrenheit => "°F",
+            Self::Kelvin => "K",
+        }
+    }
+}
+
+fn read_trimmed(path: &Path) -> Option<String> {
+    fs::read_to_string(path).ok().map(|s| s.trim().to_string())
+}
+
+fn read_millidegrees(path: &Path) -> Option<f64> {
+    Some(read_trimmed(path)?.parse::<f64>().ok()? / 1000.0)
+}
+
+/// Lists the entries of a directory, sorted by name.
+fn list_dir(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut paths = entries
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .collect::<Vec<_>>();
+    paths.sort();
+    paths
+}
+
+/// Returns the hwmon devices whose `name` matches `glob`.
+fn hwmon_devices(glob: &str) -> Vec<PathBuf> {
+    list_dir(Path::new(HWMON))
+        .into_iter()
+        .filter(|device| {
+            read_trimmed(&device.join("name"))
+                .is_some_and(|name| glob_match(glob, name.as_str()))
+        })
+        .collect()
+}
+
+/// Returns the numbers of the `{kind}*_input` files in a hwmon device, in
+/// numerical order.
+fn numbered_inputs(device: &Path, kind: &str) -> Vec<(PathBuf, u32)> {
+    let mut inputs = list_dir(device)
+        .into_iter()
+        .filter_map(|path| {
+            path.file_name()?
+                .to_str()?
+                .strip_prefix(kind)?
+                .strip_suffix("_input")?
+                .parse::<u32>()
+                .ok()
+        })
+        .map(|n| (device.to_path_buf(), n))
+        .collect::<Vec<_>>();
+    inputs.sort_by_key(|(_, n)| *n);
+    inputs
+}
+
+/// Returns the speed of each fan in RPM.
+fn query_fans(hwmon: &str) -> Vec<u64> {
+    hwmon_devices(hwmon)
+        .iter()
+        .flat_map(|device| numbered_inputs(device, "fan"))
+        .filter_map(|(device, n)| {
+            read_trimmed(&device.join(format!("fan{n}_input")))?
+                .parse()
+                .ok()
+        })
+        .collect()
+}
+
+/// Returns the files matching an absolute path containing `*` and `?`
+/// wildcards, which may appear in any component.
+fn expand_glob(pattern: &str) -> Vec<PathBuf> {
+    let mut paths = vec![PathBuf::from("/")];
+    for component in pattern.split('/').filter(|c| !c.is_empty()) {
+        paths = if component.contains(['*', '?']) {
+            paths
+                .iter()
+                .flat_map(|dir| list_dir(dir))
+                .filter(|path| {
+                    path.file_name()
+                        .and_then(|name| name.to_str())
+                        .is_some_and(|name| glob_match(component, name))
+                })
+                .collect()
+        } else {
+            paths.iter().map(|dir| dir.join(component)).collect()
+        };
+    }
+    paths.retain(|path| path.exists());
+    paths
+}
@@ -0,0 +1,546 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    pin::Pin,
+    process::Command,
+    rc::Rc,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, SystemTime},
+};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use config::{Config, Value};
+use derive_builder::Builder;
+use futures::task::AtomicWaker;
+use if_addrs::get_if_addrs;
+use lazybar_types::EventResponse;
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
+use tokio_stream::{
+    Stream, StreamExt, StreamMap, wrappers::UnboundedReceiverStream,
+};
+
+use crate::{
+    Attrs, Highlight, ManagedIntervalStream, PanelConfig, PanelRunResult,
+    Template,
+    actions::Actions,
+    array_to_struct,
+    bar::{Event, MouseButton, PanelDrawInfo},
+    common::{PanelCommon, ShowHide},
+    glob_match,
+    ipc::ChannelEndpoint,
+    netlink, remove_array_from_config, remove_string_from_config,
+    remove_uint_from_config,
+};
+
+array_to_struct!(VpnFormats, connected, disconnected);
+
+/// Displays the status of a VPN connection, detected from tun, tap, ppp, and
+/// WireGuard interfaces.
+#[derive(Builder, Debug, Clone)]
+#[builder_struct_attr(allow(missing_docs))]
+#[builder_impl_attr(allow(missing_docs))]
+pub struct Vpn {
+    name: &'static str,
+    #[builder(default = r#"Duration::from_secs(5)"#)]
+    duration: Duration,
+    #[builder(default)]
+    waker: Arc<AtomicWaker>,
+    #[builder(default)]
+    interfaces: Vec<String>,
+    #[builder(default, setter(strip_option))]
+    connection: Option<String>,
+    #[builder(default)]
+    commands: Commands,
+    #[builder(default)]
+    connected: Arc<AtomicBool>,
+    formats: VpnFormats<Template>,
+    attrs: Attrs,
+    #[builder(default, setter(strip_option))]
+    highlight: Option<Highlight>,
+    common: PanelCommon,
+}
+
+impl Vpn {
+    fn draw(
+        &self,
+        cr: &Rc<cairo::Context>,
+        height: i32,
+        paused: Arc<Mutex<bool>>,
+    ) -> Result<PanelDrawInfo> {
+        let links = query_links().unwrap_or_else(|e| {
+            log::warn!("Failed to query network interfaces: {e}");
+            Vec::new()
+        });
+        let link = links
+            .iter()
+            .filter(|link| {
+                self.interfaces.is_empty()
+                    || self
+                        .interfaces
+                        .iter()
+                        .any(|glob| glob_match(glob, link.name.as_str()))
+            })
+            .min_by_key(|link| (!link.up, link.name.as_str()));
+        let connected = link.is_some_and(|link| link.up);
+        self.connected.store(connected, Ordering::Relaxed);
+
+        let format = if connected {
+            &self.formats.connected
+        } else {
+            &self.formats.disconnected
+        };
+        let wireguard = link
+            .filter(|link| connected && link.kind == "wireguard")
+            .filter(|_| {
+                ["endpoint", "handshake", "handshake_age"]
+                    .into_iter()
+                    .any(|name| format.uses(name))
+            })
+            .and_then(|link| {
+                query_wireguard(link.index)
+                    .map_err(|e| {
+                        log::debug!("Failed to query wireguard device: {e}");
+                    })
+                    .ok()
+            });
+        let handshake = wireguard
+            .and_then(|wg| wg.handshake)
+            .and_then(|time| time.elapsed().ok())
+            .map(|age| age.as_secs());
+        let ip = link.and_then(|link| query_ip(link.name.as_str()));
+
+        let text = format.render(|name| match name {
+            "name" => Some(
+                self.connection
+                    .as_deref()
+                    .or_else(|| link.map(|link| link.name.as_str()))?
+                    .into(),
+            ),
+            "ifname" => Some(link?.name.as_str().into()),
+            "kind" => Some(link?.kind.into()),
+            "connected" => Some(connected.into()),
+            "local_ip" => Some(ip?.to_string().into()),
+            "endpoint" => Some(wireguard?.endpoint?.to_string().into()),
+            "handshake" => Some(handshake?.into()),
+            "handshake_age" => Some(format_age(handshake?).into()),
+            _ => None,
+        });
+
+        self.common.draw(
+            cr,
+            text.as_str(),
+            &self.attrs,
+            self.common.dependence,
+            self.highlight.clone(),
+            self.common.images.clone(),
+            height,
+            ShowHide::Default(paused, self.waker.clone()),
+            format!("{self:?}"),
+        )
+    }
+
+    fn process_event(
+        event: Event,
+        actions: &Actions,
+        commands: &Commands,
+        connected: &AtomicBool,
+        send: &UnboundedSender<EventResponse>,
+    ) -> Result<()> {
+        match event {
+            Event::Action(Some(value)) => {
+                let command = match value.as_str() {
+                    "up" => &commands.up,
+                    "down" => &commands.down,
+                    "toggle" => {
+                        if connected.load(Ordering::Relaxed) {
+                            &commands.down
+                        } else {
+                            &commands.up
+                        }
+                    }
+                    e => {
+                        send.send(EventResponse::Err(format!(
+                            "Unknown event {e}"
+                        )))?;
+                        return Ok(());
+                    }
+                };
+                let Some(command) = command.clone() else {
+                    send.send(EventResponse::Err(format!(
+                        "No command configured for {value}"
+                    )))?;
+                    return Ok(());
+                };
+                // the panel redraws when the interface changes, so there's no
+                // need to wait for the command here
+                tokio::task::spawn_blocking(move || {
+                    match Command::new("sh").arg("-c").arg(&command).status() {
+                        Ok(status) if !status.success() => {
+                            log::warn!("`{command}` exited with {status}");
+                        }
+                        Ok(_) => {}
+                        Err(e) => log::warn!("Failed to run `{command}`: {e}"),
+                    }
+                });
+                send.send(EventResponse::Ok(None))?;
+            }
+            Event::Action(None) => {}
+            Event::Mouse(event) => {
+                let action = match event.button {
+                    MouseButton::Left => actions.left.clone(),
+                    MouseButton::Right => actions.right.clone(),
+                    MouseButton::Middle => actions.middle.clone(),
+                    MouseButton::ScrollUp => actions.up.clone(),
+                    MouseButton::ScrollDown => actions.down.clone(),
+                    MouseButton::Drag => None,
+                };
+                Self::process_event(
+                    Event::Action(action),
+                    actions,
+                    commands,
+                    connected,
+                    send,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait(?Send)]
+impl PanelConfig for Vpn {
+    /// Parses an instance of the panel from the global [`Config`]
+    ///
+    /// Configuration options:
+    /// - `interfaces`: globs restricting which interfaces are considered (e.g.
+    ///   `["wg0", "tun*"]`). By default, every tun, tap, ppp, and WireGuard
+    ///   interface is considered. If several match, one that is up is shown.
+    ///   - type: Vec<String>
+    ///   - default: []
+    /// - `connection`: the name of the connection, shown as `%name%`
+    ///   - type: String
+    ///   - default: the name of the interface
+    /// - `up_command`: a shell command that brings the connection up, e.g.
+    ///   `nmcli connection up corp` or `wg-quick up wg0`
+    ///   - type: String
+    ///   - default: none
+    /// - `down_command`: a shell command that brings the connection down
+    ///   - type: String
+    ///   - default: none
+    /// - `interval`: the amount of time in seconds to wait between redraws.
+    ///   Interfaces coming up or going down are shown immediately regardless,
+    ///   so this only affects `%handshake%` and `%handshake_age%`.
+    ///   - type: u64
+    ///   - default: 5
+    /// - `format`: a format string to use for both states, unless overridden
+    ///   below. See [`PanelCommon::parse_templates`].
+    ///   - type: String
+    ///   - formatting options:
+    ///     - `%name%`, `%ifname%`, `%connected%`, `%local_ip%`
+    ///     - `%kind%`: `wireguard`, `tun`, `tap`, or `ppp`
+    ///     - `%endpoint%`: the address and port of the WireGuard peer
+    ///     - `%handshake%`: the number of seconds since the latest WireGuard
+    ///       handshake
+    ///     - `%handshake_age%`: the same, but formatted like `2m` or `45s`
+    ///
+    ///     The WireGuard options require the bar to have `CAP_NET_ADMIN`, and
+    ///     are empty otherwise.
+    /// - `format_connected`: the format string when the connection is up
+    ///   - type: String
+    ///   - default: "%name%"
+    /// - `format_disconnected`: the format string when the connection is down
+    ///   - type: String
+    ///   - default: ""
+    /// - `attrs`: A string specifying the attrs for the panel. See
+    ///   [`Attrs::parse`] for details.
+    /// - `highlight`: A string specifying the highlight for the panel. See
+    ///   [`Highlight::parse`] for details.
+    /// - See [`PanelCommon::parse_common`]. The supported events are `up`,
+    ///   `down`, and `toggle`, which run the commands above. For example, set
+    ///   `click_left = "toggle"`.
+    fn parse(
+        name: &'static str,
+        table: &mut HashMap<String, Value>,
+        _global: &Config,
+    ) -> Result<Self> {
+        let mut builder = VpnBuilder::default();
+
+        builder.name(name);
+        if let Some(interfaces) = remove_array_from_config("interfaces", table)
+        {
+            builder.interfaces(
+                interfaces
+                    .into_iter()
+                    .filter_map(|v| v.into_string().ok())
+                    .collect(),
+            );
+        }
+        if let Some(connection) = remove_string_from_config("connection", table)
+        {
+            builder.connection(connection);
+        }
+        builder.commands(Commands {
+            up: remove_string_from_config("up_command", table),
+            down: remove_string_from_config("down_command", table),
+        });
+        if let Some(duration) = remove_uint_from_config("interval", table) {
+            builder.duration(Duration::from_secs(duration));
+        }
+
+        let common = PanelCommon::parse_common(table)?;
+        let formats = PanelCommon::parse_templates(
+            table,
+            &["_connected", "_disconnected"],
+            &["%name%", ""],
+        )?;
+        let attrs = PanelCommon::parse_attr(table, "");
+        let highlight = PanelCommon::parse_highlight(table, "");
+
+        builder.common(common);
+        builder.formats(VpnFormats::new(formats));
+        builder.attrs(attrs);
+        builder.highlight(highlight);
+
+        Ok(builder.build()?)
+    }
+
+    fn props(&self) -> (&'static str, bool) {
+        (self.name, self.common.visible)
+    }
+
+    async fn run(
+        mut self: Box<Self>,
+        cr: Rc<cairo::Context>,
+        global_attrs: Attrs,
+        height: i32,
+    ) -> PanelRunResult {
+        self.attrs.apply_to(&global_attrs);
+
+        let actions = self.common.actions.clone();
+        let commands = self.commands.clone();
+        let connected = self.connected.clone();
+        let paused = Arc::new(Mutex::new(false));
+        let (event_send, event_recv) = unbounded_channel();
+        let (response_send, response_recv) = unbounded_channel();
+        let mut map =
+            StreamMap::<usize, Pin<Box<dyn Stream<Item = Result<()>>>>>::new();
+
+        map.insert(
+            0,
+            Box::pin(UnboundedReceiverStream::new(event_recv).map(move |s| {
+                Self::process_event(
+                    s,
+                    &actions,
+                    &commands,
+                    &connected,
+                    &response_send,
+                )
+            })),
+        );
+
+        map.insert(
+            1,
+            Box::pin(
+                ManagedIntervalStream::builder()
+                    .duration(self.duration)
+                    .paused(paused.clone())
+                    .waker(self.waker.clone())
+                    .build()?
+                    .map(|_| Ok(())),
+            ),
+        );
+
+        match netlink::Socket::route(
+            netlink::RTMGRP_LINK
+                | netlink::RTMGRP_IPV4_IFADDR
+                | netlink::RTMGRP_IPV6_IFADDR,
+        ) {
+            Ok(socket) => {
+                map.insert(
+                    2,
+                    Box::pin(netlink::Notifications::new(socket).map(Ok)),
+                );
+            }
+            Err(e) => log::warn!(
+                "Failed to listen for network changes, only polling: {e}"
+            ),
+        }
+
+        Ok((
+            Box::pin(map.map(move |(_, data)| {
+                data?;
+                self.draw(&cr, height, paused.clone())
+            })),
+            Some(ChannelEndpoint::new(event_send, response_recv)),
+        ))
+    }
+}
+
+/// The shell commands that bring a connection up and down.
+#[derive(Debug, Clone, Default)]
+struct Commands {
+    up: Option<String>,
+    down: Option<String>,
+}
+
+const RTM_GETLINK: u16 = 18;
+const IFINFO_HEADER_LEN: usize = 16;
+const IFF_UP: u32 = 0x1;
+const IFF_RUNNING: u32 = 0x40;
+const IFLA_IFNAME: u16 = 3;
+const IFLA_LINKINFO: u16 = 18;
+const IFLA_INFO_KIND: u16 = 1;
+const ARPHRD_ETHER: u16 = 1;
+const ARPHRD_PPP: u16 = 512;
+
+#[derive(Debug, Clone)]
+struct Link {
+    index: u32,
+    name: String,
+    kind: &'static str,
+    up: bool,
+}
+
+/// Lists the interfaces that look like VPN connections.
+fn query_links() -> Result<Vec<Link>> {
+    let mut socket = netlink::Socket::route(0)?;
+    let replies = socket.route_request(
+        RTM_GETLINK,
+        netlink::NLM_F_DUMP,
+        &[0; IFINFO_HEADER_LEN],
+    )?;
+
+    Ok(replies
+        .iter()
+        .filter(|reply| reply.len() >= IFINFO_HEADER_LEN)
+        .filter_map(|reply| {
+            let (header, attrs) = reply.split_at(IFINFO_HEADER_LEN);
+            // ifinfomsg: family, padding, type, index, flags, change
+            let ty = u16::from_ne_bytes([header[2], header[3]]);
+            let index = netlink::u32_attr(&header[4..8])?;
+            let flags = netlink::u32_attr(&header[8..12])?;
+            let kind = netlink::find_attr(attrs, IFLA_LINKINFO)
+                .and_then(|info| netlink::find_attr(info, IFLA_INFO_KIND));
+            let kind = match (kind, ty) {
+                (Some(b"wireguard\0"), _) => "wireguard",
+                (Some(b"tun\0"), ARPHRD_ETHER) => "tap",
+                (Some(b"tun\0"), _) => "tun",
+                (_, ARPHRD_PPP) => "ppp",
+                _ => return None,
+            };
+            let name = netlink::find_attr(attrs, IFLA_IFNAME)?;
+            let name = String::from_utf8_lossy(
+                name.strip_suffix(b"\0").unwrap_or(name),
+            )
+            .into_owned();
+            Some(Link {
+                index,
+                name,
+                kind,
+                // a tun device without a process attached isn't running
+                up: flags & (IFF_UP | IFF_RUNNING) == IFF_UP | IFF_RUNNING,
+            })
+        })
+        .collect())
+}
+
+fn query_ip(if_name: &str) -> Option<IpAddr> {
+    get_if_addrs()
+        .ok()?
+        .into_iter()
+        .find(|i| i.name == if_name)
+        .map(|i| i.ip())
+}
+
+#[derive(Debug, Clone, Copy)]
+struct WireGuard {
+    endpoint: Option<SocketAddr>,
+    handshake: Option<SystemTime>,
+}
+
+const WG_CMD_GET_DEVICE: u8 = 0;
+const WG_GENL_VERSION: u8 = 1;
+const WGDEVICE_A_IFINDEX: u16 = 1;
+const WGDEVICE_A_PEERS: u16 = 8;
+const WGPEER_A_ENDPOINT: u16 = 4;
+const WGPEER_A_LAST_HANDSHAKE_TIME: u16 = 6;
+const AF_INET: u16 = 2;
+const AF_INET6: u16 = 10;
+
+/// Returns the endpoint and latest handshake of the WireGuard peer that most
+/// recently completed a handshake.
+fn query_wireguard(index: u32) -> Result<WireGuard> {
+    let mut socket = netlink::Socket::generic()?;
+    let family = socket.family("wireguard")?;
+    let replies = socket.request(
+        family,
+        netlink::NLM_F_DUMP,
+        WG_CMD_GET_DEVICE,
+        WG_GENL_VERSION,
+        &[(WGDEVICE_A_IFINDEX, &index.to_ne_bytes())],
+    )?;
+
+    let peers = replies
+        .iter()
+        .filter_map(|reply| netlink::find_attr(reply, WGDEVICE_A_PEERS))
+        .flat_map(netlink::attrs)
+        .map(|(_, peer)| WireGuard {
+            endpoint: netlink::find_attr(peer, WGPEER_A_ENDPOINT)
+                .and_then(parse_sockaddr),
+            handshake: netlink::find_attr(peer, WGPEER_A_LAST_HANDSHAKE_TIME)
+                .and_then(parse_timespec),
+        })
+        .collect::<Vec<_>>();
+
+    Ok(peers
+        .iter()
+        .copied()
+        .max_by_key(|peer| peer.handshake)
+        .unwrap_or(WireGuard {
+            endpoint: None,
+            handshake: None,
+        }))
+}
+
+fn parse_sockaddr(buf: &[u8]) -> Option<SocketAddr> {
+    let family = u16::from_ne_bytes(buf.get(0..2)?.try_into().ok()?);
+    let port = u16::from_be_bytes(buf.get(2..4)?.try_into().ok()?);
+    let ip = match family {
+        AF_INET => IpAddr::V4(Ipv4Addr::from(
+            <[u8; 4]>::try_from(buf.get(4..8)?).ok()?,
+        )),
+        AF_INET6 => IpAddr::V6(Ipv6Addr::from(
+            <[u8; 16]>::try_from(buf.get(8..24)?).ok()?,
+        )),
+        _ => return None,
+    };
+    Some(SocketAddr::new(ip, port))
+}
+
+/// Parses a `struct __kernel_timespec`. A time of zero means that there
+/// hasn't been a handshake.
+fn parse_timespec(buf: &[u8]) -> Option<SystemTime> {
+    let secs = i64::from_ne_bytes(buf.get(0..8)?.try_into().ok()?);
+    let nanos = i64::from_ne_bytes(buf.get(8..16)?.try_into().ok()?);
+    if secs <= 0 {
+        return None;
+    }
+    SystemTime::UNIX_EPOCH.checked_add(Duration::new(
+        secs.try_into().ok()?,
+        nanos.try_into().ok()?,
+    ))
+}
+
+fn format_age(secs: u64) -> String {
+    match secs {
+        0..60 => format!("{secs}s"),
+        60..3600 => format!("{}m", secs / 60),
+        3600..86400 => format!("{}h", secs / 3600),
+        _ => format!("{}d", secs / 86400),
+    }
+}
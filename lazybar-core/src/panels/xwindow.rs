@@ -19,10 +19,10 @@ use x11rb::{
 };
 
 use crate::{
-    Attrs, Highlight, PanelConfig, PanelRunResult,
+    Attrs, Highlight, Overflow, PanelConfig, PanelRunResult, Template,
     bar::PanelDrawInfo,
     common::{PanelCommon, ShowHide},
-    remove_string_from_config, remove_uint_from_config,
+    remove_string_from_config,
     x::InternedAtoms,
 };
 
@@ -37,9 +37,7 @@ pub struct XWindow {
     name: &'static str,
     conn: Arc<RustConnection>,
     screen: usize,
-    #[builder(setter(strip_option), default = "None")]
-    max_width: Option<u32>,
-    format: &'static str,
+    format: Template,
     attrs: Attrs,
     #[builder(default, setter(strip_option))]
     highlight: Option<Highlight>,
@@ -73,49 +71,36 @@ impl XWindow {
                     .event_mask(EventMask::PROPERTY_CHANGE),
             )?;
 
-            if let Some(max_width) = self.max_width {
-                let bytes = self
+            let mut offset = 0;
+            let mut title = String::new();
+            loop {
+                let reply = self
                     .conn
                     .get_property(
-                        false, active, name_atom, utf8_atom, 0, max_width,
+                        false, active, name_atom, utf8_atom, offset, 64,
                     )?
-                    .reply()?
-                    .value;
+                    .reply()?;
 
-                unsafe { std::str::from_utf8_unchecked(bytes.as_slice()) }
-                    .chars()
-                    .take(max_width as usize)
-                    .collect()
-            } else {
-                let mut offset = 0;
-                let mut title = String::new();
-                loop {
-                    let reply = self
-                        .conn
-                        .get_property(
-                            false, active, name_atom, utf8_atom, offset, 64,
-                        )?
-                        .reply()?;
-
-                    let s = unsafe { String::from_utf8_unchecked(reply.value) };
+                let s = unsafe { String::from_utf8_unchecked(reply.value) };
 
-                    title.push_str(s.as_str());
-
-                    if reply.bytes_after == 0 {
-                        break;
-                    }
+                title.push_str(s.as_str());
 
-                    offset += 64;
+                if reply.bytes_after == 0 {
+                    break;
                 }
 
-                title
+                offset += 64;
             }
+
+            title
         };
 
-        let text = self.format.replace(
-            "%name%",
-            glib::markup_escape_text(name.as_str()).as_str(),
-        );
+        let text = self.format.render(|var| match var {
+            "name" => {
+                Some(glib::markup_escape_text(name.as_str()).as_str().into())
+            }
+            _ => None,
+        });
 
         let conn = self.conn.clone();
         let conn_ = self.conn.clone();
@@ -163,11 +148,14 @@ impl PanelConfig for XWindow {
     /// - `format`: the format string
     ///   - type: String
     ///   - default: `%name%`
-    ///   - formatting options: `%name%`
+    ///   - formatting options: `%name%`. See [`Template`] for the full syntax.
     /// - `attrs`: A string specifying the attrs for the panel. See
     ///   [`Attrs::parse`] for details.
     /// - `highlight`: A string specifying the highlight for the panel. See
     ///   [`Highlight::parse`] for details.
+    /// - `max_width`: see [`Overflow::parse`]. For compatibility, a bare number
+    ///   is a number of characters (this is deprecated; use e.g. `"40ch"` or
+    ///   `"300px"`).
     /// - See [`PanelCommon::parse_common`].
     fn parse(
         name: &'static str,
@@ -184,17 +172,15 @@ impl PanelConfig for XWindow {
             log::error!("Failed to connect to X server");
         }
 
-        if let Some(max_width) = remove_uint_from_config("max_width", table) {
-            builder.max_width(max_width as u32);
-        }
-
-        let common = PanelCommon::parse_common(table)?;
-        let format = PanelCommon::parse_format(table, "", "%name%");
+        let overflow = Overflow::parse_legacy(table);
+        let mut common = PanelCommon::parse_common(table)?;
+        common.overflow = overflow;
+        let format = PanelCommon::parse_template(table, "", "%name%")?;
         let attrs = PanelCommon::parse_attr(table, "");
         let highlight = PanelCommon::parse_highlight(table, "");
 
         builder.common(common);
-        builder.format(format.leak());
+        builder.format(format);
         builder.attrs(attrs);
         builder.highlight(highlight);
 
@@ -3,7 +3,6 @@ use std::{
     pin::Pin,
     rc::Rc,
     sync::{Arc, Mutex},
-    task::Poll,
 };
 
 use anyhow::{Context, Result, anyhow};
@@ -11,46 +10,113 @@ use async_trait::async_trait;
 use config::{Config, Value};
 use derive_builder::Builder;
 use lazybar_types::EventResponse;
-use pangocairo::functions::{create_layout, show_layout};
-use tokio::{
-    sync::mpsc::{UnboundedSender, unbounded_channel},
-    task::{self, JoinHandle},
-};
+use tokio::sync::mpsc::{UnboundedSender, unbounded_channel};
 use tokio_stream::{
     Stream, StreamExt, StreamMap, wrappers::UnboundedReceiverStream,
 };
 use x11rb::{
     CURRENT_TIME,
     connection::Connection,
-    protocol::{
-        self,
-        xproto::{
-            Atom, AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent,
-            ConnectionExt, EventMask, Window,
-        },
+    protocol::xproto::{
+        Atom, AtomEnum, ChangeWindowAttributesAux, ClientMessageEvent,
+        ConnectionExt, EventMask, Window,
     },
     rust_connection::RustConnection,
 };
 
-#[cfg(feature = "cursor")]
-use crate::bar::{Cursor, CursorInfo};
 use crate::{
-    Attrs, Highlight, PanelConfig, PanelRunResult, array_to_struct,
-    background::Bg,
-    bar::{Event, MouseButton, PanelDrawInfo},
+    Attrs, Highlight, PanelConfig, PanelRunResult, Template, array_to_struct,
+    bar::{BAR_INFO, Event, MouseButton, PanelDrawInfo},
     common::PanelCommon,
+    inline::Object,
     ipc::ChannelEndpoint,
-    remove_string_from_config,
-    x::InternedAtoms,
+    remove_bool_from_config, remove_string_from_config,
+    x::{
+        InternedAtoms, PropertyStream, Segment, WindowIcons, cycle_segments,
+        draw_segments, get_class, get_clients, is_urgent, monitor_contains,
+        segment_at, window_centers,
+    },
 };
 
 #[derive(PartialEq, Eq, Debug)]
 enum WorkspaceState {
     Active,
+    Urgent,
     Nonempty,
     Inactive,
 }
 
+/// The order in which to show workspaces.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+enum Sort {
+    /// The order set by the window manager
+    #[default]
+    None,
+    /// Alphabetical order by name
+    Name,
+    /// By the first number in each name. Workspaces without a number come
+    /// last.
+    Number,
+}
+
+impl Sort {
+    fn parse(s: &str) -> Option<Self> {
+        match s.to_lowercase().as_str() {
+            "none" => Some(Self::None),
+            "name" => Some(Self::Name),
+            "number" => Some(Self::Number),
+            _ => None,
+        }
+    }
+
+    fn apply<T>(self, workspaces: &mut [(u32, String, T)]) {
+        match self {
+            Self::None => {}
+            Self::Name => workspaces.sort_by(|a, b| a.1.cmp(&b.1)),
+            Self::Number => workspaces.sort_by_key(|(idx, name, _)| {
+                let number = name
+                    .split(|c: char| !c.is_ascii_digit())
+                    .find(|s| !s.is_empty())
+                    .and_then(|s| s.parse::<u64>().ok());
+                (number.is_none(), number, *idx)
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Atoms {
+    number: Atom,
+    names: Atom,
+    utf8: Atom,
+    current: Atom,
+    client: Atom,
+    r#type: Atom,
+    normal: Atom,
+    desktop: Atom,
+    viewport: Atom,
+    state: Atom,
+    icon: Atom,
+}
+
+impl Atoms {
+    fn get(conn: &RustConnection) -> Result<Self> {
+        Ok(Self {
+            number: InternedAtoms::get(conn, "_NET_NUMBER_OF_DESKTOPS")?,
+            names: InternedAtoms::get(conn, "_NET_DESKTOP_NAMES")?,
+            utf8: InternedAtoms::get(conn, "UTF8_STRING")?,
+            current: InternedAtoms::get(conn, "_NET_CURRENT_DESKTOP")?,
+            client: InternedAtoms::get(conn, "_NET_CLIENT_LIST")?,
+            r#type: InternedAtoms::get(conn, "_NET_WM_WINDOW_TYPE")?,
+            normal: InternedAtoms::get(conn, "_NET_WM_WINDOW_TYPE_NORMAL")?,
+            desktop: InternedAtoms::get(conn, "_NET_WM_DESKTOP")?,
+            viewport: InternedAtoms::get(conn, "_NET_DESKTOP_VIEWPORT")?,
+            state: InternedAtoms::get(conn, "_NET_WM_STATE")?,
+            icon: InternedAtoms::get(conn, "_NET_WM_ICON")?,
+        })
+    }
+}
+
 /// Display information about workspaces
 ///
 /// Requires an EWMH-compliant window manager
@@ -61,8 +127,17 @@ pub struct XWorkspaces {
     name: &'static str,
     conn: Arc<RustConnection>,
     screen: usize,
+    formats: XWorkspacesConfig<Template>,
     attrs: XWorkspacesConfig<Attrs>,
     highlights: XWorkspacesConfig<Highlight>,
+    #[builder(default)]
+    icons: WindowIcons,
+    #[builder(default)]
+    monitor_only: bool,
+    #[builder(default)]
+    hide_empty: bool,
+    #[builder(default)]
+    sort: Sort,
     common: PanelCommon,
 }
 
@@ -72,232 +147,173 @@ impl XWorkspaces {
         cr: &Rc<cairo::Context>,
         root: Window,
         height: i32,
-        width_cache: &Arc<Mutex<Vec<i32>>>,
-        number_atom: Atom,
-        names_atom: Atom,
-        utf8_atom: Atom,
-        current_atom: Atom,
-        client_atom: Atom,
-        type_atom: Atom,
-        normal_atom: Atom,
-        desktop_atom: Atom,
+        width_cache: &Arc<Mutex<Vec<(u32, i32)>>>,
+        atoms: Atoms,
     ) -> Result<PanelDrawInfo> {
-        let workspaces = get_workspaces(
-            &self.conn,
-            root,
-            number_atom,
-            names_atom,
-            utf8_atom,
-        )?;
-        let current = get_current(&self.conn, root, current_atom)?;
-        let nonempty_set = get_nonempty(
+        let names = get_workspaces(
             &self.conn,
             root,
-            client_atom,
-            type_atom,
-            normal_atom,
-            desktop_atom,
+            atoms.number,
+            atoms.names,
+            atoms.utf8,
         )?;
+        let current = get_current(&self.conn, root, atoms.current)?;
+        let clients = get_desktop_clients(&self.conn, root, atoms)?;
+        let windows: Vec<_> = clients.values().flatten().copied().collect();
+        self.icons.retain(|window| windows.contains(&window));
+        let on_monitor = if self.monitor_only {
+            Some(self.monitor_desktops(root, atoms, names.len(), &clients)?)
+        } else {
+            None
+        };
 
-        let active = self.attrs.active.clone();
-        let nonempty = self.attrs.nonempty.clone();
-        let inactive = self.attrs.inactive.clone();
-        let layouts: Vec<_> = workspaces
+        let mut workspaces: Vec<_> = names
             .into_iter()
             .enumerate()
-            .map(move |(i, w)| {
+            .map(|(i, name)| {
                 let i = i as u32;
-                let layout = create_layout(cr);
-                layout.set_text(w.as_str());
-                if i == current {
-                    active.apply_font(&layout);
-                    (WorkspaceState::Active, layout)
-                } else if nonempty_set.contains(&i) {
-                    nonempty.apply_font(&layout);
-                    (WorkspaceState::Nonempty, layout)
+                let state = if i == current {
+                    WorkspaceState::Active
+                } else if clients.get(&i).is_some_and(|windows| {
+                    windows.iter().any(|&w| is_urgent(&*self.conn, w))
+                }) {
+                    WorkspaceState::Urgent
+                } else if clients.contains_key(&i) {
+                    WorkspaceState::Nonempty
                 } else {
-                    inactive.apply_font(&layout);
-                    (WorkspaceState::Inactive, layout)
-                }
+                    WorkspaceState::Inactive
+                };
+                (i, name, state)
+            })
+            .filter(|(i, _, state)| {
+                !(self.hide_empty && *state == WorkspaceState::Inactive)
+                    && on_monitor.as_ref().is_none_or(|set| set.contains(i))
             })
             .collect();
+        self.sort.apply(&mut workspaces);
 
-        let mut cache = width_cache.lock().unwrap();
-        cache.clear();
-        for l in &layouts {
-            let size = l.1.pixel_size();
-            cache.push(
-                match l.0 {
-                    WorkspaceState::Active => &self.attrs.active,
-                    WorkspaceState::Nonempty => &self.attrs.nonempty,
-                    WorkspaceState::Inactive => &self.attrs.inactive,
-                }
-                .bg
-                .clone()
-                .map_or_else(|| size, |bg| bg.adjust_dims(size, height))
-                .0,
-            );
-        }
-        let width = cache.iter().sum::<i32>();
-        drop(cache);
-
-        let active = self.attrs.active.clone();
-        let nonempty = self.attrs.nonempty.clone();
-        let inactive = self.attrs.inactive.clone();
-        let active_highlight = self.highlights.active.clone();
-        let nonempty_highlight = self.highlights.nonempty.clone();
-        let inactive_highlight = self.highlights.inactive.clone();
-        let images = self.common.images.clone();
-        let conn = self.conn.clone();
-        let conn_ = self.conn.clone();
-
-        #[cfg(feature = "cursor")]
-        let cursor_conn = self.conn.clone();
-        #[cfg(feature = "cursor")]
-        let width_cache = width_cache.clone();
-
-        Ok(PanelDrawInfo::new(
-            (width, height),
-            self.common.dependence,
-            Box::new(move |cr, _| {
-                for image in &images {
-                    image.draw(cr)?;
-                }
-
-                for (i, layout) in &layouts {
-                    let size = layout.pixel_size();
-
-                    let (offset, highlight) = match i {
-                        WorkspaceState::Active => (
-                            active.bg.as_ref().unwrap_or(&Bg::None).draw(
-                                cr,
-                                size.0 as f64,
-                                size.1 as f64,
-                                height as f64,
-                            )?,
-                            active_highlight.clone(),
-                        ),
-                        WorkspaceState::Nonempty => (
-                            nonempty.bg.as_ref().unwrap_or(&Bg::None).draw(
-                                cr,
-                                size.0 as f64,
-                                size.1 as f64,
-                                height as f64,
-                            )?,
-                            nonempty_highlight.clone(),
-                        ),
-                        WorkspaceState::Inactive => (
-                            inactive.bg.as_ref().unwrap_or(&Bg::None).draw(
-                                cr,
-                                size.0 as f64,
-                                size.1 as f64,
-                                height as f64,
-                            )?,
-                            inactive_highlight.clone(),
-                        ),
-                    };
-
-                    cr.save()?;
-                    highlight.draw(
-                        cr,
-                        height as f64,
-                        2.0f64.mul_add(offset, f64::from(size.0)),
-                    )?;
-
-                    cr.translate(offset, f64::from(height - size.1) / 2.0);
-
-                    match i {
-                        WorkspaceState::Active => active.apply_fg(cr),
-                        WorkspaceState::Nonempty => nonempty.apply_fg(cr),
-                        WorkspaceState::Inactive => inactive.apply_fg(cr),
-                    }
-
-                    show_layout(cr, layout);
-                    cr.restore()?;
-
-                    cr.translate(
-                        2.0f64
-                            .mul_add(offset, f64::from(layout.pixel_size().0)),
-                        0.0,
-                    );
-                }
-                Ok(())
-            }),
-            Some(Box::new(move || {
-                conn.change_window_attributes(
-                    root,
-                    &ChangeWindowAttributesAux::new()
-                        .event_mask(EventMask::PROPERTY_CHANGE),
-                )?;
-                Ok(())
-            })),
-            Some(Box::new(move || {
-                conn_.change_window_attributes(
-                    root,
-                    &ChangeWindowAttributesAux::new()
-                        .event_mask(EventMask::NO_EVENT),
-                )?;
-                Ok(())
-            })),
-            None,
-            #[cfg(feature = "cursor")]
-            CursorInfo::Dynamic(Box::new(move |event| {
-                let names = get_workspaces(
-                    cursor_conn.as_ref(),
-                    root,
-                    number_atom,
-                    names_atom,
-                    utf8_atom,
-                )?;
+        let segments = workspaces
+            .into_iter()
+            .map(|(i, name, state)| {
+                let format = self.formats.get(&state);
+                let mut objects = Vec::new();
+                let icons = if format.uses("icons") {
+                    self.icons(
+                        clients.get(&i).map_or(&[], Vec::as_slice),
+                        &mut objects,
+                    )
+                } else {
+                    String::new()
+                };
+                let text = format.render(|var| match var {
+                    "name" => Some(
+                        glib::markup_escape_text(name.as_str())
+                            .to_string()
+                            .into(),
+                    ),
+                    "icons" => Some(icons.as_str().into()),
+                    _ => None,
+                });
+
+                Segment::new(
+                    cr,
+                    i,
+                    text.as_str(),
+                    objects,
+                    self.attrs.get(&state),
+                    self.highlights.get(&state),
+                )
+            })
+            .collect();
 
-                let len = names.len();
+        // watch for changes to urgency, icons, and desktops
+        draw_segments(
+            segments,
+            width_cache,
+            &self.conn,
+            root,
+            windows,
+            &self.common,
+            height,
+            format!("{self:?}"),
+        )
+    }
 
-                let idx = match event.button {
-                    MouseButton::Left
-                    | MouseButton::Right
-                    | MouseButton::Middle => {
-                        let mut idx = 0;
-                        let cache = width_cache.lock().unwrap();
-                        if cache.is_empty() {
-                            return Ok(Cursor::Default);
-                        }
-                        let mut x = cache[0];
-                        while x < event.x as i32 && idx < len {
-                            idx += 1;
-                            x += cache[idx];
-                        }
-                        drop(cache);
-
-                        idx
-                    }
-                    MouseButton::ScrollUp => {
-                        let current =
-                            get_current(&cursor_conn, root, current_atom)?;
-                        (current + 1) as usize % names.len()
-                    }
-                    MouseButton::ScrollDown => {
-                        let current =
-                            get_current(&cursor_conn, root, current_atom)?;
-                        let len = names.len();
-                        (current as usize + len - 1) % len
-                    }
+    /// Builds the text of `%icons%` for a workspace containing `windows`.
+    /// See [`WindowIcons::markup`].
+    fn icons(&self, windows: &[Window], objects: &mut Vec<Object>) -> String {
+        windows
+            .iter()
+            .map(|&window| {
+                let class = if self.icons.uses_class() {
+                    get_class(&*self.conn, window)
+                } else {
+                    Vec::new()
                 };
+                self.icons.markup(&*self.conn, window, &class, objects)
+            })
+            .collect()
+    }
 
-                Ok(if idx < len {
-                    Cursor::Click
-                } else {
-                    Cursor::Default
+    /// Finds the desktops on the bar's monitor, using
+    /// `_NET_DESKTOP_VIEWPORT` if the window manager sets it, or the
+    /// positions of each desktop's windows otherwise. Desktops without any
+    /// windows can't be placed in the latter case, so they're included.
+    fn monitor_desktops(
+        &self,
+        root: Window,
+        atoms: Atoms,
+        count: usize,
+        clients: &HashMap<u32, Vec<Window>>,
+    ) -> Result<HashSet<u32>> {
+        let monitor = &BAR_INFO.get().context("Bar not initialized")?.monitor;
+        let viewports: Vec<u32> = self
+            .conn
+            .get_property(
+                false,
+                root,
+                atoms.viewport,
+                AtomEnum::CARDINAL,
+                0,
+                2 * count as u32,
+            )?
+            .reply()?
+            .value32()
+            .map(Iterator::collect)
+            .unwrap_or_default();
+
+        // most window managers without viewports set them all to (0, 0)
+        if viewports.len() >= 2 * count && viewports.iter().any(|&v| v != 0) {
+            Ok((0..count as u32)
+                .filter(|&i| {
+                    monitor_contains(
+                        monitor,
+                        (
+                            viewports[2 * i as usize] as i32,
+                            viewports[2 * i as usize + 1] as i32,
+                        ),
+                    )
                 })
-            })),
-            format!("{self:?}"),
-        ))
+                .collect())
+        } else {
+            Ok((0..count as u32)
+                .filter(|i| {
+                    clients.get(i).is_none_or(|windows| {
+                        window_centers(&*self.conn, windows, root)
+                            .into_iter()
+                            .flatten()
+                            .any(|center| monitor_contains(monitor, center))
+                    })
+                })
+                .collect())
+        }
     }
 
     fn process_event(
         event: Event,
         conn: Arc<RustConnection>,
         root: Window,
-        width_cache: Arc<Mutex<Vec<i32>>>,
+        width_cache: Arc<Mutex<Vec<(u32, i32)>>>,
         names: &[String],
         current_atom: Atom,
         send: UnboundedSender<EventResponse>,
@@ -305,18 +321,7 @@ impl XWorkspaces {
         match event {
             Event::Action(Some(event)) => {
                 if let Some(idx) = names.iter().position(|s| *s == event) {
-                    conn.send_event(
-                        false,
-                        root,
-                        EventMask::SUBSTRUCTURE_NOTIFY
-                            | EventMask::SUBSTRUCTURE_REDIRECT,
-                        ClientMessageEvent::new(
-                            32,
-                            root,
-                            current_atom,
-                            [idx as u32, CURRENT_TIME, 0, 0, 0],
-                        ),
-                    )?;
+                    switch_desktop(&conn, root, current_atom, idx as u32)?;
                     send.send(EventResponse::Ok(None))?;
                 } else {
                     send.send(EventResponse::Err(format!(
@@ -328,46 +333,27 @@ impl XWorkspaces {
             Event::Action(None) => {}
 
             Event::Mouse(event) => {
-                let len = names.len();
-                let idx = match event.button {
+                let cache = width_cache.lock().unwrap();
+                let desktop = match event.button {
                     MouseButton::Left
                     | MouseButton::Right
-                    | MouseButton::Middle => {
-                        let mut idx = 0;
-                        let cache = width_cache.lock().unwrap();
-                        if cache.is_empty() {
-                            return Ok(());
-                        }
-                        let mut x = cache[0];
-                        while x < event.x as i32 && idx < len {
-                            idx += 1;
-                            x += cache[idx];
-                        }
-                        drop(cache);
-
-                        idx
-                    }
-                    MouseButton::ScrollUp => {
-                        let current = get_current(&conn, root, current_atom)?;
-                        (current + 1) as usize % names.len()
-                    }
-                    MouseButton::ScrollDown => {
-                        let current = get_current(&conn, root, current_atom)?;
-                        let len = names.len();
-                        (current as usize + len - 1) % len
-                    }
+                    | MouseButton::Middle => segment_at(&cache, event.x),
+                    MouseButton::ScrollUp => cycle_segments(
+                        &cache,
+                        Some(get_current(&conn, root, current_atom)?),
+                        true,
+                    ),
+                    MouseButton::ScrollDown => cycle_segments(
+                        &cache,
+                        Some(get_current(&conn, root, current_atom)?),
+                        false,
+                    ),
+                    MouseButton::Drag => None,
                 };
+                drop(cache);
 
-                if idx < len {
-                    Self::process_event(
-                        Event::Action(Some(names[idx].clone())),
-                        conn,
-                        root,
-                        width_cache,
-                        names,
-                        current_atom,
-                        send,
-                    )?;
+                if let Some(desktop) = desktop {
+                    switch_desktop(&conn, root, current_atom, desktop)?;
                 }
             }
         }
@@ -385,20 +371,58 @@ impl PanelConfig for XWorkspaces {
     ///   - type: String
     ///   - default: None (This will tell X to choose the default screen, which
     ///     is probably what you want.)
+    /// - `format_active`, `format_urgent`, `format_nonempty`,
+    ///   `format_inactive`: The format string for the workspaces in each state.
+    ///   Urgent workspaces are those other than the active workspace with a
+    ///   window that demands attention.
+    ///   - type: String
+    ///   - formatting options: `%name%`, `%icons%`
+    ///   - default: `%name%`
+    /// - `icons`: A table mapping window classes (or instances) to the markup
+    ///   that represents them in `%icons%`.
+    ///   - type: Table
+    ///   - default: empty
+    /// - `window_icons`: Whether to show the icons that windows provide
+    ///   (`_NET_WM_ICON`) in `%icons%` for windows that aren't in `icons`.
+    ///   - type: bool
+    ///   - default: true
+    /// - `icon_size`: The height in pixels of window icons.
+    ///   - type: f64
+    ///   - default: 16
     /// - `attrs_active`: A string specifying the attrs for the active
     ///   workspace. See [`Attrs::parse`] for details.
+    /// - `attrs_urgent`: A string specifying the attrs for the urgent
+    ///   workspaces. See [`Attrs::parse`] for details.
     /// - `attrs_nonempty`: A string specifying the attrs for the nonempty
     ///   workspaces. See [`Attrs::parse`] for details.
     /// - `attrs_inactive`: A string specifying the attrs for the inactive
     ///   workspaces. See [`Attrs::parse`] for details.
     /// - `highlight_active`: The highlight to be used for the active workspace.
     ///   See [`Highlight::parse`] for more details.
+    /// - `highlight_urgent`: The highlight to be used for the urgent
+    ///   workspaces. See [`Highlight::parse`] for more details.
     /// - `highlight_nonempty`: The highlight to be used for the nonempty
     ///   workspaces. See [`Highlight::parse`] for more details.
     /// - `highlight_inactive`: The highlight to be used for the inactive
     ///   workspaces. See [`Highlight::parse`] for more details.
+    /// - `monitor_only`: Whether to show only the workspaces on the bar's
+    ///   monitor. These are found using `_NET_DESKTOP_VIEWPORT` if the window
+    ///   manager sets it, or by the positions of each workspace's windows
+    ///   otherwise (in which case empty workspaces are always shown).
+    ///   - type: bool
+    ///   - default: false
+    /// - `hide_empty`: Whether to hide workspaces without any windows, other
+    ///   than the active workspace.
+    ///   - type: bool
+    ///   - default: false
+    /// - `sort`: The order in which to show workspaces.
+    ///   - type: String - `none` (the window manager's order), `name`, or
+    ///     `number` (the first number in each name)
+    ///   - default: `none`
     /// - See [`PanelCommon::parse_common`]. The supported events are each the
-    ///   name of a current workspace.
+    ///   name of a current workspace. `max_width` isn't supported, since
+    ///   cutting off or scrolling the row of workspaces would hide some of
+    ///   them; use `hide_empty` or `monitor_only` to show fewer.
     fn parse(
         name: &'static str,
         table: &mut HashMap<String, Value>,
@@ -414,17 +438,40 @@ impl PanelConfig for XWorkspaces {
             log::error!("Failed to connect to X server");
         }
 
+        if let Some(monitor_only) =
+            remove_bool_from_config("monitor_only", table)
+        {
+            builder.monitor_only(monitor_only);
+        }
+        if let Some(hide_empty) = remove_bool_from_config("hide_empty", table) {
+            builder.hide_empty(hide_empty);
+        }
+        if let Some(sort) = remove_string_from_config("sort", table) {
+            builder.sort(Sort::parse(sort.as_str()).unwrap_or_else(|| {
+                log::warn!("Invalid sort {sort}; using `none`");
+                Sort::None
+            }));
+        }
+
+        builder.icons(WindowIcons::parse(table));
+
         let common = PanelCommon::parse_common(table)?;
+        let formats = PanelCommon::parse_templates(
+            table,
+            &["_active", "_urgent", "_nonempty", "_inactive"],
+            &["%name%"; 4],
+        )?;
         let attrs = PanelCommon::parse_attrs(
             table,
-            &["_active", "_nonempty", "_inactive"],
+            &["_active", "_urgent", "_nonempty", "_inactive"],
         );
         let highlights = PanelCommon::parse_highlights(
             table,
-            &["_active", "_nonempty", "_inactive"],
+            &["_active", "_urgent", "_nonempty", "_inactive"],
         );
 
         builder.common(common);
+        builder.formats(XWorkspacesConfig::new(formats));
         builder.attrs(XWorkspacesConfig::new(attrs));
         builder.highlights(XWorkspacesConfig::new(highlights));
 
@@ -441,17 +488,7 @@ impl PanelConfig for XWorkspaces {
         global_attrs: Attrs,
         height: i32,
     ) -> PanelRunResult {
-        let number_atom =
-            InternedAtoms::get(&self.conn, "_NET_NUMBER_OF_DESKTOPS")?;
-        let names_atom = InternedAtoms::get(&self.conn, "_NET_DESKTOP_NAMES")?;
-        let utf8_atom = InternedAtoms::get(&self.conn, "UTF8_STRING")?;
-        let current_atom =
-            InternedAtoms::get(&self.conn, "_NET_CURRENT_DESKTOP")?;
-        let client_atom = InternedAtoms::get(&self.conn, "_NET_CLIENT_LIST")?;
-        let type_atom = InternedAtoms::get(&self.conn, "_NET_WM_WINDOW_TYPE")?;
-        let normal_atom =
-            InternedAtoms::get(&self.conn, "_NET_WM_WINDOW_TYPE_NORMAL")?;
-        let desktop_atom = InternedAtoms::get(&self.conn, "_NET_WM_DESKTOP")?;
+        let atoms = Atoms::get(&self.conn)?;
 
         let root = self
             .conn
@@ -468,6 +505,7 @@ impl PanelConfig for XWorkspaces {
 
         // TODO: clean up
         self.attrs.active.apply_to(&global_attrs);
+        self.attrs.urgent.apply_to(&global_attrs);
         self.attrs.nonempty.apply_to(&global_attrs);
         self.attrs.inactive.apply_to(&global_attrs);
 
@@ -478,12 +516,21 @@ impl PanelConfig for XWorkspaces {
             0,
             Box::pin(
                 tokio_stream::once(())
-                    .chain(XStream::new(
+                    .chain(PropertyStream::new(
                         self.conn.clone(),
-                        number_atom,
-                        current_atom,
-                        names_atom,
-                    ))
+                        &[
+                            atoms.number,
+                            atoms.current,
+                            atoms.names,
+                            atoms.client,
+                            atoms.viewport,
+                            atoms.desktop,
+                            atoms.state,
+                            atoms.icon,
+                            AtomEnum::WM_HINTS.into(),
+                        ],
+                        self.icons.clone(),
+                    )?)
                     .map(|()| Ok(())),
             ),
         );
@@ -496,9 +543,9 @@ impl PanelConfig for XWorkspaces {
         let names = get_workspaces(
             conn.as_ref(),
             root,
-            number_atom,
-            names_atom,
-            utf8_atom,
+            atoms.number,
+            atoms.names,
+            atoms.utf8,
         )?;
 
         map.insert(
@@ -510,7 +557,7 @@ impl PanelConfig for XWorkspaces {
                     root,
                     cache.clone(),
                     names.as_slice(),
-                    current_atom,
+                    atoms.current,
                     response_send.clone(),
                 )
             })),
@@ -518,20 +565,7 @@ impl PanelConfig for XWorkspaces {
 
         Ok((
             Box::pin(map.map(move |_| {
-                self.draw(
-                    &cr,
-                    root,
-                    height,
-                    &width_cache,
-                    number_atom,
-                    names_atom,
-                    utf8_atom,
-                    current_atom,
-                    client_atom,
-                    type_atom,
-                    normal_atom,
-                    desktop_atom,
-                )
+                self.draw(&cr, root, height, &width_cache, atoms)
             })),
             Some(ChannelEndpoint::new(event_send, response_recv)),
         ))
@@ -584,127 +618,71 @@ fn get_current(
         .context("Empty reply from X server")
 }
 
-fn get_nonempty(
+/// Groups the normal windows managed by the window manager by desktop.
+fn get_desktop_clients(
     conn: &RustConnection,
     root: Window,
-    client_atom: Atom,
-    type_atom: Atom,
-    normal_atom: Atom,
-    desktop_atom: Atom,
-) -> Result<HashSet<u32>> {
-    Ok(get_clients(conn, root, client_atom)?
-        .iter()
-        .filter(|&&w| {
-            conn.get_property(false, w, type_atom, AtomEnum::ATOM, 0, 1)
-                .map_or(true, |c| {
-                    c.reply().map_or(true, |r| {
-                        r.value32().is_none_or(|mut iter| {
-                            iter.next().is_none_or(|v| v == normal_atom)
-                        })
+    atoms: Atoms,
+) -> Result<HashMap<u32, Vec<Window>>> {
+    let mut desktops = HashMap::<u32, Vec<Window>>::new();
+    for window in get_clients(conn, root)?.into_iter().filter(|&w| {
+        conn.get_property(false, w, atoms.r#type, AtomEnum::ATOM, 0, 1)
+            .map_or(true, |c| {
+                c.reply().map_or(true, |r| {
+                    r.value32().is_none_or(|mut iter| {
+                        iter.next().is_none_or(|v| v == atoms.normal)
                     })
                 })
-        })
-        .filter_map(|&w| {
-            conn.get_property(false, w, desktop_atom, AtomEnum::CARDINAL, 0, 1)
-                .ok()
-                .and_then(|c| c.reply().ok())
-        })
-        .filter_map(|r| r.value32().and_then(|mut val| val.next()))
-        .collect())
-}
-
-fn get_clients(
-    conn: &RustConnection,
-    root: Window,
-    client_atom: Atom,
-) -> Result<Vec<Window>> {
-    let mut windows = Vec::new();
-
-    loop {
-        let reply = conn
+            })
+    }) {
+        if let Some(desktop) = conn
             .get_property(
                 false,
-                root,
-                client_atom,
-                AtomEnum::WINDOW,
-                windows.len() as u32,
-                16,
-            )?
-            .reply()?;
-
-        let wids = reply.value32().context("Invalid reply from X server")?;
-        windows.append(&mut wids.collect());
-
-        if reply.bytes_after == 0 {
-            break;
+                window,
+                atoms.desktop,
+                AtomEnum::CARDINAL,
+                0,
+                1,
+            )
+            .ok()
+            .and_then(|c| c.reply().ok())
+            .and_then(|r| r.value32().and_then(|mut val| val.next()))
+        {
+            desktops.entry(desktop).or_default().push(window);
         }
     }
-
-    Ok(windows)
+    Ok(desktops)
 }
 
-struct XStream {
-    conn: Arc<RustConnection>,
-    number_atom: Atom,
+fn switch_desktop(
+    conn: &RustConnection,
+    root: Window,
     current_atom: Atom,
-    names_atom: Atom,
-    handle: Option<JoinHandle<()>>,
-}
-
-impl XStream {
-    const fn new(
-        conn: Arc<RustConnection>,
-        number_atom: Atom,
-        current_atom: Atom,
-        names_atom: Atom,
-    ) -> Self {
-        Self {
-            conn,
-            number_atom,
+    desktop: u32,
+) -> Result<()> {
+    conn.send_event(
+        false,
+        root,
+        EventMask::SUBSTRUCTURE_NOTIFY | EventMask::SUBSTRUCTURE_REDIRECT,
+        ClientMessageEvent::new(
+            32,
+            root,
             current_atom,
-            names_atom,
-            handle: None,
-        }
-    }
+            [desktop, CURRENT_TIME, 0, 0, 0],
+        ),
+    )?;
+    Ok(())
 }
 
-impl Stream for XStream {
-    type Item = ();
-
-    fn poll_next(
-        mut self: Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> Poll<Option<Self::Item>> {
-        if let Some(handle) = &self.handle {
-            if handle.is_finished() {
-                self.handle = None;
-                Poll::Ready(Some(()))
-            } else {
-                Poll::Pending
-            }
-        } else {
-            let conn = self.conn.clone();
-            let waker = cx.waker().clone();
-            let number_atom = self.number_atom;
-            let current_atom = self.current_atom;
-            let names_atom = self.names_atom;
-            self.handle = Some(task::spawn_blocking(move || {
-                loop {
-                    let event = conn.wait_for_event();
-                    if let Ok(protocol::Event::PropertyNotify(event)) = event {
-                        if event.atom == number_atom
-                            || event.atom == current_atom
-                            || event.atom == names_atom
-                        {
-                            waker.wake();
-                            break;
-                        }
-                    }
-                }
-            }));
-            Poll::Pending
+array_to_struct!(XWorkspacesConfig, active, urgent, nonempty, inactive);
+
+impl<T> XWorkspacesConfig<T> {
+    const fn get(&self, state: &WorkspaceState) -> &T {
+        match state {
+            WorkspaceState::Active => &self.active,
+            WorkspaceState::Urgent => &self.urgent,
+            WorkspaceState::Nonempty => &self.nonempty,
+            WorkspaceState::Inactive => &self.inactive,
         }
     }
 }
-
-array_to_struct!(XWorkspacesConfig, active, nonempty, inactive);
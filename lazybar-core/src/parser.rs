@@ -15,6 +15,8 @@ use crate::panels::Clock;
 use crate::panels::Cpu;
 #[cfg(feature = "custom")]
 use crate::panels::Custom;
+#[cfg(feature = "diskio")]
+use crate::panels::DiskIo;
 #[cfg(feature = "github")]
 use crate::panels::Github;
 #[cfg(feature = "i3")]
@@ -25,6 +27,8 @@ use crate::panels::Inotify;
 use crate::panels::Memory;
 #[cfg(feature = "mpd")]
 use crate::panels::Mpd;
+#[cfg(feature = "mpris")]
+use crate::panels::Mpris;
 #[cfg(feature = "network")]
 use crate::panels::Network;
 #[cfg(feature = "ping")]
@@ -37,8 +41,12 @@ use crate::panels::Separator;
 use crate::panels::Storage;
 #[cfg(feature = "systray")]
 use crate::panels::Systray;
+#[cfg(feature = "tasklist")]
+use crate::panels::Tasklist;
 #[cfg(feature = "temp")]
 use crate::panels::Temp;
+#[cfg(feature = "vpn")]
+use crate::panels::Vpn;
 #[cfg(feature = "xwindow")]
 use crate::panels::XWindow;
 #[cfg(feature = "xworkspaces")]
@@ -48,6 +56,18 @@ use crate::{
     get_panels, get_table_from_config, remove_string_from_config,
 };
 
+/// The panel types that style themselves with
+/// [`Thresholds`][crate::Thresholds]. Thresholds set on any other built-in
+/// panel are ignored.
+const THRESHOLD_PANELS: &[&str] = &[
+    "battery", "cpu", "diskio", "memory", "ping", "storage", "temp",
+];
+
+/// The panel types that ignore the `max_width` of
+/// [`Overflow`][crate::Overflow]. Each one explains why in its `parse`
+/// documentation.
+const NO_OVERFLOW_PANELS: &[&str] = &["systray", "tasklist", "xworkspaces"];
+
 /// The `attrs` table from the global [`Config`].
 ///
 /// This cell is guaranteed to be initialized during the execution of all
@@ -60,6 +80,18 @@ pub static ATTRS: LazyLock<OnceCell<HashMap<String, Value>>> =
 /// [`PanelConfig::parse`] functions.
 pub static RAMPS: LazyLock<OnceCell<HashMap<String, Value>>> =
     LazyLock::new(OnceCell::new);
+/// The `gauges` table from the global [`Config`].
+///
+/// This cell is guaranteed to be initialized during the execution of all
+/// [`PanelConfig::parse`] functions.
+pub static GAUGES: LazyLock<OnceCell<HashMap<String, Value>>> =
+    LazyLock::new(OnceCell::new);
+/// The `graphs` table from the global [`Config`].
+///
+/// This cell is guaranteed to be initialized during the execution of all
+/// [`PanelConfig::parse`] functions.
+pub static GRAPHS: LazyLock<OnceCell<HashMap<String, Value>>> =
+    LazyLock::new(OnceCell::new);
 /// The `bgs` table from the global [`Config`].
 ///
 /// This cell is guaranteed to be initialized during the execution of all
@@ -132,6 +164,14 @@ pub fn parse(bar_name: &str, config: &Path) -> Result<BarConfig> {
         .set(config.get_table("ramps").unwrap_or_default())
         .unwrap();
 
+    GAUGES
+        .set(config.get_table("gauges").unwrap_or_default())
+        .unwrap();
+
+    GRAPHS
+        .set(config.get_table("graphs").unwrap_or_default())
+        .unwrap();
+
     BGS.set(config.get_table("bgs").unwrap_or_default())
         .unwrap();
 
@@ -323,6 +363,22 @@ fn parse_panel(
     if let Some(mut table) = get_table_from_config(p, panels_table) {
         if let Some(s) = remove_string_from_config("type", &mut table) {
             log::debug!("parsing {s} panel");
+            if table.contains_key("thresholds")
+                && !THRESHOLD_PANELS.contains(&s.as_str())
+            {
+                log::warn!(
+                    "Panel {p} (of type {s}) doesn't support thresholds; they \
+                     will be ignored"
+                );
+            }
+            if table.contains_key("max_width")
+                && NO_OVERFLOW_PANELS.contains(&s.as_str())
+            {
+                log::warn!(
+                    "Panel {p} (of type {s}) doesn't support max_width; it \
+                     will be ignored"
+                );
+            }
             return match s.as_str() {
                 #[cfg(feature = "battery")]
                 "battery" => {
@@ -342,6 +398,11 @@ fn parse_panel(
                     Custom::parse(p, &mut table, config)
                         .map::<Box<dyn PanelConfig>, _>(|p| Box::new(p))
                 }
+                #[cfg(feature = "diskio")]
+                "diskio" => {
+                    DiskIo::parse(p, &mut table, config)
+                        .map::<Box<dyn PanelConfig>, _>(|p| Box::new(p))
+                }
                 #[cfg(feature = "github")]
                 "github" => {
                     Github::parse(p, &mut table, config)
@@ -365,6 +426,11 @@ fn parse_panel(
                 #[cfg(feature = "mpd")]
                 "mpd" => Mpd::parse(p, &mut table, config)
                     .map::<Box<dyn PanelConfig>, _>(|p| Box::new(p)),
+                #[cfg(feature = "mpris")]
+                "mpris" => {
+                    Mpris::parse(p, &mut table, config)
+                        .map::<Box<dyn PanelConfig>, _>(|p| Box::new(p))
+                }
                 #[cfg(feature = "network")]
                 "network" => {
                     Network::parse(p, &mut table, config)
@@ -391,11 +457,19 @@ fn parse_panel(
                     Systray::parse(p, &mut table, config)
                         .map::<Box<dyn PanelConfig>, _>(|p| Box::new(p))
                 }
+                #[cfg(feature = "tasklist")]
+                "tasklist" => {
+                    Tasklist::parse(p, &mut table, config)
+                        .map::<Box<dyn PanelConfig>, _>(|p| Box::new(p))
+                }
                 #[cfg(feature = "temp")]
                 "temp" => {
                     Temp::parse(p, &mut table, config)
                         .map::<Box<dyn PanelConfig>, _>(|p| Box::new(p))
                 }
+                #[cfg(feature = "vpn")]
+                "vpn" => Vpn::parse(p, &mut table, config)
+                    .map::<Box<dyn PanelConfig>, _>(|p| Box::new(p)),
                 #[cfg(feature = "xwindow")]
                 "xwindow" => {
                     XWindow::parse(p, &mut table, config)
@@ -1,12 +1,42 @@
-use std::ops::Sub;
+use std::{collections::HashMap, hash::BuildHasher, ops::Sub};
 
-use crate::{parser, remove_string_from_config};
+use config::Value;
+use csscolorparser::Color;
+
+use crate::{
+    Attrs, Highlight, parser, remove_color_from_config,
+    remove_float_from_config, remove_string_from_config,
+};
+
+/// How values are mapped onto a [`Ramp`] without explicit breakpoints.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scale {
+    /// Each entry covers an equal part of the range.
+    #[default]
+    Linear,
+    /// Entries near the bottom of the range cover less of it than entries near
+    /// the top. This is useful for values like ping times, where the
+    /// difference between 10ms and 50ms matters more than the difference
+    /// between 1000ms and 1040ms.
+    Log,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, PartialOrd)]
+struct Entry {
+    at: Option<f64>,
+    icon: String,
+    color: Option<Color>,
+}
 
 /// Utility data structure to display one of several strings based on a value in
 /// a range, like a volume icon.
-#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Default)]
+///
+/// Ramps can also hold colors, which are interpolated to follow the value. See
+/// [`ColorRamps`].
+#[derive(Clone, Debug, PartialEq, PartialOrd, Default)]
 pub struct Ramp {
-    icons: Vec<String>,
+    entries: Vec<Entry>,
+    scale: Scale,
 }
 
 impl Ramp {
@@ -16,74 +46,318 @@ impl Ramp {
     /// an empty string.
     #[must_use]
     pub const fn empty() -> Self {
-        Self { icons: Vec::new() }
+        Self {
+            entries: Vec::new(),
+            scale: Scale::Linear,
+        }
+    }
+
+    fn has_breakpoints(&self) -> bool {
+        self.entries.first().is_some_and(|entry| entry.at.is_some())
+    }
+
+    /// Maps a value onto [0, 1] according to the ramp's scale.
+    fn proportion(&self, value: f64, min: f64, max: f64) -> f64 {
+        if max <= min {
+            return 0.0;
+        }
+        let prop = match self.scale {
+            Scale::Linear => (value - min) / (max - min),
+            Scale::Log => (value - min).max(0.0).ln_1p() / (max - min).ln_1p(),
+        };
+        prop.clamp(0.0, 1.0)
     }
 
     /// Given a value and a range, chooses the appropriate icon.
+    ///
+    /// If the ramp has explicit breakpoints, the range is ignored.
     pub fn choose<T>(&self, value: T, min: T, max: T) -> String
     where
         T: Sub + Copy,
         f64: From<T>,
     {
         // prevent division by zero
-        if self.icons.is_empty() {
+        if self.entries.is_empty() {
             return String::new();
         }
-        let min = f64::from(min);
-        let max = f64::from(max);
-        let mut prop = (f64::from(value) - min) / (max - min);
-        if prop < min {
-            prop = min;
-        }
-        if prop > max {
-            prop = max;
+        let value = f64::from(value);
+        if self.has_breakpoints() {
+            return self
+                .entries
+                .iter()
+                .rev()
+                .find(|entry| entry.at.is_some_and(|at| at <= value))
+                .unwrap_or(&self.entries[0])
+                .icon
+                .clone();
         }
-        let idx = prop * (self.icons.len()) as f64;
-        self.icons
-            .get((idx.trunc() as usize).min(self.icons.len() - 1))
+        let prop = self.proportion(value, f64::from(min), f64::from(max));
+        let idx = prop * (self.entries.len()) as f64;
+        self.entries
+            .get((idx.trunc() as usize).min(self.entries.len() - 1))
             .unwrap()
+            .icon
             .clone()
     }
 
+    /// Given a value and a range, interpolates between the ramp's colors.
+    ///
+    /// Returns [`None`] if the ramp has no colors. If the ramp has explicit
+    /// breakpoints, the range is ignored.
+    pub fn color<T>(&self, value: T, min: T, max: T) -> Option<Color>
+    where
+        T: Sub + Copy,
+        f64: From<T>,
+    {
+        let value = f64::from(value);
+        let stops = self
+            .entries
+            .iter()
+            .filter_map(|entry| Some((entry.at, entry.color.as_ref()?)))
+            .collect::<Vec<_>>();
+        let (first, last) = (stops.first()?, stops.last()?);
+
+        if self.has_breakpoints() {
+            if value <= first.0? {
+                return Some(first.1.clone());
+            }
+            return Some(
+                stops
+                    .windows(2)
+                    .find_map(|pair| {
+                        let (lo, lo_color) = pair[0];
+                        let (hi, hi_color) = pair[1];
+                        let (lo, hi) = (lo?, hi?);
+                        (value <= hi).then(|| {
+                            let t = if hi > lo {
+                                (value - lo) / (hi - lo)
+                            } else {
+                                1.0
+                            };
+                            lo_color.interpolate_rgb(hi_color, t as f32)
+                        })
+                    })
+                    .unwrap_or_else(|| last.1.clone()),
+            );
+        }
+
+        let pos = self.proportion(value, f64::from(min), f64::from(max))
+            * (stops.len() - 1) as f64;
+        let idx = (pos.trunc() as usize).min(stops.len() - 1);
+        let next = (idx + 1).min(stops.len() - 1);
+        Some(
+            stops[idx]
+                .1
+                .interpolate_rgb(stops[next].1, pos.fract() as f32),
+        )
+    }
+
     /// Parses a new instance with a given name from the global
     /// [`Config`][config::Config].
     ///
     /// Ramps should be defined in a table called `[ramps]`. Each ramp should be
     /// a table with keys ranging from 0 to any number. The values should be
-    /// [pango] markup strings.
+    /// [pango] markup strings, or tables with the following keys:
+    /// - `icon`: a [pango] markup string
+    /// - `color`: a color, used when the ramp is referenced by a [`ColorRamps`]
+    ///   option. See [csscolorparser] for parsing options.
+    /// - `at`: the lowest value for which this entry is chosen. If any entry
+    ///   has a breakpoint, entries without one are ignored, and the panel's
+    ///   range is not used. Colors are interpolated between breakpoints.
+    ///
+    /// Without breakpoints, the panel's range is divided evenly between the
+    /// entries. The ramp table may also contain a `scale` key, which can be
+    /// `linear` (default) or `log`, to change how the range is divided.
+    ///
+    /// ```toml
+    /// [ramps.temp]
+    /// 0 = { at = 0, icon = "", color = "#0af" }
+    /// 1 = { at = 60, icon = "", color = "#ccc" }
+    /// 2 = { at = 80, icon = "", color = "#f00" }
+    /// ```
     #[must_use]
     pub fn parse(name: impl AsRef<str>) -> Option<Self> {
         let ramps_table = parser::RAMPS.get().unwrap();
         let mut ramp_table =
             ramps_table.get(name.as_ref())?.clone().into_table().ok()?;
-        let mut key = 0;
-        let mut icons = Vec::new();
-        while let Some(icon) =
-            remove_string_from_config(&key.to_string(), &mut ramp_table)
+        let scale = match remove_string_from_config("scale", &mut ramp_table)
+            .as_deref()
         {
-            icons.push(icon);
+            None | Some("linear") => Scale::Linear,
+            Some("log") => Scale::Log,
+            Some(other) => {
+                log::warn!("Unknown ramp scale {other}, using linear");
+                Scale::Linear
+            }
+        };
+
+        let mut key = 0;
+        let mut entries = Vec::new();
+        while let Some(value) = ramp_table.remove(&key.to_string()) {
+            if let Ok(mut entry_table) = value.clone().into_table() {
+                let at = remove_float_from_config("at", &mut entry_table);
+                if let Some(at) = at
+                    && !at.is_finite()
+                {
+                    log::warn!(
+                        "Ignoring ramp entry {key} with non-finite breakpoint \
+                         {at}"
+                    );
+                    key += 1;
+                    continue;
+                }
+                entries.push(Entry {
+                    at,
+                    icon: remove_string_from_config("icon", &mut entry_table)
+                        .unwrap_or_default(),
+                    color: remove_color_from_config("color", &mut entry_table),
+                });
+            } else {
+                ramp_table.insert(key.to_string(), value);
+                if let Some(icon) =
+                    remove_string_from_config(&key.to_string(), &mut ramp_table)
+                {
+                    entries.push(Entry {
+                        at: None,
+                        icon,
+                        color: None,
+                    });
+                }
+            }
             key += 1;
         }
-        Some(Self { icons })
+
+        if entries.iter().any(|entry| entry.at.is_some()) {
+            entries.retain(|entry| {
+                if entry.at.is_none() {
+                    log::warn!(
+                        "Ignoring ramp entry {entry:?} without a breakpoint"
+                    );
+                }
+                entry.at.is_some()
+            });
+            entries.sort_by(|a, b| {
+                a.at.unwrap_or_default()
+                    .total_cmp(&b.at.unwrap_or_default())
+            });
+        }
+
+        Some(Self { entries, scale })
     }
 }
 
 impl From<Vec<String>> for Ramp {
     fn from(icons: Vec<String>) -> Self {
-        Self { icons }
+        icons.into_iter().collect()
     }
 }
 
 impl FromIterator<String> for Ramp {
     fn from_iter<T: IntoIterator<Item = String>>(iter: T) -> Self {
         Self {
-            icons: iter.into_iter().collect(),
+            entries: iter
+                .into_iter()
+                .map(|icon| Entry {
+                    at: None,
+                    icon,
+                    color: None,
+                })
+                .collect(),
+            scale: Scale::Linear,
         }
     }
 }
 
 impl Extend<String> for Ramp {
     fn extend<T: IntoIterator<Item = String>>(&mut self, iter: T) {
-        self.icons.extend(iter);
+        self.entries.extend(iter.into_iter().map(|icon| Entry {
+            at: None,
+            icon,
+            color: None,
+        }));
+    }
+}
+
+/// Ramps that change a panel's colors to follow its value.
+///
+/// Panels that support ramps also support these options.
+#[derive(Clone, Debug, Default, PartialEq, PartialOrd)]
+pub struct ColorRamps {
+    fg: Option<Ramp>,
+    bg: Option<Ramp>,
+    highlight: Option<Ramp>,
+}
+
+impl ColorRamps {
+    /// Parses an instance of this type from a subset of the global
+    /// [`Config`][config::Config].
+    ///
+    /// Configuration options:
+    /// - `ramp_fg`: A string specifying a ramp whose colors are used for the
+    ///   foreground color.
+    /// - `ramp_bg`: A string specifying a ramp whose colors are used for the
+    ///   background color. This has no effect if the panel's attrs don't have a
+    ///   background.
+    /// - `ramp_highlight`: A string specifying a ramp whose colors are used for
+    ///   the panel's highlight.
+    ///
+    /// See [`Ramp::parse`] for details.
+    pub fn parse<S: BuildHasher>(
+        table: &mut HashMap<String, Value, S>,
+    ) -> Self {
+        let mut parse =
+            |key| remove_string_from_config(key, table).and_then(Ramp::parse);
+        let ramps = Self {
+            fg: parse("ramp_fg"),
+            bg: parse("ramp_bg"),
+            highlight: parse("ramp_highlight"),
+        };
+        log::debug!("got color ramps: {ramps:?}");
+        ramps
+    }
+
+    /// Applies the colors for a given value and range to a panel's usual
+    /// attrs and highlight.
+    pub fn apply<T>(
+        &self,
+        value: T,
+        min: T,
+        max: T,
+        attrs: &Attrs,
+        highlight: Option<&Highlight>,
+    ) -> (Attrs, Option<Highlight>)
+    where
+        T: Sub + Copy,
+        f64: From<T>,
+    {
+        let mut attrs = attrs.clone();
+        let mut highlight = highlight.cloned();
+
+        if let Some(color) = self
+            .fg
+            .as_ref()
+            .and_then(|ramp| ramp.color(value, min, max))
+        {
+            attrs.set_fg(color);
+        }
+        if let Some(color) = self
+            .bg
+            .as_ref()
+            .and_then(|ramp| ramp.color(value, min, max))
+            && let Some(bg) = &mut attrs.bg
+        {
+            bg.set_color(color);
+        }
+        if let Some(color) = self
+            .highlight
+            .as_ref()
+            .and_then(|ramp| ramp.color(value, min, max))
+            && let Some(highlight) = &mut highlight
+        {
+            highlight.overline_color = color.clone();
+            highlight.underline_color = color;
+        }
+
+        (attrs, highlight)
     }
 }
@@ -0,0 +1,1023 @@
+use std::fmt::{self, Display};
+
+use anyhow::{Result, anyhow};
+
+use crate::{
+    inline::{self, Object},
+    parser,
+};
+
+/// A value that can be substituted into a [`Template`].
+#[derive(Clone, Debug, PartialEq, PartialOrd)]
+pub enum Var {
+    /// A number, along with the number of decimal places to show when no
+    /// precision is specified in the template.
+    Number(f64, usize),
+    /// Arbitrary text, which may contain [pango] markup.
+    Text(String),
+    /// An inline object, such as a gauge or graph, encoded by the library
+    /// (e.g. by [`History::var`][crate::History::var]). Unlike
+    /// [`Var::Text`], its contents are substituted without removing the
+    /// characters reserved for objects, and format specs don't apply.
+    Object(String),
+}
+
+impl Var {
+    /// Creates a number that is displayed with `precision` decimal places by
+    /// default.
+    #[must_use]
+    pub const fn float(value: f64, precision: usize) -> Self {
+        Self::Number(value, precision)
+    }
+
+    /// Creates text showing a number of bytes with binary units, like the
+    /// `human` filter.
+    #[must_use]
+    pub fn human(bytes: f64) -> Self {
+        Self::Text(scale_bytes(
+            bytes,
+            1024.0,
+            &["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"],
+        ))
+    }
+
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Self::Number(value, _) => Some(*value),
+            Self::Text(text) => text.trim().parse().ok(),
+            Self::Object(_) => None,
+        }
+    }
+
+    fn truthy(&self) -> bool {
+        match self {
+            Self::Number(value, _) => *value != 0.0,
+            Self::Text(text) => !text.is_empty(),
+            Self::Object(_) => true,
+        }
+    }
+}
+
+impl Display for Var {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Number(value, precision) => {
+                write!(f, "{value:.precision$}")
+            }
+            Self::Text(text) | Self::Object(text) => f.write_str(text),
+        }
+    }
+}
+
+macro_rules! var_from_int {
+    ($($t:ty),+) => {
+        $(
+            impl From<$t> for Var {
+                fn from(value: $t) -> Self {
+                    Self::Number(value as f64, 0)
+                }
+            }
+        )+
+    };
+}
+
+var_from_int!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, isize);
+
+impl From<String> for Var {
+    fn from(value: String) -> Self {
+        Self::Text(value)
+    }
+}
+
+impl From<&str> for Var {
+    fn from(value: &str) -> Self {
+        Self::Text(value.to_string())
+    }
+}
+
+impl From<bool> for Var {
+    fn from(value: bool) -> Self {
+        Self::Number(f64::from(u8::from(value)), 0)
+    }
+}
+
+/// A parsed format string.
+///
+/// Templates are made up of literal text and tags. The following tags are
+/// supported:
+/// - `%name%`: substitutes the variable `name`. If the panel doesn't provide a
+///   variable with that name, the text is left as-is. This is the syntax used
+///   by most panels' default format strings.
+/// - `{name}`: substitutes the variable `name`, or nothing if the panel doesn't
+///   provide it.
+/// - `{name:spec}`: substitutes the variable `name`, formatted according to
+///   `spec`. The syntax is `[[fill]align][width][.precision]`, similar to
+///   [`std::fmt`]. `align` is one of `<`, `^`, or `>`. Numbers are
+///   right-aligned by default and text is left-aligned. A `width` starting with
+///   `0` pads numbers with zeroes. `precision` sets the number of decimal
+///   places for numbers and the maximum length of text. For example,
+///   `{percentage:>3}` or `{temp:.1}`.
+/// - `{name|filter}`: substitutes the variable `name` after passing it through
+///   one or more filters, e.g. `{value|round(1)}` or `{bytes|human:>9}`.
+///   Available filters:
+///   - `round(n)`: rounds a number to `n` decimal places (default 0).
+///   - `floor`, `ceil`, `abs`: the usual numeric operations.
+///   - `human`: formats a number of bytes with binary units, e.g. `1.5 GiB`.
+///   - `si`: formats a number of bytes with decimal units, e.g. `1.6 GB`.
+///   - `upper`, `lower`, `trim`: the usual text operations.
+///   - `truncate(n)`: limits text to `n` characters.
+///   - `gauge(name)`: draws a number as the gauge called `name` in the
+///     `[gauges]` table, e.g. `{percentage|gauge(battery)}`. Without a name,
+///     the gauge called `default` is used. See
+///     [`Gauge::parse`][crate::Gauge::parse] for details.
+/// - `{if condition}...{elif condition}...{else}...{end}`: conditionally
+///   includes part of the template. `elif` and `else` are optional. Conditions
+///   compare variables (which may use filters) and literals (which may be
+///   numbers or quoted strings) using `<`, `<=`, `>`, `>=`, `==`, or `!=`, and
+///   can be combined with `and`, `or`, and `not`. A variable on its own is true
+///   if it is a nonzero number or nonempty text. For example, `{if percentage <
+///   20 and not charging}LOW {end}`.
+///
+/// Use `{{` and `}}` to include literal braces. A `{` that isn't followed by a
+/// name is also kept as-is.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    /// Creates an empty template, which always renders to an empty string.
+    #[must_use]
+    pub const fn empty() -> Self {
+        Self { nodes: Vec::new() }
+    }
+
+    /// Parses a template from a format string.
+    ///
+    /// See [`Template`] for the syntax.
+    pub fn parse(format: impl AsRef<str>) -> Result<Self> {
+        let format = format.as_ref();
+        let mut parser = Parser {
+            src: format,
+            pos: 0,
+        };
+        let (nodes, end) = parser.block()?;
+        match end {
+            None => Ok(Self { nodes }),
+            Some(keyword) => Err(parser
+                .error(format!("unexpected `{{{keyword}}}` without `{{if}}`"))),
+        }
+    }
+
+    /// Renders the template, looking up variables with `vars`.
+    ///
+    /// `vars` should return `None` for any name that the panel doesn't
+    /// recognize.
+    pub fn render<F>(&self, vars: F) -> String
+    where
+        F: Fn(&str) -> Option<Var>,
+    {
+        let mut out = String::new();
+        render_nodes(&self.nodes, &vars, &mut out);
+        out
+    }
+
+    /// Returns true if the template references the variable `name`.
+    ///
+    /// This can be used to skip expensive computations for values that won't
+    /// be displayed.
+    #[must_use]
+    pub fn uses(&self, name: &str) -> bool {
+        self.uses_matching(|n| n == name)
+    }
+
+    /// Returns true if the template references any variable whose name
+    /// satisfies `pred`, e.g. to check for numbered variables like `%fan1%`.
+    #[must_use]
+    pub fn uses_matching(&self, pred: impl Fn(&str) -> bool) -> bool {
+        nodes_use(&self.nodes, &pred)
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Text(String),
+    Legacy(String),
+    Expr(Expr, Spec),
+    If(Vec<(Cond, Vec<Node>)>, Vec<Node>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Expr {
+    name: String,
+    filters: Vec<Filter>,
+}
+
+impl Expr {
+    fn eval<F>(&self, vars: &F) -> Option<Var>
+    where
+        F: Fn(&str) -> Option<Var>,
+    {
+        self.filters
+            .iter()
+            .try_fold(vars(self.name.as_str())?, |var, filter| {
+                Some(filter.apply(var))
+            })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Filter {
+    Round(usize),
+    Floor,
+    Ceil,
+    Abs,
+    Human,
+    Si,
+    Upper,
+    Lower,
+    Trim,
+    Truncate(usize),
+    Gauge(String),
+}
+
+impl Filter {
+    fn apply(&self, var: Var) -> Var {
+        match self {
+            Self::Round(precision) => var.as_number().map_or(var, |value| {
+                let factor = 10_f64.powi(*precision as i32);
+                Var::Number((value * factor).round() / factor, *precision)
+            }),
+            Self::Floor => var
+                .as_number()
+                .map_or(var, |value| Var::Number(value.floor(), 0)),
+            Self::Ceil => var
+                .as_number()
+                .map_or(var, |value| Var::Number(value.ceil(), 0)),
+            Self::Abs => match var {
+                Var::Number(value, precision) => {
+                    Var::Number(value.abs(), precision)
+                }
+                var => var
+                    .as_number()
+                    .map_or(var, |value| Var::Number(value.abs(), 0)),
+            },
+            Self::Human => var.as_number().map_or(var, Var::human),
+            Self::Si => var.as_number().map_or(var, |value| {
+                Var::Text(scale_bytes(
+                    value,
+                    1000.0,
+                    &["kB", "MB", "GB", "TB", "PB", "EB"],
+                ))
+            }),
+            Self::Upper => Var::Text(var.to_string().to_uppercase()),
+            Self::Lower => Var::Text(var.to_string().to_lowercase()),
+            Self::Trim => Var::Text(var.to_string().trim().to_string()),
+            Self::Truncate(len) => {
+                Var::Text(var.to_string().chars().take(*len).collect())
+            }
+            Self::Gauge(name) => var.as_number().map_or(var, |value| {
+                Var::Object(Object::gauge_marker(name, value))
+            }),
+        }
+    }
+}
+
+fn scale_bytes(value: f64, base: f64, units: &[&str]) -> String {
+    if value.abs() < base {
+        return format!("{value:.0} B");
+    }
+    let mut value = value / base;
+    let mut unit = 0;
+    while value.abs() >= base && unit < units.len() - 1 {
+        value /= base;
+        unit += 1;
+    }
+    format!("{value:.1} {}", units[unit])
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct Spec {
+    fill: Option<char>,
+    align: Option<Align>,
+    width: usize,
+    precision: Option<usize>,
+}
+
+impl Spec {
+    fn parse(spec: &str) -> Option<Self> {
+        let align = |c| match c {
+            '<' => Some(Align::Left),
+            '^' => Some(Align::Center),
+            '>' => Some(Align::Right),
+            _ => None,
+        };
+
+        let mut res = Self::default();
+        let mut chars = spec.chars();
+        let mut rest = spec;
+        match (chars.next(), chars.next()) {
+            (Some(fill), Some(a)) if align(a).is_some() => {
+                res.fill = Some(fill);
+                res.align = align(a);
+                rest = &spec[fill.len_utf8() + 1..];
+            }
+            (Some(a), _) if align(a).is_some() => {
+                res.align = align(a);
+                rest = &spec[1..];
+            }
+            _ => {}
+        }
+
+        if res.fill.is_none() && res.align.is_none() && rest.starts_with('0') {
+            res.fill = Some('0');
+        }
+
+        let (width, precision) = match rest.split_once('.') {
+            Some((width, precision)) => (width, Some(precision)),
+            None => (rest, None),
+        };
+        if !width.is_empty() {
+            res.width = width.parse().ok()?;
+        }
+        if let Some(precision) = precision {
+            res.precision = Some(precision.parse().ok()?);
+        }
+
+        Some(res)
+    }
+
+    fn format(&self, var: &Var) -> String {
+        let (text, default_align) = match var {
+            Var::Object(object) => return object.clone(),
+            Var::Number(value, precision) => {
+                let precision = self.precision.unwrap_or(*precision);
+                (format!("{value:.precision$}"), Align::Right)
+            }
+            Var::Text(text) => (
+                self.precision.map_or_else(
+                    || text.clone(),
+                    |len| text.chars().take(len).collect(),
+                ),
+                Align::Left,
+            ),
+        };
+
+        let len = text.chars().count();
+        if len >= self.width {
+            return text;
+        }
+
+        let fill = self.fill.unwrap_or(' ');
+        let padding = self.width - len;
+        let (left, right) = match self.align.unwrap_or(default_align) {
+            Align::Left => (0, padding),
+            Align::Center => (padding / 2, padding - padding / 2),
+            Align::Right => (padding, 0),
+        };
+
+        // zero padding goes after the sign, like std::fmt
+        if self.fill == Some('0')
+            && self.align.is_none()
+            && let Some(digits) = text.strip_prefix('-')
+        {
+            return format!("-{}{digits}", "0".repeat(left));
+        }
+
+        let mut out = String::with_capacity(text.len() + padding);
+        out.extend(std::iter::repeat_n(fill, left));
+        out.push_str(text.as_str());
+        out.extend(std::iter::repeat_n(fill, right));
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CmpOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Operand {
+    Var(Expr),
+    Literal(Var),
+}
+
+impl Operand {
+    fn eval<F>(&self, vars: &F) -> Option<Var>
+    where
+        F: Fn(&str) -> Option<Var>,
+    {
+        match self {
+            Self::Var(expr) => expr.eval(vars),
+            Self::Literal(var) => Some(var.clone()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Cond {
+    Or(Box<Cond>, Box<Cond>),
+    And(Box<Cond>, Box<Cond>),
+    Not(Box<Cond>),
+    Cmp(Operand, CmpOp, Operand),
+    Truthy(Operand),
+}
+
+impl Cond {
+    fn eval<F>(&self, vars: &F) -> bool
+    where
+        F: Fn(&str) -> Option<Var>,
+    {
+        match self {
+            Self::Or(lhs, rhs) => lhs.eval(vars) || rhs.eval(vars),
+            Self::And(lhs, rhs) => lhs.eval(vars) && rhs.eval(vars),
+            Self::Not(cond) => !cond.eval(vars),
+            Self::Truthy(operand) => {
+                operand.eval(vars).is_some_and(|var| var.truthy())
+            }
+            Self::Cmp(lhs, op, rhs) => {
+                let (Some(lhs), Some(rhs)) = (lhs.eval(vars), rhs.eval(vars))
+                else {
+                    return false;
+                };
+                let ordering = match (lhs.as_number(), rhs.as_number()) {
+                    (Some(lhs), Some(rhs)) => lhs.partial_cmp(&rhs),
+                    _ => Some(lhs.to_string().cmp(&rhs.to_string())),
+                };
+                ordering.is_some_and(|ordering| match op {
+                    CmpOp::Lt => ordering.is_lt(),
+                    CmpOp::Le => ordering.is_le(),
+                    CmpOp::Gt => ordering.is_gt(),
+                    CmpOp::Ge => ordering.is_ge(),
+                    CmpOp::Eq => ordering.is_eq(),
+                    CmpOp::Ne => ordering.is_ne(),
+                })
+            }
+        }
+    }
+
+    fn uses(&self, pred: &dyn Fn(&str) -> bool) -> bool {
+        match self {
+            Self::Or(lhs, rhs) | Self::And(lhs, rhs) => {
+                lhs.uses(pred) || rhs.uses(pred)
+            }
+            Self::Not(cond) => cond.uses(pred),
+            Self::Truthy(operand) => operand_uses(operand, pred),
+            Self::Cmp(lhs, _, rhs) => {
+                operand_uses(lhs, pred) || operand_uses(rhs, pred)
+            }
+        }
+    }
+}
+
+fn operand_uses(operand: &Operand, pred: &dyn Fn(&str) -> bool) -> bool {
+    matches!(operand, Operand::Var(expr) if pred(expr.name.as_str()))
+}
+
+fn render_nodes<F>(nodes: &[Node], vars: &F, out: &mut String)
+where
+    F: Fn(&str) -> Option<Var>,
+{
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text.as_str()),
+            Node::Legacy(name) => match vars(name.as_str()) {
+                Some(Var::Object(object)) => out.push_str(object.as_str()),
+                Some(var) => out.push_str(&inline::sanitize(&var.to_string())),
+                None => {
+                    out.push('%');
+                    out.push_str(name.as_str());
+                    out.push('%');
+                }
+            },
+            Node::Expr(expr, spec) => match expr.eval(vars) {
+                Some(var @ Var::Object(_)) => {
+                    out.push_str(spec.format(&var).as_str());
+                }
+                Some(var) => {
+                    out.push_str(&inline::sanitize(&spec.format(&var)));
+                }
+                None => {}
+            },
+            Node::If(branches, otherwise) => {
+                let nodes = branches
+                    .iter()
+                    .find(|(cond, _)| cond.eval(vars))
+                    .map_or(otherwise, |(_, nodes)| nodes);
+                render_nodes(nodes, vars, out);
+            }
+        }
+    }
+}
+
+fn nodes_use(nodes: &[Node], pred: &dyn Fn(&str) -> bool) -> bool {
+    nodes.iter().any(|node| match node {
+        Node::Text(_) => false,
+        Node::Legacy(name) => pred(name.as_str()),
+        Node::Expr(expr, _) => pred(expr.name.as_str()),
+        Node::If(branches, otherwise) => {
+            branches
+                .iter()
+                .any(|(cond, nodes)| cond.uses(pred) || nodes_use(nodes, pred))
+                || nodes_use(otherwise, pred)
+        }
+    })
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: impl Display) -> anyhow::Error {
+        anyhow!(
+            "Invalid format string {:?} at offset {}: {msg}",
+            self.src,
+            self.pos
+        )
+    }
+
+    fn rest(&self) -> &'a str {
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        if self.rest().starts_with(s) {
+            self.pos += s.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn skip_ws(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start().len();
+    }
+
+    fn expect(&mut self, s: &str) -> Result<()> {
+        self.skip_ws();
+        if self.eat(s) {
+            Ok(())
+        } else {
+            Err(self.error(format!("expected `{s}`")))
+        }
+    }
+
+    fn ident(&mut self) -> Option<&'a str> {
+        let rest = self.rest();
+        if !rest.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') {
+            return None;
+        }
+        let len = rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len());
+        self.pos += len;
+        Some(&rest[..len])
+    }
+
+    /// Parses nodes until the end of the input or an `elif`, `else`, or `end`
+    /// tag, which is returned.
+    fn block(&mut self) -> Result<(Vec<Node>, Option<&'a str>)> {
+        let mut nodes = Vec::new();
+        let mut text = String::new();
+
+        while let Some(c) = self.peek() {
+            if self.eat("{{") {
+                text.push('{');
+            } else if self.eat("}}") {
+                text.push('}');
+            } else if c == '{' && self.is_tag() {
+                if !text.is_empty() {
+                    nodes.push(Node::Text(std::mem::take(&mut text)));
+                }
+                self.pos += 1;
+                self.skip_ws();
+                let start = self.pos;
+                match self.ident() {
+                    Some(keyword @ ("elif" | "else" | "end")) => {
+                        if keyword == "elif" {
+                            // let the caller parse the condition
+                            self.pos = start;
+                        } else {
+                            self.expect("}")?;
+                        }
+                        return Ok((nodes, Some(keyword)));
+                    }
+                    Some("if") => nodes.push(self.conditional()?),
+                    _ => {
+                        self.pos = start;
+                        nodes.push(self.expr_tag().map_err(|e| {
+                            anyhow!("{e} (use `{{{{` for a literal `{{`)")
+                        })?);
+                    }
+                }
+            } else if c == '%'
+                && let Some(name) = self.legacy()
+            {
+                if !text.is_empty() {
+                    nodes.push(Node::Text(std::mem::take(&mut text)));
+                }
+                nodes.push(Node::Legacy(name.to_string()));
+            } else {
+                text.push(c);
+                self.pos += c.len_utf8();
+            }
+        }
+
+        if !text.is_empty() {
+            nodes.push(Node::Text(text));
+        }
+
+        Ok((nodes, None))
+    }
+
+    fn is_tag(&self) -> bool {
+        self.rest()[1..]
+            .trim_start()
+            .starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+    }
+
+    fn legacy(&mut self) -> Option<&'a str> {
+        let rest = &self.rest()[1..];
+        let len =
+            rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))?;
+        if len == 0 || !rest[len..].starts_with('%') {
+            return None;
+        }
+        self.pos += len + 2;
+        Some(&rest[..len])
+    }
+
+    fn conditional(&mut self) -> Result<Node> {
+        let mut branches = Vec::new();
+        let mut cond = self.cond()?;
+        self.expect("}")?;
+        loop {
+            let (nodes, end) = self.block()?;
+            branches.push((cond, nodes));
+            match end {
+                Some("elif") => {
+                    self.ident();
+                    cond = self.cond()?;
+                    self.expect("}")?;
+                }
+                Some("else") => {
+                    let (otherwise, end) = self.block()?;
+                    return match end {
+                        Some("end") => Ok(Node::If(branches, otherwise)),
+                        Some(keyword) => Err(self.error(format!(
+                            "unexpected `{{{keyword}}}` after `{{else}}`"
+                        ))),
+                        None => Err(self.error("missing `{end}`")),
+                    };
+                }
+                Some(_) => return Ok(Node::If(branches, Vec::new())),
+                None => return Err(self.error("missing `{end}`")),
+            }
+        }
+    }
+
+    fn cond(&mut self) -> Result<Cond> {
+        let mut lhs = self.and_cond()?;
+        while self.keyword("or") {
+            lhs = Cond::Or(Box::new(lhs), Box::new(self.and_cond()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and_cond(&mut self) -> Result<Cond> {
+        let mut lhs = self.not_cond()?;
+        while self.keyword("and") {
+            lhs = Cond::And(Box::new(lhs), Box::new(self.not_cond()?));
+        }
+        Ok(lhs)
+    }
+
+    fn not_cond(&mut self) -> Result<Cond> {
+        if self.keyword("not") {
+            return Ok(Cond::Not(Box::new(self.not_cond()?)));
+        }
+        let lhs = self.operand()?;
+        self.skip_ws();
+        let op = if self.eat("<=") {
+            CmpOp::Le
+        } else if self.eat(">=") {
+            CmpOp::Ge
+        } else if self.eat("==") {
+            CmpOp::Eq
+        } else if self.eat("!=") {
+            CmpOp::Ne
+        } else if self.eat("<") {
+            CmpOp::Lt
+        } else if self.eat(">") {
+            CmpOp::Gt
+        } else {
+            return Ok(Cond::Truthy(lhs));
+        };
+        Ok(Cond::Cmp(lhs, op, self.operand()?))
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        self.skip_ws();
+        let start = self.pos;
+        if self.ident() == Some(keyword) {
+            true
+        } else {
+            self.pos = start;
+            false
+        }
+    }
+
+    fn operand(&mut self) -> Result<Operand> {
+        self.skip_ws();
+        match self.peek() {
+            Some(quote @ ('"' | '\'')) => {
+                self.pos += 1;
+                let rest = self.rest();
+                let len = rest
+                    .find(quote)
+                    .ok_or_else(|| self.error("unterminated string"))?;
+                self.pos += len + 1;
+                Ok(Operand::Literal(Var::Text(rest[..len].to_string())))
+            }
+            Some(c) if c.is_ascii_digit() || c == '-' || c == '.' => {
+                let rest = self.rest();
+                let len = rest[1..]
+                    .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+                    .map_or(rest.len(), |len| len + 1);
+                let value = rest[..len]
+                    .parse::<f64>()
+                    .map_err(|_| self.error("invalid number"))?;
+                self.pos += len;
+                Ok(Operand::Literal(Var::Number(value, 0)))
+            }
+            _ => Ok(Operand::Var(self.expr()?)),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        self.skip_ws();
+        let name = self
+            .ident()
+            .ok_or_else(|| self.error("expected a variable name"))?
+            .to_string();
+        let mut filters = Vec::new();
+        loop {
+            self.skip_ws();
+            if !self.eat("|") {
+                break;
+            }
+            self.skip_ws();
+            let filter = self
+                .ident()
+                .ok_or_else(|| self.error("expected a filter name"))?;
+            self.skip_ws();
+            if filter == "gauge" {
+                filters.push(Filter::Gauge(self.gauge_name()?));
+                continue;
+            }
+            let arg = if self.eat("(") {
+                self.skip_ws();
+                let rest = self.rest();
+                let len = rest
+                    .find(|c: char| !c.is_ascii_digit())
+                    .unwrap_or(rest.len());
+                let arg = rest[..len].parse::<usize>().map_err(|_| {
+                    self.error(format!(
+                        "expected a nonnegative integer argument to `{filter}`"
+                    ))
+                })?;
+                self.pos += len;
+                self.expect(")")?;
+                Some(arg)
+            } else {
+                None
+            };
+            filters.push(match (filter, arg) {
+                ("round", arg) => Filter::Round(arg.unwrap_or(0)),
+                ("truncate", Some(arg)) => Filter::Truncate(arg),
+                ("floor", None) => Filter::Floor,
+                ("ceil", None) => Filter::Ceil,
+                ("abs", None) => Filter::Abs,
+                ("human", None) => Filter::Human,
+                ("si", None) => Filter::Si,
+                ("upper", None) => Filter::Upper,
+                ("lower", None) => Filter::Lower,
+                ("trim", None) => Filter::Trim,
+                ("truncate", None) => {
+                    return Err(self.error("`truncate` requires an argument"));
+                }
+                (filter, Some(_)) => {
+                    return Err(self.error(format!(
+                        "`{filter}` is not a filter that takes an argument"
+                    )));
+                }
+                (filter, None) => {
+                    return Err(
+                        self.error(format!("unknown filter `{filter}`"))
+                    );
+                }
+            });
+        }
+        Ok(Expr { name, filters })
+    }
+
+    fn gauge_name(&mut self) -> Result<String> {
+        let name = if self.eat("(") {
+            let rest = self.rest();
+            let len =
+                rest.find(')').ok_or_else(|| self.error("expected `)`"))?;
+            let name = rest[..len].trim();
+            if name.is_empty() {
+                return Err(self.error("expected a gauge name"));
+            }
+            self.pos += len + 1;
+            name
+        } else {
+            "default"
+        };
+        // the gauges table isn't available when templates are parsed outside
+        // of a bar, so unknown names are only caught here when it is
+        if parser::GAUGES.get().is_some() && Object::gauge(name).is_none() {
+            return Err(self.error(format!("unknown gauge `{name}`")));
+        }
+        Ok(name.to_string())
+    }
+
+    fn expr_tag(&mut self) -> Result<Node> {
+        let expr = self.expr()?;
+        self.skip_ws();
+        let spec = if self.eat(":") {
+            let rest = self.rest();
+            let len =
+                rest.find('}').ok_or_else(|| self.error("expected `}`"))?;
+            let spec = Spec::parse(&rest[..len]).ok_or_else(|| {
+                self.error(format!("invalid format spec `{}`", &rest[..len]))
+            })?;
+            self.pos += len;
+            spec
+        } else {
+            Spec::default()
+        };
+        self.expect("}")?;
+        Ok(Node::Expr(expr, spec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(format: &str, vars: &[(&str, Var)]) -> String {
+        Template::parse(format).unwrap().render(|name| {
+            vars.iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, var)| var.clone())
+        })
+    }
+
+    #[test]
+    fn literal_text() {
+        assert_eq!(render("plain text", &[]), "plain text");
+        assert_eq!(render("", &[]), "");
+        assert_eq!(render("50% { } {1}", &[]), "50% { } {1}");
+    }
+
+    #[test]
+    fn escaped_braces() {
+        assert_eq!(render("{{name}}", &[("name", 1.into())]), "{name}");
+        assert_eq!(render("{{{name}}}", &[("name", 1.into())]), "{1}");
+    }
+
+    #[test]
+    fn unescaped_word_in_braces_is_a_tag() {
+        assert_eq!(render("{name}", &[]), "");
+        let err = Template::parse("{oops").unwrap_err().to_string();
+        assert!(err.contains("use `{{` for a literal `{`"), "{err}");
+    }
+
+    #[test]
+    fn legacy_syntax() {
+        assert_eq!(
+            render("%percentage%%", &[("percentage", 42.into())]),
+            "42%"
+        );
+        assert_eq!(render("%unknown%", &[]), "%unknown%");
+        assert!(Template::parse("%a%").unwrap().uses("a"));
+    }
+
+    #[test]
+    fn reserved_characters() {
+        let title = Var::from("a\u{f8f0}gauge\u{1f}x\u{1f}1\u{fffc}b\u{f8f1}");
+        assert_eq!(
+            render("%t%", &[("t", title.clone())]),
+            "agauge\u{1f}x\u{1f}1b"
+        );
+        assert_eq!(render("{t}", &[("t", title)]), "agauge\u{1f}x\u{1f}1b");
+        let object = Var::Object(Object::gauge_marker("default", 1.0));
+        assert_eq!(
+            render("{o:>40}", &[("o", object.clone())]),
+            object.to_string()
+        );
+    }
+
+    #[test]
+    fn specs() {
+        let vars = [("n", 7.into()), ("f", Var::float(-2.5, 1))];
+        assert_eq!(render("{n:>3}", &vars), "  7");
+        assert_eq!(render("{n:<3}|", &vars), "7  |");
+        assert_eq!(render("{n:*^5}", &vars), "**7**");
+        assert_eq!(render("{n:03}", &vars), "007");
+        assert_eq!(render("{f:06.2}", &vars), "-02.50");
+        assert_eq!(render("{f}", &vars), "-2.5");
+        assert_eq!(render("{f:.0}", &vars), "-2");
+        assert_eq!(render("{t:.3}", &[("t", "abcdef".into())]), "abc");
+        assert_eq!(render("{t:4}|", &[("t", "ab".into())]), "ab  |");
+        assert!(Template::parse("{n:x}").is_err());
+    }
+
+    #[test]
+    fn filters() {
+        let vars = [
+            ("v", Var::float(2.345, 3)),
+            ("bytes", 1536.into()),
+            ("t", " Mixed ".into()),
+        ];
+        assert_eq!(render("{v|round(1)}", &vars), "2.3");
+        assert_eq!(render("{v|round}", &vars), "2");
+        assert_eq!(render("{v|floor} {v|ceil}", &vars), "2 3");
+        assert_eq!(render("{bytes|human}", &vars), "1.5 KiB");
+        assert_eq!(render("{bytes|si}", &vars), "1.5 kB");
+        assert_eq!(render("{bytes|human:>9}", &vars), "  1.5 KiB");
+        assert_eq!(render("{t|trim|upper}", &vars), "MIXED");
+        assert_eq!(render("{t|trim|lower|truncate(3)}", &vars), "mix");
+        assert!(Template::parse("{v|nope}").is_err());
+        assert!(Template::parse("{v|truncate}").is_err());
+        assert!(Template::parse("{v|upper(2)}").is_err());
+    }
+
+    #[test]
+    fn conditionals() {
+        let format = "{if percentage < 20 and not charging}LOW{elif \
+                      percentage >= 90}FULL{else}{percentage}{end}";
+        let check = |percentage: u32, charging: bool| {
+            render(
+                format,
+                &[
+                    ("percentage", percentage.into()),
+                    ("charging", charging.into()),
+                ],
+            )
+        };
+        assert_eq!(check(10, false), "LOW");
+        assert_eq!(check(10, true), "10");
+        assert_eq!(check(95, false), "FULL");
+        assert_eq!(check(50, false), "50");
+
+        let format = "{if state == 'on' or missing}yes{end}";
+        assert_eq!(render(format, &[("state", "on".into())]), "yes");
+        assert_eq!(render(format, &[("state", "off".into())]), "");
+
+        let template = Template::parse(format).unwrap();
+        assert!(template.uses("state"));
+        assert!(template.uses("missing"));
+        assert!(!template.uses("other"));
+    }
+
+    #[test]
+    fn uses_matching() {
+        let template =
+            Template::parse("%temp% {if fan2 > 0}{fan2}{end}").unwrap();
+        assert!(template.uses_matching(|name| name.starts_with("fan")));
+        assert!(!template.uses_matching(|name| name.starts_with("load")));
+        assert!(!Template::parse("fan").unwrap().uses_matching(|_| true));
+    }
+
+    #[test]
+    fn unbalanced_conditionals() {
+        assert!(Template::parse("{if a}x").is_err());
+        assert!(Template::parse("x{end}").is_err());
+        assert!(Template::parse("{else}").is_err());
+        assert!(Template::parse("{if a}x{else}y{elif b}z{end}").is_err());
+        assert!(Template::parse("{if a == 'x}y{end}").is_err());
+    }
+}
@@ -0,0 +1,405 @@
+use std::{
+    collections::HashMap,
+    hash::BuildHasher,
+    pin::Pin,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use anyhow::Result;
+use config::Value;
+use futures::task::AtomicWaker;
+use tokio::time::{MissedTickBehavior, interval};
+use tokio_stream::{Stream, StreamExt};
+
+use crate::{
+    Attrs, Highlight, ManagedIntervalStream, Template, get_table_from_config,
+    remove_bool_from_config, remove_float_from_config,
+    remove_string_from_config, remove_uint_from_config,
+};
+
+/// The styling applied to a panel once its value crosses a threshold.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Threshold {
+    /// The value at which this threshold takes effect
+    pub value: f64,
+    /// The attrs to use, falling back to the panel's attrs for any unset
+    /// fields
+    pub attrs: Attrs,
+    /// The highlight to use instead of the panel's highlight
+    pub highlight: Option<Highlight>,
+    /// The format string to use instead of the panel's format string
+    pub format: Option<Template>,
+    /// Whether the panel should alternate between this styling and its usual
+    /// styling
+    pub blink: bool,
+}
+
+/// How severe a panel's value is, according to its [`Thresholds`].
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    /// No threshold has been crossed
+    #[default]
+    Normal,
+    /// The warning threshold has been crossed
+    Warn,
+    /// The critical threshold has been crossed
+    Crit,
+}
+
+impl Severity {
+    /// Returns `normal`, `warn`, or `crit`, matching the suffixes of the
+    /// configuration options.
+    #[must_use]
+    pub const fn as_str(self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::Warn => "warn",
+            Self::Crit => "crit",
+        }
+    }
+}
+
+/// Warning and critical thresholds for a panel's primary value.
+///
+/// Panels that support thresholds check their value with
+/// [`Thresholds::style`] before drawing.
+#[derive(Clone, Debug, Default)]
+pub struct Thresholds {
+    warn: Option<Threshold>,
+    crit: Option<Threshold>,
+    below: Option<bool>,
+    blink_interval: Duration,
+    blinking: Arc<AtomicBool>,
+    phase: Arc<AtomicBool>,
+}
+
+impl Thresholds {
+    /// Parses an instance of this type from a subset of the global
+    /// [`Config`][config::Config].
+    ///
+    /// Configuration options:
+    /// - `thresholds`: A table with the following keys:
+    ///   - `warn`: The value at which the panel enters the warning state.
+    ///     - type: f64
+    ///   - `crit`: The value at which the panel enters the critical state.
+    ///     - type: f64
+    ///   - `below`: Whether lower values are worse (e.g. for a battery). If
+    ///     both `warn` and `crit` are set, this defaults to whether `crit` is
+    ///     less than `warn`. Otherwise it defaults to the panel's usual
+    ///     direction, which is `true` for battery and `false` elsewhere.
+    ///     - type: bool
+    ///   - `blink_interval`: How often (in milliseconds) to blink.
+    ///     - type: u64
+    ///     - default: 500
+    /// - `attrs_warn`, `attrs_crit`: A string specifying the attrs to use in
+    ///   each state. Any attributes that are not set are taken from the panel's
+    ///   `attrs`. See [`Attrs::parse`] for details.
+    /// - `highlight_warn`, `highlight_crit`: A string specifying the highlight
+    ///   to use in each state. See [`Highlight::parse`] for details.
+    /// - `format_warn`, `format_crit`: The format string to use in each state.
+    ///   The formatting options are the same as the panel's usual format
+    ///   string.
+    /// - `blink_warn`, `blink_crit`: Whether to alternate between the styling
+    ///   for each state and the panel's usual styling.
+    ///   - type: bool
+    ///   - default: false
+    pub fn parse<S: BuildHasher>(
+        table: &mut HashMap<String, Value, S>,
+    ) -> Result<Self> {
+        let Some(mut thresholds) = get_table_from_config("thresholds", table)
+        else {
+            return Ok(Self::default());
+        };
+        table.remove("thresholds");
+
+        let warn = remove_float_from_config("warn", &mut thresholds)
+            .map(|value| Self::parse_threshold(table, "_warn", value))
+            .transpose()?;
+        let crit = remove_float_from_config("crit", &mut thresholds)
+            .map(|value| Self::parse_threshold(table, "_crit", value))
+            .transpose()?;
+        let below =
+            remove_bool_from_config("below", &mut thresholds).or_else(|| {
+                warn.as_ref()
+                    .zip(crit.as_ref())
+                    .filter(|(warn, crit)| crit.value != warn.value)
+                    .map(|(warn, crit)| crit.value < warn.value)
+            });
+        let blink_interval = Duration::from_millis(
+            remove_uint_from_config("blink_interval", &mut thresholds)
+                .unwrap_or(500),
+        );
+
+        let thresholds = Self {
+            warn,
+            crit,
+            below,
+            blink_interval,
+            blinking: Arc::new(AtomicBool::new(false)),
+            phase: Arc::new(AtomicBool::new(true)),
+        };
+        log::debug!("got thresholds: {thresholds:?}");
+        Ok(thresholds)
+    }
+
+    fn parse_threshold<S: BuildHasher>(
+        table: &mut HashMap<String, Value, S>,
+        suffix: &'static str,
+        value: f64,
+    ) -> Result<Threshold> {
+        Ok(Threshold {
+            value,
+            attrs: remove_string_from_config(
+                format!("attrs{suffix}").as_str(),
+                table,
+            )
+            .map_or_else(Attrs::default, |name| {
+                Attrs::parse(name).unwrap_or_else(|e| {
+                    log::warn!("Ignoring attrs{suffix}: {e}");
+                    Attrs::default()
+                })
+            }),
+            highlight: remove_string_from_config(
+                format!("highlight{suffix}").as_str(),
+                table,
+            )
+            .and_then(Highlight::parse),
+            format: remove_string_from_config(
+                format!("format{suffix}").as_str(),
+                table,
+            )
+            .map(Template::parse)
+            .transpose()?,
+            blink: remove_bool_from_config(
+                format!("blink{suffix}").as_str(),
+                table,
+            )
+            .unwrap_or(false),
+        })
+    }
+
+    /// Sets whether lower values are worse when the config doesn't say so,
+    /// either explicitly with `below` or by setting both thresholds. Panels
+    /// whose values get worse as they drop should call this after parsing.
+    pub const fn default_below(&mut self, below: bool) {
+        if self.below.is_none() {
+            self.below = Some(below);
+        }
+    }
+
+    fn crossed(&self, value: f64, threshold: &Threshold) -> bool {
+        if self.below.unwrap_or(false) {
+            value <= threshold.value
+        } else {
+            value >= threshold.value
+        }
+    }
+
+    /// Returns the most severe threshold that `value` has crossed, if any.
+    #[must_use]
+    pub fn level(&self, value: f64) -> Option<&Threshold> {
+        let crossed = |threshold: &&Threshold| self.crossed(value, threshold);
+        self.crit
+            .as_ref()
+            .filter(crossed)
+            .or_else(|| self.warn.as_ref().filter(crossed))
+    }
+
+    /// Returns which threshold, if any, `value` has crossed.
+    #[must_use]
+    pub fn severity(&self, value: f64) -> Severity {
+        let crossed = |threshold: &Option<Threshold>| {
+            threshold
+                .as_ref()
+                .is_some_and(|threshold| self.crossed(value, threshold))
+        };
+        if crossed(&self.crit) {
+            Severity::Crit
+        } else if crossed(&self.warn) {
+            Severity::Warn
+        } else {
+            Severity::Normal
+        }
+    }
+
+    /// Like [`Thresholds::severity`], but the severity only drops below
+    /// `current` once `value` is at least `hysteresis` past the threshold.
+    /// This keeps a value that hovers around a threshold from crossing it
+    /// repeatedly.
+    #[must_use]
+    pub fn severity_from(
+        &self,
+        current: Severity,
+        value: f64,
+        hysteresis: f64,
+    ) -> Severity {
+        let severity = self.severity(value);
+        if severity >= current {
+            return severity;
+        }
+        let relaxed = if self.below.unwrap_or(false) {
+            value - hysteresis
+        } else {
+            value + hysteresis
+        };
+        self.severity(relaxed).min(current)
+    }
+
+    /// Returns true if any threshold's format string references the variable
+    /// `name`. See [`Template::uses`].
+    #[must_use]
+    pub fn uses(&self, name: &str) -> bool {
+        self.uses_matching(|n| n == name)
+    }
+
+    /// Returns true if any threshold's format string references a variable
+    /// whose name satisfies `pred`. See [`Template::uses_matching`].
+    #[must_use]
+    pub fn uses_matching(&self, pred: impl Fn(&str) -> bool) -> bool {
+        [&self.warn, &self.crit]
+            .into_iter()
+            .flatten()
+            .filter_map(|threshold| threshold.format.as_ref())
+            .any(|format| format.uses_matching(&pred))
+    }
+
+    /// Chooses the format, attrs, and highlight that a panel should draw with
+    /// given its current value and its usual styling.
+    ///
+    /// This also starts or stops blinking as appropriate. While the panel is
+    /// blinking, the usual styling (but the threshold's format) is returned
+    /// for every other blink interval.
+    pub fn style<'a>(
+        &'a self,
+        value: f64,
+        format: &'a Template,
+        attrs: &Attrs,
+        highlight: Option<&Highlight>,
+    ) -> (&'a Template, Attrs, Option<Highlight>) {
+        let Some(threshold) = self.level(value) else {
+            self.blinking.store(false, Ordering::Relaxed);
+            self.phase.store(true, Ordering::Relaxed);
+            return (format, attrs.clone(), highlight.cloned());
+        };
+
+        let format = threshold.format.as_ref().unwrap_or(format);
+        self.blinking.store(threshold.blink, Ordering::Relaxed);
+        if threshold.blink && !self.phase.load(Ordering::Relaxed) {
+            return (format, attrs.clone(), highlight.cloned());
+        }
+
+        let mut new_attrs = threshold.attrs.clone();
+        new_attrs.apply_to(attrs);
+        (
+            format,
+            new_attrs,
+            threshold.highlight.clone().or_else(|| highlight.cloned()),
+        )
+    }
+
+    /// Wraps a panel's stream so that it also yields while the panel is
+    /// blinking.
+    ///
+    /// Items from `stream` are wrapped in [`Some`], and [`None`] is yielded
+    /// each time the panel should be redrawn with the same data. If no
+    /// threshold is configured to blink, `stream` is returned unchanged
+    /// (other than the wrapping).
+    ///
+    /// Blinking is driven by a [`ManagedIntervalStream`] using `paused` and
+    /// `waker`, so it stops while the panel is hidden. These should be the
+    /// same ones the panel passes to
+    /// [`ShowHide::Default`][crate::common::ShowHide::Default].
+    pub fn blink<S>(
+        &self,
+        stream: S,
+        paused: Arc<Mutex<bool>>,
+        waker: Arc<AtomicWaker>,
+    ) -> Pin<Box<dyn Stream<Item = Option<S::Item>>>>
+    where
+        S: Stream + 'static,
+    {
+        let stream = stream.map(Some);
+        if ![&self.warn, &self.crit]
+            .into_iter()
+            .flatten()
+            .any(|threshold| threshold.blink)
+        {
+            return Box::pin(stream);
+        }
+
+        let mut interval = interval(self.blink_interval);
+        interval.set_missed_tick_behavior(MissedTickBehavior::Skip);
+        let ticks = ManagedIntervalStream::new(
+            Arc::new(Mutex::new(interval)),
+            paused,
+            waker,
+        );
+        let blinking = self.blinking.clone();
+        let phase = self.phase.clone();
+        Box::pin(stream.merge(ticks.filter_map(move |_| {
+            if blinking.load(Ordering::Relaxed) {
+                phase.fetch_xor(true, Ordering::Relaxed);
+                Some(None)
+            } else {
+                None
+            }
+        })))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(thresholds: &[(&str, Value)]) -> Thresholds {
+        let thresholds = thresholds
+            .iter()
+            .map(|(key, value)| ((*key).to_string(), value.clone()))
+            .collect::<HashMap<_, _>>();
+        let mut table = HashMap::from([(
+            String::from("thresholds"),
+            Value::from(thresholds),
+        )]);
+        Thresholds::parse(&mut table).unwrap()
+    }
+
+    fn level(thresholds: &Thresholds, value: f64) -> Option<f64> {
+        thresholds.level(value).map(|threshold| threshold.value)
+    }
+
+    #[test]
+    fn direction() {
+        let both =
+            parse(&[("warn", Value::from(20.0)), ("crit", Value::from(5.0))]);
+        assert_eq!(level(&both, 50.0), None);
+        assert_eq!(level(&both, 15.0), Some(20.0));
+        assert_eq!(level(&both, 5.0), Some(5.0));
+
+        let above =
+            parse(&[("warn", Value::from(80.0)), ("crit", Value::from(95.0))]);
+        assert_eq!(level(&above, 50.0), None);
+        assert_eq!(level(&above, 90.0), Some(80.0));
+        assert_eq!(level(&above, 99.0), Some(95.0));
+    }
+
+    #[test]
+    fn one_threshold() {
+        let mut crit = parse(&[("crit", Value::from(5.0))]);
+        assert_eq!(level(&crit, 50.0), Some(5.0));
+        crit.default_below(true);
+        assert_eq!(level(&crit, 50.0), None);
+        assert_eq!(level(&crit, 4.0), Some(5.0));
+
+        let mut explicit = parse(&[
+            ("crit", Value::from(90.0)),
+            ("below", Value::from(false)),
+        ]);
+        explicit.default_below(true);
+        assert_eq!(level(&explicit, 50.0), None);
+        assert_eq!(level(&explicit, 95.0), Some(90.0));
+    }
+}
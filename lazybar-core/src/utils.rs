@@ -1,30 +1,48 @@
 use std::{
     borrow::Cow,
     collections::HashMap,
-    env,
+    env, fs,
     pin::Pin,
+    process::Command,
     sync::{Arc, LazyLock, Mutex},
     task::{Context, Poll},
     time::Duration,
 };
 
-use anyhow::Result;
+use anyhow::{Result, anyhow};
 use config::{Map, Value, ValueKind};
 use csscolorparser::Color;
 use derive_builder::Builder;
-use futures::{Stream, task::AtomicWaker};
+use derive_debug::Dbg;
+use futures::{FutureExt, Stream, task::AtomicWaker};
 use lazybar_types::EventResponse;
-use regex::{Captures, Regex};
 use tokio::{
     io::AsyncWriteExt,
     net::UnixStream,
+    sync::broadcast,
+    task::JoinHandle,
     time::{Instant, Interval, interval},
 };
+use x11rb::{
+    resource_manager::{self, Database},
+    rust_connection::RustConnection,
+};
 
-use crate::{ipc::ChannelEndpoint, parser};
+use crate::{
+    ipc::{self, ChannelEndpoint},
+    parser,
+};
 
-static REGEX: LazyLock<Regex> =
-    LazyLock::new(|| Regex::new(r"%\{(?<const>[^}]+)}").unwrap());
+static XRDB: LazyLock<Option<Database>> = LazyLock::new(|| {
+    let (conn, _) = RustConnection::connect(None)
+        .map_err(|e| log::warn!("Failed to connect to X server: {e}"))
+        .ok()?;
+    resource_manager::new_from_default(&conn)
+        .map_err(|e| log::warn!("Failed to read X resource database: {e}"))
+        .ok()
+});
+static COMMAND_CACHE: LazyLock<Mutex<HashMap<String, String>>> =
+    LazyLock::new(|| Mutex::new(HashMap::new()));
 
 /// A wrapper struct to read indefinitely from a [`UnixStream`] and send the
 /// results through a channel.
@@ -44,6 +62,10 @@ impl UnixStreamWrapper {
     }
 
     /// Reads a message from the inner [`UnixStream`] and returns a response
+    ///
+    /// If the message is `subscribe`, the stream is instead kept open, and
+    /// every event passed to [`ipc::publish`][crate::ipc::publish] is written
+    /// to it as a line of JSON.
     pub async fn run(mut self) -> Result<()> {
         let mut data = [0; 1024];
         self.inner.readable().await?;
@@ -52,6 +74,9 @@ impl UnixStreamWrapper {
         if message.is_empty() {
             return Ok(());
         }
+        if message.trim() == "subscribe" {
+            return self.subscribe().await;
+        }
         self.endpoint.send.send(message.to_string())?;
         let response = self
             .endpoint
@@ -68,6 +93,33 @@ impl UnixStreamWrapper {
 
         Ok(())
     }
+
+    async fn subscribe(self) -> Result<()> {
+        let Self {
+            mut inner,
+            endpoint,
+        } = self;
+        let mut events = ipc::subscribe();
+        // the bar waits for a message until the sender is dropped
+        drop(endpoint);
+
+        loop {
+            match events.recv().await {
+                Ok(event) => {
+                    let mut line = serde_json::to_string(&event)?;
+                    line.push('\n');
+                    // this fails once the subscriber disconnects
+                    if inner.write_all(line.as_bytes()).await.is_err() {
+                        return Ok(());
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    log::warn!("Subscriber missed {n} events");
+                }
+                Err(broadcast::error::RecvError::Closed) => return Ok(()),
+            }
+        }
+    }
 }
 
 ///Custom [`IntervalStream`]
@@ -152,6 +204,68 @@ impl ManagedIntervalStreamBuilder {
     }
 }
 
+/// A stream that runs a blocking function on tokio's blocking thread pool
+/// over and over, yielding each time it returns. This is useful for waiting on
+/// file descriptors that tokio can't watch, like netlink sockets or files that
+/// signal changes through `poll`.
+///
+/// If the function returns an error, a warning is logged and the stream ends.
+#[derive(Dbg)]
+pub struct BlockingStream {
+    name: String,
+    #[dbg(skip)]
+    wait: Arc<dyn Fn() -> Result<()> + Send + Sync>,
+    handle: Option<JoinHandle<Result<()>>>,
+}
+
+impl BlockingStream {
+    /// Creates a new stream. `name` describes what is being waited on for
+    /// log messages.
+    pub fn new(
+        name: impl Into<String>,
+        wait: impl Fn() -> Result<()> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            name: name.into(),
+            wait: Arc::new(wait),
+            handle: None,
+        }
+    }
+}
+
+impl Stream for BlockingStream {
+    type Item = ();
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        if let Some(handle) = &mut self.handle {
+            let value = handle.poll_unpin(cx).map(|res| match res {
+                Ok(Ok(())) => Some(()),
+                Ok(Err(e)) => {
+                    log::warn!("Stopped watching {}: {e}", self.name);
+                    None
+                }
+                Err(_) => None,
+            });
+            if value.is_ready() {
+                self.handle = None;
+            }
+            value
+        } else {
+            let wait = self.wait.clone();
+            let waker = cx.waker().clone();
+            self.handle = Some(tokio::task::spawn_blocking(move || {
+                let res = wait();
+                waker.wake();
+                res
+            }));
+            Poll::Pending
+        }
+    }
+}
+
 /// Removes a value from a given config table and returns an attempt at parsing
 /// it into a table.
 pub fn get_table_from_config<S: std::hash::BuildHasher>(
@@ -182,10 +296,12 @@ pub fn remove_string_from_config<S: std::hash::BuildHasher>(
                 None
             },
             |s| {
-                Some(
-                    replace_consts(s.as_str(), parser::CONSTS.get().unwrap())
-                        .to_string(),
-                )
+                replace_consts(s.as_str(), parser::CONSTS.get().unwrap())
+                    .map_err(|e| {
+                        log::error!("Failed to resolve `{id}`: {e}");
+                    })
+                    .ok()
+                    .map(|s| s.to_string())
             },
         )
     })
@@ -206,18 +322,25 @@ pub fn remove_array_from_config<S: std::hash::BuildHasher>(
             |v| {
                 Some(
                     v.into_iter()
-                        .map(|val| {
+                        .filter_map(|val| {
                             let origin = val.origin().map(ToString::to_string);
-                            val.clone().into_string().map_or(val, |val| {
+                            let Ok(s) = val.clone().into_string() else {
+                                return Some(val);
+                            };
+                            replace_consts(
+                                s.as_str(),
+                                parser::CONSTS.get().unwrap(),
+                            )
+                            .map_err(|e| {
+                                log::error!(
+                                    "Failed to resolve element of `{id}`: {e}"
+                                );
+                            })
+                            .ok()
+                            .map(|s| {
                                 Value::new(
                                     origin.as_ref(),
-                                    ValueKind::String(
-                                        replace_consts(
-                                            val.as_str(),
-                                            parser::CONSTS.get().unwrap(),
-                                        )
-                                        .to_string(),
-                                    ),
+                                    ValueKind::String(s.to_string()),
                                 )
                             })
                         })
@@ -293,6 +416,10 @@ pub fn remove_color_from_config<S: std::hash::BuildHasher>(
             },
             |val| {
                 replace_consts(val.as_str(), parser::CONSTS.get().unwrap())
+                    .map_err(|e| {
+                        log::error!("Failed to resolve `{id}`: {e}");
+                    })
+                    .ok()?
                     .parse()
                     .map_or_else(
                         |_| {
@@ -306,28 +433,267 @@ pub fn remove_color_from_config<S: std::hash::BuildHasher>(
     })
 }
 
+/// Returns whether `text` matches a shell-style glob `pattern`, where `*`
+/// matches any sequence of characters and `?` matches any single character.
+#[must_use]
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern = pattern.chars().collect::<Vec<_>>();
+    let text = text.chars().collect::<Vec<_>>();
+    let (mut p, mut t) = (0, 0);
+    // the position of the last `*` and the text position it was tried at
+    let mut star = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => {
+                let Some((star_p, star_t)) = star else {
+                    return false;
+                };
+                p = star_p + 1;
+                t = star_t + 1;
+                star = Some((star_p, star_t + 1));
+            }
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
 /// Replaces references to constants (of the form `%{const_name}`) with their
 /// respective constants.
+///
+/// In addition to the `consts` table, the following sources are supported:
+/// - `%{env:KEY}`: the environment variable `KEY`
+/// - `%{xrdb:name}`: the value of `name` in the X resource database (e.g.
+///   `%{xrdb:color4}` for colors set by pywal or `~/.Xresources`)
+/// - `%{file:/path}`: the contents of a file, with surrounding whitespace
+///   trimmed
+/// - `%{cmd:command}`: the output of a shell command, with surrounding
+///   whitespace trimmed. Each distinct command is run once, when it is first
+///   referenced.
+///
+/// `env`, `xrdb`, and `file` references accept a fallback value using
+/// `%{env:KEY:-default}` syntax, which is used if the value is unset or
+/// empty (or, for files, unreadable).
+///
+/// A reference ends at the `}` that balances its opening `{`, so commands like
+/// `%{cmd:awk '{print $1}' file}` work as written. Unbalanced braces can be
+/// written as `\{` and `\}`.
+///
+/// # Errors
+///
+/// If a reference can't be resolved and has no fallback value.
 pub fn replace_consts<'a, S: std::hash::BuildHasher>(
     format: &'a str,
     consts: &HashMap<String, Value, S>,
-) -> Cow<'a, str> {
-    REGEX.replace_all(format, |caps: &Captures| {
-        let con = &caps["const"];
-        if let Some(c) = con.strip_prefix("env:") {
-            if let Ok(c) = env::var(c) {
-                return c;
+) -> Result<Cow<'a, str>> {
+    let mut result = String::new();
+    let mut last = 0;
+    let mut search = 0;
+    while let Some(start) = format[search..].find("%{").map(|i| search + i) {
+        search = start + 2;
+        let Some((con, len)) = find_const(&format[search..]) else {
+            continue;
+        };
+        result.push_str(&format[last..start]);
+        result.push_str(resolve_const(con.as_str(), consts)?.as_str());
+        search += len;
+        last = search;
+    }
+
+    if last == 0 {
+        Ok(Cow::Borrowed(format))
+    } else {
+        result.push_str(&format[last..]);
+        Ok(Cow::Owned(result))
+    }
+}
+
+/// Reads the body of a reference from just after its opening `%{`, returning
+/// it with escapes removed along with the length of the reference that
+/// remains, including the closing `}`. Returns [`None`] if the reference is
+/// empty or never closed.
+fn find_const(s: &str) -> Option<(String, usize)> {
+    let mut con = String::new();
+    let mut depth = 0;
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' if s[i + 1..].starts_with(['{', '}']) => {
+                con.extend(chars.next().map(|(_, c)| c));
+            }
+            '{' => {
+                depth += 1;
+                con.push(c);
+            }
+            '}' if depth == 0 => {
+                return (!con.is_empty()).then_some((con, i + 1));
             }
+            '}' => {
+                depth -= 1;
+                con.push(c);
+            }
+            c => con.push(c),
+        }
+    }
+    None
+}
+
+fn resolve_const<S: std::hash::BuildHasher>(
+    con: &str,
+    consts: &HashMap<String, Value, S>,
+) -> Result<String> {
+    if let Some(cmd) = con.strip_prefix("cmd:") {
+        return run_const_command(cmd);
+    }
+
+    let (source, fallback) = match con.split_once(":-") {
+        Some((source, fallback))
+            if ["env:", "xrdb:", "file:"]
+                .iter()
+                .any(|prefix| source.starts_with(prefix)) =>
+        {
+            (source, Some(fallback))
         }
-        consts
+        _ => (con, None),
+    };
+
+    let value = if let Some(key) = source.strip_prefix("env:") {
+        env::var(key)
+            .ok()
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| anyhow!("environment variable `{key}` is not set"))
+    } else if let Some(name) = source.strip_prefix("xrdb:") {
+        XRDB.as_ref()
+            .ok_or_else(|| anyhow!("X resource database is unavailable"))?
+            .get_string(name, "")
+            .filter(|v| !v.is_empty())
+            .map(ToString::to_string)
+            .ok_or_else(|| anyhow!("X resource `{name}` is not set"))
+    } else if let Some(path) = source.strip_prefix("file:") {
+        fs::read_to_string(path)
+            .map_err(|e| anyhow!("failed to read file `{path}`: {e}"))
+            .and_then(|s| {
+                Some(s.trim().to_string())
+                    .filter(|s| !s.is_empty())
+                    .ok_or_else(|| anyhow!("file `{path}` is empty"))
+            })
+    } else {
+        return consts
             .get(con)
             .and_then(|c| c.clone().into_string().ok())
-            .map_or_else(
-                || {
-                    log::warn!("Invalid constant: {con}");
-                    String::new()
-                },
-                |con| con,
-            )
-    })
+            .ok_or_else(|| anyhow!("undefined constant `{con}`"));
+    };
+
+    match (value, fallback) {
+        (Ok(value), _) => Ok(value),
+        (Err(_), Some(fallback)) => Ok(fallback.to_string()),
+        (Err(e), None) => Err(e),
+    }
+}
+
+fn run_const_command(cmd: &str) -> Result<String> {
+    let mut cache = COMMAND_CACHE.lock().unwrap();
+    if let Some(output) = cache.get(cmd) {
+        return Ok(output.clone());
+    }
+
+    let output = Command::new("sh").arg("-c").arg(cmd).output()?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "command `{cmd}` exited with {}: {}",
+            output.status,
+            String::from_utf8_lossy(output.stderr.as_slice()).trim()
+        ));
+    }
+
+    let output = String::from_utf8_lossy(output.stdout.as_slice())
+        .trim()
+        .to_string();
+    cache.insert(cmd.to_string(), output.clone());
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use super::*;
+
+    fn replace(format: &str) -> Result<String> {
+        let consts =
+            HashMap::from([(String::from("accent"), Value::from("#ff0000"))]);
+        replace_consts(format, &consts).map(Cow::into_owned)
+    }
+
+    #[test]
+    fn consts() {
+        assert_eq!(replace("fg=%{accent}").unwrap(), "fg=#ff0000");
+        assert_eq!(replace("%{} and %{accent").unwrap(), "%{} and %{accent");
+        assert!(replace("%{missing}").is_err());
+    }
+
+    #[test]
+    fn env() {
+        // SAFETY: no other test reads or writes this variable
+        unsafe {
+            env::set_var("LAZYBAR_TEST_SET", "set");
+            env::set_var("LAZYBAR_TEST_EMPTY", "");
+        }
+        assert_eq!(replace("%{env:LAZYBAR_TEST_SET}").unwrap(), "set");
+        assert_eq!(replace("%{env:LAZYBAR_TEST_SET:-other}").unwrap(), "set");
+        assert_eq!(
+            replace("%{env:LAZYBAR_TEST_EMPTY:-other}").unwrap(),
+            "other"
+        );
+        assert_eq!(replace("%{env:LAZYBAR_TEST_UNSET:-}").unwrap(), "");
+        assert!(replace("%{env:LAZYBAR_TEST_UNSET}").is_err());
+        assert!(replace("%{env:LAZYBAR_TEST_EMPTY}").is_err());
+    }
+
+    #[test]
+    fn file() {
+        let dir = env::temp_dir();
+        let full = dir.join(format!("lazybar-test-{}-full", process::id()));
+        let empty = dir.join(format!("lazybar-test-{}-empty", process::id()));
+        fs::write(&full, "  contents\n").unwrap();
+        fs::write(&empty, "\n").unwrap();
+        let (full_path, empty_path) = (full.display(), empty.display());
+
+        assert_eq!(
+            replace(&format!("%{{file:{full_path}}}")).unwrap(),
+            "contents"
+        );
+        assert_eq!(
+            replace(&format!("%{{file:{empty_path}:-fallback}}")).unwrap(),
+            "fallback"
+        );
+        assert!(replace(&format!("%{{file:{empty_path}}}")).is_err());
+        assert_eq!(
+            replace("%{file:/nonexistent/lazybar:-fallback}").unwrap(),
+            "fallback"
+        );
+
+        fs::remove_file(full).unwrap();
+        fs::remove_file(empty).unwrap();
+    }
+
+    #[test]
+    fn cmd() {
+        assert_eq!(replace("%{cmd:echo hi}").unwrap(), "hi");
+        assert_eq!(
+            replace("%{cmd:echo a b | awk '{print $2}'}!").unwrap(),
+            "b!"
+        );
+        assert_eq!(replace(r"%{cmd:printf '%s' '\}'}").unwrap(), "}");
+        assert!(replace("%{cmd:exit 1}").is_err());
+    }
 }
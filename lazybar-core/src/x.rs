@@ -1,5 +1,7 @@
 #[cfg(feature = "cursor")]
 use std::cell::OnceCell;
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+use std::collections::HashMap;
 use std::{
     pin::Pin,
     sync::{Arc, LazyLock, Mutex},
@@ -10,11 +12,25 @@ use std::{
 use anyhow::anyhow;
 use anyhow::{Context, Result};
 use cairo::XCBSurface;
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+use config::Value;
 use csscolorparser::Color;
 use futures::FutureExt;
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+use pango::Layout;
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+use pangocairo::functions::{create_layout, show_layout};
 use rustix::system::uname;
 use tokio::task::JoinHandle;
 use tokio_stream::Stream;
+#[cfg(any(
+    feature = "cursor",
+    feature = "tasklist",
+    feature = "xworkspaces"
+))]
+use x11rb::protocol::xproto::ChangeWindowAttributesAux;
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+use x11rb::rust_connection::RustConnection;
 use x11rb::{
     connection::Connection,
     protocol::{
@@ -33,12 +49,27 @@ use x11rb::{
 use x11rb::{
     cursor::Handle,
     errors::ReplyError,
-    protocol::xproto::ChangeWindowAttributesAux,
     resource_manager::{self, Database},
 };
 
 #[cfg(feature = "cursor")]
 use crate::bar::Cursor;
+#[cfg(all(
+    feature = "cursor",
+    any(feature = "tasklist", feature = "xworkspaces")
+))]
+use crate::bar::CursorInfo;
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+use crate::{
+    Attrs, Highlight,
+    background::Bg,
+    bar::PanelDrawInfo,
+    common::PanelCommon,
+    get_table_from_config,
+    image::Image,
+    inline::{OBJECT, Object, PlacedObject},
+    remove_bool_from_config, remove_float_from_config,
+};
 use crate::{Position, interned_atoms};
 
 static ATOMS: LazyLock<Mutex<InternedAtoms>> =
@@ -53,12 +84,15 @@ interned_atoms!(
     _NET_WM_PID,
     _XEMBED_INFO,
     _NET_WM_NAME,
+    _NET_WM_ICON,
     _NET_WM_STATE,
     _NET_WM_STRUT,
     _NET_WM_DESKTOP,
     _NET_CLIENT_LIST,
+    _NET_CLOSE_WINDOW,
     _NET_ACTIVE_WINDOW,
     _NET_DESKTOP_NAMES,
+    _NET_DESKTOP_VIEWPORT,
     _NET_WM_WINDOW_TYPE,
     _NET_WM_STATE_STICKY,
     _NET_CURRENT_DESKTOP,
@@ -68,7 +102,9 @@ interned_atoms!(
     _NET_NUMBER_OF_DESKTOPS,
     _NET_WM_WINDOW_TYPE_DOCK,
     _NET_WM_WINDOW_TYPE_NORMAL,
+    _NET_WM_STATE_SKIP_TASKBAR,
     _NET_SYSTEM_TRAY_ORIENTATION,
+    _NET_WM_STATE_DEMANDS_ATTENTION,
 );
 
 #[cfg(feature = "cursor")]
@@ -206,7 +242,9 @@ pub fn create_window(
             .event_mask(
                 EventMask::EXPOSURE
                     | EventMask::BUTTON_PRESS
-                    | EventMask::POINTER_MOTION,
+                    | EventMask::BUTTON_RELEASE
+                    | EventMask::POINTER_MOTION
+                    | EventMask::LEAVE_WINDOW,
             )
             .colormap(colormap),
     )?;
@@ -441,7 +479,11 @@ pub fn create_surface(
     }?)
 }
 
-#[cfg(feature = "systray")]
+/// The urgency flag in `WM_HINTS`.
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+const URGENCY_HINT: u32 = 1 << 8;
+
+#[cfg(any(feature = "systray", feature = "tasklist"))]
 pub fn get_window_name(
     conn: &impl Connection,
     window: Window,
@@ -481,6 +523,540 @@ pub fn get_window_name(
     }
 }
 
+/// Returns the windows managed by the window manager, from
+/// `_NET_CLIENT_LIST`.
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+pub fn get_clients(
+    conn: &impl Connection,
+    root: Window,
+) -> Result<Vec<Window>> {
+    let client_atom = InternedAtoms::get(conn, "_NET_CLIENT_LIST")?;
+    let mut windows = Vec::new();
+
+    loop {
+        let reply = conn
+            .get_property(
+                false,
+                root,
+                client_atom,
+                AtomEnum::WINDOW,
+                windows.len() as u32,
+                16,
+            )?
+            .reply()?;
+
+        let wids = reply.value32().context("Invalid reply from X server")?;
+        windows.append(&mut wids.collect());
+
+        if reply.bytes_after == 0 {
+            break;
+        }
+    }
+
+    Ok(windows)
+}
+
+/// Returns the center of each of `windows` relative to `root`, or `None` for
+/// those that couldn't be found. Every request is sent before any reply is
+/// read, so this takes one round trip however many windows there are.
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+pub fn window_centers(
+    conn: &impl Connection,
+    windows: &[Window],
+    root: Window,
+) -> Vec<Option<(i32, i32)>> {
+    let cookies: Vec<_> = windows
+        .iter()
+        .map(|&window| {
+            (
+                conn.get_geometry(window).ok(),
+                conn.translate_coordinates(window, root, 0, 0).ok(),
+            )
+        })
+        .collect();
+    cookies
+        .into_iter()
+        .map(|(geometry, pos)| {
+            let geometry = geometry?.reply().ok()?;
+            let pos = pos?.reply().ok()?;
+            Some((
+                i32::from(pos.dst_x) + i32::from(geometry.width) / 2,
+                i32::from(pos.dst_y) + i32::from(geometry.height) / 2,
+            ))
+        })
+        .collect()
+}
+
+/// Returns whether the point `(x, y)` lies on `monitor`.
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+pub fn monitor_contains(monitor: &MonitorInfo, (x, y): (i32, i32)) -> bool {
+    (i32::from(monitor.x)..i32::from(monitor.x) + i32::from(monitor.width))
+        .contains(&x)
+        && (i32::from(monitor.y)
+            ..i32::from(monitor.y) + i32::from(monitor.height))
+            .contains(&y)
+}
+
+/// Returns whether `window` demands attention, either through
+/// `_NET_WM_STATE` or the urgency flag in `WM_HINTS`.
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+pub fn is_urgent(conn: &impl Connection, window: Window) -> bool {
+    let demands_attention = InternedAtoms::get(conn, "_NET_WM_STATE")
+        .and_then(|state_atom| {
+            Ok((
+                state_atom,
+                InternedAtoms::get(conn, "_NET_WM_STATE_DEMANDS_ATTENTION")?,
+            ))
+        })
+        .ok()
+        .and_then(|(state_atom, attention_atom)| {
+            conn.get_property(false, window, state_atom, AtomEnum::ATOM, 0, 64)
+                .ok()
+                .and_then(|c| c.reply().ok())
+                .map(|r| (r, attention_atom))
+        })
+        .is_some_and(|(r, attention_atom)| {
+            r.value32()
+                .is_some_and(|mut iter| iter.any(|a| a == attention_atom))
+        });
+    demands_attention
+        || conn
+            .get_property(
+                false,
+                window,
+                AtomEnum::WM_HINTS,
+                AtomEnum::WM_HINTS,
+                0,
+                1,
+            )
+            .ok()
+            .and_then(|c| c.reply().ok())
+            .and_then(|r| r.value32().and_then(|mut iter| iter.next()))
+            .is_some_and(|flags| flags & URGENCY_HINT != 0)
+}
+
+/// Returns the instance and class names of `window` from `WM_CLASS`.
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+pub fn get_class(conn: &impl Connection, window: Window) -> Vec<String> {
+    conn.get_property(
+        false,
+        window,
+        AtomEnum::WM_CLASS,
+        AtomEnum::STRING,
+        0,
+        256,
+    )
+    .ok()
+    .and_then(|c| c.reply().ok())
+    .map(|r| {
+        r.value
+            .split(|&b| b == 0)
+            .filter(|s| !s.is_empty())
+            .map(|s| String::from_utf8_lossy(s).into_owned())
+            .collect()
+    })
+    .unwrap_or_default()
+}
+
+/// Fetches the icon of `window` from `_NET_WM_ICON`, choosing the smallest
+/// one at least `size` pixels tall (or the largest if none are).
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+pub fn get_icon(
+    conn: &impl Connection,
+    window: Window,
+    size: f64,
+) -> Option<Image> {
+    let icon_atom = InternedAtoms::get(conn, "_NET_WM_ICON").ok()?;
+    let reply = conn
+        .get_property(false, window, icon_atom, AtomEnum::CARDINAL, 0, u32::MAX)
+        .ok()?
+        .reply()
+        .ok()?;
+    let data: Vec<u32> = reply.value32()?.collect();
+
+    let mut best: Option<(u32, u32, &[u32])> = None;
+    let mut rest = data.as_slice();
+    while let [width, height, pixels @ ..] = rest {
+        let len = *width as usize * *height as usize;
+        if len == 0 || pixels.len() < len {
+            break;
+        }
+        let big_enough = |h: u32| f64::from(h) >= size;
+        if best.is_none_or(|(_, h, _)| {
+            if big_enough(h) {
+                big_enough(*height) && *height < h
+            } else {
+                *height > h
+            }
+        }) {
+            best = Some((*width, *height, &pixels[..len]));
+        }
+        rest = &pixels[len..];
+    }
+
+    let (width, height, pixels) = best?;
+    Image::from_argb_data(pixels, width as i32, height as i32, 0.0, 0.0, size)
+        .map_err(|e| log::warn!("Failed to load window icon: {e}"))
+        .ok()
+}
+
+/// The icons shown for windows: a glyph (any markup) for each window class,
+/// falling back to the icon the window provides in `_NET_WM_ICON`.
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+#[derive(Clone, Debug)]
+pub struct WindowIcons {
+    glyphs: HashMap<String, String>,
+    window_icons: bool,
+    size: f64,
+    cache: Arc<Mutex<HashMap<Window, Option<Image>>>>,
+}
+
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+impl Default for WindowIcons {
+    fn default() -> Self {
+        Self {
+            glyphs: HashMap::new(),
+            window_icons: true,
+            size: 16.0,
+            cache: Arc::default(),
+        }
+    }
+}
+
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+impl WindowIcons {
+    /// Parses `icons`, `window_icons`, and `icon_size` from `table`.
+    pub fn parse(table: &mut HashMap<String, Value>) -> Self {
+        let mut icons = Self::default();
+        if let Some(glyphs) = get_table_from_config("icons", table) {
+            table.remove("icons");
+            icons.glyphs = glyphs
+                .into_iter()
+                .filter_map(|(class, glyph)| {
+                    glyph
+                        .into_string()
+                        .map_err(|e| {
+                            log::warn!("Ignoring icon for {class}: {e}");
+                        })
+                        .ok()
+                        .map(|glyph| (class, glyph))
+                })
+                .collect();
+        }
+        if let Some(window_icons) =
+            remove_bool_from_config("window_icons", table)
+        {
+            icons.window_icons = window_icons;
+        }
+        if let Some(size) = remove_float_from_config("icon_size", table) {
+            icons.size = size;
+        }
+        icons
+    }
+
+    /// Whether [`Self::markup`] needs the class of each window.
+    pub fn uses_class(&self) -> bool {
+        !self.glyphs.is_empty()
+    }
+
+    /// Returns the markup for the icon of `window`: the glyph for its class
+    /// if there is one, or otherwise its `_NET_WM_ICON`, which is added to
+    /// `objects`.
+    pub(crate) fn markup(
+        &self,
+        conn: &impl Connection,
+        window: Window,
+        class: &[String],
+        objects: &mut Vec<Object>,
+    ) -> String {
+        if let Some(glyph) =
+            class.iter().find_map(|class| self.glyphs.get(class))
+        {
+            glyph.clone()
+        } else if self.window_icons
+            && let Some(icon) = self
+                .cache
+                .lock()
+                .unwrap()
+                .entry(window)
+                .or_insert_with(|| get_icon(conn, window, self.size))
+        {
+            objects.push(Object::Image(icon.clone()));
+            OBJECT.to_string()
+        } else {
+            String::new()
+        }
+    }
+
+    /// Drops the cached icons of the windows for which `keep` returns false.
+    pub fn retain(&self, mut keep: impl FnMut(Window) -> bool) {
+        self.cache.lock().unwrap().retain(|&window, _| keep(window));
+    }
+}
+
+/// A [`Stream`] that yields each time one of `atoms` changes on a window
+/// whose `PROPERTY_CHANGE` events are selected. When a window's
+/// `_NET_WM_ICON` changes, its cached icon is dropped first.
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+pub struct PropertyStream {
+    conn: Arc<RustConnection>,
+    atoms: Arc<[Atom]>,
+    icon_atom: Atom,
+    icons: WindowIcons,
+    /// Resolves to the window whose icon changed, if that's what happened
+    handle: Option<JoinHandle<Option<Window>>>,
+}
+
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+impl PropertyStream {
+    pub fn new(
+        conn: Arc<RustConnection>,
+        atoms: &[Atom],
+        icons: WindowIcons,
+    ) -> Result<Self> {
+        let icon_atom = InternedAtoms::get(&*conn, "_NET_WM_ICON")?;
+        Ok(Self {
+            conn,
+            atoms: atoms.into(),
+            icon_atom,
+            icons,
+            handle: None,
+        })
+    }
+}
+
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+impl Stream for PropertyStream {
+    type Item = ();
+
+    fn poll_next(
+        mut self: Pin<&mut Self>,
+        cx: &mut task::Context<'_>,
+    ) -> Poll<Option<Self::Item>> {
+        if let Some(handle) = &mut self.handle {
+            if handle.is_finished() {
+                if let Poll::Ready(Ok(Some(window))) = handle.poll_unpin(cx) {
+                    self.icons.cache.lock().unwrap().remove(&window);
+                }
+                self.handle = None;
+                Poll::Ready(Some(()))
+            } else {
+                Poll::Pending
+            }
+        } else {
+            let conn = self.conn.clone();
+            let waker = cx.waker().clone();
+            let atoms = self.atoms.clone();
+            let icon_atom = self.icon_atom;
+            self.handle = Some(tokio::task::spawn_blocking(move || {
+                loop {
+                    let event = conn.wait_for_event();
+                    if let Ok(Event::PropertyNotify(event)) = event
+                        && atoms.contains(&event.atom)
+                    {
+                        waker.wake();
+                        break (event.atom == icon_atom)
+                            .then_some(event.window);
+                    }
+                }
+            }));
+            Poll::Pending
+        }
+    }
+}
+
+/// One of the items that [`draw_segments`] draws side by side, like a
+/// workspace or a window.
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+pub struct Segment<K> {
+    key: K,
+    layout: Layout,
+    objects: Vec<PlacedObject>,
+    attrs: Attrs,
+    highlight: Highlight,
+}
+
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+impl<K> Segment<K> {
+    /// Lays out `text` in the font from `attrs`, placing `objects` in it.
+    pub(crate) fn new(
+        cr: &cairo::Context,
+        key: K,
+        text: &str,
+        objects: Vec<Object>,
+        attrs: &Attrs,
+        highlight: &Highlight,
+    ) -> Self {
+        let layout = create_layout(cr);
+        layout.set_markup(text);
+        attrs.apply_font(&layout);
+        let objects = PlacedObject::attach(&layout, objects);
+        Self {
+            key,
+            layout,
+            objects,
+            attrs: attrs.clone(),
+            highlight: highlight.clone(),
+        }
+    }
+}
+
+/// Draws `segments` side by side after the panel's images, recording the key
+/// and width of each in `width_cache` for [`segment_at`] and
+/// [`cycle_segments`].
+///
+/// `PROPERTY_CHANGE` events are selected on `windows` now, and on `root` and
+/// `windows` whenever the panel is shown. They're deselected while it's
+/// hidden.
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+pub fn draw_segments<K: Copy + 'static>(
+    segments: Vec<Segment<K>>,
+    width_cache: &Arc<Mutex<Vec<(K, i32)>>>,
+    conn: &Arc<RustConnection>,
+    root: Window,
+    windows: Vec<Window>,
+    common: &PanelCommon,
+    height: i32,
+    dump: String,
+) -> Result<PanelDrawInfo> {
+    select_property_changes(conn, &windows, EventMask::PROPERTY_CHANGE)?;
+
+    let mut cache = width_cache.lock().unwrap();
+    cache.clear();
+    for segment in &segments {
+        let size = segment.layout.pixel_size();
+        cache.push((
+            segment.key,
+            segment
+                .attrs
+                .bg
+                .as_ref()
+                .map_or(size, |bg| bg.adjust_dims(size, height))
+                .0,
+        ));
+    }
+    let width = cache.iter().map(|(_, width)| width).sum::<i32>();
+    drop(cache);
+
+    let images = common.images.clone();
+    let mut watched = windows;
+    watched.push(root);
+    let watched = Arc::new(watched);
+    let (conn, conn_) = (conn.clone(), conn.clone());
+    let watched_ = watched.clone();
+
+    #[cfg(feature = "cursor")]
+    let width_cache = width_cache.clone();
+
+    Ok(PanelDrawInfo::new(
+        (width, height),
+        common.dependence,
+        Box::new(move |cr, _| {
+            for image in &images {
+                image.draw(cr)?;
+            }
+
+            for segment in &segments {
+                let size = segment.layout.pixel_size();
+
+                let offset = segment
+                    .attrs
+                    .bg
+                    .as_ref()
+                    .unwrap_or(&Bg::None)
+                    .draw(cr, size.0 as f64, size.1 as f64, height as f64)?;
+
+                cr.save()?;
+                segment.highlight.draw(
+                    cr,
+                    height as f64,
+                    2.0f64.mul_add(offset, f64::from(size.0)),
+                )?;
+
+                cr.translate(offset, f64::from(height - size.1) / 2.0);
+
+                segment.attrs.apply_fg(cr);
+                show_layout(cr, &segment.layout);
+                for object in &segment.objects {
+                    object.draw(cr, &segment.layout)?;
+                }
+                cr.restore()?;
+
+                cr.translate(2.0f64.mul_add(offset, f64::from(size.0)), 0.0);
+            }
+            Ok(())
+        }),
+        Some(Box::new(move || {
+            select_property_changes(&conn, &watched, EventMask::PROPERTY_CHANGE)
+        })),
+        Some(Box::new(move || {
+            select_property_changes(&conn_, &watched_, EventMask::NO_EVENT)
+        })),
+        None,
+        #[cfg(feature = "cursor")]
+        CursorInfo::Dynamic(Box::new(move |event| {
+            Ok(
+                if segment_at(&width_cache.lock().unwrap(), event.x).is_some() {
+                    Cursor::Click
+                } else {
+                    Cursor::Default
+                },
+            )
+        })),
+        dump,
+    ))
+}
+
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+fn select_property_changes(
+    conn: &RustConnection,
+    windows: &[Window],
+    mask: EventMask,
+) -> Result<()> {
+    for &window in windows {
+        conn.change_window_attributes(
+            window,
+            &ChangeWindowAttributesAux::new().event_mask(mask),
+        )?;
+    }
+    Ok(())
+}
+
+/// Finds the segment drawn at `x`, given the key and width of each segment in
+/// the order they're drawn.
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+pub fn segment_at<K: Copy>(cache: &[(K, i32)], x: i16) -> Option<K> {
+    let mut end = 0;
+    cache
+        .iter()
+        .find(|(_, width)| {
+            end += width;
+            i32::from(x) <= end
+        })
+        .map(|(key, _)| *key)
+}
+
+/// Finds the segment drawn after (or before) `current`, wrapping around. If
+/// `current` isn't drawn, this starts from the first (or last) segment.
+#[cfg(any(feature = "tasklist", feature = "xworkspaces"))]
+pub fn cycle_segments<K: Copy + PartialEq>(
+    cache: &[(K, i32)],
+    current: Option<K>,
+    forward: bool,
+) -> Option<K> {
+    let len = cache.len();
+    if len == 0 {
+        return None;
+    }
+    let idx = match cache.iter().position(|(key, _)| Some(*key) == current) {
+        Some(pos) if forward => (pos + 1) % len,
+        Some(pos) => (pos + len - 1) % len,
+        None if forward => 0,
+        None => len - 1,
+    };
+    Some(cache[idx].0)
+}
+
 #[cfg(feature = "cursor")]
 pub fn set_cursor(
     conn: &impl Connection,
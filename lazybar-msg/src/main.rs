@@ -9,11 +9,11 @@ use std::{
 use anyhow::Result;
 use clap::{Command, CommandFactory, Parser, Subcommand};
 use clap_complete::{Generator, Shell, generate};
-use lazybar_types::EventResponse;
+use lazybar_types::{EventResponse, PanelEvent};
 use log::LevelFilter;
 use simple_logger::SimpleLogger;
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
     net::UnixStream,
 };
 
@@ -35,6 +35,13 @@ enum Mode {
     Bars { bars: Vec<String>, message: String },
     /// Send a message to all bars
     All { message: String },
+    /// Print each event published by a bar's panels until the bar exits
+    Subscribe {
+        bar: String,
+        /// Print events as JSON instead of `<panel> <event>`
+        #[arg(long)]
+        json: bool,
+    },
     /// Generate completions for the given shell
     Generate { shell: Shell },
 }
@@ -82,6 +89,9 @@ async fn main() -> Result<ExitCode> {
                 .collect::<Vec<_>>(),
             message,
         ),
+        Mode::Subscribe { bar, json } => {
+            return subscribe(bar.as_str(), json).await;
+        }
         Mode::Generate { shell: _ } => unreachable!(),
     };
 
@@ -138,3 +148,32 @@ async fn main() -> Result<ExitCode> {
 
     Ok(exit_code)
 }
+
+async fn subscribe(bar: &str, json: bool) -> Result<ExitCode> {
+    let path = PathBuf::from(format!("/tmp/lazybar-ipc/{bar}"));
+    let mut stream = match UnixStream::connect(path.as_path()).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            log::warn!(
+                "{bar}: Error opening file (is the bar running? does it have \
+                 ipc enabled?): {e}"
+            );
+            return Ok(ExitCode::from(1));
+        }
+    };
+    stream.write_all(b"subscribe").await?;
+
+    let mut lines = BufReader::new(stream).lines();
+    while let Some(line) = lines.next_line().await? {
+        if json {
+            println!("{line}");
+            continue;
+        }
+        match serde_json::from_str::<PanelEvent>(&line) {
+            Ok(event) => println!("{event}"),
+            Err(e) => log::warn!("received invalid event from {bar}: {e}"),
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
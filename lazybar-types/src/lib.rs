@@ -13,6 +13,24 @@ pub enum EventResponse {
     Err(String),
 }
 
+/// An event published by a panel, which is sent to every client that has
+/// subscribed to the bar
+#[derive(
+    Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize,
+)]
+pub struct PanelEvent {
+    /// The name of the panel that published the event
+    pub panel: String,
+    /// The event itself
+    pub event: String,
+}
+
+impl Display for PanelEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.panel, self.event)
+    }
+}
+
 impl Display for EventResponse {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {